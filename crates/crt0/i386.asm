@@ -0,0 +1,22 @@
+; Entry of dynamically linked programs
+;
+; The entry has to be part of the program, so this calls _start_stack of the shared libstd with
+; the stack the program was started with, like _start of libstd does in static programs.
+
+[BITS 32]
+
+extern _GLOBAL_OFFSET_TABLE_
+extern _start_stack
+
+section .text
+global _start
+_start:
+    mov eax, esp
+    ; The procedure linkage table of a position independent executable uses ebx
+    call .get_got
+.get_got:
+    pop ebx
+    add ebx, _GLOBAL_OFFSET_TABLE_ + $$ - .get_got wrt ..gotpc
+    push eax
+    call _start_stack wrt ..plt
+    ud2
@@ -0,0 +1,16 @@
+; Entry of dynamically linked programs
+;
+; The entry has to be part of the program, so this calls _start_stack of the shared libstd with
+; the stack the program was started with, like _start of libstd does in static programs.
+
+[BITS 64]
+
+extern _start_stack
+
+section .text
+global _start
+_start:
+    mov rdi, rsp
+    and rsp, -16
+    call _start_stack wrt ..plt
+    ud2
@@ -0,0 +1,170 @@
+//! ELF structures used by the dynamic loader
+//!
+//! The structures use `usize` for addresses, so that the same definitions describe 32 bit objects
+//! on x86 and 64 bit objects on x86_64. Only the order of fields differs.
+
+/// The object is a shared object, or a position independent executable
+pub const ET_DYN: u16 = 3;
+
+/// A segment loaded into memory
+pub const PT_LOAD: u32 = 1;
+/// The dynamic section
+pub const PT_DYNAMIC: u32 = 2;
+/// The program headers
+pub const PT_PHDR: u32 = 6;
+
+/// The segment is writeable
+pub const PF_W: u32 = 2;
+
+pub const DT_NULL: isize = 0;
+pub const DT_NEEDED: isize = 1;
+pub const DT_PLTRELSZ: isize = 2;
+pub const DT_HASH: isize = 4;
+pub const DT_STRTAB: isize = 5;
+pub const DT_SYMTAB: isize = 6;
+pub const DT_RELA: isize = 7;
+pub const DT_RELASZ: isize = 8;
+pub const DT_RELAENT: isize = 9;
+pub const DT_SYMENT: isize = 11;
+pub const DT_INIT: isize = 12;
+pub const DT_REL: isize = 17;
+pub const DT_RELSZ: isize = 18;
+pub const DT_RELENT: isize = 19;
+pub const DT_PLTREL: isize = 20;
+pub const DT_JMPREL: isize = 23;
+pub const DT_INIT_ARRAY: isize = 25;
+pub const DT_INIT_ARRAYSZ: isize = 27;
+pub const DT_GNU_HASH: isize = 0x6ffffef5;
+
+/// The symbol is not defined in this object
+pub const SHN_UNDEF: u16 = 0;
+
+pub const STB_GLOBAL: u8 = 1;
+pub const STB_WEAK: u8 = 2;
+
+// The relocation types of x86 and x86_64 have the same numbers, only the width of R_ABS differs
+pub const R_NONE: usize = 0;
+/// S + A, the width of an address
+pub const R_ABS: usize = 1;
+/// S + A - P, 32 bits wide
+pub const R_PC32: usize = 2;
+/// Copy the data of the symbol from a shared object
+pub const R_COPY: usize = 5;
+/// S
+pub const R_GLOB_DAT: usize = 6;
+/// S
+pub const R_JUMP_SLOT: usize = 7;
+/// B + A
+pub const R_RELATIVE: usize = 8;
+
+/// The ELF header
+#[repr(C)]
+pub struct Header {
+    pub ident: [u8; 16],
+    pub _type: u16,
+    pub machine: u16,
+    pub version: u32,
+    pub entry: usize,
+    pub ph_off: usize,
+    pub sh_off: usize,
+    pub flags: u32,
+    pub eh_size: u16,
+    pub ph_ent_size: u16,
+    pub ph_num: u16,
+    pub sh_ent_size: u16,
+    pub sh_num: u16,
+    pub sh_str_index: u16,
+}
+
+/// A program header
+#[cfg(target_arch = "x86")]
+#[repr(C)]
+pub struct ProgramHeader {
+    pub _type: u32,
+    pub offset: usize,
+    pub vaddr: usize,
+    pub paddr: usize,
+    pub file_size: usize,
+    pub mem_size: usize,
+    pub flags: u32,
+    pub align: usize,
+}
+
+/// A program header
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+pub struct ProgramHeader {
+    pub _type: u32,
+    pub flags: u32,
+    pub offset: usize,
+    pub vaddr: usize,
+    pub paddr: usize,
+    pub file_size: usize,
+    pub mem_size: usize,
+    pub align: usize,
+}
+
+/// An entry of the dynamic section
+#[repr(C)]
+pub struct Dynamic {
+    pub tag: isize,
+    pub val: usize,
+}
+
+/// A symbol
+#[cfg(target_arch = "x86")]
+#[repr(C)]
+pub struct Symbol {
+    pub name: u32,
+    pub value: usize,
+    pub size: usize,
+    pub info: u8,
+    pub other: u8,
+    pub sh_index: u16,
+}
+
+/// A symbol
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+pub struct Symbol {
+    pub name: u32,
+    pub info: u8,
+    pub other: u8,
+    pub sh_index: u16,
+    pub value: usize,
+    pub size: usize,
+}
+
+impl Symbol {
+    /// The binding of the symbol, like `STB_GLOBAL`
+    pub fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+}
+
+/// A relocation, with the addend stored at the relocated address
+#[repr(C)]
+pub struct Rel {
+    pub offset: usize,
+    pub info: usize,
+}
+
+/// A relocation with an explicit addend
+#[repr(C)]
+pub struct Rela {
+    pub offset: usize,
+    pub info: usize,
+    pub addend: isize,
+}
+
+/// Split the info field of a relocation into the symbol index and the relocation type
+#[cfg(target_arch = "x86")]
+pub fn rel_info(info: usize) -> (usize, usize) {
+    (info >> 8, info & 0xFF)
+}
+
+/// Split the info field of a relocation into the symbol index and the relocation type
+#[cfg(target_arch = "x86_64")]
+pub fn rel_info(info: usize) -> (usize, usize) {
+    (info >> 32, info & 0xFFFFFFFF)
+}
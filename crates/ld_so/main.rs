@@ -0,0 +1,175 @@
+//! The dynamic loader
+//!
+//! The kernel starts the loader instead of a program that names it in its `PT_INTERP` segment,
+//! with the stack of the program. The loader relocates itself, loads the shared objects needed by
+//! the program from `file:/lib/`, applies the relocations of all objects and jumps to the entry
+//! of the program.
+//!
+//! Symbols are looked up in the program first, then in the shared objects in the order they were
+//! loaded. All relocations are applied before the program starts, there is no lazy binding.
+
+#![crate_type="bin"]
+#![feature(asm)]
+#![feature(lang_items)]
+#![feature(naked_functions)]
+#![no_main]
+#![no_std]
+
+extern crate system;
+
+use core::{fmt, slice};
+
+use system::syscall::{sys_exit, sys_write, AT_BASE, AT_ENTRY, AT_NULL, AT_PHDR, AT_PHNUM};
+
+use elf::{Header, ProgramHeader, PT_PHDR};
+use object::Object;
+
+pub mod elf;
+pub mod externs;
+pub mod object;
+
+/// The maximum number of objects, including the program
+const MAX_OBJECTS: usize = 32;
+
+/// Write an error message and exit
+pub fn fail(parts: &[&[u8]]) -> ! {
+    for part in parts.iter() {
+        let _ = sys_write(2, part);
+    }
+
+    loop {
+        let _ = sys_exit(127);
+    }
+}
+
+#[lang = "panic_fmt"]
+extern "C" fn panic_fmt(_args: fmt::Arguments, _file: &'static str, _line: u32) -> ! {
+    fail(&[b"ld.so: panic\n"])
+}
+
+#[no_mangle]
+#[naked]
+#[cfg(target_arch = "x86")]
+pub unsafe fn _start() {
+    asm!("push esp
+        call ld_main"
+        :
+        :
+        : "memory"
+        : "intel", "volatile");
+}
+
+#[no_mangle]
+#[naked]
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn _start() {
+    asm!("mov rdi, rsp
+        and rsp, 0xFFFFFFFFFFFFFFF0
+        call ld_main"
+        :
+        :
+        : "memory"
+        : "intel", "volatile");
+}
+
+/// Jump to the entry of the program, with the stack the loader was started with
+#[cfg(target_arch = "x86")]
+unsafe fn start(stack: *const usize, entry: usize) -> ! {
+    asm!("mov esp, $0
+        jmp $1"
+        :
+        : "r"(stack), "r"(entry)
+        : "memory"
+        : "intel", "volatile");
+    loop {}
+}
+
+/// Jump to the entry of the program, with the stack the loader was started with
+#[cfg(target_arch = "x86_64")]
+unsafe fn start(stack: *const usize, entry: usize) -> ! {
+    asm!("mov rsp, $0
+        jmp $1"
+        :
+        : "r"(stack), "r"(entry)
+        : "memory"
+        : "intel", "volatile");
+    loop {}
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ld_main(stack: *const usize) -> ! {
+    // The auxiliary vector follows the arguments and the environment variables
+    let argc = *stack;
+    let mut envp = stack.offset(argc as isize + 2);
+    while *envp != 0 {
+        envp = envp.offset(1);
+    }
+    let mut auxv = envp.offset(1);
+
+    let mut base = 0;
+    let mut entry = 0;
+    let mut phdr = 0;
+    let mut phnum = 0;
+    loop {
+        let value = *auxv.offset(1);
+        match *auxv {
+            AT_NULL => break,
+            AT_BASE => base = value,
+            AT_ENTRY => entry = value,
+            AT_PHDR => phdr = value,
+            AT_PHNUM => phnum = value,
+            _ => ()
+        }
+        auxv = auxv.offset(2);
+    }
+
+    if base == 0 || entry == 0 || phdr == 0 {
+        fail(&[b"ld.so: not started as an interpreter\n"]);
+    }
+
+    // The loader relocates itself first, nothing that needs a relocation can be used before
+    let header = &*(base as *const Header);
+    let loader = Object::new(b"ld.so", base, slice::from_raw_parts((base + header.ph_off) as *const ProgramHeader,
+                                                                   header.ph_num as usize));
+    loader.relocate(&[loader]);
+
+    // The program is loaded at a bias if it is position independent
+    let phdrs = slice::from_raw_parts(phdr as *const ProgramHeader, phnum);
+    let bias = phdrs.iter().find(|ph| ph._type == PT_PHDR).map_or(0, |ph| phdr - ph.vaddr);
+
+    let mut objects = [Object::empty(); MAX_OBJECTS];
+    objects[0] = Object::new(&[], bias, phdrs);
+    let mut count = 1;
+
+    // Load the needed objects breadth first, each of them once
+    let mut i = 0;
+    while i < count {
+        let object = objects[i];
+        object.each_needed(|name| {
+            if ! objects[.. count].iter().any(|loaded| loaded.name == name) {
+                if count >= MAX_OBJECTS {
+                    fail(&[b"ld.so: too many shared objects\n"]);
+                }
+                objects[count] = Object::load(name);
+                count += 1;
+            }
+        });
+        i += 1;
+    }
+
+    // The shared objects are relocated before the program, which may copy data from them
+    let scope = &objects[.. count];
+    for object in scope.iter().rev() {
+        object.relocate(scope);
+    }
+
+    for object in scope.iter() {
+        object.protect();
+    }
+
+    for object in scope.iter().rev() {
+        object.init();
+    }
+
+    start(stack, entry)
+}
@@ -0,0 +1,447 @@
+//! Objects loaded by the dynamic loader: the program, its shared objects, and the loader itself
+
+use core::{cmp, mem, ptr, slice};
+
+use system::c_string_to_slice;
+use system::syscall::{sys_close, sys_lseek, sys_mmap, sys_mprotect, sys_munmap, sys_open,
+                      MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, O_RDONLY, PROT_EXEC, PROT_READ,
+                      PROT_WRITE, SEEK_END};
+
+use elf::*;
+use externs::{memcpy, memset};
+use fail;
+
+/// The path shared objects are loaded from
+const LIB_PATH: &'static [u8] = b"file:/lib/";
+
+/// The maximum number of memory maps of a shared object
+const MAX_MAPS: usize = 8;
+
+/// The hash function of `DT_HASH`
+fn elf_hash(name: &[u8]) -> u32 {
+    let mut hash: u32 = 0;
+    for &c in name.iter() {
+        hash = (hash << 4).wrapping_add(c as u32);
+        let high = hash & 0xF0000000;
+        if high != 0 {
+            hash ^= high >> 24;
+        }
+        hash &= !high;
+    }
+    hash
+}
+
+/// The hash function of `DT_GNU_HASH`
+fn gnu_hash(name: &[u8]) -> u32 {
+    let mut hash: u32 = 5381;
+    for &c in name.iter() {
+        hash = hash.wrapping_mul(33).wrapping_add(c as u32);
+    }
+    hash
+}
+
+/// A memory map of a shared object
+#[derive(Clone, Copy)]
+struct Map {
+    address: usize,
+    size: usize,
+    writeable: bool,
+}
+
+/// An object, found through its dynamic section
+#[derive(Clone, Copy)]
+pub struct Object {
+    /// The name the object was needed as, empty for the program
+    pub name: &'static [u8],
+    /// The load bias, added to the addresses in the object
+    pub base: usize,
+    dynamic: usize,
+    strtab: usize,
+    symtab: usize,
+    syment: usize,
+    hash: usize,
+    gnu_hash: usize,
+    rel: usize,
+    rel_size: usize,
+    rel_ent: usize,
+    rela: usize,
+    rela_size: usize,
+    rela_ent: usize,
+    jmprel: usize,
+    jmprel_size: usize,
+    pltrel: usize,
+    init: usize,
+    init_array: usize,
+    init_array_size: usize,
+    maps: [Map; MAX_MAPS],
+    maps_len: usize,
+}
+
+impl Object {
+    /// An object without a dynamic section
+    pub fn empty() -> Object {
+        Object {
+            name: &[],
+            base: 0,
+            dynamic: 0,
+            strtab: 0,
+            symtab: 0,
+            syment: mem::size_of::<Symbol>(),
+            hash: 0,
+            gnu_hash: 0,
+            rel: 0,
+            rel_size: 0,
+            rel_ent: mem::size_of::<Rel>(),
+            rela: 0,
+            rela_size: 0,
+            rela_ent: mem::size_of::<Rela>(),
+            jmprel: 0,
+            jmprel_size: 0,
+            pltrel: 0,
+            init: 0,
+            init_array: 0,
+            init_array_size: 0,
+            maps: [Map { address: 0, size: 0, writeable: false }; MAX_MAPS],
+            maps_len: 0,
+        }
+    }
+
+    /// Find the dynamic section of an object loaded at `base`, using its program headers
+    ///
+    /// This does not use anything that needs a relocation, so that the loader can relocate itself.
+    pub unsafe fn new(name: &'static [u8], base: usize, phdrs: &[ProgramHeader]) -> Object {
+        let mut object = Object::empty();
+        object.name = name;
+        object.base = base;
+
+        for ph in phdrs.iter() {
+            if ph._type == PT_DYNAMIC {
+                object.dynamic = base + ph.vaddr;
+            }
+        }
+
+        if object.dynamic == 0 {
+            return object;
+        }
+
+        let mut dynamic = object.dynamic as *const Dynamic;
+        while (*dynamic).tag != DT_NULL {
+            let val = (*dynamic).val;
+            match (*dynamic).tag {
+                DT_HASH => object.hash = base + val,
+                DT_GNU_HASH => object.gnu_hash = base + val,
+                DT_STRTAB => object.strtab = base + val,
+                DT_SYMTAB => object.symtab = base + val,
+                DT_SYMENT => object.syment = val,
+                DT_REL => object.rel = base + val,
+                DT_RELSZ => object.rel_size = val,
+                DT_RELENT => object.rel_ent = val,
+                DT_RELA => object.rela = base + val,
+                DT_RELASZ => object.rela_size = val,
+                DT_RELAENT => object.rela_ent = val,
+                DT_JMPREL => object.jmprel = base + val,
+                DT_PLTRELSZ => object.jmprel_size = val,
+                DT_PLTREL => object.pltrel = val,
+                DT_INIT => object.init = base + val,
+                DT_INIT_ARRAY => object.init_array = base + val,
+                DT_INIT_ARRAYSZ => object.init_array_size = val,
+                _ => ()
+            }
+            dynamic = dynamic.offset(1);
+        }
+
+        object
+    }
+
+    /// Load the shared object `name` from `LIB_PATH`
+    ///
+    /// Every segment gets its own anonymous memory map inside of the span of all segments, which
+    /// stays writeable until `protect` is called, so that relocations can be applied to it.
+    pub unsafe fn load(name: &'static [u8]) -> Object {
+        let mut path = [0; 256];
+        if LIB_PATH.len() + name.len() >= path.len() {
+            fail(&[b"ld.so: path too long: ", name, b"\n"]);
+        }
+        memcpy(path.as_mut_ptr(), LIB_PATH.as_ptr(), LIB_PATH.len());
+        memcpy(path.as_mut_ptr().offset(LIB_PATH.len() as isize), name.as_ptr(), name.len());
+
+        let fd = match sys_open(path.as_ptr(), O_RDONLY, 0) {
+            Ok(fd) => fd,
+            Err(_) => fail(&[b"ld.so: failed to open ", name, b"\n"])
+        };
+        let size = sys_lseek(fd, 0, SEEK_END).unwrap_or(0);
+        let file = if size >= mem::size_of::<Header>() {
+            sys_mmap(0, size, PROT_READ, MAP_PRIVATE, fd, 0).unwrap_or(0)
+        } else {
+            0
+        };
+        let _ = sys_close(fd);
+        if file == 0 {
+            fail(&[b"ld.so: failed to read ", name, b"\n"]);
+        }
+
+        let header = &*(file as *const Header);
+        if &header.ident[.. 4] != b"\x7FELF" || header._type != ET_DYN {
+            fail(&[b"ld.so: not a shared object: ", name, b"\n"]);
+        }
+        let phdrs = slice::from_raw_parts((file + header.ph_off) as *const ProgramHeader,
+                                          header.ph_num as usize);
+
+        let mut span_start = !0;
+        let mut span_end = 0;
+        for ph in phdrs.iter() {
+            if ph._type == PT_LOAD {
+                span_start = cmp::min(span_start, ph.vaddr & !4095);
+                span_end = cmp::max(span_end, (ph.vaddr + ph.mem_size + 4095) & !4095);
+            }
+        }
+        if span_end <= span_start {
+            fail(&[b"ld.so: no segments: ", name, b"\n"]);
+        }
+
+        // The span of all segments is reserved with one map, so that no segment lands on another
+        // map. Maps can not be split, so the reservation is released before the segments are
+        // mapped inside of it, and nothing else is mapped in between
+        let base = match sys_mmap(0, span_end - span_start, PROT_READ | PROT_WRITE | PROT_EXEC,
+                                  MAP_PRIVATE | MAP_ANONYMOUS, 0, 0) {
+            Ok(address) => address - span_start,
+            Err(_) => fail(&[b"ld.so: failed to map ", name, b"\n"])
+        };
+        let _ = sys_munmap(base + span_start, span_end - span_start);
+
+        let mut mapped = 0;
+        let mut maps = [Map { address: 0, size: 0, writeable: false }; MAX_MAPS];
+        let mut maps_len = 0;
+        for ph in phdrs.iter() {
+            if ph._type != PT_LOAD {
+                continue;
+            }
+
+            let writeable = ph.flags & PF_W == PF_W;
+            let start = ph.vaddr & !4095;
+            let end = (ph.vaddr + ph.mem_size + 4095) & !4095;
+
+            // A page shared with the previous segment is already mapped
+            if base + start < mapped && writeable {
+                maps[maps_len - 1].writeable = true;
+            }
+
+            let address = cmp::max(base + start, mapped);
+            if base + end > address {
+                if maps_len >= MAX_MAPS {
+                    fail(&[b"ld.so: too many segments: ", name, b"\n"]);
+                }
+                if sys_mmap(address, base + end - address, PROT_READ | PROT_WRITE | PROT_EXEC,
+                            MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, 0, 0).is_err() {
+                    fail(&[b"ld.so: failed to map ", name, b"\n"]);
+                }
+                maps[maps_len] = Map { address: address, size: base + end - address, writeable: writeable };
+                maps_len += 1;
+            }
+            mapped = cmp::max(mapped, base + end);
+
+            memcpy((base + ph.vaddr) as *mut u8, (file + ph.offset) as *const u8, ph.file_size);
+            memset((base + ph.vaddr + ph.file_size) as *mut u8, 0, ph.mem_size - ph.file_size);
+        }
+
+        let mut object = Object::new(name, base, phdrs);
+        object.maps = maps;
+        object.maps_len = maps_len;
+
+        let _ = sys_munmap(file, size);
+
+        object
+    }
+
+    /// Call a function for every shared object needed by this object
+    pub unsafe fn each_needed<F: FnMut(&'static [u8])>(&self, mut f: F) {
+        if self.dynamic == 0 {
+            return;
+        }
+
+        let mut dynamic = self.dynamic as *const Dynamic;
+        while (*dynamic).tag != DT_NULL {
+            if (*dynamic).tag == DT_NEEDED {
+                f(self.string((*dynamic).val));
+            }
+            dynamic = dynamic.offset(1);
+        }
+    }
+
+    /// Get a null terminated string of the string table
+    unsafe fn string(&self, offset: usize) -> &'static [u8] {
+        c_string_to_slice((self.strtab + offset) as *const u8)
+    }
+
+    /// Get a symbol of the symbol table
+    unsafe fn symbol(&self, index: usize) -> &'static Symbol {
+        &*((self.symtab + index * self.syment) as *const Symbol)
+    }
+
+    /// Get the address of the symbol at `index`, if it is defined by this object and named `name`
+    unsafe fn defined(&self, index: usize, name: &[u8]) -> Option<usize> {
+        let symbol = self.symbol(index);
+        let binding = symbol.binding();
+        if symbol.sh_index != SHN_UNDEF && (binding == STB_GLOBAL || binding == STB_WEAK)
+           && self.string(symbol.name as usize) == name {
+            Some(self.base + symbol.value)
+        } else {
+            None
+        }
+    }
+
+    /// Find the address of a symbol defined by this object, using its hash table
+    pub unsafe fn lookup(&self, name: &[u8]) -> Option<usize> {
+        if self.gnu_hash > 0 {
+            let table = self.gnu_hash as *const u32;
+            let buckets_len = *table as usize;
+            let symbol_offset = *table.offset(1) as usize;
+            let bloom_len = *table.offset(2) as usize;
+            let buckets = (self.gnu_hash + 16 + bloom_len * mem::size_of::<usize>()) as *const u32;
+            let chains = buckets.offset(buckets_len as isize);
+
+            let hash = gnu_hash(name);
+            let mut i = *buckets.offset((hash as usize % buckets_len) as isize) as usize;
+            if i < symbol_offset {
+                return None;
+            }
+
+            loop {
+                let chain_hash = *chains.offset((i - symbol_offset) as isize);
+                if chain_hash | 1 == hash | 1 {
+                    if let Some(address) = self.defined(i, name) {
+                        return Some(address);
+                    }
+                }
+                // The last symbol of a chain has the lowest bit set
+                if chain_hash & 1 == 1 {
+                    return None;
+                }
+                i += 1;
+            }
+        } else if self.hash > 0 {
+            let table = self.hash as *const u32;
+            let buckets_len = *table as usize;
+            let buckets = table.offset(2);
+            let chains = buckets.offset(buckets_len as isize);
+
+            let mut i = *buckets.offset((elf_hash(name) as usize % buckets_len) as isize) as usize;
+            while i != 0 {
+                if let Some(address) = self.defined(i, name) {
+                    return Some(address);
+                }
+                i = *chains.offset(i as isize) as usize;
+            }
+            None
+        } else {
+            None
+        }
+    }
+
+    /// Apply a relocation, with symbols looked up in the objects of `scope` in order
+    ///
+    /// Copy relocations skip the first object of the scope, which is the program they are in.
+    unsafe fn relocate_one(&self, scope: &[Object], offset: usize, info: usize, addend: Option<isize>) {
+        let (index, kind) = rel_info(info);
+        let target = self.base + offset;
+
+        let addend = match addend {
+            Some(addend) => addend as usize,
+            None => if kind == R_PC32 {
+                ptr::read(target as *const u32) as i32 as isize as usize
+            } else {
+                ptr::read(target as *const usize)
+            }
+        };
+
+        let (value, size) = if index > 0 {
+            let symbol = self.symbol(index);
+            let name = self.string(symbol.name as usize);
+            let skip = if kind == R_COPY { 1 } else { 0 };
+            match scope.iter().skip(skip).filter_map(|object| object.lookup(name)).next() {
+                Some(value) => (value, symbol.size),
+                None => if symbol.binding() == STB_WEAK {
+                    (0, 0)
+                } else {
+                    fail(&[b"ld.so: ", self.name, b": symbol not found: ", name, b"\n"])
+                }
+            }
+        } else {
+            (0, 0)
+        };
+
+        match kind {
+            R_NONE => (),
+            R_ABS => ptr::write(target as *mut usize, value.wrapping_add(addend)),
+            R_PC32 => ptr::write(target as *mut u32, value.wrapping_add(addend).wrapping_sub(target) as u32),
+            R_COPY => {
+                memcpy(target as *mut u8, value as *const u8, size);
+            },
+            R_GLOB_DAT | R_JUMP_SLOT => ptr::write(target as *mut usize, value),
+            R_RELATIVE => ptr::write(target as *mut usize, self.base.wrapping_add(addend)),
+            _ => fail(&[b"ld.so: ", self.name, b": unsupported relocation type\n"])
+        }
+    }
+
+    /// Apply the relocations of a table of `Rel` entries
+    unsafe fn relocate_rel(&self, scope: &[Object], table: usize, size: usize) {
+        let mut entry = table;
+        while entry < table + size {
+            let rel = &*(entry as *const Rel);
+            self.relocate_one(scope, rel.offset, rel.info, None);
+            entry += self.rel_ent;
+        }
+    }
+
+    /// Apply the relocations of a table of `Rela` entries
+    unsafe fn relocate_rela(&self, scope: &[Object], table: usize, size: usize) {
+        let mut entry = table;
+        while entry < table + size {
+            let rela = &*(entry as *const Rela);
+            self.relocate_one(scope, rela.offset, rela.info, Some(rela.addend));
+            entry += self.rela_ent;
+        }
+    }
+
+    /// Apply all relocations of this object, including those of the procedure linkage table
+    pub unsafe fn relocate(&self, scope: &[Object]) {
+        if self.rel > 0 {
+            self.relocate_rel(scope, self.rel, self.rel_size);
+        }
+        if self.rela > 0 {
+            self.relocate_rela(scope, self.rela, self.rela_size);
+        }
+        if self.jmprel > 0 {
+            if self.pltrel == DT_RELA as usize {
+                self.relocate_rela(scope, self.jmprel, self.jmprel_size);
+            } else {
+                self.relocate_rel(scope, self.jmprel, self.jmprel_size);
+            }
+        }
+    }
+
+    /// Remove write access from the memory maps of segments that are not writeable
+    pub unsafe fn protect(&self) {
+        for map in self.maps[.. self.maps_len].iter() {
+            if ! map.writeable {
+                let _ = sys_mprotect(map.address, map.size, PROT_READ | PROT_EXEC);
+            }
+        }
+    }
+
+    /// Call the initialization functions of this object
+    pub unsafe fn init(&self) {
+        if self.init > 0 {
+            mem::transmute::<usize, extern "C" fn()>(self.init)();
+        }
+
+        let mut entry = self.init_array;
+        while entry < self.init_array + self.init_array_size {
+            let function = ptr::read(entry as *const usize);
+            if function != 0 && function != !0 {
+                mem::transmute::<usize, extern "C" fn()>(function)();
+            }
+            entry += mem::size_of::<usize>();
+        }
+    }
+}
@@ -1,10 +1,129 @@
 #![deny(warnings)]
 
+extern crate system;
+
 use std::env;
 use std::fs::File;
-use std::io::{stdin, stdout, Read, Write};
+use std::io::{self, stdin, stdout, Read, Write};
+use std::os::unix::process::CommandExt;
 use std::process::Command;
 
+use sha256::sha256;
+use system::syscall::{sys_setsid, sys_write};
+
+mod sha256;
+
+/// A user of `/etc/passwd`, which has a line `name;uid;gid;home;shell` for every user
+struct Passwd {
+    uid: u32,
+    gid: u32,
+    home: String,
+    shell: String,
+}
+
+impl Passwd {
+    /// Find a user in `/etc/passwd`
+    fn find(name: &str) -> Option<Passwd> {
+        let mut passwd_string = String::new();
+        if File::open("/etc/passwd").and_then(|mut passwd| passwd.read_to_string(&mut passwd_string)).is_err() {
+            return None;
+        }
+
+        for line in passwd_string.lines() {
+            let mut parts = line.split(';');
+            if parts.next() != Some(name) {
+                continue;
+            }
+
+            let uid = parts.next().and_then(|uid| uid.parse().ok());
+            let gid = parts.next().and_then(|gid| gid.parse().ok());
+            let home = parts.next();
+            let shell = parts.next();
+            if let (Some(uid), Some(gid), Some(home), Some(shell)) = (uid, gid, home, shell) {
+                return Some(Passwd {
+                    uid: uid,
+                    gid: gid,
+                    home: home.to_string(),
+                    shell: shell.to_string(),
+                });
+            }
+        }
+
+        None
+    }
+}
+
+/// The password of a user in `/etc/shadow`, which has a line `name;salt;hash` for every user with
+/// a password
+///
+/// The hash is the SHA-256 of the salt followed by the password, in hexadecimal. A hash of `!`
+/// locks the user, and a user without a line logs in without being asked for a password.
+enum Shadow {
+    None,
+    Locked,
+    Hash(String, String),
+}
+
+impl Shadow {
+    /// Find the password of a user in `/etc/shadow`
+    fn find(name: &str) -> Shadow {
+        let mut shadow_string = String::new();
+        if File::open("/etc/shadow").and_then(|mut shadow| shadow.read_to_string(&mut shadow_string)).is_err() {
+            return Shadow::None;
+        }
+
+        for line in shadow_string.lines() {
+            let mut parts = line.split(';');
+            if parts.next() != Some(name) {
+                continue;
+            }
+
+            return match (parts.next(), parts.next()) {
+                (Some(salt), Some(hash)) if hash != "!" => Shadow::Hash(salt.to_string(), hash.to_lowercase()),
+                _ => Shadow::Locked
+            };
+        }
+
+        Shadow::None
+    }
+
+    /// Check a password against the hash
+    fn verify(salt: &str, hash: &str, password: &str) -> bool {
+        let mut salted = salt.as_bytes().to_vec();
+        salted.extend_from_slice(password.as_bytes());
+
+        let mut hex = String::new();
+        for b in sha256(&salted).iter() {
+            hex.push_str(&format!("{:02x}", b));
+        }
+
+        // Every byte is compared, so the time taken does not tell how much of the hash matched
+        hex.len() == hash.len() && hex.bytes().zip(hash.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+/// Read a line without printing it, using the raw mode of the console
+fn read_password() -> String {
+    print!("\x1B[r");
+    stdout().flush().unwrap();
+
+    let mut password = Vec::new();
+    let mut buf = [0; 1];
+    while let Ok(1) = stdin().read(&mut buf) {
+        match buf[0] {
+            b'\n' | b'\r' => break,
+            0x7F => {
+                password.pop();
+            },
+            b => password.push(b),
+        }
+    }
+
+    print!("\x1B[R");
+    println!("");
+    String::from_utf8_lossy(&password).into_owned()
+}
+
 fn main() {
     loop {
         if let Ok(mut issue) = File::open("/etc/issue") {
@@ -20,6 +139,31 @@ fn main() {
         let mut buffer = String::new();
         stdin().read_line(&mut buffer).unwrap();
 
+        let user = match Passwd::find(buffer.trim()) {
+            Some(user) => user,
+            None => {
+                println!("login: unknown user");
+                continue;
+            }
+        };
+
+        match Shadow::find(buffer.trim()) {
+            Shadow::None => (),
+            Shadow::Locked => {
+                println!("login: user is locked");
+                continue;
+            },
+            Shadow::Hash(salt, hash) => {
+                print!("password: ");
+                stdout().flush().unwrap();
+
+                if ! Shadow::verify(&salt, &hash, &read_password()) {
+                    println!("login: incorrect password");
+                    continue;
+                }
+            }
+        }
+
         if let Ok(mut motd) = File::open("/etc/motd") {
             let mut motd_string = String::new();
             if let Ok(_) = motd.read_to_string(&mut motd_string) {
@@ -27,15 +171,27 @@ fn main() {
             }
         }
 
-        match env::home_dir() {
-            Some(home) => match env::set_current_dir(home) {
-                Ok(()) => (),
-                Err(err) => println!("login: could not set home directory: {}", err)
+        if let Err(err) = env::set_current_dir(&user.home) {
+            println!("login: could not set home directory: {}", err);
+        }
+
+        // The shell runs as the user, while login keeps running as root for the next login. It
+        // leads a new session, whose process group is put in the foreground of the console.
+        let spawn = Command::new(&user.shell).uid(user.uid).gid(user.gid).before_exec(|| {
+            let sid = try!(sys_setsid().map_err(io::Error::from_sys));
+            try!(sys_write(1, format!("\x1B[{}p", sid).as_bytes()).map_err(io::Error::from_sys));
+            Ok(())
+        }).spawn();
+
+        match spawn {
+            Ok(mut child) => {
+                child.wait().unwrap();
             },
-            None => println!("login: could not get home directory")
+            Err(err) => println!("login: could not run {}: {}", user.shell, err)
         }
 
-        let mut child = Command::new("/bin/sh").spawn().unwrap();
-        child.wait().unwrap();
+        // Leave the console without a foreground process group until the next login
+        print!("\x1B[0p");
+        stdout().flush().unwrap();
     }
 }
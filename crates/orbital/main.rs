@@ -16,7 +16,7 @@ use std::time::Instant;
 
 use system::error::{Error, Result, EBADF};
 use system::scheme::{Packet, Scheme};
-use system::syscall::SYS_READ;
+use system::syscall::{EVENT_READ, EVENT_WRITE, SYS_READ};
 
 pub use self::color::Color;
 pub use self::event::{Event, EventOption};
@@ -356,6 +356,19 @@ impl Scheme for OrbitalScheme {
         }
     }
 
+    /// A window is readable once it has events, the scheme notifies `event:` when they arrive
+    fn fevent(&mut self, id: usize, flags: usize) -> Result<usize> {
+        if let Some(window) = self.windows.get(&id) {
+            let mut events = EVENT_WRITE;
+            if window.readable() {
+                events |= EVENT_READ;
+            }
+            Ok(events & flags)
+        } else {
+            Err(Error::new(EBADF))
+        }
+    }
+
     fn fpath(&self, id: usize, buf: &mut [u8]) -> Result<usize> {
         if let Some(window) = self.windows.get(&id) {
             window.path(buf)
@@ -400,6 +413,11 @@ fn event_loop(scheme_mutex: Arc<Mutex<OrbitalScheme>>, display: Arc<Socket>, soc
                 scheme.event(event);
             }
 
+            // Windows may have become readable
+            if count > 0 {
+                responses.push(Packet::fevent_notify());
+            }
+
             let mut packets = Vec::new();
             mem::swap(&mut scheme.todo, &mut packets);
             for mut packet in packets.iter_mut() {
@@ -0,0 +1,257 @@
+#![deny(warnings)]
+
+extern crate system;
+
+use std::env;
+use std::io::{stderr, Write};
+use std::process::{self, Command};
+use std::thread;
+
+use system::error::Error;
+use system::syscall::*;
+
+/// The longest path argument that is printed
+const PATH_MAX: usize = 256;
+
+/// The name of a syscall
+fn name(number: usize) -> &'static str {
+    match number {
+        SYS_BRK => "brk",
+        SYS_CHDIR => "chdir",
+        SYS_CHMOD => "chmod",
+        SYS_CHOWN => "chown",
+        SYS_CLONE => "clone",
+        SYS_CLOSE => "close",
+        SYS_CLOCK_GETTIME => "clock_gettime",
+        SYS_DUP => "dup",
+        SYS_EXECVE => "execve",
+        SYS_EXIT => "exit",
+        SYS_FCHMOD => "fchmod",
+        SYS_FCHOWN => "fchown",
+        SYS_FCNTL => "fcntl",
+        SYS_FEVENT => "fevent",
+        SYS_FPATH => "fpath",
+        SYS_FSTAT => "fstat",
+        SYS_FSYNC => "fsync",
+        SYS_FTRUNCATE => "ftruncate",
+        SYS_FUTEX => "futex",
+        SYS_FUTIMENS => "futimens",
+        SYS_GETEGID => "getegid",
+        SYS_GETEUID => "geteuid",
+        SYS_GETGID => "getgid",
+        SYS_GETPGID => "getpgid",
+        SYS_GETPID => "getpid",
+        SYS_GETPRIORITY => "getpriority",
+        SYS_GETRLIMIT => "getrlimit",
+        SYS_GETUID => "getuid",
+        SYS_IOPL => "iopl",
+        SYS_KILL => "kill",
+        SYS_LINK => "link",
+        SYS_LSEEK => "lseek",
+        SYS_MKDIR => "mkdir",
+        SYS_MMAP => "mmap",
+        SYS_MPROTECT => "mprotect",
+        SYS_MUNMAP => "munmap",
+        SYS_NANOSLEEP => "nanosleep",
+        SYS_OPEN => "open",
+        SYS_PIPE2 => "pipe2",
+        SYS_READ => "read",
+        SYS_READLINK => "readlink",
+        SYS_RENAME => "rename",
+        SYS_RMDIR => "rmdir",
+        SYS_SETGID => "setgid",
+        SYS_SETPGID => "setpgid",
+        SYS_SETPRIORITY => "setpriority",
+        SYS_SETRLIMIT => "setrlimit",
+        SYS_SETSID => "setsid",
+        SYS_SETUID => "setuid",
+        SYS_SIGACTION => "sigaction",
+        SYS_SIGPROCMASK => "sigprocmask",
+        SYS_SIGRETURN => "sigreturn",
+        SYS_STAT => "stat",
+        SYS_SYMLINK => "symlink",
+        SYS_SUPERVISE => "supervise",
+        SYS_UNLINK => "unlink",
+        SYS_UTIMENS => "utimens",
+        SYS_WAITPID => "waitpid",
+        SYS_WRITE => "write",
+        SYS_YIELD => "yield",
+        _ => "unknown",
+    }
+}
+
+/// A supervised process
+struct Tracee {
+    pid: usize,
+    fd: usize,
+}
+
+impl Tracee {
+    fn attach(pid: usize) -> Result<Tracee, Error> {
+        Ok(Tracee {
+            pid: pid,
+            fd: try!(sys_supervise(pid)),
+        })
+    }
+
+    fn command(&self, op: usize, a: usize, b: usize, c: usize) -> Result<usize, Error> {
+        let command = SupervisorCommand {
+            op: op,
+            a: a,
+            b: b,
+            c: c,
+        };
+        sys_write(self.fd, &command)
+    }
+
+    /// Read a C string from the memory of the process
+    ///
+    /// The string is read in small pieces, as the memory after it may not be mapped.
+    fn read_string(&self, address: usize) -> String {
+        let mut bytes = Vec::new();
+        let mut buf = [0; 16];
+        while bytes.len() < PATH_MAX {
+            if self.command(SUPERVISE_READ, address + bytes.len(), buf.as_mut_ptr() as usize, buf.len()).is_err() {
+                break;
+            }
+
+            match buf.iter().position(|&b| b == 0) {
+                Some(end) => {
+                    bytes.extend_from_slice(&buf[.. end]);
+                    return format!("{:?}", String::from_utf8_lossy(&bytes));
+                },
+                None => bytes.extend_from_slice(&buf)
+            }
+        }
+
+        format!("{:?}...", String::from_utf8_lossy(&bytes))
+    }
+
+    /// Format a syscall with its arguments, decoding the paths
+    fn format_syscall(&self, event: &SupervisorEvent) -> String {
+        match event.a {
+            SYS_CHDIR | SYS_RMDIR | SYS_UNLINK => {
+                format!("{}({})", name(event.a), self.read_string(event.b))
+            },
+            SYS_CHMOD | SYS_EXECVE | SYS_MKDIR | SYS_OPEN | SYS_STAT | SYS_UTIMENS => {
+                format!("{}({}, {:#X})", name(event.a), self.read_string(event.b), event.c)
+            },
+            SYS_LINK | SYS_RENAME | SYS_SYMLINK => {
+                format!("{}({}, {})", name(event.a), self.read_string(event.b), self.read_string(event.c))
+            },
+            SYS_CHOWN => {
+                format!("{}({}, {}, {})", name(event.a), self.read_string(event.b), event.c, event.d)
+            },
+            SYS_READLINK => {
+                format!("{}({}, {:#X}, {})", name(event.a), self.read_string(event.b), event.c, event.d)
+            },
+            SYS_CLOSE | SYS_DUP | SYS_EXIT | SYS_FSYNC | SYS_SUPERVISE => {
+                format!("{}({})", name(event.a), event.b)
+            },
+            SYS_READ | SYS_WRITE => {
+                format!("{}({}, {:#X}, {})", name(event.a), event.b, event.c, event.d)
+            },
+            _ => {
+                format!("{}({:#X}, {:#X}, {:#X})", name(event.a), event.b, event.c, event.d)
+            }
+        }
+    }
+
+    /// Print the syscalls of the process until it exits
+    ///
+    /// Children of the process are traced by new threads.
+    fn trace(self) {
+        let mut pending: Option<String> = None;
+        loop {
+            let mut event = SupervisorEvent::default();
+            match sys_read(self.fd, &mut event) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(err) => {
+                    let _ = writeln!(stderr(), "strace: {}: failed to read event: {}", self.pid, err);
+                    break;
+                }
+            }
+
+            match event.kind {
+                SUPERVISE_SYSCALL => {
+                    // A syscall that did not return, like a successful execve
+                    if let Some(call) = pending.take() {
+                        println!("[{}] {} = ?", self.pid, call);
+                    }
+                    pending = Some(self.format_syscall(&event));
+                    let _ = self.command(SUPERVISE_CONTINUE, 0, 0, 0);
+                },
+                SUPERVISE_RETURN => {
+                    let call = pending.take().unwrap_or_else(|| format!("{}()", name(event.a)));
+                    match Error::demux(event.b) {
+                        Ok(value) => println!("[{}] {} = {}", self.pid, call, value),
+                        Err(err) => println!("[{}] {} = -1 {} ({})", self.pid, call, err.errno, err),
+                    }
+                    let _ = self.command(SUPERVISE_CONTINUE, 0, 0, 0);
+                },
+                SUPERVISE_CHILD => {
+                    println!("[{}] +++ created child {} +++", self.pid, event.a);
+                    match Tracee::attach(event.a) {
+                        Ok(child) => {
+                            thread::spawn(move || child.trace());
+                        },
+                        Err(err) => {
+                            let _ = writeln!(stderr(), "strace: {}: failed to supervise: {}", event.a, err);
+                        }
+                    }
+                },
+                SUPERVISE_EXIT => {
+                    if let Some(call) = pending.take() {
+                        println!("[{}] {} = ?", self.pid, call);
+                    }
+                    if wifexited(event.a) {
+                        println!("[{}] +++ exited with {} +++", self.pid, wexitstatus(event.a));
+                    } else if wcoredump(event.a) {
+                        println!("[{}] +++ killed by signal {} (core dumped) +++", self.pid, wtermsig(event.a));
+                    } else {
+                        println!("[{}] +++ killed by signal {} +++", self.pid, wtermsig(event.a));
+                    }
+                },
+                _ => ()
+            }
+        }
+
+        let _ = sys_close(self.fd);
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let program = match args.next() {
+        Some(program) => program,
+        None => {
+            let _ = writeln!(stderr(), "usage: strace PROGRAM [ARGS]...");
+            process::exit(1);
+        }
+    };
+
+    let mut command = Command::new(&program);
+    for arg in args {
+        command.arg(&arg);
+    }
+
+    let mut child = match command.spawn_supervise() {
+        Ok(child) => child,
+        Err(err) => {
+            let _ = writeln!(stderr(), "strace: failed to run {}: {}", program, err);
+            process::exit(1);
+        }
+    };
+
+    match Tracee::attach(child.id() as usize) {
+        Ok(tracee) => tracee.trace(),
+        Err(err) => {
+            let _ = writeln!(stderr(), "strace: failed to supervise {}: {}", program, err);
+            let _ = child.kill();
+        }
+    }
+
+    let status = child.wait().ok().and_then(|status| status.code()).unwrap_or(1);
+    process::exit(status);
+}
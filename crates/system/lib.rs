@@ -2,6 +2,7 @@
 #![crate_type="lib"]
 #![feature(asm)]
 #![feature(lang_items)]
+#![feature(naked_functions)]
 #![no_std]
 
 use core::{ptr, slice, str};
@@ -12,7 +12,25 @@ pub struct Packet {
     pub a: usize,
     pub b: usize,
     pub c: usize,
-    pub d: usize
+    pub d: usize,
+    /// The effective user ID of the process making the call
+    pub uid: usize,
+    /// The effective group ID of the process making the call
+    pub gid: usize
+}
+
+impl Packet {
+    /// A packet that notifies the readers of `event:` that the events of resources of the scheme
+    /// may have changed, which is written to the scheme like the replies
+    ///
+    /// The kernel only asks a scheme for events again after such a notification.
+    pub fn fevent_notify() -> Packet {
+        Packet {
+            id: 0,
+            a: SYS_FEVENT,
+            ..Packet::default()
+        }
+    }
 }
 
 impl Deref for Packet {
@@ -32,6 +50,10 @@ impl DerefMut for Packet {
     }
 }
 
+/// A scheme in userspace
+///
+/// The credentials of the caller are in `Packet::uid` and `Packet::gid`, a scheme that checks
+/// permissions can read them before calling `handle`.
 pub trait Scheme {
     fn handle(&mut self, packet: &mut Packet) {
         packet.a = Error::mux(match packet.a {
@@ -40,6 +62,13 @@ pub trait Scheme {
             SYS_RMDIR => self.rmdir(c_string_to_str(packet.b as *const u8)),
             SYS_STAT => self.stat(c_string_to_str(packet.b as *const u8), unsafe { &mut *(packet.c as *mut Stat) }),
             SYS_UNLINK => self.unlink(c_string_to_str(packet.b as *const u8)),
+            SYS_CHMOD => self.chmod(c_string_to_str(packet.b as *const u8), packet.c),
+            SYS_CHOWN => self.chown(c_string_to_str(packet.b as *const u8), packet.c, packet.d),
+            SYS_UTIMENS => self.utimens(c_string_to_str(packet.b as *const u8), unsafe { &*(packet.c as *const [TimeSpec; 2]) }),
+            SYS_RENAME => self.rename(c_string_to_str(packet.b as *const u8), c_string_to_str(packet.c as *const u8)),
+            SYS_LINK => self.link(c_string_to_str(packet.b as *const u8), c_string_to_str(packet.c as *const u8)),
+            SYS_SYMLINK => self.symlink(c_string_to_str(packet.b as *const u8), c_string_to_str(packet.c as *const u8)),
+            SYS_READLINK => self.readlink(c_string_to_str(packet.b as *const u8), unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }),
 
             SYS_DUP => self.dup(packet.b),
             SYS_READ => self.read(packet.b, unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }),
@@ -47,8 +76,14 @@ pub trait Scheme {
             SYS_LSEEK => self.seek(packet.b, packet.c, packet.d),
             SYS_FPATH => self.fpath(packet.b, unsafe { slice::from_raw_parts_mut(packet.c as *mut u8, packet.d) }),
             SYS_FSTAT => self.fstat(packet.b, unsafe { &mut *(packet.c as *mut Stat) }),
+            SYS_FEVENT => self.fevent(packet.b, packet.c),
+            SYS_FCHMOD => self.fchmod(packet.b, packet.c),
+            SYS_FCHOWN => self.fchown(packet.b, packet.c, packet.d),
+            SYS_FCNTL => self.fcntl(packet.b, packet.c, packet.d),
+            SYS_FUTIMENS => self.futimens(packet.b, unsafe { &*(packet.c as *const [TimeSpec; 2]) }),
             SYS_FSYNC => self.fsync(packet.b),
             SYS_FTRUNCATE => self.ftruncate(packet.b, packet.c),
+            SYS_MMAP => self.mmap(packet.b, packet.c, packet.d),
             SYS_CLOSE => self.close(packet.b),
 
             _ => Err(Error::new(ENOSYS))
@@ -82,6 +117,53 @@ pub trait Scheme {
         Err(Error::new(ENOENT))
     }
 
+    /// Change the permissions of the resource at `path` to the lower bits of `mode`
+    #[allow(unused_variables)]
+    fn chmod(&mut self, path: &str, mode: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Change the owner and group of the resource at `path`
+    ///
+    /// A scheme should only allow this to callers with a `Packet::uid` of zero.
+    #[allow(unused_variables)]
+    fn chown(&mut self, path: &str, uid: usize, gid: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Set the access and modification times of the resource at `path`, in that order
+    ///
+    /// The kernel has replaced `UTIME_NOW` by the current time, a time with `UTIME_OMIT` is left
+    /// as it is.
+    #[allow(unused_variables)]
+    fn utimens(&mut self, path: &str, times: &[TimeSpec; 2]) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Rename the resource at `old` to `new`, both paths are in this scheme
+    #[allow(unused_variables)]
+    fn rename(&mut self, old: &str, new: &str) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Create a hard link `new` to the resource at `old`, both paths are in this scheme
+    #[allow(unused_variables)]
+    fn link(&mut self, old: &str, new: &str) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Create a symbolic link at `path`, containing `target` as it was given
+    #[allow(unused_variables)]
+    fn symlink(&mut self, target: &str, path: &str) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Read the target of the symbolic link at `path`, returning its length
+    #[allow(unused_variables)]
+    fn readlink(&mut self, path: &str, buf: &mut [u8]) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
     /* Resource operations */
     #[allow(unused_variables)]
     fn dup(&mut self, old_id: usize) -> Result<usize> {
@@ -113,6 +195,40 @@ pub trait Scheme {
         Err(Error::new(EBADF))
     }
 
+    /// Get the events of a resource that are ready, out of the `EVENT_*` flags in `flags`
+    ///
+    /// This must not block. The kernel calls it again once the scheme writes
+    /// `Packet::fevent_notify`, which it has to do whenever events may have become ready.
+    #[allow(unused_variables)]
+    fn fevent(&mut self, id: usize, flags: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    #[allow(unused_variables)]
+    fn fchmod(&mut self, id: usize, mode: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    #[allow(unused_variables)]
+    fn fchown(&mut self, id: usize, uid: usize, gid: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Get the status flags of a resource with `F_GETFL`, or set them with `F_SETFL`
+    ///
+    /// The flags of `open` are passed too. A resource with `O_NONBLOCK` should return `EAGAIN`
+    /// instead of blocking.
+    #[allow(unused_variables)]
+    fn fcntl(&mut self, id: usize, cmd: usize, arg: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Set the access and modification times of a resource, like `utimens`
+    #[allow(unused_variables)]
+    fn futimens(&mut self, id: usize, times: &[TimeSpec; 2]) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
     #[allow(unused_variables)]
     fn fsync(&mut self, id: usize) -> Result<usize> {
         Err(Error::new(EBADF))
@@ -123,6 +239,15 @@ pub trait Scheme {
         Err(Error::new(EBADF))
     }
 
+    /// Map a resource into memory, returning the page aligned address of the data at `offset`
+    ///
+    /// The kernel maps the pages backing the returned address into the caller, so they must stay
+    /// allocated for as long as the resource is open.
+    #[allow(unused_variables)]
+    fn mmap(&mut self, id: usize, offset: usize, size: usize) -> Result<usize> {
+        Err(Error::new(EBADF))
+    }
+
     #[allow(unused_variables)]
     fn close(&mut self, id: usize) -> Result<usize> {
         Err(Error::new(EBADF))
@@ -1,7 +1,159 @@
-use syscall::arch::syscall1;
+use core::ops::{Deref, DerefMut};
+use core::{mem, slice};
+
+use syscall::arch::{syscall1, syscall2};
 use error::Result;
 
+pub const SYS_FEVENT: usize = 927;
+    /// The file can be read without blocking
+    pub const EVENT_READ: usize = 1;
+    /// The file can be written without blocking
+    pub const EVENT_WRITE: usize = 2;
+    /// The other end of the file was closed, reading it returns 0 bytes or writing it fails
+    pub const EVENT_HUP: usize = 4;
 pub const SYS_SUPERVISE: usize = 1638; // loominatzi confirmed
+    /// The process made a syscall and is stopped before it runs
+    ///
+    /// `a` is the number of the syscall, `b`, `c` and `d` are its arguments.
+    pub const SUPERVISE_SYSCALL: usize = 1;
+    /// The syscall of the process returned and the process is stopped before it continues
+    ///
+    /// `a` is the number of the syscall and `b` its result, in the form of `Error::mux`.
+    pub const SUPERVISE_RETURN: usize = 2;
+    /// The process created a child, which is supervised and stopped until it gets a supervisor
+    ///
+    /// `a` is the PID of the child.
+    pub const SUPERVISE_CHILD: usize = 3;
+    /// The process exited, `a` is its exit status as returned by `sys_waitpid`
+    pub const SUPERVISE_EXIT: usize = 4;
+
+    /// Let the syscall of a stopped process proceed, or continue it after a syscall returned
+    pub const SUPERVISE_CONTINUE: usize = 1;
+    /// Answer the stop of a process with the result `a`, in the form of `Error::mux`
+    ///
+    /// At a `SUPERVISE_SYSCALL` stop the syscall does not run, at a `SUPERVISE_RETURN` stop its
+    /// result is replaced.
+    pub const SUPERVISE_RESULT: usize = 2;
+    /// Copy the registers of a stopped process to the `Registers` at `a`
+    pub const SUPERVISE_GET_REGS: usize = 3;
+    /// Set the registers of a stopped process to the `Registers` at `a`
+    ///
+    /// The segment registers and the privileged flags can not be changed.
+    pub const SUPERVISE_SET_REGS: usize = 4;
+    /// Copy `c` bytes at address `a` of the process to the buffer at `b`
+    pub const SUPERVISE_READ: usize = 5;
+    /// Copy `c` bytes from the buffer at `b` to address `a` of the process
+    pub const SUPERVISE_WRITE: usize = 6;
+
+/// An event of a supervised process, read from the handle returned by `sys_supervise`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct SupervisorEvent {
+    /// The kind of event, like `SUPERVISE_SYSCALL`
+    pub kind: usize,
+    /// The PID of the process
+    pub pid: usize,
+    pub a: usize,
+    pub b: usize,
+    pub c: usize,
+    pub d: usize,
+}
+
+impl Deref for SupervisorEvent {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self as *const SupervisorEvent as *const u8, mem::size_of::<SupervisorEvent>()) as &[u8]
+        }
+    }
+}
+
+impl DerefMut for SupervisorEvent {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(self as *mut SupervisorEvent as *mut u8, mem::size_of::<SupervisorEvent>()) as &mut [u8]
+        }
+    }
+}
+
+/// A command for a supervised process, written to the handle returned by `sys_supervise`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct SupervisorCommand {
+    /// The operation, like `SUPERVISE_CONTINUE`
+    pub op: usize,
+    pub a: usize,
+    pub b: usize,
+    pub c: usize,
+}
+
+impl Deref for SupervisorCommand {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self as *const SupervisorCommand as *const u8, mem::size_of::<SupervisorCommand>()) as &[u8]
+        }
+    }
+}
+
+impl DerefMut for SupervisorCommand {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(self as *mut SupervisorCommand as *mut u8, mem::size_of::<SupervisorCommand>()) as &mut [u8]
+        }
+    }
+}
+
+/// An event of a file descriptor, written to and read from an `event:` resource
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct FileEvent {
+    /// The file descriptor
+    pub id: usize,
+    /// The events, like `EVENT_READ`
+    pub flags: usize,
+    /// A value of the caller, returned with the events of the file descriptor
+    pub data: usize,
+}
+
+impl Deref for FileEvent {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self as *const FileEvent as *const u8, mem::size_of::<FileEvent>()) as &[u8]
+        }
+    }
+}
+
+impl DerefMut for FileEvent {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(self as *mut FileEvent as *mut u8, mem::size_of::<FileEvent>()) as &mut [u8]
+        }
+    }
+}
+
+/// <!-- @MANSTART{fevent} -->
+/// Get the events of a file descriptor that are ready, out of the events in `flags`.
+/// `EVENT_HUP` is returned even if it is not in `flags`.
+///
+/// This does not block. To wait for the events of several file descriptors at once, open
+/// `event:` and write a `FileEvent` for each of them, with the events to wait for in `flags`. A
+/// `FileEvent` with empty `flags` removes its file descriptor again, and file descriptors that
+/// are closed are removed too.
+///
+/// Reading from the `event:` resource then blocks until at least one of the file descriptors is
+/// ready, and reads a `FileEvent` for each ready one, with the `data` it was written with. If no
+/// file descriptor is registered, 0 bytes are read. A signal interrupts the read with EINTR.
+/// If more file descriptors are ready than fit into the buffer, the next read starts after the
+/// last one read. A file descriptor that fails to report its events is read with `EVENT_HUP`.
+///
+/// Pipes, `debug:`, `display:`, `tcp:`, `udp:` and user schemes that implement `fevent` support
+/// events. Other file descriptors result in `ENOSYS`, both here and when written to `event:`.
+/// <!-- @MANEND -->
+pub fn sys_fevent(fd: usize, flags: usize) -> Result<usize> {
+    unsafe { syscall2(SYS_FEVENT, fd, flags) }
+}
 
 /// <!-- @MANSTART{supervise} -->
 /// Supervise a given child process' system calls.
@@ -11,26 +163,35 @@ pub const SYS_SUPERVISE: usize = 1638; // loominatzi confirmed
 /// logging, and debugging.
 ///
 /// SUPERVISE takes a PID specifing the process to be supervised. This PID must be a child process
-/// of the invoker. If not, EACCES will be returned.
+/// of the invoker, or a child of a process the invoker supervises. If not, EACCES will be returned.
 ///
 /// A process can only have one supervisor at a time. If SUPERVISE is called on a process, which
 /// already have a supervisor EPERM will be returned.
 ///
 /// The process identified by the given PID will be restricted in such a way, that every syscall
-/// made will mark the process as blocked and store the syscall until it is handled by the parrent.
+/// made will stop the process until it is handled by the supervisor. Children created by a
+/// supervised process are supervised as well, and stay stopped until they get a supervisor.
+///
+/// The return value (if successful) is a file descriptor, from which events can be read and to
+/// which commands can be written:
 ///
-/// The return value (if successful) is a file descriptor, from which syscalls can be read and written:
-/// the syscalls are read in `Packet` sized packages, containing the respective blocking syscall. If
-/// no syscall is blocking (or the last blocking syscall have been handled), 0 bytes will be read to
-/// the buffer.
+/// - Reading blocks until an event happens, and reads one `SupervisorEvent`. A
+///   `SUPERVISE_SYSCALL` event stops the process before the syscall runs, a `SUPERVISE_RETURN`
+///   event stops it after the syscall returned. `SUPERVISE_CHILD` and `SUPERVISE_EXIT` events
+///   only notify the supervisor. Once the process exited and all events were read, 0 bytes are
+///   read.
 ///
-/// Writing pointer sized integers to this file handle will set the EAX register of the particular
-/// process, after which the process is unblocked and the syscall buffer is emptied. The behavior of
-/// writing packages of unexpected size is unspecified.
+/// - Writing a `SupervisorCommand` answers a stop, or accesses the registers and the memory of
+///   the process. A stop is answered with `SUPERVISE_CONTINUE`, which lets the syscall proceed,
+///   or with `SUPERVISE_RESULT`, which returns a result to the process instead. Registers can
+///   only be accessed while the process is stopped, EINVAL is returned otherwise. An address
+///   that is not mapped in the process results in EFAULT.
 ///
-/// Note that a process blocked by a syscall will have its potential sleep cleared (i.e., it will
+/// Note that a process stopped by a syscall will have its potential sleep cleared (i.e., it will
 /// not wake up after the sleep is finished).
 ///
+/// Closing the file descriptor releases the process, which continues without a supervisor.
+///
 /// Passing a non-existent PID results in ESRCH.
 ///
 /// A process being supervised is referred to as 'jailed' or 'supervised'.
@@ -1,8 +1,10 @@
-use syscall::arch::{syscall0, syscall1, syscall2, syscall3};
+use syscall::arch::{signal_restorer, syscall0, syscall1, syscall2, syscall3};
 use error::Result;
 
 pub const SYS_BRK: usize = 45;
 pub const SYS_CHDIR: usize = 12;
+pub const SYS_CHMOD: usize = 15;
+pub const SYS_CHOWN: usize = 182;
 pub const SYS_CLONE: usize = 120;
     pub const CLONE_VM: usize = 0x100;
     pub const CLONE_FS: usize = 0x200;
@@ -23,19 +25,96 @@ pub const SYS_CLOCK_GETTIME: usize = 265;
     pub const CLOCK_MONOTONIC: usize = 4;
 pub const SYS_DUP: usize = 41;
 pub const SYS_EXECVE: usize = 11;
+    pub const AT_NULL: usize = 0;
+    pub const AT_PHDR: usize = 3;
+    pub const AT_PHENT: usize = 4;
+    pub const AT_PHNUM: usize = 5;
+    pub const AT_PAGESZ: usize = 6;
+    pub const AT_BASE: usize = 7;
+    pub const AT_ENTRY: usize = 9;
+    pub const AT_RANDOM: usize = 25;
 pub const SYS_EXIT: usize = 1;
+pub const SYS_FCHMOD: usize = 94;
+pub const SYS_FCHOWN: usize = 95;
+pub const SYS_FCNTL: usize = 55;
+    pub const F_GETFL: usize = 3;
+    pub const F_SETFL: usize = 4;
 pub const SYS_FPATH: usize = 928;
 pub const SYS_FSTAT: usize = 28;
 pub const SYS_FSYNC: usize = 118;
 pub const SYS_FTRUNCATE: usize = 93;
+pub const SYS_FUTEX: usize = 240;
+    pub const FUTEX_WAIT: usize = 0;
+    pub const FUTEX_WAKE: usize = 1;
+pub const SYS_FUTIMENS: usize = 320;
+    /// A `tv_nsec` that sets the time to the current time
+    pub const UTIME_NOW: i32 = (1 << 30) - 1;
+    /// A `tv_nsec` that leaves the time as it is
+    pub const UTIME_OMIT: i32 = (1 << 30) - 2;
+pub const SYS_GETEGID: usize = 50;
+pub const SYS_GETEUID: usize = 49;
+pub const SYS_GETGID: usize = 47;
+pub const SYS_GETPGID: usize = 132;
 pub const SYS_GETPID: usize = 20;
+pub const SYS_GETPRIORITY: usize = 96;
+    pub const PRIO_PROCESS: usize = 0;
+    pub const PRIO_PGRP: usize = 1;
+    pub const PRIO_USER: usize = 2;
+pub const SYS_GETRLIMIT: usize = 76;
+    pub const RLIMIT_STACK: usize = 3;
+pub const SYS_GETUID: usize = 24;
 pub const SYS_IOPL: usize = 110;
+pub const SYS_KILL: usize = 37;
+    pub const SIGHUP: usize = 1;
+    pub const SIGINT: usize = 2;
+    pub const SIGQUIT: usize = 3;
+    pub const SIGILL: usize = 4;
+    pub const SIGTRAP: usize = 5;
+    pub const SIGABRT: usize = 6;
+    pub const SIGBUS: usize = 7;
+    pub const SIGFPE: usize = 8;
+    pub const SIGKILL: usize = 9;
+    pub const SIGUSR1: usize = 10;
+    pub const SIGSEGV: usize = 11;
+    pub const SIGUSR2: usize = 12;
+    pub const SIGPIPE: usize = 13;
+    pub const SIGALRM: usize = 14;
+    pub const SIGTERM: usize = 15;
+    pub const SIGSTKFLT: usize = 16;
+    pub const SIGCHLD: usize = 17;
+    pub const SIGCONT: usize = 18;
+    pub const SIGSTOP: usize = 19;
+    pub const SIGTSTP: usize = 20;
+    pub const SIGTTIN: usize = 21;
+    pub const SIGTTOU: usize = 22;
+    pub const SIGURG: usize = 23;
+    pub const SIGXCPU: usize = 24;
+    pub const SIGXFSZ: usize = 25;
+    pub const SIGVTALRM: usize = 26;
+    pub const SIGPROF: usize = 27;
+    pub const SIGWINCH: usize = 28;
+    pub const SIGIO: usize = 29;
+    pub const SIGPWR: usize = 30;
+    pub const SIGSYS: usize = 31;
+    /// The number of signals, signal numbers are in the range `1..NSIG`
+    pub const NSIG: usize = 32;
 pub const SYS_LINK: usize = 9;
 pub const SYS_LSEEK: usize = 19;
     pub const SEEK_SET: usize = 0;
     pub const SEEK_CUR: usize = 1;
     pub const SEEK_END: usize = 2;
 pub const SYS_MKDIR: usize = 39;
+pub const SYS_MMAP: usize = 90;
+    pub const PROT_NONE: usize = 0;
+    pub const PROT_READ: usize = 1;
+    pub const PROT_WRITE: usize = 2;
+    pub const PROT_EXEC: usize = 4;
+    pub const MAP_SHARED: usize = 0x01;
+    pub const MAP_PRIVATE: usize = 0x02;
+    pub const MAP_FIXED: usize = 0x10;
+    pub const MAP_ANONYMOUS: usize = 0x20;
+pub const SYS_MPROTECT: usize = 125;
+pub const SYS_MUNMAP: usize = 91;
 pub const SYS_NANOSLEEP: usize = 162;
 pub const SYS_OPEN: usize = 5;
     pub const O_RDONLY: usize = 0;
@@ -52,29 +131,102 @@ pub const SYS_OPEN: usize = 5;
     pub const O_EXCL: usize = 0x800;
 pub const SYS_PIPE2: usize = 331;
 pub const SYS_READ: usize = 3;
+pub const SYS_READLINK: usize = 85;
+pub const SYS_RENAME: usize = 38;
 pub const SYS_RMDIR: usize = 84;
+pub const SYS_SETGID: usize = 46;
+pub const SYS_SETPGID: usize = 57;
+pub const SYS_SETPRIORITY: usize = 97;
+pub const SYS_SETRLIMIT: usize = 75;
+pub const SYS_SETSID: usize = 66;
+pub const SYS_SETUID: usize = 23;
+pub const SYS_SIGACTION: usize = 67;
+    pub const SIG_DFL: usize = 0;
+    pub const SIG_IGN: usize = 1;
+pub const SYS_SIGPROCMASK: usize = 126;
+    pub const SIG_BLOCK: usize = 0;
+    pub const SIG_UNBLOCK: usize = 1;
+    pub const SIG_SETMASK: usize = 2;
+pub const SYS_SIGRETURN: usize = 119;
 pub const SYS_STAT: usize = 18;
     pub const MODE_DIR: u16 = 0x4000;
     pub const MODE_FILE: u16 = 0x8000;
+    pub const MODE_PERM: u16 = 0x0FFF;
+pub const SYS_SYMLINK: usize = 83;
 pub const SYS_UNLINK: usize = 10;
+pub const SYS_UTIMENS: usize = 271;
 pub const SYS_WAITPID: usize = 7;
+    /// Return immediately if no child has exited
+    pub const WNOHANG: usize = 1;
+    /// Also report children that were stopped by a signal
+    pub const WUNTRACED: usize = 2;
+    /// Also report children that were continued by `SIGCONT`
+    pub const WCONTINUED: usize = 8;
 pub const SYS_WRITE: usize = 4;
 pub const SYS_YIELD: usize = 158;
 
+/// Information about a file, filled by `sys_stat` and `sys_fstat`
+///
+/// The times are seconds since the Unix epoch, with the nanoseconds in the `_nsec` fields.
 #[derive(Copy, Clone, Debug, Default)]
 #[repr(packed)]
 pub struct Stat {
-    pub st_dev: u16,
-    pub st_ino: u16,
+    pub st_dev: u64,
+    pub st_ino: u64,
+    /// The type of the file, like `MODE_DIR`, and its permissions in `MODE_PERM`
     pub st_mode: u16,
-    pub st_nlink: u16,
-    pub st_uid: u16,
-    pub st_gid: u16,
-    pub st_rdev: u16,
-    pub st_size: u32,
-    pub st_atime: u32,
-    pub st_mtime: u32,
-    pub st_ctime: u32
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    /// The size in bytes
+    pub st_size: u64,
+    /// The preferred size of a block for I/O
+    pub st_blksize: u32,
+    /// The number of blocks of 512 bytes that are allocated
+    pub st_blocks: u64,
+    pub st_atime: u64,
+    pub st_atime_nsec: u32,
+    pub st_mtime: u64,
+    pub st_mtime_nsec: u32,
+    pub st_ctime: u64,
+    pub st_ctime_nsec: u32
+}
+
+/// A limit of a resource, used by `sys_getrlimit` and `sys_setrlimit`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct Rlimit {
+    /// The limit that is enforced
+    pub rlim_cur: usize,
+    /// The highest value `rlim_cur` can be set to
+    pub rlim_max: usize,
+}
+
+/// The arguments of `sys_mmap`, which are passed in memory as there are too many for registers
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct MmapArgs {
+    pub addr: usize,
+    pub len: usize,
+    pub prot: usize,
+    pub flags: usize,
+    pub fd: usize,
+    pub offset: usize,
+}
+
+/// A signal action, as used by `sys_sigaction`
+///
+/// `sa_mask` is a bitmask of signals, where bit `n` blocks signal `n` while the handler runs.
+/// `sa_restorer` is the address the handler returns to, which must call `SYS_SIGRETURN`. When it
+/// is zero, `sys_sigaction` fills in `signal_restorer`.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct SigAction {
+    pub sa_handler: usize,
+    pub sa_mask: usize,
+    pub sa_flags: usize,
+    pub sa_restorer: usize,
 }
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -96,6 +248,16 @@ pub unsafe fn sys_clone(flags: usize) -> Result<usize> {
     syscall1(SYS_CLONE, flags)
 }
 
+/// Change the permissions of the file at `path` to the lower bits of `mode`
+pub unsafe fn sys_chmod(path: *const u8, mode: usize) -> Result<usize> {
+    syscall2(SYS_CHMOD, path as usize, mode)
+}
+
+/// Change the owner and group of the file at `path`
+pub unsafe fn sys_chown(path: *const u8, uid: usize, gid: usize) -> Result<usize> {
+    syscall3(SYS_CHOWN, path as usize, uid, gid)
+}
+
 pub fn sys_close(fd: usize) -> Result<usize> {
     unsafe { syscall1(SYS_CLOSE, fd) }
 }
@@ -108,14 +270,38 @@ pub fn sys_dup(fd: usize) -> Result<usize> {
     unsafe { syscall1(SYS_DUP, fd) }
 }
 
-pub unsafe fn sys_execve(path: *const u8, args: *const *const u8) -> Result<usize> {
-    syscall2(SYS_EXECVE, path as usize, args as usize)
+/// Execute a program, with null terminated arrays of arguments and of `NAME=VALUE` environment
+/// variables
+///
+/// If `envs` is null, the new program starts with an empty environment. It
+/// finds the arguments, the environment variables and an auxiliary vector of `AT_*` entries on its
+/// stack.
+pub unsafe fn sys_execve(path: *const u8, args: *const *const u8, envs: *const *const u8) -> Result<usize> {
+    syscall3(SYS_EXECVE, path as usize, args as usize, envs as usize)
 }
 
 pub fn sys_exit(status: usize) -> Result<usize> {
     unsafe { syscall1(SYS_EXIT, status) }
 }
 
+/// Change the permissions of an open file to the lower bits of `mode`
+pub fn sys_fchmod(fd: usize, mode: usize) -> Result<usize> {
+    unsafe { syscall2(SYS_FCHMOD, fd, mode) }
+}
+
+/// Change the owner and group of an open file
+pub fn sys_fchown(fd: usize, uid: usize, gid: usize) -> Result<usize> {
+    unsafe { syscall3(SYS_FCHOWN, fd, uid, gid) }
+}
+
+/// Get the file status flags of `fd` with `F_GETFL`, or set them to `arg` with `F_SETFL`
+///
+/// Only `O_NONBLOCK` can be changed. Reads that would block on a nonblocking file descriptor
+/// return `EAGAIN` instead. File descriptors without status flags return `ENOSYS`.
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> Result<usize> {
+    unsafe { syscall3(SYS_FCNTL, fd, cmd, arg) }
+}
+
 pub fn sys_fpath(fd: usize, buf: &mut [u8]) -> Result<usize> {
     unsafe { syscall3(SYS_FPATH, fd, buf.as_mut_ptr() as usize, buf.len()) }
 }
@@ -132,14 +318,74 @@ pub fn sys_ftruncate(fd: usize, len: usize) -> Result<usize> {
     unsafe { syscall2(SYS_FTRUNCATE, fd, len) }
 }
 
+/// Wait on the futex at `addr` while it holds `val`, or wake up to `val` contexts waiting on it
+///
+/// Futexes are `usize` words. `FUTEX_WAIT` returns `EAGAIN` if the futex no longer holds `val`.
+pub unsafe fn sys_futex(addr: *mut usize, op: usize, val: usize) -> Result<usize> {
+    syscall3(SYS_FUTEX, addr as usize, op, val)
+}
+
+/// Set the access and modification times of an open file, in that order
+///
+/// A `tv_nsec` of `UTIME_NOW` sets a time to the current time, and `UTIME_OMIT` leaves it as it is.
+pub fn sys_futimens(fd: usize, times: &[TimeSpec; 2]) -> Result<usize> {
+    unsafe { syscall2(SYS_FUTIMENS, fd, times.as_ptr() as usize) }
+}
+
+/// Get the effective group ID of the current process
+pub fn sys_getegid() -> Result<usize> {
+    unsafe { syscall0(SYS_GETEGID) }
+}
+
+/// Get the effective user ID of the current process, which permissions are checked against
+pub fn sys_geteuid() -> Result<usize> {
+    unsafe { syscall0(SYS_GETEUID) }
+}
+
+/// Get the real group ID of the current process
+pub fn sys_getgid() -> Result<usize> {
+    unsafe { syscall0(SYS_GETGID) }
+}
+
+/// Get the process group of a process, or of the current process if `pid` is zero
+pub fn sys_getpgid(pid: usize) -> Result<usize> {
+    unsafe { syscall1(SYS_GETPGID, pid) }
+}
+
 pub fn sys_getpid() -> Result<usize> {
     unsafe { syscall0(SYS_GETPID) }
 }
 
+/// Get the nice value of a process, or of the current process if `who` is zero
+///
+/// The kernel returns `20 - nice`, so that the result is never negative.
+pub fn sys_getpriority(which: usize, who: usize) -> Result<isize> {
+    unsafe { syscall2(SYS_GETPRIORITY, which, who) }.map(|prio| 20 - prio as isize)
+}
+
+/// Get the limit of a resource of the current process, like the size of its stack
+pub fn sys_getrlimit(resource: usize, rlim: &mut Rlimit) -> Result<usize> {
+    unsafe { syscall2(SYS_GETRLIMIT, resource, rlim as *mut Rlimit as usize) }
+}
+
+/// Get the real user ID of the current process
+pub fn sys_getuid() -> Result<usize> {
+    unsafe { syscall0(SYS_GETUID) }
+}
+
 pub unsafe fn sys_iopl(level: usize) -> Result<usize> {
     syscall1(SYS_IOPL, level)
 }
 
+/// Send a signal to a process
+///
+/// A `pid` of zero sends the signal to the process group of the current process, and a negative
+/// `pid`, like `-(pgid as isize) as usize`, sends it to the process group `pgid`.
+pub fn sys_kill(pid: usize, sig: usize) -> Result<usize> {
+    unsafe { syscall2(SYS_KILL, pid, sig) }
+}
+
+/// Create a hard link `new` to the file at `old`, which must be in the same scheme
 pub unsafe fn sys_link(old: *const u8, new: *const u8) -> Result<usize> {
     syscall2(SYS_LINK, old as usize, new as usize)
 }
@@ -152,6 +398,26 @@ pub unsafe fn sys_mkdir(path: *const u8, mode: usize) -> Result<usize> {
     syscall2(SYS_MKDIR, path as usize, mode)
 }
 
+pub unsafe fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> Result<usize> {
+    let args = MmapArgs {
+        addr: addr,
+        len: len,
+        prot: prot,
+        flags: flags,
+        fd: fd,
+        offset: offset,
+    };
+    syscall1(SYS_MMAP, &args as *const MmapArgs as usize)
+}
+
+pub unsafe fn sys_mprotect(addr: usize, len: usize, prot: usize) -> Result<usize> {
+    syscall3(SYS_MPROTECT, addr, len, prot)
+}
+
+pub unsafe fn sys_munmap(addr: usize, len: usize) -> Result<usize> {
+    syscall2(SYS_MUNMAP, addr, len)
+}
+
 pub fn sys_nanosleep(req: &TimeSpec, rem: &mut TimeSpec) -> Result<usize> {
     unsafe { syscall2(SYS_NANOSLEEP, req as *const TimeSpec as usize, rem as *mut TimeSpec as usize) }
 }
@@ -168,22 +434,152 @@ pub fn sys_read(fd: usize, buf: &mut [u8]) -> Result<usize> {
     unsafe { syscall3(SYS_READ, fd, buf.as_mut_ptr() as usize, buf.len()) }
 }
 
+/// Read the target of the symbolic link at `path` into `buf`, returning its length
+///
+/// The target is not terminated by a zero, and is truncated if `buf` is too small.
+pub unsafe fn sys_readlink(path: *const u8, buf: &mut [u8]) -> Result<usize> {
+    syscall3(SYS_READLINK, path as usize, buf.as_mut_ptr() as usize, buf.len())
+}
+
+/// Rename the file at `old` to `new`, which must be in the same scheme
+pub unsafe fn sys_rename(old: *const u8, new: *const u8) -> Result<usize> {
+    syscall2(SYS_RENAME, old as usize, new as usize)
+}
+
 pub unsafe fn sys_rmdir(path: *const u8) -> Result<usize> {
     syscall1(SYS_RMDIR, path as usize)
 }
 
+/// Set the group IDs of the current process
+///
+/// With an effective user ID of zero, both the real and the effective group ID are set. Otherwise
+/// only the effective group ID can be set, back to the real group ID.
+pub fn sys_setgid(gid: usize) -> Result<usize> {
+    unsafe { syscall1(SYS_SETGID, gid) }
+}
+
+/// Move a process into a process group
+///
+/// A `pid` of zero is the current process, which must be the process or its parent. A `pgid` of
+/// zero creates a new process group with the PID of the process. The group must be in the session
+/// of the current process.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> Result<usize> {
+    unsafe { syscall2(SYS_SETPGID, pid, pgid) }
+}
+
+/// Set the nice value of a process, or of the current process if `who` is zero
+pub fn sys_setpriority(which: usize, who: usize, prio: isize) -> Result<usize> {
+    unsafe { syscall3(SYS_SETPRIORITY, which, who, prio as usize) }
+}
+
+/// Set the limit of a resource of the current process
+///
+/// `RLIMIT_STACK` limits how far the stack grows, the stack of a new thread inherits it.
+pub fn sys_setrlimit(resource: usize, rlim: &Rlimit) -> Result<usize> {
+    unsafe { syscall2(SYS_SETRLIMIT, resource, rlim as *const Rlimit as usize) }
+}
+
+/// Create a new session and process group, led by the current process
+///
+/// The process must not lead a process group already. The new session ID is returned.
+pub fn sys_setsid() -> Result<usize> {
+    unsafe { syscall0(SYS_SETSID) }
+}
+
+/// Set the user IDs of the current process
+///
+/// With an effective user ID of zero, both the real and the effective user ID are set, which drops
+/// the privileges for good. Otherwise only the effective user ID can be set, back to the real
+/// user ID.
+pub fn sys_setuid(uid: usize) -> Result<usize> {
+    unsafe { syscall1(SYS_SETUID, uid) }
+}
+
+pub fn sys_sigaction(sig: usize, act: Option<&SigAction>, oldact: Option<&mut SigAction>) -> Result<usize> {
+    let mut new_act;
+    let act_ptr = if let Some(act) = act {
+        new_act = *act;
+        if new_act.sa_restorer == 0 {
+            new_act.sa_restorer = signal_restorer as usize;
+        }
+        &new_act as *const SigAction as usize
+    } else {
+        0
+    };
+    let oldact_ptr = oldact.map_or(0, |oldact| oldact as *mut SigAction as usize);
+    unsafe { syscall3(SYS_SIGACTION, sig, act_ptr, oldact_ptr) }
+}
+
+pub fn sys_sigprocmask(how: usize, set: Option<&usize>, oldset: Option<&mut usize>) -> Result<usize> {
+    let set_ptr = set.map_or(0, |set| set as *const usize as usize);
+    let oldset_ptr = oldset.map_or(0, |oldset| oldset as *mut usize as usize);
+    unsafe { syscall3(SYS_SIGPROCMASK, how, set_ptr, oldset_ptr) }
+}
+
 pub unsafe fn sys_stat(path: *const u8, stat: &mut Stat) -> Result<usize> {
     syscall2(SYS_STAT, path as usize, stat as *mut Stat as usize)
 }
 
+/// Create a symbolic link at `path`, pointing to `target`
+///
+/// The target is stored as it is, and is resolved when the link is followed.
+pub unsafe fn sys_symlink(target: *const u8, path: *const u8) -> Result<usize> {
+    syscall2(SYS_SYMLINK, target as usize, path as usize)
+}
+
 pub unsafe fn sys_unlink(path: *const u8) -> Result<usize> {
     syscall1(SYS_UNLINK, path as usize)
 }
 
+/// Set the access and modification times of the file at `path`, like `sys_futimens`
+pub unsafe fn sys_utimens(path: *const u8, times: &[TimeSpec; 2]) -> Result<usize> {
+    syscall2(SYS_UTIMENS, path as usize, times.as_ptr() as usize)
+}
+
 pub fn sys_waitpid(pid: usize, status: &mut usize, options: usize) -> Result<usize> {
     unsafe { syscall3(SYS_WAITPID, pid, status as *mut usize as usize, options) }
 }
 
+/// True if the wait status is from a child that exited normally
+pub fn wifexited(status: usize) -> bool {
+    status & 0x7F == 0
+}
+
+/// The exit code of a child that exited normally
+pub fn wexitstatus(status: usize) -> usize {
+    (status >> 8) & 0xFF
+}
+
+/// True if the wait status is from a child that was terminated by a signal
+pub fn wifsignaled(status: usize) -> bool {
+    status & 0x7F != 0 && status & 0x7F != 0x7F
+}
+
+/// The signal that terminated a child
+pub fn wtermsig(status: usize) -> usize {
+    status & 0x7F
+}
+
+/// True if a child that was terminated by a signal wrote a core dump
+pub fn wcoredump(status: usize) -> bool {
+    status & 0x80 == 0x80
+}
+
+/// True if the wait status is from a child that was stopped by a signal, see `WUNTRACED`
+pub fn wifstopped(status: usize) -> bool {
+    status & 0xFF == 0x7F
+}
+
+/// The signal that stopped a child
+pub fn wstopsig(status: usize) -> usize {
+    (status >> 8) & 0xFF
+}
+
+/// True if the wait status is from a child that was continued by `SIGCONT`, see `WCONTINUED`
+pub fn wifcontinued(status: usize) -> bool {
+    status == 0xFFFF
+}
+
 pub fn sys_write(fd: usize, buf: &[u8]) -> Result<usize> {
     unsafe { syscall3(SYS_WRITE, fd, buf.as_ptr() as usize, buf.len()) }
 }
@@ -1,5 +1,19 @@
 use error::{Error, Result};
 
+/// Return from a signal handler
+///
+/// Signal handlers return to this function, which calls `SYS_SIGRETURN` to restore the registers
+/// saved when the signal was delivered. It must not be called directly.
+#[naked]
+pub unsafe extern "C" fn signal_restorer() {
+    asm!("mov eax, 119
+        int 0x80"
+        :
+        :
+        : "memory"
+        : "intel", "volatile");
+}
+
 pub unsafe fn syscall0(mut a: usize) -> Result<usize> {
     asm!("int 0x80"
         : "={eax}"(a)
@@ -59,3 +73,23 @@ pub unsafe fn syscall5(mut a: usize, b: usize, c: usize, d: usize, e: usize, f:
 
     Error::demux(a)
 }
+
+/// The registers of a supervised process, as used by `SUPERVISE_GET_REGS` and `SUPERVISE_SET_REGS`
+///
+/// The layout matches the registers the kernel saves on a syscall.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct Registers {
+    pub ax: usize,
+    pub bx: usize,
+    pub cx: usize,
+    pub dx: usize,
+    pub di: usize,
+    pub si: usize,
+    pub bp: usize,
+    pub ip: usize,
+    pub cs: usize,
+    pub flags: usize,
+    pub sp: usize,
+    pub ss: usize,
+}
@@ -1,5 +1,19 @@
 use error::{Error, Result};
 
+/// Return from a signal handler
+///
+/// Signal handlers return to this function, which calls `SYS_SIGRETURN` to restore the registers
+/// saved when the signal was delivered. It must not be called directly.
+#[naked]
+pub unsafe extern "C" fn signal_restorer() {
+    asm!("mov rax, 119
+        int 0x80"
+        :
+        :
+        : "memory"
+        : "intel", "volatile");
+}
+
 pub unsafe fn syscall0(mut a: usize) -> Result<usize> {
     asm!("int 0x80"
         : "={rax}"(a)
@@ -59,3 +73,31 @@ pub unsafe fn syscall5(mut a: usize, b: usize, c: usize, d: usize, e: usize, f:
 
     Error::demux(a)
 }
+
+/// The registers of a supervised process, as used by `SUPERVISE_GET_REGS` and `SUPERVISE_SET_REGS`
+///
+/// The layout matches the registers the kernel saves on a syscall.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(packed)]
+pub struct Registers {
+    pub ax: usize,
+    pub bx: usize,
+    pub cx: usize,
+    pub dx: usize,
+    pub di: usize,
+    pub si: usize,
+    pub r8: usize,
+    pub r9: usize,
+    pub r10: usize,
+    pub r11: usize,
+    pub r12: usize,
+    pub r13: usize,
+    pub r14: usize,
+    pub r15: usize,
+    pub bp: usize,
+    pub ip: usize,
+    pub cs: usize,
+    pub flags: usize,
+    pub sp: usize,
+    pub ss: usize,
+}
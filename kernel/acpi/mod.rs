@@ -1,6 +1,6 @@
 use alloc::boxed::Box;
 use fs::{KScheme, Resource, Url};
-use system::error::{Error, Result, ENOENT};
+use system::error::{Error, Result, EACCES, ENOENT};
 use system::syscall::O_CREAT;
 pub use self::dsdt::DSDT;
 pub use self::fadt::FADT;
@@ -74,6 +74,11 @@ impl Acpi {
             }
         }
     }
+
+    /// Get the MADT, which lists the processors and interrupt controllers
+    pub fn madt(&self) -> Option<&MADT> {
+        self.madt.as_ref()
+    }
 }
 
 impl KScheme for Acpi {
@@ -81,8 +86,12 @@ impl KScheme for Acpi {
         "acpi"
     }
 
-    fn open(&mut self, url: Url, flags: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, flags: usize, uid: usize, _: usize) -> Result<Box<Resource>> {
         if url.reference() == "off" && flags & O_CREAT == O_CREAT {
+            if uid != 0 {
+                return Err(Error::new(EACCES));
+            }
+
             match self.fadt {
                 Some(fadt) => {
                     debugln!("Powering Off");
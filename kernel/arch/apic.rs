@@ -0,0 +1,394 @@
+//! Local APIC and I/O APIC
+//!
+//! When the MADT lists an I/O APIC, legacy IRQs are routed through it instead of the 8259 PICs,
+//! using the interrupt source overrides of the MADT, and the local APIC timer of every processor
+//! replaces the PIT as the scheduler tick.
+//!
+//! Interrupt vectors:
+//! - `IRQ_BASE` to `IRQ_BASE + 15`: ISA IRQs, from the PICs or the I/O APIC
+//! - `IRQ_BASE + 16` to `IRQ_BASE + IRQ_COUNT - 1`: other I/O APIC inputs, which are masked until
+//!   the PCI device using them gets a driver, see `pci_device`
+//! - `SPURIOUS_INTERRUPT` and `TIMER_INTERRUPT`: local APIC interrupts
+
+use acpi::MADT;
+
+use collections::Vec;
+
+use drivers::io::{Io, Mmio, Pio};
+
+/// The default physical address of the local APIC
+pub const LOCAL_APIC_ADDRESS: usize = 0xFEE00000;
+
+/// The vector of IRQ 0
+pub const IRQ_BASE: usize = 0x20;
+/// The number of IRQs with a vector
+pub const IRQ_COUNT: usize = 64;
+
+/// The interrupt used by the local APIC for spurious interrupts
+pub const SPURIOUS_INTERRUPT: usize = 0xEF;
+/// The interrupt of the local APIC timer
+pub const TIMER_INTERRUPT: usize = 0xF0;
+
+const LAPIC_ID: usize = 0x20;
+const LAPIC_EOI: usize = 0xB0;
+const LAPIC_SPURIOUS: usize = 0xF0;
+const LAPIC_ICR_LOW: usize = 0x300;
+const LAPIC_ICR_HIGH: usize = 0x310;
+const LAPIC_LVT_TIMER: usize = 0x320;
+const LAPIC_TIMER_INITIAL: usize = 0x380;
+const LAPIC_TIMER_CURRENT: usize = 0x390;
+const LAPIC_TIMER_DIVIDE: usize = 0x3E0;
+
+/// Delivery mode of an INIT IPI
+pub const ICR_INIT: u32 = 0x500;
+/// Delivery mode of a startup IPI, the vector is the page of the trampoline
+pub const ICR_STARTUP: u32 = 0x600;
+/// Set while an IPI is being sent
+pub const ICR_PENDING: u32 = 1 << 12;
+/// Level assert, used by all IPIs except INIT deassert
+pub const ICR_ASSERT: u32 = 1 << 14;
+/// Send the IPI to all processors but the current one
+pub const ICR_ALL_BUT_SELF: u32 = 0b11 << 18;
+
+/// Periodic mode of the local APIC timer
+const TIMER_PERIODIC: u32 = 1 << 17;
+/// Divide the bus clock by 16 for the local APIC timer
+const TIMER_DIVIDE_16: u32 = 0b0011;
+
+const IOAPIC_REGSEL: usize = 0x00;
+const IOAPIC_WINDOW: usize = 0x10;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION: u32 = 0x10;
+
+/// Redirection entry flags
+const REDIRECTION_ACTIVE_LOW: u32 = 1 << 13;
+const REDIRECTION_LEVEL: u32 = 1 << 15;
+const REDIRECTION_MASKED: u32 = 1 << 16;
+
+/// Interrupt source override flags
+const OVERRIDE_POLARITY_MASK: u16 = 0b11;
+const OVERRIDE_POLARITY_LOW: u16 = 0b11;
+const OVERRIDE_TRIGGER_MASK: u16 = 0b11 << 2;
+const OVERRIDE_TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+/// The duration of a tick of the local APIC timer, in nanoseconds, the same as a tick of the PIT
+const TICK_NANOS: u64 = 4500572;
+
+/// The local APIC of the current processor
+///
+/// Every processor sees its own local APIC at the same address.
+#[derive(Copy, Clone, Debug)]
+pub struct LocalApic {
+    pub address: usize,
+}
+
+impl LocalApic {
+    pub fn new(address: usize) -> Self {
+        LocalApic {
+            address: if address > 0 {
+                address
+            } else {
+                LOCAL_APIC_ADDRESS
+            },
+        }
+    }
+
+    unsafe fn read(&self, reg: usize) -> u32 {
+        (&*((self.address + reg) as *const Mmio<u32>)).read()
+    }
+
+    unsafe fn write(&self, reg: usize, value: u32) {
+        (&mut *((self.address + reg) as *mut Mmio<u32>)).write(value);
+    }
+
+    /// Get the ID of the local APIC
+    pub fn id(&self) -> u32 {
+        unsafe { self.read(LAPIC_ID) >> 24 }
+    }
+
+    /// Enable the local APIC, so that it accepts interrupts
+    pub unsafe fn enable(&self, spurious_vector: u8) {
+        let value = self.read(LAPIC_SPURIOUS);
+        self.write(LAPIC_SPURIOUS, (value & !0xFF) | 1 << 8 | spurious_vector as u32);
+    }
+
+    /// Signal the end of an interrupt
+    pub unsafe fn eoi(&self) {
+        self.write(LAPIC_EOI, 0);
+    }
+
+    /// Send an interprocessor interrupt
+    pub unsafe fn ipi(&self, apic_id: u32, command: u32) {
+        self.write(LAPIC_ICR_HIGH, apic_id << 24);
+        self.write(LAPIC_ICR_LOW, command);
+        while self.read(LAPIC_ICR_LOW) & ICR_PENDING == ICR_PENDING {}
+    }
+
+    /// Count the ticks of the timer during 10 milliseconds, measured with channel 2 of the PIT
+    unsafe fn calibrate_timer(&self) -> u32 {
+        let mut gate = Pio::<u8>::new(0x61);
+        let mut command = Pio::<u8>::new(0x43);
+        let mut channel = Pio::<u8>::new(0x42);
+
+        // Enable the gate of channel 2, with the speaker off
+        let value = gate.read();
+        gate.write((value & !2) | 1);
+
+        // Mode 0, which sets the output when the count reaches 0
+        let count: u16 = 11932;
+        command.write(0b10110000);
+        channel.write(count as u8);
+        channel.write((count >> 8) as u8);
+
+        // Restart the count
+        let value = gate.read() & !1;
+        gate.write(value);
+        gate.write(value | 1);
+
+        self.write(LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_16);
+        self.write(LAPIC_TIMER_INITIAL, 0xFFFFFFFF);
+
+        while gate.read() & 0x20 == 0 {}
+
+        let ticks = 0xFFFFFFFF - self.read(LAPIC_TIMER_CURRENT);
+        self.write(LAPIC_TIMER_INITIAL, 0);
+
+        ticks
+    }
+
+    /// Start the timer in periodic mode
+    pub unsafe fn start_timer(&self, vector: u8, count: u32) {
+        self.write(LAPIC_TIMER_DIVIDE, TIMER_DIVIDE_16);
+        self.write(LAPIC_LVT_TIMER, TIMER_PERIODIC | vector as u32);
+        self.write(LAPIC_TIMER_INITIAL, count);
+    }
+}
+
+/// An I/O APIC, which routes a range of global system interrupts to local APICs
+#[derive(Copy, Clone, Debug)]
+pub struct IoApic {
+    pub address: usize,
+    /// The first global system interrupt handled by this I/O APIC
+    pub gsi_base: u32,
+    /// The number of inputs
+    pub count: u32,
+}
+
+impl IoApic {
+    pub unsafe fn new(address: usize, gsi_base: u32) -> Self {
+        let mut ioapic = IoApic {
+            address: address,
+            gsi_base: gsi_base,
+            count: 0,
+        };
+        ioapic.count = ((ioapic.read(IOAPIC_VERSION) >> 16) & 0xFF) + 1;
+        ioapic
+    }
+
+    unsafe fn read(&self, reg: u32) -> u32 {
+        (&mut *((self.address + IOAPIC_REGSEL) as *mut Mmio<u32>)).write(reg);
+        (&*((self.address + IOAPIC_WINDOW) as *const Mmio<u32>)).read()
+    }
+
+    unsafe fn write(&self, reg: u32, value: u32) {
+        (&mut *((self.address + IOAPIC_REGSEL) as *mut Mmio<u32>)).write(reg);
+        (&mut *((self.address + IOAPIC_WINDOW) as *mut Mmio<u32>)).write(value);
+    }
+
+    /// Check if a global system interrupt is an input of this I/O APIC
+    pub fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.count
+    }
+
+    /// Set the redirection entry of a global system interrupt
+    pub unsafe fn set(&self, gsi: u32, low: u32, apic_id: u32) {
+        let reg = IOAPIC_REDIRECTION + (gsi - self.gsi_base) * 2;
+        self.write(reg, REDIRECTION_MASKED);
+        self.write(reg + 1, apic_id << 24);
+        self.write(reg, low);
+    }
+
+    /// Get the low half of the redirection entry of a global system interrupt
+    pub unsafe fn get(&self, gsi: u32) -> u32 {
+        self.read(IOAPIC_REDIRECTION + (gsi - self.gsi_base) * 2)
+    }
+}
+
+/// An IRQ routed through an I/O APIC
+#[derive(Copy, Clone, Debug)]
+struct Route {
+    irq: usize,
+    gsi: u32,
+    ioapic: usize,
+}
+
+static mut LOCAL_APIC: Option<LocalApic> = None;
+static mut IO_APICS: Option<Vec<IoApic>> = None;
+static mut ROUTES: Option<Vec<Route>> = None;
+/// The initial count of the local APIC timer for one tick
+static mut TIMER_COUNT: u32 = 0;
+
+/// Get the local APIC, if the MADT was found
+pub fn local_apic() -> Option<LocalApic> {
+    unsafe { LOCAL_APIC }
+}
+
+/// Check if IRQs are routed through I/O APICs instead of the PICs
+pub fn routed() -> bool {
+    unsafe { ROUTES.is_some() }
+}
+
+/// Find the global system interrupt of an IRQ
+pub fn irq_gsi(irq: usize) -> Option<u32> {
+    unsafe { ROUTES.as_ref() }.and_then(|routes| {
+        routes.iter().find(|route| route.irq == irq).map(|route| route.gsi)
+    })
+}
+
+/// Set up the local APIC of the boot processor, the I/O APICs, and the local APIC timer
+pub unsafe fn init(madt: &MADT) {
+    let local_apic = LocalApic::new(madt.local_apic_address as usize);
+    local_apic.enable(SPURIOUS_INTERRUPT as u8);
+    LOCAL_APIC = Some(local_apic);
+
+    let mut io_apics = Vec::new();
+    for entry in madt.io_apics.iter() {
+        let ioapic = IoApic::new(entry.address as usize, entry.gsi_base);
+        for gsi in ioapic.gsi_base..ioapic.gsi_base + ioapic.count {
+            ioapic.set(gsi, REDIRECTION_MASKED, 0);
+        }
+        debugln!("  * IOAPIC {:X}: GSI {} to {}", ioapic.address, ioapic.gsi_base, ioapic.gsi_base + ioapic.count - 1);
+        io_apics.push(ioapic);
+    }
+
+    if ! io_apics.is_empty() {
+        let mut routes = Vec::new();
+
+        // ISA IRQs are edge triggered and active high, unless overridden
+        for irq in 1..16 {
+            let mut gsi = irq as u32;
+            let mut flags = 0;
+            for int_override in madt.int_source_overrides.iter() {
+                if int_override.bus_source == 0 && int_override.irq_source as usize == irq {
+                    gsi = int_override.gsi;
+                    flags = int_override.flags;
+                }
+            }
+
+            // The timer and the cascade are not used, the local APIC timer is used instead
+            if irq == 2 && gsi == 2 {
+                continue;
+            }
+
+            let mut low = (IRQ_BASE + irq) as u32;
+            if flags & OVERRIDE_POLARITY_MASK == OVERRIDE_POLARITY_LOW {
+                low |= REDIRECTION_ACTIVE_LOW;
+            }
+            if flags & OVERRIDE_TRIGGER_MASK == OVERRIDE_TRIGGER_LEVEL {
+                low |= REDIRECTION_LEVEL;
+            }
+
+            if let Some(i) = io_apics.iter().position(|ioapic| ioapic.handles(gsi)) {
+                io_apics[i].set(gsi, low, local_apic.id());
+                routes.push(Route {
+                    irq: irq,
+                    gsi: gsi,
+                    ioapic: i,
+                });
+            }
+        }
+
+        // Other inputs are PCI interrupts, which are level triggered and active low
+        for (i, ioapic) in io_apics.iter().enumerate() {
+            for gsi in ioapic.gsi_base..ioapic.gsi_base + ioapic.count {
+                let irq = gsi as usize;
+                if irq >= 16 && irq < IRQ_COUNT && ! routes.iter().any(|route| route.gsi == gsi) {
+                    ioapic.set(gsi,
+                               (IRQ_BASE + irq) as u32 | REDIRECTION_ACTIVE_LOW | REDIRECTION_LEVEL | REDIRECTION_MASKED,
+                               local_apic.id());
+                    routes.push(Route {
+                        irq: irq,
+                        gsi: gsi,
+                        ioapic: i,
+                    });
+                }
+            }
+        }
+
+        // Mask the PICs, which are replaced by the I/O APICs
+        Pio::<u8>::new(0xA1).write(0xFF);
+        Pio::<u8>::new(0x21).write(0xFF);
+
+        ROUTES = Some(routes);
+    } else {
+        // Only mask the PIT, the PICs are still used for the other IRQs
+        let mut mask = Pio::<u8>::new(0x21);
+        let value = mask.read();
+        mask.write(value | 1);
+    }
+
+    IO_APICS = Some(io_apics);
+
+    TIMER_COUNT = ((local_apic.calibrate_timer() as u64 * TICK_NANOS) / 10000000) as u32;
+    debugln!("  * Local APIC timer: {} ticks per {} ns", TIMER_COUNT, TICK_NANOS);
+    local_apic.start_timer(TIMER_INTERRUPT as u8, TIMER_COUNT);
+}
+
+/// Set up the local APIC of an application processor, starting its timer
+pub unsafe fn init_ap() {
+    if let Some(local_apic) = LOCAL_APIC {
+        local_apic.enable(SPURIOUS_INTERRUPT as u8);
+        if TIMER_COUNT > 0 {
+            local_apic.start_timer(TIMER_INTERRUPT as u8, TIMER_COUNT);
+        }
+    }
+}
+
+/// Enable or disable delivery of an IRQ routed through an I/O APIC
+///
+/// ISA IRQs are enabled when they are routed, other inputs of the I/O APICs are disabled until
+/// a driver handling them enables them.
+pub unsafe fn set_irq_enabled(irq: usize, enabled: bool) -> bool {
+    if let (Some(routes), Some(io_apics)) = (ROUTES.as_ref(), IO_APICS.as_ref()) {
+        if let Some(route) = routes.iter().find(|route| route.irq == irq) {
+            let ioapic = &io_apics[route.ioapic];
+            let low = ioapic.get(route.gsi);
+            let apic_id = LOCAL_APIC.map_or(0, |local_apic| local_apic.id());
+            if enabled {
+                ioapic.set(route.gsi, low & !REDIRECTION_MASKED, apic_id);
+            } else {
+                ioapic.set(route.gsi, low | REDIRECTION_MASKED, apic_id);
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// Signal the end of an IRQ, to the local APIC or to the PICs
+pub unsafe fn eoi_irq(irq: usize) {
+    if routed() {
+        eoi();
+    } else if irq < 16 {
+        if irq >= 8 {
+            Pio::<u8>::new(0xA0).write(0x20);
+        }
+
+        Pio::<u8>::new(0x20).write(0x20);
+    }
+}
+
+/// Signal the end of an interrupt sent by the local APIC
+pub unsafe fn eoi() {
+    if let Some(local_apic) = LOCAL_APIC {
+        local_apic.eoi();
+    }
+}
+
+/// Wait for about `us` microseconds, using writes to the POST port which take about a microsecond
+pub fn delay(us: usize) {
+    let mut port = Pio::<u8>::new(0x80);
+    for _ in 0..us {
+        port.write(0);
+    }
+}
@@ -6,6 +6,7 @@ use alloc::boxed::{Box, FnBox};
 use arch::memory;
 use arch::paging::Page;
 use arch::regs::Regs;
+use arch::smp;
 
 use collections::borrow::Cow;
 use collections::string::{String, ToString};
@@ -14,17 +15,22 @@ use collections::vec::Vec;
 use common::time::Duration;
 
 use core::cell::UnsafeCell;
+use core::cmp;
 use core::slice::{self, Iter, IterMut};
-use core::{mem, ptr};
+use core::{isize, mem, ptr};
 use core::ops::DerefMut;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use fs::Resource;
+use fs::supervisor_resource::SupervisorQueue;
 
 use syscall;
 
-use system::error::{Error, Result, EBADF, EFAULT, ENOMEM, ESRCH, ENOENT, EINVAL};
+use system::error::{Error, Result, EBADF, EFAULT, ENOMEM, ESRCH, EINVAL};
+use system::syscall::{SigAction, SupervisorEvent, NSIG, SIGCHLD, SIGCONT, SIGKILL, SIGSTOP, SIGTSTP,
+                      SIGTTIN, SIGTTOU, SIGURG, SIGWINCH, SIG_DFL, SIG_IGN, SUPERVISE_CHILD};
 
-use sync::WaitMap;
+use sync::{TicketLock, WaitMap};
 
 pub const CONTEXT_IMAGE_ADDR: usize = 0x8048000;
 pub const CONTEXT_IMAGE_SIZE: usize = 0x10000000;
@@ -37,31 +43,68 @@ pub const CONTEXT_MMAP_SIZE: usize = 0x20000000;
 
 pub const CONTEXT_STACK_ADDR: usize = CONTEXT_MMAP_ADDR + CONTEXT_MMAP_SIZE + memory::CLUSTER_SIZE;
 pub const CONTEXT_STACK_SIZE: usize = 0x100000;
+/// The range that the top of the user stack is placed in at random
+pub const CONTEXT_STACK_RANDOM: usize = 0x1000000;
+/// The size the user stack grows to by default, when it is used below `CONTEXT_STACK_SIZE`
+pub const CONTEXT_STACK_LIMIT: usize = 0x800000;
+/// The highest limit of the size of the user stack
+pub const CONTEXT_STACK_LIMIT_MAX: usize = 0x4000000;
+/// The size of the guard page below the lowest stack address, which is never mapped
+pub const CONTEXT_STACK_GUARD: usize = 4096;
+/// The end of the range that user stacks are placed in
+pub const CONTEXT_STACK_END: usize = CONTEXT_STACK_ADDR + CONTEXT_STACK_GUARD + CONTEXT_STACK_LIMIT_MAX + CONTEXT_STACK_RANDOM;
+
+/// The nice value with the highest priority
+pub const NICE_MIN: isize = -20;
+/// The nice value with the lowest priority
+pub const NICE_MAX: isize = 19;
+
+/// Signals that stop a context, unless they are caught or ignored
+pub const SIG_STOP: usize = 1 << SIGSTOP | 1 << SIGTSTP | 1 << SIGTTIN | 1 << SIGTTOU;
+
+/// The limit of `Context::cpu_usage`, so that a context that was busy for a long time does not
+/// need many seconds to regain its priority
+const CPU_USAGE_MAX: usize = 512;
 
 pub struct ContextManager {
     pub inner: Vec<Box<Context>>,
     pub enabled: bool,
-    pub i: usize,
+    /// The index of the current context of each processor
+    pub running: [Option<usize>; smp::MAX_CPUS],
     pub next_pid: usize,
+    /// The ID of the context that runs `init`, or 0 before it is spawned
+    pub init_pid: usize,
 }
 
+/// The lock of the list of contexts of the `ContextManager`, of `running` and of `next_pid`
+///
+/// It is held while contexts are added, removed or switched to. The state of each context is
+/// still protected by the kernel lock.
+pub static CONTEXTS_LOCK: TicketLock = TicketLock::new();
+
 impl ContextManager {
     pub fn new() -> ContextManager {
         ContextManager {
             inner: Vec::new(),
             enabled: false,
-            i: 0,
+            running: [None; smp::MAX_CPUS],
             next_pid: 1,
+            init_pid: 0,
         }
     }
 
     pub fn current(&self) -> Result<&Box<Context>> {
-        self.get(self.i)
+        match self.running[smp::cpu_id()] {
+            Some(i) => self.get(i),
+            None => Err(Error::new(ESRCH)),
+        }
     }
 
     pub fn current_mut(&mut self) -> Result<&mut Box<Context>> {
-        let i = self.i;
-        self.get_mut(i)
+        match self.running[smp::cpu_id()] {
+            Some(i) => self.get_mut(i),
+            None => Err(Error::new(ESRCH)),
+        }
     }
 
     pub fn iter(&self) -> Iter<Box<Context>> {
@@ -105,32 +148,92 @@ impl ContextManager {
     }
 
     pub unsafe fn push(&mut self, context: Box<Context>) {
+        let _lock = CONTEXTS_LOCK.lock();
         self.inner.push(context);
     }
 
-    pub unsafe fn clean(&mut self) {
-        loop {
-            while self.i >= self.len() {
-                self.i -= self.len();
-            }
+    /// Add the idle context of a processor, which becomes its current context
+    pub unsafe fn push_idle(&mut self, cpu: usize) {
+        let context = Context::root(cpu);
+
+        let _lock = CONTEXTS_LOCK.lock();
+        self.inner.push(context);
+        self.running[cpu] = Some(self.inner.len() - 1);
+    }
+
+    /// Get the processor running the context at index `i`
+    pub fn running_on(&self, i: usize) -> Option<usize> {
+        self.running.iter().position(|running| *running == Some(i))
+    }
 
-            let mut remove = false;
-            if let Ok(next) = self.current() {
-                if next.exited {
-                    remove = true;
+    /// Halve the recent usage of the processor of every context, called every second
+    pub fn decay(&mut self) {
+        for context in self.inner.iter_mut() {
+            context.cpu_usage /= 2;
+        }
+    }
+
+    /// Remove exited contexts that are not running on any processor, with the lock held
+    ///
+    /// The contexts are returned, so that they can be dropped after the lock is released.
+    unsafe fn clean(&mut self) -> Vec<Box<Context>> {
+        let mut removed = Vec::new();
+
+        let mut i = 0;
+        while i < self.len() {
+            if self.inner[i].exited && self.running_on(i).is_none() {
+                removed.push(self.inner.remove(i));
+
+                for running in self.running.iter_mut() {
+                    if let Some(ref mut j) = *running {
+                        if *j > i {
+                            *j -= 1;
+                        }
+                    }
                 }
+            } else {
+                i += 1;
+            }
+        }
+
+        removed
+    }
+
+    /// Check if the context at index `i` can be switched to by processor `cpu`
+    ///
+    /// Contexts sharing memory with a context running on another processor are skipped, as memory
+    /// is only mapped for the current context of each processor when switching.
+    unsafe fn runnable(&mut self, i: usize, cpu: usize) -> bool {
+        if let Some(other) = self.running_on(i) {
+            if other != cpu {
+                return false;
             }
+        }
 
-            if remove {
-                let i = self.i;
-                drop(self.inner.remove(i));
-            } else {
-                break;
+        for (j, running) in self.running.iter().enumerate() {
+            if j != cpu {
+                if let Some(running) = *running {
+                    if running != i && self.inner[running].heap.get() == self.inner[i].heap.get() {
+                        return false;
+                    }
+                }
             }
         }
 
-        while self.i >= self.len() {
-            self.i -= self.len();
+        let next = &mut self.inner[i];
+        if next.exited || next.cpu.map_or(false, |next_cpu| next_cpu != cpu) {
+            false
+        } else if next.blocked > 0 {
+            if let Some(wake) = next.wake {
+                if wake <= Duration::monotonic() {
+                    next.wake = None;
+                    next.unblock("context_switch wake");
+                    return true;
+                }
+            }
+            false
+        } else {
+            true
         }
     }
 }
@@ -139,63 +242,72 @@ impl ContextManager {
 ///
 /// Unsafe due to interrupt disabling, raw pointers, and unsafe Context functions
 pub unsafe fn context_switch() {
+    let interrupts = smp::disable_interrupts();
+
+    smp::relax();
+
     let mut current_ptr: *mut Context = 0 as *mut Context;
     let mut next_ptr: *mut Context = 0 as *mut Context;
+    let mut removed = Vec::new();
 
     {
         let contexts = &mut *::env().contexts.get();
+        let _lock = CONTEXTS_LOCK.lock();
         if contexts.enabled {
-            let current_i = contexts.i;
-            'searching: loop {
-                contexts.i += 1;
-                contexts.clean();
-                if let Ok(mut next) = contexts.current_mut() {
-                    if next.blocked > 0 {
-                        if let Some(wake) = next.wake {
-                            if wake <= Duration::monotonic() {
-                                next.wake = None;
-                                next.unblock("context_switch wake");
-                                break 'searching;
-                            }
+            removed = contexts.clean();
+
+            let cpu = smp::cpu_id();
+            if let Some(current_i) = contexts.running[cpu] {
+                // Find the runnable context with the highest priority, the one with the lowest
+                // value, going round-robin from the current context between equal priorities
+                let len = contexts.len();
+                let mut next_i = current_i;
+                let mut next_priority = isize::MAX;
+                for offset in 1..len + 1 {
+                    let i = (current_i + offset) % len;
+                    if contexts.runnable(i, cpu) {
+                        let priority = contexts.inner[i].priority();
+                        if priority < next_priority {
+                            next_i = i;
+                            next_priority = priority;
                         }
-                    } else {
-                        break 'searching;
                     }
                 }
-                if contexts.i == current_i {
-                    break 'searching;
-                }
-            }
 
-            if contexts.i != current_i {
-                if let Ok(mut current) = contexts.get_mut(current_i) {
-                    current.unmap();
+                if next_i != current_i {
+                    if let Ok(mut current) = contexts.get_mut(current_i) {
+                        current.unmap();
 
-                    current_ptr = current.deref_mut();
-                }
+                        current_ptr = current.deref_mut();
+                    }
+
+                    contexts.running[cpu] = Some(next_i);
 
-                if let Ok(mut next) = contexts.current_mut() {
-                    next.switch += 1;
+                    if let Ok(mut next) = contexts.get_mut(next_i) {
+                        next.switch += 1;
 
-                    if let Some(ref mut tss) = ::TSS_PTR {
                         if next.kernel_stack > 0 {
-                            tss.sp0 = next.kernel_stack + CONTEXT_STACK_SIZE - 128;
-                        } else {
-                            tss.sp0 = 0x800000 - 128;
+                            if let Some(tss) = smp::tss() {
+                                tss.sp0 = next.kernel_stack + CONTEXT_STACK_SIZE - 128;
+                            }
                         }
-                    }
 
-                    next.map();
+                        next.map();
 
-                    next_ptr = next.deref_mut();
+                        next_ptr = next.deref_mut();
+                    }
                 }
             }
         }
     }
 
+    drop(removed);
+
     if current_ptr as usize > 0 && next_ptr as usize > 0 {
         (*current_ptr).switch_to(&mut *next_ptr);
     }
+
+    smp::restore_interrupts(interrupts);
 }
 
 pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
@@ -212,7 +324,7 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
             //debugln!("{}: {}: clone to {}: {:X}", parent.pid, parent.name, clone_pid, flags);
 
             let regs_size = mem::size_of::<Regs>();
-            let extra_size = mem::size_of::<usize>() * 3; /* Return pointer, interrupt code, regs pointer */
+            let extra_size = mem::size_of::<usize>() * 5; /* Return pointer, interrupt code, regs pointer, error code, padding */
             let parent_regs_addr = (regs as *const Regs) as usize;
             let child_regs_addr = parent_regs_addr - parent.kernel_stack + kernel_stack;
             ::memcpy((child_regs_addr - extra_size) as *mut u8,
@@ -222,12 +334,16 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
             let child_regs = &mut *(child_regs_addr as *mut Regs);
             child_regs.ax = 0;
 
+            // The child returns to userspace from the interrupt handler, after releasing the kernel
+            // lock held by the processor that switches to it
             let mut kernel_regs = parent.regs;
-            kernel_regs.sp = child_regs_addr - extra_size;
+            kernel_regs.sp = child_regs_addr - extra_size - mem::size_of::<usize>();
+            *(kernel_regs.sp as *mut usize) = smp::unlock as usize;
 
             let fx = kernel_stack + CONTEXT_STACK_SIZE;
             ::memcpy(fx as *mut u8, parent.fx as *const u8, 512);
 
+            // Both contexts write to the stack as soon as the clone returns, so it is copied now
             let stack = if let Some(ref entry) = parent.stack {
                 let physical_address = memory::alloc(entry.virtual_size);
                 if physical_address > 0 {
@@ -240,6 +356,8 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
                         virtual_size: entry.virtual_size,
                         writeable: entry.writeable,
                         allocated: true,
+                        shared: None,
+                        borrowed: None,
                     })
                 } else {
                     None
@@ -272,10 +390,10 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
                 Arc::new(UnsafeCell::new((*parent.mmap.get()).dup()))
             };
 
-            let env_vars = if flags & syscall::CLONE_VM == syscall::CLONE_VM {
-                parent.env_vars.clone()
+            let sig_actions = if flags & syscall::CLONE_VM == syscall::CLONE_VM {
+                parent.sig_actions.clone()
             } else {
-                Arc::new(UnsafeCell::new((*parent.env_vars.get()).clone()))
+                Arc::new(UnsafeCell::new(*parent.sig_actions.get()))
             };
 
             let cwd = if flags & syscall::CLONE_FS == syscall::CLONE_FS {
@@ -317,29 +435,55 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
             box Context {
                 pid: clone_pid,
                 ppid: parent.pid,
+                pgid: parent.pgid,
+                sid: parent.sid,
+                uid: parent.uid,
+                euid: parent.euid,
+                gid: parent.gid,
+                egid: parent.egid,
                 name: parent.name.clone(),
                 iopl: parent.iopl,
                 blocked: 0,
                 exited: false,
+                stopped: false,
+                waiting: false,
                 switch: 0,
                 time: 0,
+                user_time: Duration::new(0, 0),
+                system_time: Duration::new(0, 0),
+                nice: parent.nice,
+                cpu_usage: parent.cpu_usage,
                 vfork: vfork,
+                futex: None,
                 wake: None,
-
-                supervised: flags & syscall::CLONE_SUPERVISE == syscall::CLONE_SUPERVISE,
-                blocked_syscall: false,
+                cpu: None,
+
+                // A supervised context can not create children that escape supervision. Without a
+                // supervisor attached, nobody would be told of the child and answer its stops
+                supervised: flags & syscall::CLONE_SUPERVISE == syscall::CLONE_SUPERVISE ||
+                            (parent.supervised && parent.supervisor.is_some()),
+                supervisor: None,
+                supervisor_stop: None,
+                supervisor_regs: ptr::null_mut(),
+                supervisor_result: None,
                 current_syscall: None,
 
+                sig_pending: 0,
+                sig_mask: parent.sig_mask,
+
                 kernel_stack: kernel_stack,
                 regs: kernel_regs,
                 fx: fx,
                 stack: stack,
+                stack_limit: parent.stack_limit,
                 loadable: parent.loadable,
 
                 image: image,
+                image_base: parent.image_base,
                 heap: heap,
                 mmap: mmap,
-                env_vars: env_vars,
+                sig_actions: sig_actions,
+                env_vars: parent.env_vars.clone(),
                 cwd: cwd,
                 files: files,
 
@@ -347,6 +491,19 @@ pub unsafe fn context_clone(regs: &Regs) -> Result<usize> {
             }
         };
 
+        // The supervisor must know the child before a vfork parent waits for it
+        {
+            let parent = try!(contexts.current());
+            if parent.supervised {
+                parent.supervisor_event(SupervisorEvent {
+                    kind: SUPERVISE_CHILD,
+                    pid: parent.pid,
+                    a: clone_pid,
+                    ..SupervisorEvent::default()
+                });
+            }
+        }
+
         contexts.push(context);
 
         if flags & syscall::CLONE_VFORK == syscall::CLONE_VFORK {
@@ -415,18 +572,177 @@ unsafe extern "cdecl" fn context_box(/*Throw away extra params from ABI*/ _rdi:
     syscall::process::exit(0);
 }
 
+/// Physical memory shared copy-on-write between contexts, freed when the last reference is dropped
+pub struct SharedMemory {
+    pub physical_address: usize,
+    /// The number of contexts mapping the memory
+    owners: AtomicUsize,
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        unsafe { memory::unalloc(self.physical_address) };
+    }
+}
+
+/// The reference of one context to memory shared copy-on-write
+///
+/// The memory maps split off by `copy_on_write` keep the reference of their context, so the
+/// memory is only copied while another context maps it.
+pub struct SharedOwner {
+    pub memory: Arc<SharedMemory>,
+}
+
+impl SharedOwner {
+    fn new(memory: Arc<SharedMemory>) -> SharedOwner {
+        memory.owners.fetch_add(1, Ordering::SeqCst);
+        SharedOwner {
+            memory: memory
+        }
+    }
+}
+
+impl Drop for SharedOwner {
+    fn drop(&mut self) {
+        self.memory.owners.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 pub struct ContextMemory {
     pub physical_address: usize,
     pub virtual_address: usize,
     pub virtual_size: usize,
     pub writeable: bool,
     pub allocated: bool,
+    /// The reference of the context to the physical memory, if it is shared copy-on-write
+    pub shared: Option<Arc<SharedOwner>>,
+    /// The memory of another context that is mapped shared, which is kept alive by this reference
+    pub borrowed: Option<Arc<SharedMemory>>,
 }
 
 impl ContextMemory {
+    /// Check if the memory is shared with another context, and has to be copied before writing
+    pub fn is_cow(&self) -> bool {
+        if let Some(ref shared) = self.shared {
+            shared.memory.owners.load(Ordering::SeqCst) > 1
+        } else {
+            false
+        }
+    }
+
+    /// Duplicate the memory map for a new context
+    ///
+    /// Allocated memory is shared copy-on-write, and mapped read only until it is written to.
+    /// Memory that is not owned by the memory map, like that of a device or of a scheme, is mapped
+    /// at the same physical address. `owners` pairs the references of this context with those of
+    /// the new one, so that the new context has one reference to each shared memory, however
+    /// often it was split.
+    pub unsafe fn dup(&mut self, owners: &mut Vec<(Arc<SharedOwner>, Arc<SharedOwner>)>) -> ContextMemory {
+        self.share();
+
+        if let Some(parent) = self.shared.clone() {
+            let existing = owners.iter().find(|&&(ref owner, _)| {
+                &**owner as *const SharedOwner == &*parent as *const SharedOwner
+            }).map(|&(_, ref owner)| owner.clone());
+            let shared = match existing {
+                Some(shared) => shared,
+                None => {
+                    let shared = Arc::new(SharedOwner::new(parent.memory.clone()));
+                    owners.push((parent, shared.clone()));
+                    shared
+                }
+            };
+
+            self.map();
+
+            ContextMemory {
+                physical_address: self.physical_address,
+                virtual_address: self.virtual_address,
+                virtual_size: self.virtual_size,
+                writeable: self.writeable,
+                allocated: false,
+                shared: Some(shared),
+                borrowed: None,
+            }
+        } else {
+            ContextMemory {
+                physical_address: self.physical_address,
+                virtual_address: self.virtual_address,
+                virtual_size: self.virtual_size,
+                writeable: self.writeable,
+                allocated: false,
+                shared: None,
+                borrowed: self.borrowed.clone(),
+            }
+        }
+    }
+
+    /// Get a reference keeping the memory alive while another context maps it
+    ///
+    /// Allocated memory becomes shared, which does not make it copy-on-write, as only the
+    /// references of contexts count for that. The memory of devices returns `None`.
+    pub fn lend(&mut self) -> Option<Arc<SharedMemory>> {
+        self.share();
+
+        if let Some(ref shared) = self.shared {
+            Some(shared.memory.clone())
+        } else {
+            self.borrowed.clone()
+        }
+    }
+
+    /// Move allocated memory into a `SharedMemory`, which frees it once it is not referenced
+    fn share(&mut self) {
+        if self.allocated {
+            let memory = Arc::new(SharedMemory {
+                physical_address: self.physical_address,
+                owners: AtomicUsize::new(0),
+            });
+            self.shared = Some(Arc::new(SharedOwner::new(memory)));
+            self.allocated = false;
+        }
+    }
+
+    /// Give the memory map its own allocation, copying the memory if it is still shared
+    /// Returns false if memory could not be allocated. The memory map has to be mapped again.
+    pub unsafe fn unshare(&mut self) -> bool {
+        if let Some(mut shared) = self.shared.take() {
+            // Take over the allocation if this is the last reference to all of it
+            let physical_address = self.physical_address;
+            let taken = Arc::get_mut(&mut shared).and_then(|owner| Arc::get_mut(&mut owner.memory)).map_or(false, |memory| {
+                if memory.physical_address == physical_address {
+                    // Nothing is freed when the shared memory is dropped
+                    memory.physical_address = 0;
+                    true
+                } else {
+                    false
+                }
+            });
+            if taken {
+                self.allocated = true;
+                return true;
+            }
+
+            let physical_address = memory::alloc_aligned(self.virtual_size, 4096);
+            if physical_address == 0 {
+                self.shared = Some(shared);
+                return false;
+            }
+
+            ::memcpy(physical_address as *mut u8,
+                     self.physical_address as *const u8,
+                     self.virtual_size);
+
+            self.physical_address = physical_address;
+            self.allocated = true;
+        }
+
+        true
+    }
+
     pub unsafe fn map(&mut self) {
         for i in 0..(self.virtual_size + 4095) / 4096 {
-            if self.writeable {
+            if self.writeable && ! self.is_cow() {
                 Page::new(self.virtual_address + i * 4096)
                     .map_user_write(self.physical_address + i * 4096);
             } else {
@@ -472,30 +788,12 @@ impl ContextZone {
         }
     }
 
-    pub fn dup(&self) -> ContextZone {
+    /// Duplicate the memory for a new context, sharing it copy-on-write
+    pub unsafe fn dup(&mut self) -> ContextZone {
         let mut mem: Vec<ContextMemory> = Vec::new();
-        for entry in self.memory.iter() {
-            let physical_address = unsafe { memory::alloc(entry.virtual_size) };
-            if physical_address > 0 {
-                //TODO: Remap pages during memcpy
-                unsafe {
-                    ::memcpy(physical_address as *mut u8,
-                             entry.physical_address as *const u8,
-                             entry.virtual_size);
-                }
-
-                //debugln!("{}: {}: dup memory {:X}:{:X} for {}", parent.pid, parent.name, entry.virtual_address, entry.virtual_address + entry.virtual_size, clone_pid);
-
-                mem.push(ContextMemory {
-                    physical_address: physical_address,
-                    virtual_address: entry.virtual_address,
-                    virtual_size: entry.virtual_size,
-                    writeable: entry.writeable,
-                    allocated: true,
-                });
-            } else {
-                //debugln!("{}: {}: failed to dup memory {:X}:{:X} for {}", parent.pid, parent.name, entry.virtual_address, entry.virtual_address + entry.virtual_size, clone_pid);
-            }
+        let mut owners = Vec::new();
+        for entry in self.memory.iter_mut() {
+            mem.push(entry.dup(&mut owners));
         }
 
         ContextZone {
@@ -576,6 +874,208 @@ impl ContextZone {
         Err(Error::new(ENOMEM))
     }
 
+    /// Check that every memory map touching a range is completely inside of it,
+    /// returning the number of memory maps inside of the range
+    fn check_range(&self, address: usize, size: usize) -> Result<usize> {
+        let mut count = 0;
+
+        for mem in self.memory.iter() {
+            let start = mem.virtual_address;
+            let end = mem.virtual_address + (mem.virtual_size + 4095) / 4096 * 4096;
+            if start < address + size && end > address {
+                if start >= address && end <= address + size {
+                    count += 1;
+                } else {
+                    return Err(Error::new(EINVAL));
+                }
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Unmap and free all memory maps inside of a range
+    /// Returns `EINVAL` if a memory map is only partially inside of the range
+    pub unsafe fn unmap_range(&mut self, address: usize, size: usize) -> Result<usize> {
+        let count = try!(self.check_range(address, size));
+
+        let mut i = 0;
+        while i < self.memory.len() {
+            if self.memory[i].virtual_address >= address && self.memory[i].virtual_address < address + size {
+                let mut mem = self.memory.remove(i);
+                mem.unmap();
+            } else {
+                i += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Change the permission of all memory maps inside of a range
+    /// Returns `EINVAL` if a memory map is only partially inside of the range
+    pub unsafe fn protect_range(&mut self, address: usize, size: usize, writeable: bool) -> Result<usize> {
+        let count = try!(self.check_range(address, size));
+
+        for mem in self.memory.iter_mut() {
+            if mem.virtual_address >= address && mem.virtual_address < address + size {
+                mem.writeable = writeable;
+                mem.map();
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Copy the page containing `address`, if it is in writeable memory shared copy-on-write
+    ///
+    /// The memory map is split around the page, so that only the page is copied. Returns false if
+    /// the address is not in such memory, or the copy could not be allocated.
+    pub unsafe fn copy_on_write(&mut self, address: usize) -> bool {
+        let i = match self.memory.iter().position(|mem| {
+            address >= mem.virtual_address && address < mem.virtual_address + (mem.virtual_size + 4095) / 4096 * 4096
+        }) {
+            Some(i) => i,
+            None => return false
+        };
+
+        if ! self.memory[i].writeable || self.memory[i].shared.is_none() {
+            return false;
+        }
+
+        // The other contexts have released the memory, so it can be written to in place
+        if ! self.memory[i].is_cow() {
+            self.memory[i].map();
+            return true;
+        }
+
+        let mem = self.memory.remove(i);
+        let offset = (address - mem.virtual_address) / 4096 * 4096;
+        let size = cmp::min(4096, mem.virtual_size - offset);
+
+        let physical_address = memory::alloc_aligned(4096, 4096);
+        if physical_address == 0 {
+            self.memory.insert(i, mem);
+            return false;
+        }
+
+        ::memcpy(physical_address as *mut u8,
+                 (mem.physical_address + offset) as *const u8,
+                 size);
+
+        let mut pieces = Vec::new();
+        if offset > 0 {
+            pieces.push(ContextMemory {
+                physical_address: mem.physical_address,
+                virtual_address: mem.virtual_address,
+                virtual_size: offset,
+                writeable: true,
+                allocated: false,
+                shared: mem.shared.clone(),
+                borrowed: None,
+            });
+        }
+        pieces.push(ContextMemory {
+            physical_address: physical_address,
+            virtual_address: mem.virtual_address + offset,
+            virtual_size: size,
+            writeable: true,
+            allocated: true,
+            shared: None,
+            borrowed: None,
+        });
+        if offset + size < mem.virtual_size {
+            pieces.push(ContextMemory {
+                physical_address: mem.physical_address + offset + size,
+                virtual_address: mem.virtual_address + offset + size,
+                virtual_size: mem.virtual_size - offset - size,
+                writeable: true,
+                allocated: false,
+                shared: mem.shared.clone(),
+                borrowed: None,
+            });
+        }
+
+        // The pieces keep the reference of this context, so the shared memory is not released
+        drop(mem);
+
+        for (j, mut piece) in pieces.into_iter().enumerate() {
+            piece.map();
+            self.memory.insert(i + j, piece);
+        }
+
+        true
+    }
+
+    /// Make the memory in a range private and physically contiguous, so that it can be accessed
+    /// using its physical address
+    ///
+    /// Memory maps that were split by `copy_on_write` are merged again if the range crosses them.
+    /// Returns true if the zone has changed, and has to be mapped again.
+    pub unsafe fn unshare(&mut self, ptr: usize, len: usize) -> bool {
+        let end = ptr + cmp::max(len, 1);
+
+        let mut first = None;
+        let mut count = 0;
+        for (i, mem) in self.memory.iter().enumerate() {
+            if mem.virtual_address < end && mem.virtual_address + mem.virtual_size > ptr {
+                if first.is_none() {
+                    first = Some(i);
+                }
+                count += 1;
+            }
+        }
+
+        let first = match first {
+            Some(first) => first,
+            None => return false
+        };
+
+        if count == 1 {
+            return self.memory[first].is_cow() && self.memory[first].unshare();
+        }
+
+        // Only merge owned memory maps that are in order and adjacent
+        let mut next_address = self.memory[first].virtual_address;
+        for mem in self.memory[first .. first + count].iter() {
+            if mem.virtual_address != next_address || ! (mem.allocated || mem.shared.is_some()) {
+                return false;
+            }
+            next_address = mem.virtual_address + (mem.virtual_size + 4095) / 4096 * 4096;
+        }
+
+        let virtual_address = self.memory[first].virtual_address;
+        let virtual_size = {
+            let last = &self.memory[first + count - 1];
+            last.virtual_address + last.virtual_size - virtual_address
+        };
+
+        let physical_address = memory::alloc_aligned(virtual_size, 4096);
+        if physical_address == 0 {
+            return false;
+        }
+
+        let mut writeable = true;
+        for mem in self.memory.drain(first .. first + count) {
+            ::memcpy((physical_address + mem.virtual_address - virtual_address) as *mut u8,
+                     mem.physical_address as *const u8,
+                     mem.virtual_size);
+            writeable = writeable && mem.writeable;
+        }
+
+        self.memory.insert(first, ContextMemory {
+            physical_address: physical_address,
+            virtual_address: virtual_address,
+            virtual_size: virtual_size,
+            writeable: writeable,
+            allocated: true,
+            shared: None,
+            borrowed: None,
+        });
+
+        true
+    }
+
     /// Cleanup empty memory
     pub unsafe fn clean_mem(&mut self) {
         self.memory.retain(|mem| mem.virtual_size > 0);
@@ -594,25 +1094,24 @@ impl ContextZone {
     }
 }
 
-#[derive(Clone)]
-pub struct EnvVar(pub String, pub String);
-
-impl EnvVar {
-    pub fn name(&self) -> &str {
-        &self.0
-    }
-
-    pub fn value(&self) -> &str {
-        &self.1
-    }
-}
-
 pub struct Context {
     // These members are used for control purposes by the scheduler {
     /// The PID of the context
     pub pid: usize,
     /// The PID of the parent
     pub ppid: usize,
+    /// The process group, which receives the signals of the console while it is in the foreground
+    pub pgid: usize,
+    /// The session, which contains the process groups of a login
+    pub sid: usize,
+    /// The real user ID
+    pub uid: usize,
+    /// The effective user ID, which permissions are checked against
+    pub euid: usize,
+    /// The real group ID
+    pub gid: usize,
+    /// The effective group ID, which permissions are checked against
+    pub egid: usize,
     /// The name of the context
     pub name: Cow<'static, str>,
     /// The I/O privilege level
@@ -621,14 +1120,31 @@ pub struct Context {
     pub blocked: usize,
     /// Indicates that the context exited
     pub exited: bool,
+    /// Indicates that the context was stopped by a signal, until it is continued by `SIGCONT`
+    pub stopped: bool,
+    /// Indicates that the context is blocked in a `WaitCondition`, which a signal stopping the
+    /// context interrupts
+    pub waiting: bool,
     /// How many times was the context switched to
     pub switch: usize,
     /// The number of time slices used
     pub time: usize,
+    /// The time spent in userspace
+    pub user_time: Duration,
+    /// The time spent in the kernel
+    pub system_time: Duration,
+    /// The nice value, from `NICE_MIN` for the highest priority to `NICE_MAX` for the lowest
+    pub nice: isize,
+    /// Recent usage of the processor, incremented on every time slice and halved every second
+    pub cpu_usage: usize,
     /// Indicates that the context needs to unblock parent
     pub vfork: Option<*mut Context>,
+    /// The physical address of the futex the context waits on
+    pub futex: Option<usize>,
     /// When to wake up
     pub wake: Option<Duration>,
+    /// The processor of an idle context, which only runs there. Other contexts run on any processor
+    pub cpu: Option<usize>,
     // }
 
     /// Is this process supervised?
@@ -636,13 +1152,24 @@ pub struct Context {
     /// i.e., will the syscalls made by this process block the process until handled by
     /// a supervisor?
     pub supervised: bool,
-    /// Is this process currently blocked by a syscall?
+    /// The events for the supervisor, once a supervisor is attached
+    pub supervisor: Option<Arc<UnsafeCell<SupervisorQueue>>>,
+    /// The event this process is stopped at
     ///
-    /// This means that the process is waiting for the superviser to handle the syscall.
-    pub blocked_syscall: bool,
+    /// This means that the process is waiting for the superviser to answer the event.
+    pub supervisor_stop: Option<SupervisorEvent>,
+    /// The registers of the syscall this process is stopped at, valid while `supervisor_stop` is set
+    pub supervisor_regs: *mut Regs,
+    /// The result the supervisor answered the stop with, instead of letting the syscall proceed
+    pub supervisor_result: Option<usize>,
     /// The current syscall
     pub current_syscall: Option<(usize, usize, usize, usize, usize)>,
 
+    /// Pending signals, as a bitmask indexed by signal number
+    pub sig_pending: usize,
+    /// Blocked signals, as a bitmask indexed by signal number
+    pub sig_mask: usize,
+
     // These members control the stack and registers and are unique to each context {
     // The kernel stack
     pub kernel_stack: usize,
@@ -652,6 +1179,8 @@ pub struct Context {
     pub fx: usize,
     /// The context stack
     pub stack: Option<ContextMemory>,
+    /// The size the context stack can grow to, below its top
+    pub stack_limit: usize,
     /// Indicates that registers can be loaded (they must be saved first)
     pub loadable: bool,
     // }
@@ -659,13 +1188,18 @@ pub struct Context {
     // These members are cloned for threads, copied or created for processes {
     /// Program memory, cloned for threads, copied or created for processes. Modified by exec
     pub image: Arc<UnsafeCell<ContextZone>>,
+    /// The address the executable is loaded at, zero unless it is position independent. Modified
+    /// by exec
+    pub image_base: usize,
     /// Heap, cloned for threads, copied or created for processes. Modified by memory allocation
     pub heap: Arc<UnsafeCell<ContextZone>>,
     /// Mmap memory, cloned for threads, copied or created for processes. Modified by mmap
     pub mmap: Arc<UnsafeCell<ContextZone>>,
-    /// Environment variables, cloned for threads, copied or created for
-    /// processes. Modified by set_env
-    pub env_vars: Arc<UnsafeCell<Vec<EnvVar>>>,
+    /// Signal actions, cloned for threads, copied for processes. Reset by exec
+    pub sig_actions: Arc<UnsafeCell<[SigAction; NSIG]>>,
+    /// Environment variables given to exec as `NAME=value`, shared by threads and processes until
+    /// the next exec. The program changes its own copy on its stack, so these are not updated
+    pub env_vars: Arc<Vec<String>>,
 
     /// Program working directory, cloned for threads, copied or created for processes. Modified by chdir
     pub cwd: Arc<UnsafeCell<String>>,
@@ -680,6 +1214,7 @@ pub struct Context {
 impl Context {
     pub fn next_pid() -> usize {
         let contexts = unsafe { &mut *::env().contexts.get() };
+        let _lock = CONTEXTS_LOCK.lock();
 
         let mut next_pid = contexts.next_pid;
 
@@ -707,35 +1242,61 @@ impl Context {
         ret
     }
 
-    pub unsafe fn root() -> Box<Self> {
+    /// Create the idle context of a processor, which runs on the stack the processor started with
+    pub unsafe fn root(cpu: usize) -> Box<Self> {
         let fx = memory::alloc(512);
 
+        let pid = Context::next_pid();
+
         box Context {
-            pid: Context::next_pid(),
+            pid: pid,
             ppid: 0,
+            pgid: pid,
+            sid: pid,
+            uid: 0,
+            euid: 0,
+            gid: 0,
+            egid: 0,
             name: "kidle".into(),
             iopl: 3,
             blocked: 0,
             exited: false,
+            stopped: false,
+            waiting: false,
             switch: 0,
             time: 0,
+            user_time: Duration::new(0, 0),
+            system_time: Duration::new(0, 0),
+            nice: 0,
+            cpu_usage: 0,
             vfork: None,
+            futex: None,
             wake: None,
+            cpu: Some(cpu),
 
             supervised: false,
-            blocked_syscall: false,
+            supervisor: None,
+            supervisor_stop: None,
+            supervisor_regs: ptr::null_mut(),
+            supervisor_result: None,
             current_syscall: None,
 
+            sig_pending: 0,
+            sig_mask: 0,
+
             kernel_stack: 0,
             regs: Regs::default(),
             fx: fx,
             stack: None,
+            stack_limit: CONTEXT_STACK_LIMIT,
             loadable: false,
 
             image: Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_IMAGE_ADDR, CONTEXT_IMAGE_SIZE))),
+            image_base: 0,
             heap: Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_HEAP_ADDR, CONTEXT_HEAP_SIZE))),
             mmap: Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_MMAP_ADDR, CONTEXT_MMAP_SIZE))),
-            env_vars: Arc::new(UnsafeCell::new(Vec::new())),
+            sig_actions: Arc::new(UnsafeCell::new([SigAction::default(); NSIG])),
+            env_vars: Arc::new(Vec::new()),
 
             cwd: Arc::new(UnsafeCell::new(String::new())),
             files: Arc::new(UnsafeCell::new(Vec::new())),
@@ -752,32 +1313,57 @@ impl Context {
 
         let fx = kernel_stack + CONTEXT_STACK_SIZE;
 
+        let pid = Context::next_pid();
+
         let mut ret = box Context {
-            pid: Context::next_pid(),
+            pid: pid,
             ppid: 0,
+            pgid: pid,
+            sid: pid,
+            uid: 0,
+            euid: 0,
+            gid: 0,
+            egid: 0,
             name: name,
             iopl: 3,
             blocked: 0,
             exited: false,
+            stopped: false,
+            waiting: false,
             switch: 0,
             time: 0,
+            user_time: Duration::new(0, 0),
+            system_time: Duration::new(0, 0),
+            nice: 0,
+            cpu_usage: 0,
             vfork: None,
+            futex: None,
             wake: None,
+            cpu: None,
 
             supervised: false,
-            blocked_syscall: false,
+            supervisor: None,
+            supervisor_stop: None,
+            supervisor_regs: ptr::null_mut(),
+            supervisor_result: None,
             current_syscall: None,
 
+            sig_pending: 0,
+            sig_mask: 0,
+
             kernel_stack: kernel_stack,
             regs: regs,
             fx: fx,
             stack: None,
+            stack_limit: CONTEXT_STACK_LIMIT,
             loadable: false,
 
             image: Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_IMAGE_ADDR, CONTEXT_IMAGE_SIZE))),
+            image_base: 0,
             heap: Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_HEAP_ADDR, CONTEXT_HEAP_SIZE))),
             mmap: Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_MMAP_ADDR, CONTEXT_MMAP_SIZE))),
-            env_vars: Arc::new(UnsafeCell::new(Vec::new())),
+            sig_actions: Arc::new(UnsafeCell::new([SigAction::default(); NSIG])),
+            env_vars: Arc::new(Vec::new()),
 
             cwd: Arc::new(UnsafeCell::new(String::new())),
             files: Arc::new(UnsafeCell::new(Vec::new())),
@@ -815,6 +1401,30 @@ impl Context {
         ret
     }
 
+    /// Get the priority used by the scheduler, a lower value being a higher priority
+    ///
+    /// Contexts that use little of the processor, such as interactive contexts which are mostly
+    /// blocked waiting for events, are favored over contexts that are busy. Idle contexts only
+    /// run when nothing else can.
+    pub fn priority(&self) -> isize {
+        if self.cpu.is_some() {
+            isize::MAX - 1
+        } else {
+            (self.cpu_usage / 8) as isize + self.nice * 2
+        }
+    }
+
+    /// Account a time slice used by the context, in userspace or in the kernel
+    pub fn tick(&mut self, user: bool, duration: Duration) {
+        self.time += 1;
+        if user {
+            self.user_time = self.user_time + duration;
+        } else {
+            self.system_time = self.system_time + duration;
+        }
+        self.cpu_usage = cmp::min(self.cpu_usage + 1, CPU_USAGE_MAX);
+    }
+
     pub fn block(&mut self, _reason: &str) {
         self.blocked += 1;
         // debugln!("    BLOCK {}: {}: {} {}", self.pid, self.name, self.blocked, reason);
@@ -827,6 +1437,93 @@ impl Context {
         }
     }
 
+    /// Get the action for a signal
+    pub fn sig_action(&self, sig: usize) -> SigAction {
+        unsafe { (*self.sig_actions.get())[sig] }
+    }
+
+    /// Check if a signal would be ignored if it was delivered now
+    pub fn sig_ignored(&self, sig: usize) -> bool {
+        match self.sig_action(sig).sa_handler {
+            SIG_DFL => match sig {
+                SIGCHLD | SIGCONT | SIGURG | SIGWINCH => true,
+                _ => false,
+            },
+            SIG_IGN => sig != SIGKILL,
+            _ => false,
+        }
+    }
+
+    /// Check if a signal would stop the context if it was delivered now
+    pub fn sig_stops(&self, sig: usize) -> bool {
+        SIG_STOP & 1 << sig == 1 << sig &&
+        (sig == SIGSTOP || (self.sig_mask & 1 << sig == 0 && self.sig_action(sig).sa_handler == SIG_DFL))
+    }
+
+    /// Check if a signal would terminate the context if it was delivered now
+    pub fn sig_fatal(&self, sig: usize) -> bool {
+        sig == SIGKILL || (self.sig_mask & 1 << sig == 0 && self.sig_action(sig).sa_handler == SIG_DFL && ! self.sig_ignored(sig) && ! self.sig_stops(sig))
+    }
+
+    /// Get the first pending signal that will terminate the context, if any
+    pub fn fatal_signal(&self) -> Option<usize> {
+        for sig in 1..NSIG {
+            if self.sig_pending & 1 << sig == 1 << sig && self.sig_fatal(sig) {
+                return Some(sig);
+            }
+        }
+        None
+    }
+
+    /// Get the first pending signal that will stop the context, if any
+    pub fn stop_signal(&self) -> Option<usize> {
+        for sig in 1..NSIG {
+            if self.sig_pending & 1 << sig == 1 << sig && self.sig_stops(sig) {
+                return Some(sig);
+            }
+        }
+        None
+    }
+
+    /// Send a signal to this context
+    ///
+    /// Signals are delivered when the context returns to userspace. If the signal would
+    /// terminate the context, it is woken up so that it does not stay blocked forever. A signal
+    /// that would stop the context, or any other signal that is not masked, interrupts a
+    /// `WaitCondition` it is blocked in, so that the system call can return `EINTR`.
+    ///
+    /// `SIGCONT` continues a stopped context, even if it is ignored, and discards pending stops.
+    /// A stop discards a pending `SIGCONT` in turn.
+    pub fn signal(&mut self, sig: usize) {
+        if sig == SIGCONT {
+            self.sig_pending &= !SIG_STOP;
+            if self.stopped {
+                self.stopped = false;
+                self.unblock("Context::signal SIGCONT");
+            }
+        } else if SIG_STOP & 1 << sig == 1 << sig {
+            self.sig_pending &= !(1 << SIGCONT);
+        }
+
+        if sig > 0 && sig < NSIG && ! self.sig_ignored(sig) {
+            self.sig_pending |= 1 << sig;
+
+            if self.sig_fatal(sig) {
+                self.blocked = 0;
+                self.wake = None;
+            } else if self.waiting && (self.sig_stops(sig) || self.sig_mask & 1 << sig == 0) {
+                self.blocked = 0;
+            }
+        }
+    }
+
+    /// Send an event to the supervisor of this context, if one is attached
+    pub fn supervisor_event(&self, event: SupervisorEvent) {
+        if let Some(ref supervisor) = self.supervisor {
+            unsafe { (*supervisor.get()).events.push_back(event) };
+        }
+    }
+
     pub fn exit(&mut self) {
         // debugln!("    EXIT {}: {}", self.pid, self.name);
         self.files = Arc::new(UnsafeCell::new(Vec::new()));
@@ -937,6 +1634,8 @@ impl Context {
 
     /// Check permission of segment, if inside of mapped memory
     pub fn permission(&self, ptr: usize, len: usize, writeable: bool) -> Result<()> {
+        self.grow_stack_to(ptr, len);
+
         if let Some(ref stack) = self.stack {
             if ptr >= stack.virtual_address && ptr + len <= stack.virtual_address + stack.virtual_size {
                 return Ok(());
@@ -958,8 +1657,114 @@ impl Context {
         Err(Error::new(EFAULT))
     }
 
+    /// Check if `address` is below the user stack, where a fault grows the stack
+    ///
+    /// Returns `Some(true)` if the stack can grow down to the address, `Some(false)` if it is past
+    /// the stack limit, and `None` if the address is not below the stack.
+    pub fn stack_fault(&self, address: usize) -> Option<bool> {
+        if let Some(ref stack) = self.stack {
+            let top = stack.virtual_address + stack.virtual_size;
+            if address < stack.virtual_address && address >= top - CONTEXT_STACK_LIMIT_MAX - CONTEXT_STACK_GUARD {
+                return Some(address >= top - cmp::max(self.stack_limit, stack.virtual_size));
+            }
+        }
+
+        None
+    }
+
+    /// Grow the user stack down to the page containing `address`
+    ///
+    /// The stack at least doubles in size, so that a deep recursion does not fault on every page,
+    /// but never grows past the stack limit.
+    pub unsafe fn grow_stack(&mut self, address: usize) -> Result<()> {
+        let stack_limit = self.stack_limit;
+        if let Some(ref mut stack) = self.stack {
+            let top = stack.virtual_address + stack.virtual_size;
+            let bottom = cmp::max(cmp::min(address / 4096 * 4096, top - 2 * stack.virtual_size),
+                                  top - cmp::max(stack_limit, stack.virtual_size));
+            if bottom >= stack.virtual_address {
+                return Ok(());
+            }
+
+            let grown = stack.virtual_address - bottom;
+            let physical_address = memory::alloc_aligned(top - bottom, 4096);
+            if physical_address == 0 {
+                return Err(Error::new(ENOMEM));
+            }
+
+            ::memset(physical_address as *mut u8, 0, grown);
+            ::memcpy((physical_address + grown) as *mut u8,
+                     stack.physical_address as *const u8,
+                     stack.virtual_size);
+
+            stack.unmap();
+            *stack = ContextMemory {
+                physical_address: physical_address,
+                virtual_address: bottom,
+                virtual_size: top - bottom,
+                writeable: true,
+                allocated: true,
+                shared: None,
+                borrowed: None,
+            };
+            stack.map();
+
+            Ok(())
+        } else {
+            Err(Error::new(EFAULT))
+        }
+    }
+
+    /// Grow the user stack of the current context if the segment is below it, within the stack limit
+    ///
+    /// The kernel does not fault when it accesses memory of userspace, so the stack is grown here
+    /// like the page fault handler would do it.
+    fn grow_stack_to(&self, ptr: usize, len: usize) {
+        let growable = match self.stack {
+            Some(ref stack) => ptr + len <= stack.virtual_address + stack.virtual_size &&
+                               self.stack_fault(ptr) == Some(true),
+            None => false
+        };
+
+        if growable {
+            let contexts = unsafe { &mut *::env().contexts.get() };
+            if let Ok(current) = contexts.current_mut() {
+                // Another context does not have its stack mapped
+                if &**current as *const Context == self as *const Context {
+                    let _ = unsafe { current.grow_stack(ptr) };
+                }
+            }
+        }
+    }
+
+    /// Copy the page containing `address` if it is shared copy-on-write
+    /// Returns false if the address is not in such a page.
+    pub unsafe fn copy_on_write(&mut self, address: usize) -> bool {
+        (*self.image.get()).copy_on_write(address) ||
+        (*self.heap.get()).copy_on_write(address) ||
+        (*self.mmap.get()).copy_on_write(address)
+    }
+
     /// Translate to physical if a ptr is inside of the mapped memory
+    ///
+    /// The memory is made private first, as it may be written to using the physical address.
     pub fn translate(&self, ptr: usize, len: usize) -> Result<usize> {
+        unsafe {
+            let changed = (*self.image.get()).unshare(ptr, len) |
+                          (*self.heap.get()).unshare(ptr, len) |
+                          (*self.mmap.get()).unshare(ptr, len);
+
+            // Memory of other address spaces is mapped when switching to them
+            let contexts = &*::env().contexts.get();
+            if changed && contexts.current().map(|current| current.heap.get() == self.heap.get()).unwrap_or(false) {
+                (*self.image.get()).map();
+                (*self.heap.get()).map();
+                (*self.mmap.get()).map();
+            }
+        }
+
+        self.grow_stack_to(ptr, len);
+
         if let Some(ref stack) = self.stack {
             if ptr >= stack.virtual_address && ptr + len <= stack.virtual_address + stack.virtual_size {
                 return Ok(ptr - stack.virtual_address + stack.physical_address);
@@ -981,48 +1786,33 @@ impl Context {
         Err(Error::new(EFAULT))
     }
 
-    /// Gets an environment variable. Returns `Err` if the variable is not
-    /// defined
-    pub fn get_env_var(&self, var_name: &str) -> Result<&str> {
-        for variable in unsafe { (*self.env_vars.get()).iter() } {
-            if variable.name() == var_name {
-                return Ok(variable.value());
-            }
-        }
-        Err(Error::new(ENOENT))
-    }
-
-    /// Sets an environment variable. Returns `Err` if the variable name contains the `=`
-    /// character
-    pub fn set_env_var(&mut self, name: &str, value: &str) -> Result<()> {
-        if name.contains('=') {
-            return Err(Error::new(EINVAL));
-        }
-
-        for mut variable in unsafe { (*self.env_vars.get()).iter_mut() } {
-            if variable.name() == name {
-                variable.0 = String::from(value);
-                return Ok(());
+    /// Translate memory of this context for another context to map, returning the physical
+    /// address and the reference keeping the memory alive while it is mapped
+    ///
+    /// The stack is moved when it grows, so it can not be mapped by other contexts.
+    pub fn lend(&self, ptr: usize, len: usize) -> Result<(usize, Option<Arc<SharedMemory>>)> {
+        let physical_address = try!(self.translate(ptr, len));
+
+        for zone in [self.image.get(), self.heap.get(), self.mmap.get()].iter() {
+            for mem in unsafe { (**zone).memory.iter_mut() } {
+                if ptr >= mem.virtual_address && ptr + len <= mem.virtual_address + mem.virtual_size {
+                    return Ok((physical_address, mem.lend()));
+                }
             }
         }
-        unsafe { (*self.env_vars.get()).push(EnvVar(String::from(name), String::from(value))) };
-        Ok(())
-    }
 
-    /// Returns a slice of the environment variables
-    pub fn list_env_vars(&self) -> &[EnvVar] {
-        unsafe { &*self.env_vars.get() }
+        Err(Error::new(EFAULT))
     }
 
-    /// Removes the environment variable named `name`. Returns `Err` if the variable doesn't exist
-    pub fn remove_env_var(&self, name: &str) -> Result<()> {
-        for (i, variable) in unsafe { (*self.env_vars.get()).iter().enumerate() } {
-            if variable.name() == name {
-                unsafe { (*self.env_vars.get()).remove(i) };
-                return Ok(());
+    /// Gets an environment variable given to exec. Returns `None` if the variable is not defined
+    pub fn get_env_var(&self, name: &str) -> Option<&str> {
+        for var in self.env_vars.iter() {
+            let mut parts = var.splitn(2, '=');
+            if parts.next() == Some(name) {
+                return parts.next();
             }
         }
-        Err(Error::new(ENOENT))
+        None
     }
 
     pub unsafe fn map(&mut self) {
@@ -2,7 +2,7 @@
 
 use collections::{String, Vec};
 
-use core::{mem, ptr, str, slice};
+use core::{cmp, mem, ptr, str, slice};
 
 use common::slice::GetSlice;
 
@@ -16,6 +16,23 @@ mod arch;
 #[path="x86_64/elf.rs"]
 mod arch;
 
+/// A relocatable object
+pub const ET_REL: usize = 1;
+/// An executable with a fixed load address
+pub const ET_EXEC: usize = 2;
+/// A shared object, or a position independent executable
+pub const ET_DYN: usize = 3;
+
+/// A segment loaded into memory
+pub const PT_LOAD: usize = 1;
+/// A segment holding the path of the interpreter
+pub const PT_INTERP: usize = 3;
+/// A segment holding the program headers
+pub const PT_PHDR: usize = 6;
+
+/// A symbol of a function
+const STT_FUNC: u8 = 2;
+
 /// An ELF executable
 pub struct Elf<'a> {
     pub data: &'a [u8],
@@ -43,7 +60,7 @@ impl<'a> Elf<'a> {
         for i in 0..header.ph_len {
             let segment = ptr::read((self.data.as_ptr() as usize + header.ph_off as usize + i as usize * header.ph_ent_len as usize) as *const ElfSegment);
 
-            if segment._type == 1 {
+            if segment._type as usize == PT_LOAD {
                 segments.push(segment);
             }
         }
@@ -57,18 +74,85 @@ impl<'a> Elf<'a> {
         header.entry as usize
     }
 
-    /// ELF symbol
-    pub unsafe fn symbol(&self, name: &str) -> usize {
+    /// Get the object type of the header, like `ET_EXEC` or `ET_DYN`
+    pub unsafe fn object_type(&self) -> usize {
+        let header = &*(self.data.as_ptr() as usize as *const ElfHeader);
+        header._type as usize
+    }
+
+    /// Get the path of the interpreter, if the executable has to be started by one
+    pub unsafe fn interpreter(&self) -> Option<&'a str> {
+        let header = &*(self.data.as_ptr() as usize as *const ElfHeader);
+
+        for i in 0..header.ph_len {
+            let segment = ptr::read((self.data.as_ptr() as usize + header.ph_off as usize + i as usize * header.ph_ent_len as usize) as *const ElfSegment);
+
+            if segment._type as usize == PT_INTERP {
+                let start = segment.off as usize;
+                let end = start + segment.file_len as usize;
+                if end > self.data.len() {
+                    return None;
+                }
+
+                let path = self.data.get_slice(start .. end);
+                let len = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+                return str::from_utf8(path.get_slice(.. len)).ok();
+            }
+        }
+
+        None
+    }
+
+    /// Get the address of the program headers once loaded, their entry length and their number
+    ///
+    /// The address is zero if the program headers are not in a loaded segment.
+    pub unsafe fn program_headers(&self) -> (usize, usize, usize) {
         let header = &*(self.data.as_ptr() as usize as *const ElfHeader);
 
+        let mut address = 0;
+        for i in 0..header.ph_len {
+            let segment = ptr::read((self.data.as_ptr() as usize + header.ph_off as usize + i as usize * header.ph_ent_len as usize) as *const ElfSegment);
+
+            // The PHDR segment gives the address directly, otherwise find the segment containing them
+            if segment._type as usize == PT_PHDR {
+                address = segment.vaddr as usize;
+                break;
+            } else if segment._type as usize == PT_LOAD && header.ph_off >= segment.off && header.ph_off < segment.off + segment.file_len {
+                address = (segment.vaddr + header.ph_off - segment.off) as usize;
+            }
+        }
+
+        (address, header.ph_ent_len as usize, header.ph_len as usize)
+    }
+
+    /// Get a string of a string table, which ends at a zero byte or at the end of the table
+    unsafe fn string(&self, str_section: &ElfSection, offset: usize) -> &'a str {
+        let start = str_section.off as usize + offset;
+        let end = cmp::min(str_section.off as usize + str_section.len as usize, self.data.len());
+        if start >= end {
+            return "";
+        }
+
+        let data = self.data.get_slice(start .. end);
+        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        str::from_utf8_unchecked(data.get_slice(.. len))
+    }
+
+    /// Get the symbol table and its string table, if the executable has them
+    unsafe fn symbol_tables(&self) -> Option<(&'a ElfSection, &'a ElfSection)> {
+        let header = &*(self.data.as_ptr() as usize as *const ElfHeader);
+
+        if header.sh_len == 0 || header.sh_off as usize + header.sh_len as usize * header.sh_ent_len as usize > self.data.len() {
+            return None;
+        }
+
         let sh_str_section =
             &*((self.data.as_ptr() as usize + header.sh_off as usize +
                 header.sh_str_index as usize *
                 header.sh_ent_len as usize) as *const ElfSection);
 
-        let mut sym_section = &*((self.data.as_ptr() as usize + header.sh_off as usize) as *const ElfSection);
-
-        let mut str_section = &*((self.data.as_ptr() as usize + header.sh_off as usize) as *const ElfSection);
+        let mut sym_section = None;
+        let mut str_section = None;
 
         for i in 0..header.sh_len {
             let section =
@@ -76,50 +160,40 @@ impl<'a> Elf<'a> {
                     i as usize *
                     header.sh_ent_len as usize) as *const ElfSection);
 
-            let section_name_ptr =
-                (self.data.as_ptr() as usize + sh_str_section.off as usize + section.name as usize) as *const u8;
-            let mut section_name_len = 0;
-            for j in 0..4096 {
-                section_name_len = j;
-                if ptr::read(section_name_ptr.offset(j)) == 0 {
-                    break;
-                }
-            }
-            let section_name =
-                str::from_utf8_unchecked(slice::from_raw_parts(section_name_ptr,
-                                                               section_name_len as usize));
-
+            let section_name = self.string(sh_str_section, section.name as usize);
             if section_name == ".symtab" {
-                sym_section = section;
+                sym_section = Some(section);
             } else if section_name == ".strtab" {
-                str_section = section;
+                str_section = Some(section);
             }
         }
 
-        if sym_section.off > 0 && str_section.off > 0 {
-            if sym_section.ent_len > 0 {
-                let len = sym_section.len / sym_section.ent_len;
-                for i in 0..len {
-                    let symbol = &*((self.data.as_ptr() as usize + sym_section.off as usize + i as usize * sym_section.ent_len as usize) as *const ElfSymbol);
-
-                    let symbol_name_ptr =
-                        (self.data.as_ptr() as usize + str_section.off as usize +
-                         symbol.name as usize) as *const u8;
-                    let mut symbol_name_len = 0;
-                    for j in 0..4096 {
-                        symbol_name_len = j;
-                        if ptr::read(symbol_name_ptr.offset(j)) == 0 {
-                            break;
-                        }
-                    }
-                    let symbol_name = str::from_utf8_unchecked(slice::from_raw_parts(symbol_name_ptr, symbol_name_len as usize));
-
-                    if name == symbol_name {
-                        return symbol.value as usize;
-                    }
+        match (sym_section, str_section) {
+            (Some(sym_section), Some(str_section)) if sym_section.off as usize + sym_section.len as usize <= self.data.len() => {
+                Some((sym_section, str_section))
+            },
+            _ => None
+        }
+    }
+
+    /// Get the symbols of the symbol table
+    unsafe fn symbols(&self, sym_section: &ElfSection) -> &'a [ElfSymbol] {
+        if sym_section.ent_len as usize != mem::size_of::<ElfSymbol>() {
+            debug!("Invalid sym_section ent len\n");
+            return &[];
+        }
+
+        slice::from_raw_parts((self.data.as_ptr() as usize + sym_section.off as usize) as *const ElfSymbol,
+                              sym_section.len as usize / mem::size_of::<ElfSymbol>())
+    }
+
+    /// ELF symbol
+    pub unsafe fn symbol(&self, name: &str) -> usize {
+        if let Some((sym_section, str_section)) = self.symbol_tables() {
+            for symbol in self.symbols(sym_section).iter() {
+                if name == self.string(str_section, symbol.name as usize) {
+                    return symbol.value as usize;
                 }
-            } else {
-                debug!("No sym_section ent len\n");
             }
         } else {
             debug!("No sym_section or str_section\n");
@@ -127,4 +201,19 @@ impl<'a> Elf<'a> {
 
         0
     }
+
+    /// Find the function containing an address, as its name and the offset of the address in it
+    pub unsafe fn symbol_at(&self, address: usize) -> Option<(&'a str, usize)> {
+        if let Some((sym_section, str_section)) = self.symbol_tables() {
+            for symbol in self.symbols(sym_section).iter() {
+                let start = symbol.value as usize;
+                let end = start + cmp::max(symbol.size as usize, 1);
+                if symbol.info & 0xF == STT_FUNC && address >= start && address < end {
+                    return Some((self.string(str_section, symbol.name as usize), address - start));
+                }
+            }
+        }
+
+        None
+    }
 }
@@ -4,6 +4,8 @@ use core::{cmp, intrinsics, mem};
 use core::ops::{Index, IndexMut};
 use core::{ptr, slice};
 
+use sync::TicketLock;
+
 use system::error::{Result, Error, ENOMEM};
 
 use super::paging::{Page, PAGE_END};
@@ -185,6 +187,9 @@ struct MemoryMapEntry {
 
 const MEMORY_MAP: *const MemoryMapEntry = 0x500 as *const MemoryMapEntry;
 
+/// The lock of the cluster table, so that processors can allocate without the kernel lock
+static LOCK: TicketLock = TicketLock::new();
+
 /// Get the data (address) of a given cluster
 pub unsafe fn cluster(number: usize) -> usize {
     if number < CLUSTER_COUNT {
@@ -245,6 +250,12 @@ pub unsafe fn alloc(size: usize) -> usize {
 
 /// Allocate memory, aligned
 pub unsafe fn alloc_aligned(size: usize, align: usize) -> usize {
+    let _lock = LOCK.lock();
+    alloc_aligned_locked(size, align)
+}
+
+/// Allocate memory, aligned, with the lock of the cluster table held
+unsafe fn alloc_aligned_locked(size: usize, align: usize) -> usize {
     if size > 0 {
         let mut number = 0;
         let mut count = 0;
@@ -312,6 +323,12 @@ pub unsafe fn alloc_size(ptr: usize) -> usize {
 }
 
 pub unsafe fn unalloc(ptr: usize) {
+    let _lock = LOCK.lock();
+    unalloc_locked(ptr);
+}
+
+/// Free memory, with the lock of the cluster table held
+unsafe fn unalloc_locked(ptr: usize) {
     if ptr > 0 {
         for i in address_to_cluster(ptr)..CLUSTER_COUNT {
             if cluster(i) == ptr {
@@ -333,18 +350,20 @@ pub unsafe fn realloc(ptr: usize, size: usize) -> usize {
 }
 
 pub unsafe fn realloc_aligned(ptr: usize, size: usize, align: usize) -> usize {
+    let _lock = LOCK.lock();
+
     let mut ret = 0;
 
     if size == 0 {
         if ptr > 0 {
-            unalloc(ptr);
+            unalloc_locked(ptr);
         }
     } else {
         let old_size = alloc_size(ptr);
         if size <= old_size {
             ret = ptr;
         } else {
-            ret = alloc_aligned(size, align);
+            ret = alloc_aligned_locked(size, align);
             if ptr > 0 {
                 if ret > 0 {
                     let copy_size = cmp::min(old_size, size);
@@ -373,7 +392,7 @@ pub unsafe fn realloc_aligned(ptr: usize, size: usize, align: usize) -> usize {
                         read_page.flush();
                     }
                 }
-                unalloc(ptr);
+                unalloc_locked(ptr);
             }
         }
     }
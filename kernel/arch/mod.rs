@@ -1,6 +1,8 @@
+pub mod apic;
 pub mod context;
 pub mod elf;
 pub mod memory;
 pub mod paging;
 pub mod regs;
+pub mod smp;
 pub mod tss;
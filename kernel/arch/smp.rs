@@ -0,0 +1,340 @@
+//! Symmetric multiprocessing
+//!
+//! The boot processor starts the other processors listed in the MADT. All processors run contexts
+//! from the same `ContextManager`. A processor holds the kernel lock whenever it runs kernel code,
+//! except for the idle loop while it is halted, so it is taken when entering the kernel from
+//! userspace and released when returning to userspace.
+//!
+//! Some state has its own lock, so that it does not depend on the kernel lock:
+//!
+//! - the cluster table of the allocator, see `memory::alloc_aligned`
+//! - the list of contexts, see `context::CONTEXTS_LOCK`
+//! - the list of schemes, see `Environment::schemes_lock`
+//!
+//! Everything else is still only protected by the kernel lock: the state of each context, the
+//! resources and schemes themselves, the console, disks, network interfaces, futexes and logs.
+
+use acpi::MADT;
+
+use arch::apic::{self, LocalApic, ICR_ASSERT, ICR_INIT, ICR_STARTUP};
+use arch::context::{CONTEXT_IMAGE_ADDR, CONTEXT_STACK_END};
+use arch::memory;
+use arch::paging::Page;
+use arch::tss::Tss;
+
+use core::intrinsics::volatile_load;
+use core::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+use core::{mem, ptr};
+
+/// The maximum number of processors
+pub const MAX_CPUS: usize = 16;
+
+/// The copy of the trampoline made by the bootloader, see `startup-common.asm`
+const TRAMPOLINE: usize = 0x6000;
+const TRAMPOLINE_READY: usize = TRAMPOLINE + 8;
+const TRAMPOLINE_CPU: usize = TRAMPOLINE + 16;
+const TRAMPOLINE_PAGE_TABLE: usize = TRAMPOLINE + 24;
+const TRAMPOLINE_STACK: usize = TRAMPOLINE + 32;
+
+/// The size of the stack used by the idle loop of an application processor
+const AP_STACK_SIZE: usize = 65536;
+
+#[derive(Copy, Clone)]
+struct Cpu {
+    /// The ID of the local APIC, used to find the current processor
+    apic_id: u32,
+    /// The processor has finished starting and runs contexts
+    online: bool,
+    /// The task state segment, which holds the stack used for interrupts from userspace
+    tss: usize,
+    /// The global descriptor table, a copy with a descriptor for this task state segment
+    gdt: usize,
+}
+
+static mut CPUS: [Cpu; MAX_CPUS] = [Cpu {
+    apic_id: 0,
+    online: false,
+    tss: 0,
+    gdt: 0,
+}; MAX_CPUS];
+
+/// The number of processors that have been started, including the boot processor
+static mut CPU_COUNT: usize = 1;
+
+/// The global descriptor table of the boot processor, copied for the other processors
+static mut GDTR: DescriptorTablePointer = DescriptorTablePointer { limit: 0, base: 0 };
+static mut TSS_SELECTOR: u16 = 0;
+
+/// The kernel lock is a ticket lock, so that a processor waiting for it is not starved
+static LOCK_NEXT: AtomicUsize = ATOMIC_USIZE_INIT;
+static LOCK_SERVING: AtomicUsize = ATOMIC_USIZE_INIT;
+/// The processor holding the kernel lock, plus one, or zero if it is free
+static LOCK_OWNER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+#[repr(packed)]
+struct DescriptorTablePointer {
+    limit: u16,
+    base: usize,
+}
+
+/// Get the initial APIC ID of the current processor
+fn apic_id() -> u32 {
+    let _eax: u32;
+    let ebx: u32;
+    let _ecx: u32;
+    let _edx: u32;
+    unsafe {
+        asm!("cpuid"
+            : "={eax}"(_eax), "={ebx}"(ebx), "={ecx}"(_ecx), "={edx}"(_edx)
+            : "{eax}"(1), "{ecx}"(0)
+            :
+            : "intel", "volatile");
+    }
+    ebx >> 24
+}
+
+/// Get the number of the current processor, the boot processor is 0
+pub fn cpu_id() -> usize {
+    unsafe {
+        if CPU_COUNT > 1 {
+            let apic_id = apic_id();
+            for cpu in 1..CPU_COUNT {
+                if CPUS[cpu].apic_id == apic_id {
+                    return cpu;
+                }
+            }
+        }
+    }
+    0
+}
+
+/// Get the number of processors that are running contexts
+pub fn cpu_count() -> usize {
+    unsafe { CPUS[..CPU_COUNT].iter().filter(|cpu| cpu.online).count() }
+}
+
+/// Get the task state segment of the current processor
+pub fn tss() -> Option<&'static mut Tss> {
+    let tss = unsafe { CPUS[cpu_id()].tss };
+    if tss > 0 {
+        Some(unsafe { &mut *(tss as *mut Tss) })
+    } else {
+        None
+    }
+}
+
+/// Disable interrupts, returning true if they were enabled
+#[cfg(target_arch = "x86")]
+pub unsafe fn disable_interrupts() -> bool {
+    let flags: usize;
+    asm!("pushfd ; pop $0 ; cli" : "=r"(flags) : : "memory" : "intel", "volatile");
+    flags & 1 << 9 == 1 << 9
+}
+
+/// Disable interrupts, returning true if they were enabled
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn disable_interrupts() -> bool {
+    let flags: usize;
+    asm!("pushfq ; pop $0 ; cli" : "=r"(flags) : : "memory" : "intel", "volatile");
+    flags & 1 << 9 == 1 << 9
+}
+
+/// Enable interrupts again if `disable_interrupts` returned true
+pub unsafe fn restore_interrupts(enabled: bool) {
+    if enabled {
+        asm!("sti" : : : "memory" : "intel", "volatile");
+    }
+}
+
+/// Take the kernel lock
+///
+/// Returns false if the current processor already holds it, in which case it must not be released
+/// by the caller.
+pub fn lock() -> bool {
+    unsafe {
+        // The processor must not change while waiting
+        let enabled = disable_interrupts();
+
+        let owner = cpu_id() + 1;
+        let locked = if LOCK_OWNER.load(Ordering::SeqCst) == owner {
+            false
+        } else {
+            let ticket = LOCK_NEXT.fetch_add(1, Ordering::SeqCst);
+            while LOCK_SERVING.load(Ordering::SeqCst) != ticket {
+                asm!("pause" : : : "memory" : "intel", "volatile");
+            }
+            LOCK_OWNER.store(owner, Ordering::SeqCst);
+            true
+        };
+
+        restore_interrupts(enabled);
+
+        locked
+    }
+}
+
+/// Release the kernel lock
+///
+/// New userspace contexts return to this before returning to userspace, so it takes no arguments.
+pub extern "cdecl" fn unlock() {
+    LOCK_OWNER.store(0, Ordering::SeqCst);
+    LOCK_SERVING.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Let other processors waiting for the kernel lock take it
+///
+/// This is called by the scheduler, so that kernel contexts looping until an interrupt is handled
+/// do not keep the lock from the boot processor, which handles the legacy IRQs.
+pub unsafe fn relax() {
+    let enabled = disable_interrupts();
+
+    if LOCK_OWNER.load(Ordering::SeqCst) == cpu_id() + 1 &&
+       LOCK_NEXT.load(Ordering::SeqCst) != LOCK_SERVING.load(Ordering::SeqCst).wrapping_add(1) {
+        unlock();
+        lock();
+    }
+
+    restore_interrupts(enabled);
+}
+
+/// Register the boot processor, using the task state segment set up by the bootloader
+pub unsafe fn init(tss: usize) {
+    CPUS[0] = Cpu {
+        apic_id: apic_id(),
+        online: true,
+        tss: tss,
+        gdt: 0,
+    };
+}
+
+/// Start the application processors listed in the MADT, after `apic::init`
+pub unsafe fn start(madt: &MADT) {
+    let local_apic = match apic::local_apic() {
+        Some(local_apic) => local_apic,
+        None => return
+    };
+
+    let tr: usize;
+    asm!("sgdt [$1]
+        str $0"
+        : "=r"(tr)
+        : "r"(&mut GDTR)
+        : "memory"
+        : "intel", "volatile");
+    TSS_SELECTOR = tr as u16;
+
+    for entry in madt.local_apics.iter() {
+        // Skip disabled processors and the boot processor
+        if entry.flags & 1 == 0 || entry.id as u32 == CPUS[0].apic_id {
+            continue;
+        }
+
+        if CPU_COUNT >= MAX_CPUS {
+            debugln!("  * SMP: only {} processors are supported", MAX_CPUS);
+            break;
+        }
+
+        if ! start_cpu(&local_apic, entry.id as u32) {
+            debugln!("  * SMP: processor with APIC ID {} did not start", entry.id);
+        }
+    }
+
+    debugln!("  * SMP: {} processors", CPU_COUNT);
+}
+
+/// Start one application processor, returning false if it did not start
+unsafe fn start_cpu(local_apic: &LocalApic, apic_id: u32) -> bool {
+    let gdt_size = GDTR.limit as usize + 1;
+
+    let page_table = Page::init_cpu(CONTEXT_IMAGE_ADDR, CONTEXT_STACK_END);
+    let stack = memory::alloc(AP_STACK_SIZE);
+    let tss = memory::alloc_type::<Tss>() as usize;
+    let gdt = memory::alloc(gdt_size);
+    if page_table == 0 || stack == 0 || tss == 0 || gdt == 0 {
+        memory::unalloc(gdt);
+        memory::unalloc(tss);
+        memory::unalloc(stack);
+        return false;
+    }
+
+    // The task state segment starts as a copy of the one of the boot processor
+    ::memcpy(tss as *mut u8, CPUS[0].tss as *const u8, mem::size_of::<Tss>());
+    (*(tss as *mut Tss)).sp0 = stack + AP_STACK_SIZE - 128;
+
+    ::memcpy(gdt as *mut u8, GDTR.base as *const u8, gdt_size);
+    {
+        let descriptor = (gdt + (TSS_SELECTOR as usize & !7)) as *mut u8;
+        *descriptor.offset(2) = tss as u8;
+        *descriptor.offset(3) = (tss >> 8) as u8;
+        *descriptor.offset(4) = (tss >> 16) as u8;
+        *descriptor.offset(7) = (tss >> 24) as u8;
+        // The descriptor of the boot processor is marked busy
+        *descriptor.offset(5) &= !2;
+        // Long mode descriptors of task state segments are 16 bytes
+        if mem::size_of::<usize>() == 8 {
+            ptr::write(descriptor.offset(8) as *mut u32, ((tss as u64) >> 32) as u32);
+        }
+    }
+
+    let cpu = CPU_COUNT;
+    CPUS[cpu] = Cpu {
+        apic_id: apic_id,
+        online: false,
+        tss: tss,
+        gdt: gdt,
+    };
+    CPU_COUNT += 1;
+
+    ptr::write(TRAMPOLINE_READY as *mut usize, 0);
+    ptr::write(TRAMPOLINE_CPU as *mut usize, cpu);
+    ptr::write(TRAMPOLINE_PAGE_TABLE as *mut usize, page_table);
+    ptr::write(TRAMPOLINE_STACK as *mut usize, stack + AP_STACK_SIZE - 128);
+
+    // INIT, then a startup IPI, which is repeated once as the first one may be missed
+    local_apic.ipi(apic_id, ICR_INIT | ICR_ASSERT);
+    apic::delay(10000);
+    for _ in 0..2 {
+        local_apic.ipi(apic_id, ICR_STARTUP | ICR_ASSERT | (TRAMPOLINE >> 12) as u32);
+        apic::delay(200);
+        if volatile_load(TRAMPOLINE_READY as *const usize) != 0 {
+            return true;
+        }
+    }
+
+    // Wait up to a second for a slow processor
+    for _ in 0..1000 {
+        if volatile_load(TRAMPOLINE_READY as *const usize) != 0 {
+            return true;
+        }
+        apic::delay(1000);
+    }
+
+    CPU_COUNT -= 1;
+    CPUS[cpu].apic_id = 0;
+    memory::unalloc(gdt);
+    memory::unalloc(tss);
+    memory::unalloc(stack);
+    false
+}
+
+/// Finish starting an application processor, called by the trampoline with the kernel lock held
+pub unsafe fn init_ap(cpu: usize) {
+    let gdtr = DescriptorTablePointer {
+        limit: GDTR.limit,
+        base: CPUS[cpu].gdt,
+    };
+    asm!("lgdt [$0]
+        ltr $1"
+        :
+        : "r"(&gdtr), "r"(TSS_SELECTOR)
+        : "memory"
+        : "intel", "volatile");
+
+    apic::init_ap();
+
+    (&mut *::env().contexts.get()).push_idle(cpu);
+
+    CPUS[cpu].online = true;
+
+    syslog_info!("SMP: processor {} is online", cpu);
+}
@@ -1,3 +1,5 @@
+use arch::regs::Regs;
+
 pub const ELF_CLASS: u8 = 1;
 pub type ElfAddr = u32;
 pub type ElfHalf = u16;
@@ -85,3 +87,18 @@ pub struct ElfSymbol {
     pub other: u8,
     pub sh_index: ElfHalf,
 }
+
+/// The machine of x86 ELF files
+pub const ELF_MACHINE: ElfHalf = 3;
+
+/// The number of registers in the `NT_PRSTATUS` note of a core dump
+pub const ELF_NGREG: usize = 17;
+
+/// The registers in the order of the `NT_PRSTATUS` note of a core dump
+///
+/// The data segment registers are not saved, they always equal `ss` in userspace.
+pub fn elf_gregset(regs: &Regs) -> [usize; ELF_NGREG] {
+    [regs.bx, regs.cx, regs.dx, regs.si, regs.di, regs.bp, regs.ax,
+     regs.ss, regs.ss, regs.ss, regs.ss, !0,
+     regs.ip, regs.cs, regs.flags, regs.sp, regs.ss]
+}
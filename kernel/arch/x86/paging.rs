@@ -236,6 +236,58 @@ impl Page {
             : "intel", "volatile");
     }
 
+    /// Create the page tables of another processor, returning the address of its page directory
+    ///
+    /// The kernel mappings are shared, but the tables covering `start` to `end` are private, so
+    /// that each processor can map the memory of the context it is running. The private tables are
+    /// mapped where the shared ones are, so `Page` manages the tables of the current processor.
+    /// Returns 0 if memory could not be allocated.
+    pub unsafe fn init_cpu(start: usize, end: usize) -> usize {
+        let table_span = PAGE_TABLE_SIZE * PAGE_SIZE;
+
+        let directory = memory::alloc_aligned(PAGE_SIZE, PAGE_SIZE);
+        if directory == 0 {
+            return 0;
+        }
+        ::memcpy(directory as *mut u8, PAGE_DIRECTORY as *const u8, PAGE_SIZE);
+
+        for table_i in start / table_span..(end + table_span - 1) / table_span {
+            let table = memory::alloc_aligned(PAGE_SIZE, PAGE_SIZE);
+            if table == 0 {
+                return 0;
+            }
+
+            // Identity map, like the shared table when no context is mapped
+            for entry_i in 0..PAGE_TABLE_SIZE {
+                let addr = (table_i * PAGE_TABLE_SIZE + entry_i) * PAGE_SIZE;
+                ptr::write((table + entry_i * PAGE_ENTRY_SIZE) as *mut usize,
+                           addr | PF_WRITE | PF_PRESENT);
+            }
+
+            ptr::write((directory + table_i * PAGE_ENTRY_SIZE) as *mut usize,
+                       table | PF_USER | PF_WRITE | PF_PRESENT);
+
+            // The table that maps the page tables also has to be private, to map this table
+            let window = PAGE_TABLES + table_i * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
+            let window_entry = (directory + (window / table_span) * PAGE_ENTRY_SIZE) as *mut usize;
+            let shared = PAGE_TABLES + (window / table_span) * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
+            if ptr::read(window_entry) & PF_NONE == shared {
+                let window_table = memory::alloc_aligned(PAGE_SIZE, PAGE_SIZE);
+                if window_table == 0 {
+                    return 0;
+                }
+                ::memcpy(window_table as *mut u8, shared as *const u8, PAGE_SIZE);
+                ptr::write(window_entry, window_table | PF_USER | PF_WRITE | PF_PRESENT);
+            }
+
+            ptr::write(((ptr::read(window_entry) & PF_NONE) +
+                        ((window / PAGE_SIZE) % PAGE_TABLE_SIZE) * PAGE_ENTRY_SIZE) as *mut usize,
+                       table | PF_WRITE | PF_PRESENT);
+        }
+
+        directory
+    }
+
     /// Create a new memory page from a virtual address
     pub fn new(virtual_address: usize) -> Self {
         Page { virtual_address: virtual_address }
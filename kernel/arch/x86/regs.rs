@@ -1,3 +1,4 @@
+/// The registers saved on an interrupt, the layout must match `system::syscall::Registers`
 #[derive(Copy, Clone, Debug, Default)]
 #[repr(packed)]
 pub struct Regs {
@@ -14,3 +15,74 @@ pub struct Regs {
     pub sp: usize,
     pub ss: usize,
 }
+
+/// The number of registers in the order of GDB
+pub const GDB_REGS: usize = 16;
+
+impl Regs {
+    /// Get the registers at the interrupt they were saved by
+    ///
+    /// The processor only pushes the stack pointer and the stack segment when the privilege level
+    /// changes, so in the kernel the stack pointer is where they would have been saved.
+    pub fn interrupted(&self) -> Regs {
+        let mut regs = *self;
+        if self.cs & 3 == 0 {
+            let ss: usize;
+            unsafe { asm!("mov $0, ss" : "=r"(ss) : : : "intel", "volatile") };
+            regs.sp = &self.sp as *const usize as usize;
+            regs.ss = ss;
+        }
+        regs
+    }
+
+    /// Set the registers at the interrupt they were saved by, see `interrupted`
+    ///
+    /// The segment registers are kept, as is the stack pointer in the kernel.
+    pub fn set_interrupted(&mut self, regs: Regs) {
+        let old = *self;
+        *self = regs;
+        self.cs = old.cs;
+        self.ss = old.ss;
+        if old.cs & 3 == 0 {
+            self.sp = old.sp;
+        }
+    }
+
+    /// Get a register by its number in GDB, with its size in bytes
+    ///
+    /// The data segment registers are not saved, they are reported as the stack segment.
+    pub fn gdb_reg(&self, n: usize) -> (usize, usize) {
+        let value = match n {
+            0 => self.ax,
+            1 => self.cx,
+            2 => self.dx,
+            3 => self.bx,
+            4 => self.sp,
+            5 => self.bp,
+            6 => self.si,
+            7 => self.di,
+            8 => self.ip,
+            9 => self.flags,
+            10 => self.cs,
+            _ => self.ss,
+        };
+        (value, 4)
+    }
+
+    /// Set a register by its number in GDB, the segment registers can not be set
+    pub fn set_gdb_reg(&mut self, n: usize, value: usize) {
+        match n {
+            0 => self.ax = value,
+            1 => self.cx = value,
+            2 => self.dx = value,
+            3 => self.bx = value,
+            4 => self.sp = value,
+            5 => self.bp = value,
+            6 => self.si = value,
+            7 => self.di = value,
+            8 => self.ip = value,
+            9 => self.flags = value,
+            _ => (),
+        }
+    }
+}
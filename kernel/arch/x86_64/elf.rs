@@ -1,3 +1,5 @@
+use arch::regs::Regs;
+
 pub const ELF_CLASS: u8 = 2;
 pub type ElfAddr = u64;
 pub type ElfOff = u64;
@@ -86,3 +88,19 @@ pub struct ElfSymbol {
     pub value: ElfAddr,
     pub size: ElfXword,
 }
+
+/// The machine of x86_64 ELF files
+pub const ELF_MACHINE: ElfHalf = 62;
+
+/// The number of registers in the `NT_PRSTATUS` note of a core dump
+pub const ELF_NGREG: usize = 27;
+
+/// The registers in the order of the `NT_PRSTATUS` note of a core dump
+///
+/// The data segment registers are not saved, they always equal `ss` in userspace.
+pub fn elf_gregset(regs: &Regs) -> [usize; ELF_NGREG] {
+    [regs.r15, regs.r14, regs.r13, regs.r12, regs.bp, regs.bx, regs.r11, regs.r10, regs.r9,
+     regs.r8, regs.ax, regs.cx, regs.dx, regs.si, regs.di, !0,
+     regs.ip, regs.cs, regs.flags, regs.sp, regs.ss, 0, 0,
+     regs.ss, regs.ss, regs.ss, regs.ss]
+}
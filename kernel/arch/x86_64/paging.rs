@@ -1,3 +1,5 @@
+use arch::memory;
+
 use core::ptr;
 
 //Page flags
@@ -93,6 +95,67 @@ impl Page {
             : "intel", "volatile");
     }
 
+    /// Create the page tables of another processor, returning the address of its level 4 table
+    ///
+    /// The kernel mappings are shared, but the tables covering `start` to `end` are private, so
+    /// that each processor can map the memory of the context it is running. The private tables are
+    /// mapped where the shared ones are, so `Page` manages the tables of the current processor.
+    /// Returns 0 if memory could not be allocated.
+    pub unsafe fn init_cpu(start: usize, end: usize) -> usize {
+        let table_span = PAGE_TABLE_SIZE * PAGE_SIZE;
+
+        // The level 4 table, the directory pointers and the four directories are contiguous
+        let level_4 = memory::alloc_aligned(6 * PAGE_SIZE, PAGE_SIZE);
+        if level_4 == 0 {
+            return 0;
+        }
+        ::memcpy(level_4 as *mut u8, PAGE_LEVEL_4 as *const u8, 6 * PAGE_SIZE);
+
+        let dir_ptrs = level_4 + PAGE_SIZE;
+        let directories = dir_ptrs + PAGE_SIZE;
+        ptr::write(level_4 as *mut usize, dir_ptrs | PF_USER | PF_WRITE | PF_PRESENT);
+        for dp_i in 0..4 {
+            ptr::write((dir_ptrs + dp_i * PAGE_ENTRY_SIZE) as *mut usize,
+                       (directories + dp_i * PAGE_SIZE) | PF_USER | PF_WRITE | PF_PRESENT);
+        }
+
+        for table_i in start / table_span..(end + table_span - 1) / table_span {
+            let table = memory::alloc_aligned(PAGE_SIZE, PAGE_SIZE);
+            if table == 0 {
+                return 0;
+            }
+
+            // Identity map, like the shared table when no context is mapped
+            for entry_i in 0..PAGE_TABLE_SIZE {
+                let addr = (table_i * PAGE_TABLE_SIZE + entry_i) * PAGE_SIZE;
+                ptr::write((table + entry_i * PAGE_ENTRY_SIZE) as *mut usize,
+                           addr | PF_WRITE | PF_PRESENT);
+            }
+
+            ptr::write((directories + table_i * PAGE_ENTRY_SIZE) as *mut usize,
+                       table | PF_USER | PF_WRITE | PF_PRESENT);
+
+            // The table that maps the page tables also has to be private, to map this table
+            let window = PAGE_TABLES + table_i * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
+            let window_entry = (directories + (window / table_span) * PAGE_ENTRY_SIZE) as *mut usize;
+            let shared = PAGE_TABLES + (window / table_span) * PAGE_TABLE_SIZE * PAGE_ENTRY_SIZE;
+            if ptr::read(window_entry) & PF_NONE == shared {
+                let window_table = memory::alloc_aligned(PAGE_SIZE, PAGE_SIZE);
+                if window_table == 0 {
+                    return 0;
+                }
+                ::memcpy(window_table as *mut u8, shared as *const u8, PAGE_SIZE);
+                ptr::write(window_entry, window_table | PF_USER | PF_WRITE | PF_PRESENT);
+            }
+
+            ptr::write(((ptr::read(window_entry) & PF_NONE) +
+                        ((window / PAGE_SIZE) % PAGE_TABLE_SIZE) * PAGE_ENTRY_SIZE) as *mut usize,
+                       table | PF_WRITE | PF_PRESENT);
+        }
+
+        level_4
+    }
+
     /// Create a new memory page from a virtual address
     pub fn new(virtual_address: usize) -> Self {
         Page { virtual_address: virtual_address }
@@ -108,7 +171,7 @@ impl Page {
     }
 
     /// Flush the memory page
-    unsafe fn flush(&self) {
+    pub unsafe fn flush(&self) {
         asm!("invlpg [$0]"
             :
             : "{rax}"(self.virtual_address)
@@ -116,6 +179,16 @@ impl Page {
             : "intel", "volatile");
     }
 
+    /// Get the current entry data
+    pub unsafe fn entry_data(&self) -> usize {
+        ptr::read(self.entry_address() as *mut usize)
+    }
+
+    /// Set the current entry data
+    pub unsafe fn set_entry_data(&mut self, data: usize) {
+        ptr::write(self.entry_address() as *mut usize, data)
+    }
+
     /// Get the current physical address
     pub fn phys_addr(&self) -> usize {
         unsafe { (ptr::read(self.entry_address() as *mut usize) & PF_NONE) as usize }
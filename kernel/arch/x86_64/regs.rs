@@ -1,3 +1,4 @@
+/// The registers saved on an interrupt, the layout must match `system::syscall::Registers`
 #[derive(Copy, Clone, Debug, Default)]
 #[repr(packed)]
 pub struct Regs {
@@ -22,3 +23,76 @@ pub struct Regs {
     pub sp: usize,
     pub ss: usize,
 }
+
+/// The number of registers in the order of GDB
+pub const GDB_REGS: usize = 24;
+
+impl Regs {
+    /// Get the registers at the interrupt they were saved by
+    ///
+    /// The processor always pushes the stack pointer and the stack segment in long mode.
+    pub fn interrupted(&self) -> Regs {
+        *self
+    }
+
+    /// Set the registers at the interrupt they were saved by, keeping the segment registers
+    pub fn set_interrupted(&mut self, regs: Regs) {
+        let old = *self;
+        *self = regs;
+        self.cs = old.cs;
+        self.ss = old.ss;
+    }
+
+    /// Get a register by its number in GDB, with its size in bytes
+    ///
+    /// The data segment registers are not saved, they are reported as the stack segment.
+    pub fn gdb_reg(&self, n: usize) -> (usize, usize) {
+        match n {
+            0 => (self.ax, 8),
+            1 => (self.bx, 8),
+            2 => (self.cx, 8),
+            3 => (self.dx, 8),
+            4 => (self.si, 8),
+            5 => (self.di, 8),
+            6 => (self.bp, 8),
+            7 => (self.sp, 8),
+            8 => (self.r8, 8),
+            9 => (self.r9, 8),
+            10 => (self.r10, 8),
+            11 => (self.r11, 8),
+            12 => (self.r12, 8),
+            13 => (self.r13, 8),
+            14 => (self.r14, 8),
+            15 => (self.r15, 8),
+            16 => (self.ip, 8),
+            17 => (self.flags, 4),
+            18 => (self.cs, 4),
+            _ => (self.ss, 4),
+        }
+    }
+
+    /// Set a register by its number in GDB, the segment registers can not be set
+    pub fn set_gdb_reg(&mut self, n: usize, value: usize) {
+        match n {
+            0 => self.ax = value,
+            1 => self.bx = value,
+            2 => self.cx = value,
+            3 => self.dx = value,
+            4 => self.si = value,
+            5 => self.di = value,
+            6 => self.bp = value,
+            7 => self.sp = value,
+            8 => self.r8 = value,
+            9 => self.r9 = value,
+            10 => self.r10 = value,
+            11 => self.r11 = value,
+            12 => self.r12 = value,
+            13 => self.r13 = value,
+            14 => self.r14 = value,
+            15 => self.r15 = value,
+            16 => self.ip = value,
+            17 => self.flags = value,
+            _ => (),
+        }
+    }
+}
@@ -11,16 +11,26 @@ USE32
 
 interrupts:
 .first:
-    mov [.entry], byte 0
+    push byte 0
+    push strict dword 0
     jmp dword .handle
 .second:
 %assign i 1
 %rep 255
-    mov [.entry], byte i
+    ; Exceptions that push an error code: 8, 10 to 14, 17 and 30
+    %if i == 8 || (i >= 10 && i <= 14) || i == 17 || i == 30
+        nop
+        nop
+    %else
+        push byte 0
+    %endif
+    push strict dword i
     jmp dword .handle
 %assign i i+1
 %endrep
 .handle:
+    ; The interrupt number and the error code are kept on the stack, so that processors do not
+    ; share any state while entering the kernel
     push ebp
     push esi
     push edi
@@ -29,8 +39,23 @@ interrupts:
     push ebx
     push eax
 
-    push esp
-    push dword [.entry]
+    ; Move the saved registers over the interrupt number and error code, so that the frame can be
+    ; returned from
+    mov eax, [esp + 28]
+    mov ebx, [esp + 32]
+%assign j 6
+%rep 7
+    mov ecx, [esp + j * 4]
+    mov [esp + j * 4 + 8], ecx
+%assign j j-1
+%endrep
+    add esp, 8
+
+    mov ecx, esp
+    push dword 0 ; Padding, to match the x86_64 stack layout
+    push ebx
+    push ecx
+    push eax
 
     mov eax, gdt.kernel_data
     mov ds, eax
@@ -46,7 +71,7 @@ interrupts:
     mov fs, eax
     mov gs, eax
 
-    add esp, 8 ; Skip interrupt code and reg pointer
+    add esp, 16 ; Skip interrupt code, reg pointer, error code and padding
 
     pop eax
     pop ebx
@@ -59,7 +84,6 @@ interrupts:
     iretd
 
 .handler: dd 0
-.entry: dd 0
 
 idtr:
     dw (idt.end - idt) + 1
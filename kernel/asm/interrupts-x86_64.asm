@@ -12,16 +12,26 @@ SECTION .text
 USE64
 interrupts:
 .first:
-	mov [.entry], byte 0
+	push byte 0
+	push strict qword 0
     jmp qword .handle
 .second:
 %assign i 1
 %rep 255
-	mov [.entry], byte i
+	; Exceptions that push an error code: 8, 10 to 14, 17 and 30
+	%if i == 8 || (i >= 10 && i <= 14) || i == 17 || i == 30
+		nop
+		nop
+	%else
+		push byte 0
+	%endif
+	push strict qword i
     jmp qword .handle
 %assign i i+1
 %endrep
 .handle:
+	; The interrupt number and the error code are kept on the stack, so that processors do not
+	; share any state while entering the kernel
 	push rbp
 	push r15
 	push r14
@@ -38,9 +48,22 @@ interrupts:
 	push rbx
 	push rax
 
+	; Move the saved registers over the interrupt number and error code, so that the frame can be
+	; returned from
+	mov rdi, [rsp + 120]
+	mov rdx, [rsp + 128]
+%assign j 14
+%rep 15
+	mov rcx, [rsp + j * 8]
+	mov [rsp + j * 8 + 16], rcx
+%assign j j-1
+%endrep
+	add rsp, 16
+
 	mov rsi, rsp
+	push qword 0 ; Padding, to keep the stack aligned
+	push rdx
 	push rsi
-	mov rdi, qword [.entry]
 	push rdi
 
     mov rax, gdt.kernel_data
@@ -57,7 +80,7 @@ interrupts:
     mov fs, rax
     mov gs, rax
 
-	add rsp, 16 ; Skip interrupt code and reg pointer
+	add rsp, 32 ; Skip interrupt code, reg pointer, error code and padding
 
 	pop rax
 	pop rbx
@@ -78,7 +101,6 @@ interrupts:
     iretq
 
 .handler: dq 0
-.entry: dq 0
 
 idtr:
     dw (idt.end - idt) + 1
@@ -79,8 +79,52 @@ finished_loading:
     call initialize.pit
     call initialize.pic
 
+    ; copy the trampoline for application processors below 1 MiB
+    xor ax, ax
+    mov ds, ax
+    mov es, ax
+    mov si, ap_trampoline
+    mov di, ap_trampoline_base
+    mov cx, ap_trampoline.end - ap_trampoline
+    cld
+    rep movsb
+
+    ; pass the boot flags to the kernel
+%ifdef GDB
+    mov dword [boot_flags], boot_flags_gdb
+%else
+    mov dword [boot_flags], 0
+%endif
+
     jmp startup_arch
 
+; The boot flags are read by the kernel, they follow the memory map
+boot_flags equ 0x5000
+; enable the GDB stub on the second serial port
+boot_flags_gdb equ 1
+
+; Application processors start in real mode at a page aligned address below 1 MiB, which is given
+; by the startup IPI. The kernel fills in the data of the copy before starting each processor, and
+; the processor sets ready once it no longer needs the copy.
+ap_trampoline_base equ 0x6000
+
+ap_trampoline:
+    jmp short .start
+    times 8 - ($ - ap_trampoline) nop
+.ready: dq 0
+.cpu: dq 0
+.page_table: dq 0
+.stack: dq 0
+.start:
+    jmp 0:startup_ap
+.end:
+
+; addresses of the data in the copy of the trampoline
+ap_ready equ ap_trampoline_base + ap_trampoline.ready - ap_trampoline
+ap_cpu equ ap_trampoline_base + ap_trampoline.cpu - ap_trampoline
+ap_page_table equ ap_trampoline_base + ap_trampoline.page_table - ap_trampoline
+ap_stack equ ap_trampoline_base + ap_trampoline.stack - ap_trampoline
+
 %include "asm/descriptor_flags.inc"
 %include "asm/gdt_entry.inc"
 %include "asm/unreal.asm"
@@ -39,6 +39,57 @@ protected_mode:
     hlt
     jmp .lp
 
+USE16
+startup_ap:
+    cli
+    xor ax, ax
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    mov sp, ap_trampoline_base + 0x1000
+
+    call initialize.fpu
+    call initialize.sse
+
+    ; load protected mode GDT and IDT
+    lgdt [gdtr]
+    lidt [idtr]
+    ; set protected mode bit of cr0
+    mov eax, cr0
+    or eax, 1
+    mov cr0, eax
+
+    ; far jump to load CS with 32 bit segment
+    jmp gdt.kernel_code:protected_mode_ap
+
+USE32
+protected_mode_ap:
+    ; load all the other segments with 32 bit data segments
+    mov eax, gdt.kernel_data
+    mov ds, eax
+    mov es, eax
+    mov fs, eax
+    mov gs, eax
+    mov ss, eax
+
+    ; enable paging with the page directory prepared for this processor
+    mov eax, [ap_page_table]
+    mov cr3, eax
+    mov eax, cr0
+    or eax, 1 << 31 | 1 << 16
+    mov cr0, eax
+
+    mov esp, [ap_stack]
+
+    ;rust init, the trampoline can be reused once the processor number is read
+    mov eax, [ap_cpu]
+    mov dword [ap_ready], 1
+    int 254
+.lp:
+    sti
+    hlt
+    jmp .lp
+
 gdtr:
     dw gdt.end + 1  ; size
     dd gdt          ; offset
@@ -85,6 +85,71 @@ long_mode:
     hlt
     jmp .lp
 
+USE16
+startup_ap:
+    cli
+    xor ax, ax
+    mov ds, ax
+    mov es, ax
+    mov ss, ax
+    mov sp, ap_trampoline_base + 0x1000
+
+    call initialize.fpu
+    call initialize.sse
+
+    ;use the identity mapped page tables of the boot processor to enter long mode
+    mov edi, 0x70000
+    mov cr3, edi
+
+    ;enable Page Address Extension and Page Size Extension
+    mov eax, cr4
+    or eax, 1 << 5 | 1 << 4
+    mov cr4, eax
+
+    ; load protected mode GDT
+    lgdt [gdtr]
+
+    mov ecx, 0xC0000080               ; Read from the EFER MSR.
+    rdmsr
+    or eax, 0x00000100                ; Set the Long-Mode-Enable bit.
+    wrmsr
+
+    ;enabling paging and protection simultaneously
+    mov ebx, cr0
+    or ebx, 0x80010001                ;Bit 31: Paging, Bit 16: Write Protect, Bit 0: Protected Mode
+    mov cr0, ebx
+
+    ; far jump to enable Long Mode and load CS with 64 bit segment
+    jmp gdt.kernel_code:long_mode_ap
+
+USE64
+long_mode_ap:
+    ; load all the other segments with 64 bit data segments
+    mov rax, gdt.kernel_data
+    mov ds, rax
+    mov es, rax
+    mov fs, rax
+    mov gs, rax
+    mov ss, rax
+
+    ; load long mode IDT
+    lidt [idtr]
+
+    ; switch to the page tables prepared for this processor
+    mov rax, [ap_page_table]
+    mov cr3, rax
+
+    mov rsp, [ap_stack]
+
+    ;rust init, the trampoline can be reused once the processor number is read
+    mov rax, [ap_cpu]
+    mov qword [ap_ready], 1
+    int 0xFE
+.lp:
+    sti
+    hlt
+    jmp .lp
+
     gdtr:
         dw gdt.end + 1  ; size
         dq gdt          ; offset
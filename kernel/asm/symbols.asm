@@ -0,0 +1,19 @@
+; The symbol table of the kernel, which is read by kernel/backtrace.rs
+;
+; build/symbols.gen is generated from a first link of the kernel, with one line per symbol:
+; symbol address, length of the name, "name"
+SECTION .symbols align=8
+
+%macro symbol 3
+%ifdef ARCH_x86_64
+    dq %1, %2
+    db %3
+    align 8, db 0
+%else
+    dd %1, %2
+    db %3
+    align 4, db 0
+%endif
+%endmacro
+
+%include "symbols.gen"
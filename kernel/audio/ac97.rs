@@ -195,7 +195,7 @@ impl KScheme for Ac97 {
         "audio"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> syscall::Result<Box<Resource>> {
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> syscall::Result<Box<Resource>> {
         Ok(box Ac97Resource {
             audio: self.audio,
             bus_master: self.bus_master,
@@ -217,7 +217,7 @@ impl Ac97 {
         let module = box Ac97 {
             audio: pci.read(0x10) as usize & 0xFFFFFFF0,
             bus_master: pci.read(0x14) as usize & 0xFFFFFFF0,
-            irq: pci.read(0x3C) as u8 & 0xF,
+            irq: pci.read(0x3C) as u8,
             bdl: memory::alloc(32 * mem::size_of::<Bd>()) as *mut Bd,
         };
 
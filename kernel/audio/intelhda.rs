@@ -164,7 +164,7 @@ impl KScheme for IntelHda {
         "hda"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> syscall::Result<Box<Resource>> {
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> syscall::Result<Box<Resource>> {
         Ok(box IntelHdaResource { base: self.base })
     }
 
@@ -182,7 +182,7 @@ impl IntelHda {
             pci: pci,
             base: base & 0xFFFFFFF0,
             memory_mapped: base & 1 == 0,
-            irq: pci.read(0x3C) as u8 & 0xF,
+            irq: pci.read(0x3C) as u8,
         };
         module.init();
         module
@@ -0,0 +1,247 @@
+//! Backtraces of the kernel and of userspace
+//!
+//! Stacks are unwound by following the frame pointers, which every function saves at the start of
+//! its frame, next to its return address.
+//!
+//! The kernel is linked twice. The symbols of the first link are written to `build/symbols.gen`,
+//! which is assembled into the `.symbols` section of the second link. The section comes after the
+//! code and the data, so their addresses are the same in both links. Every entry of the table is
+//! the address of a symbol, the length of its name and its name, padded to the width of a word.
+//!
+//! Userspace is described with the symbols of the executable of the current context, which is
+//! read again from its path.
+
+use arch::context::Context;
+use arch::elf::Elf;
+use arch::paging::{Page, PAGE_END, PAGE_ENTRY_SIZE, PAGE_SIZE, PAGE_TABLES, PF_PRESENT, PF_USER};
+
+use collections::{String, Vec};
+use collections::string::ToString;
+
+use core::{mem, ptr, slice, str};
+
+use fs::Url;
+
+use syscall::execute::read_executable;
+
+extern {
+    /// The starting byte of the text (code) data segment.
+    static mut __text_start: u8;
+    /// The ending byte of the text (code) data segment.
+    static mut __text_end: u8;
+    /// The starting byte of the symbol table.
+    static mut __symbols_start: u8;
+    /// The ending byte of the symbol table.
+    static mut __symbols_end: u8;
+}
+
+/// The most frames of a backtrace
+const FRAMES_MAX: usize = 64;
+
+/// Find the symbol of the kernel containing an address, as its name and the offset of the address
+pub fn kernel_symbol(address: usize) -> Option<(&'static str, usize)> {
+    let (text_start, text_end, mut entry, end) = unsafe {
+        (& __text_start as *const u8 as usize, & __text_end as *const u8 as usize,
+         & __symbols_start as *const u8 as usize, & __symbols_end as *const u8 as usize)
+    };
+    if address < text_start || address >= text_end {
+        return None;
+    }
+
+    let word = mem::size_of::<usize>();
+    let mut symbol = None;
+    while entry + 2 * word <= end {
+        let symbol_address = unsafe { ptr::read(entry as *const usize) };
+        let len = unsafe { ptr::read((entry + word) as *const usize) };
+        if symbol_address > address || entry + 2 * word + len > end {
+            break;
+        }
+
+        let name = unsafe { str::from_utf8_unchecked(slice::from_raw_parts((entry + 2 * word) as *const u8, len)) };
+        symbol = Some((name, address - symbol_address));
+
+        entry += 2 * word + (len + word - 1) / word * word;
+    }
+
+    symbol
+}
+
+/// Demangle a symbol of Rust, like `_ZN4core3fmt5write17h0123456789abcdefE`
+///
+/// Other symbols are returned as they are. The hash at the end of the path is left out.
+pub fn demangle(symbol: &str) -> String {
+    if !symbol.starts_with("_ZN") || !symbol.ends_with('E') {
+        return symbol.to_string();
+    }
+
+    let mut parts = Vec::new();
+    let mut rest = &symbol[3 .. symbol.len() - 1];
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_digit(10)).unwrap_or(rest.len());
+        let len = match rest[.. digits].parse::<usize>() {
+            Ok(len) if digits + len <= rest.len() => len,
+            _ => return symbol.to_string(),
+        };
+        parts.push(&rest[digits .. digits + len]);
+        rest = &rest[digits + len ..];
+    }
+
+    if parts.last().map_or(false, |part| part.len() == 17 && part.starts_with('h')) {
+        parts.pop();
+    }
+
+    let mut name = String::new();
+    for part in parts.iter() {
+        if !name.is_empty() {
+            name.push_str("::");
+        }
+
+        let mut part = *part;
+        if part.starts_with("_$") {
+            part = &part[1 ..];
+        }
+        name.push_str(&part.replace("..", "::")
+                           .replace("$LT$", "<")
+                           .replace("$GT$", ">")
+                           .replace("$LP$", "(")
+                           .replace("$RP$", ")")
+                           .replace("$RF$", "&")
+                           .replace("$BP$", "*")
+                           .replace("$C$", ",")
+                           .replace("$u20$", " ")
+                           .replace("$u27$", "'")
+                           .replace("$u5b$", "[")
+                           .replace("$u5d$", "]")
+                           .replace("$u7b$", "{")
+                           .replace("$u7d$", "}")
+                           .replace("$u7e$", "~"));
+    }
+    name
+}
+
+/// The return addresses of a stack, found by following the frame pointers
+pub struct Frames<'a> {
+    /// The context of the stack, or `None` for a stack of the kernel
+    context: Option<&'a Context>,
+    /// The frame pointer of the next frame
+    bp: usize,
+    /// The number of frames so far
+    count: usize,
+}
+
+impl<'a> Frames<'a> {
+    /// Unwind a stack, starting at the frame pointer `bp`
+    pub fn new(bp: usize, context: Option<&'a Context>) -> Frames<'a> {
+        Frames {
+            context: context,
+            bp: bp,
+            count: 0,
+        }
+    }
+
+    /// Read a word of the stack, if it is mapped
+    fn read(&self, address: usize) -> Option<usize> {
+        let word = mem::size_of::<usize>();
+        if address % word != 0 {
+            return None;
+        }
+
+        if let Some(context) = self.context {
+            return context.translate(address, word).ok().map(|physical_address| unsafe { ptr::read(physical_address as *const usize) });
+        }
+
+        if address / PAGE_SIZE < (PAGE_END - PAGE_TABLES) / PAGE_ENTRY_SIZE {
+            let entry = unsafe { Page::new(address).entry_data() };
+            if entry & PF_PRESENT == PF_PRESENT && entry & PF_USER == 0 {
+                return Some(unsafe { ptr::read(address as *const usize) });
+            }
+        }
+
+        None
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.bp == 0 || self.count >= FRAMES_MAX {
+            return None;
+        }
+
+        let bp = self.bp;
+        match (self.read(bp), self.read(bp + mem::size_of::<usize>())) {
+            (Some(next_bp), Some(ip)) if ip != 0 => {
+                // The stack grows down, so the frames of callers are above
+                self.bp = if next_bp > bp { next_bp } else { 0 };
+                self.count += 1;
+                Some(ip)
+            },
+            _ => {
+                self.bp = 0;
+                None
+            }
+        }
+    }
+}
+
+/// Get the frame pointer of the calling function
+#[cfg(target_arch = "x86")]
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let bp: usize;
+    unsafe { asm!("mov $0, ebp" : "=r"(bp) : : : "intel", "volatile") };
+    bp
+}
+
+/// Get the frame pointer of the calling function
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let bp: usize;
+    unsafe { asm!("mov $0, rbp" : "=r"(bp) : : : "intel", "volatile") };
+    bp
+}
+
+/// Describe an address with the symbol containing it
+fn describe(i: usize, ip: usize, symbol: Option<(String, usize)>) -> String {
+    match symbol {
+        Some((name, offset)) => format!("{:>2}: {:08X} {}+{:#X}", i, ip, name, offset),
+        None => format!("{:>2}: {:08X}", i, ip),
+    }
+}
+
+/// Describe the frames of a stack, starting with the code at `ip` and the frame pointer `bp`
+///
+/// If `user` is set, the stack belongs to userspace of the current context.
+pub fn trace(ip: usize, bp: usize, user: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if user {
+        let contexts = unsafe { & *::env().contexts.get() };
+        if let Ok(current) = contexts.current() {
+            let executable = Url::from_str(&current.name).and_then(|url| read_executable(current, url)).ok();
+            let elf = executable.as_ref().and_then(|data| Elf::from(data).ok());
+            let symbol = |address: usize| {
+                elf.as_ref().and_then(|elf| {
+                    unsafe { elf.symbol_at(address.wrapping_sub(current.image_base)) }
+                }).map(|(name, offset)| (demangle(name), offset))
+            };
+
+            lines.push(describe(0, ip, symbol(ip)));
+            for (i, ret) in Frames::new(bp, Some(&**current)).enumerate() {
+                // The return address follows the call, which may be the last instruction
+                lines.push(describe(i + 1, ret, symbol(ret - 1).map(|(name, offset)| (name, offset + 1))));
+            }
+        }
+    } else {
+        let symbol = |address: usize| kernel_symbol(address).map(|(name, offset)| (name.to_string(), offset));
+
+        lines.push(describe(0, ip, symbol(ip)));
+        for (i, ret) in Frames::new(bp, None).enumerate() {
+            lines.push(describe(i + 1, ret, symbol(ret - 1).map(|(name, offset)| (name, offset + 1))));
+        }
+    }
+
+    lines
+}
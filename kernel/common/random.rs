@@ -6,7 +6,7 @@ pub fn rand() -> usize {
         seed ^= seed << 12;
         seed ^= seed << 25;
         seed ^= seed << 27;
-        seed = seed * 82724793451 + 12345;
+        seed = seed.wrapping_mul(82724793451).wrapping_add(12345);
         seed as usize % ::core::usize::MAX
     }
 }
@@ -17,3 +17,73 @@ pub fn srand(s: usize) {
         seed = s as u64;
     }
 }
+
+/// Mix a value into the seed, without losing the entropy already in it
+pub fn add_entropy(value: u64) {
+    unsafe {
+        seed = (seed ^ value).rotate_left(23).wrapping_mul(0x9E3779B97F4A7C15);
+    }
+}
+
+/// Read the time stamp counter of the processor
+pub fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!("rdtsc"
+            : "={eax}"(low), "={edx}"(high)
+            :
+            :
+            : "intel", "volatile");
+    }
+    (high as u64) << 32 | low as u64
+}
+
+/// Read a random number generated by the processor, if it supports `RDRAND`
+fn rdrand() -> Option<u32> {
+    let ecx: u32;
+    unsafe {
+        asm!("cpuid"
+            : "={ecx}"(ecx)
+            : "{eax}"(1), "{ecx}"(0)
+            : "eax", "ebx", "edx"
+            : "intel", "volatile");
+    }
+
+    if ecx & 1 << 30 == 0 {
+        return None;
+    }
+
+    // The processor may run out of entropy for a moment, so it is retried a few times
+    for _ in 0..10 {
+        let value: u32;
+        let ok: u8;
+        unsafe {
+            asm!("rdrand $0
+                setc $1"
+                : "=r"(value), "=r"(ok)
+                :
+                : "cc"
+                : "intel", "volatile");
+        }
+        if ok == 1 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// Seed the generator from the hardware
+///
+/// `RDRAND` is used if the processor supports it. It is mixed with the time stamp counter and
+/// with `time`, like the time of the real time clock, so that the seed differs on every boot.
+pub fn init(time: u64) {
+    add_entropy(time);
+    add_entropy(rdtsc());
+    if let Some(high) = rdrand() {
+        if let Some(low) = rdrand() {
+            add_entropy((high as u64) << 32 | low as u64);
+        }
+    }
+}
@@ -143,7 +143,7 @@ impl Ide {
         let bar2 = unsafe { pci.read(0x18) } as u16 & 0xFFF0;
         let bar3 = unsafe { pci.read(0x1C) } as u16 & 0xFFF0;
         let bar4 = unsafe { pci.read(0x20) } as u16 & 0xFFF0;
-        let irq = unsafe { pci.read(0x3C) } as u8 & 0xF;
+        let irq = unsafe { pci.read(0x3C) } as u8;
 
         debugln!(" + IDE on {:X}, {:X}, {:X}, {:X}, {:X}, IRQ: {:X}", bar0, bar1, bar2, bar3, bar4, irq);
 
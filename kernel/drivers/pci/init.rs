@@ -1,9 +1,12 @@
 use disk::ahci::Ahci;
 use disk::ide::Ide;
 
+use arch::apic;
+
 use env::Environment;
 
 use super::config::PciConfig;
+use super::common::config::*;
 use super::common::class::*;
 use super::common::subclass::*;
 use super::common::programming_interface::*;
@@ -30,20 +33,76 @@ pub unsafe fn pci_device(env: &mut Environment,
                          interface_id: u8,
                          vendor_code: u16,
                          device_code: u16) {
-    match (class_id, subclass_id, interface_id) {
-        (MASS_STORAGE, IDE, _) => (&mut *env.disks.get()).append(&mut Ide::disks(pci)),
-        (MASS_STORAGE, SATA, AHCI) => (&mut *env.disks.get()).append(&mut Ahci::disks(pci)),
-        (SERIAL_BUS, USB, UHCI) => (&mut *env.schemes.get()).push(Uhci::new(pci)),
-        (SERIAL_BUS, USB, OHCI) => (&mut *env.schemes.get()).push(Ohci::new(pci)),
-        (SERIAL_BUS, USB, EHCI) => (&mut *env.schemes.get()).push(Ehci::new(pci)),
-        (SERIAL_BUS, USB, XHCI) => (&mut *env.schemes.get()).push(Xhci::new(pci)),
+    let driven = match (class_id, subclass_id, interface_id) {
+        (MASS_STORAGE, IDE, _) => {
+            (&mut *env.disks.get()).append(&mut Ide::disks(pci));
+            true
+        }
+        (MASS_STORAGE, SATA, AHCI) => {
+            (&mut *env.disks.get()).append(&mut Ahci::disks(pci));
+            true
+        }
+        (SERIAL_BUS, USB, UHCI) => {
+            env.push_scheme(Uhci::new(pci));
+            true
+        }
+        (SERIAL_BUS, USB, OHCI) => {
+            env.push_scheme(Ohci::new(pci));
+            true
+        }
+        (SERIAL_BUS, USB, EHCI) => {
+            env.push_scheme(Ehci::new(pci));
+            true
+        }
+        (SERIAL_BUS, USB, XHCI) => {
+            env.push_scheme(Xhci::new(pci));
+            true
+        }
         _ => match (vendor_code, device_code) {
-            (REALTEK, RTL8139) => (&mut *env.schemes.get()).push(Rtl8139::new(pci)),
-            (INTEL, GBE_82540EM) => (&mut *env.schemes.get()).push(Intel8254x::new(pci)),
-            (INTEL, AC97_82801AA) => (&mut *env.schemes.get()).push(Ac97::new(pci)),
-            (INTEL, AC97_ICH4) => (&mut *env.schemes.get()).push(Ac97::new(pci)),
-            (INTEL, INTELHDA_ICH6) => (&mut *env.schemes.get()).push(IntelHda::new(pci)),
-            _ => debugln!(" ? CLASS {:02X}.{:02X}.{:02X} ID {:04X}:{:04X}", class_id, subclass_id, interface_id, vendor_code, device_code),
+            (REALTEK, RTL8139) => {
+                env.push_scheme(Rtl8139::new(pci));
+                true
+            }
+            (INTEL, GBE_82540EM) => {
+                env.push_scheme(Intel8254x::new(pci));
+                true
+            }
+            (INTEL, AC97_82801AA) | (INTEL, AC97_ICH4) => {
+                env.push_scheme(Ac97::new(pci));
+                true
+            }
+            (INTEL, INTELHDA_ICH6) => {
+                env.push_scheme(IntelHda::new(pci));
+                true
+            }
+            _ => {
+                debugln!(" ? CLASS {:02X}.{:02X}.{:02X} ID {:04X}:{:04X}", class_id, subclass_id, interface_id, vendor_code, device_code);
+                false
+            }
+        },
+    };
+
+    if driven {
+        pci_irq_enable(pci);
+    }
+}
+
+/// Enable the IRQ of a PCI device once its driver is registered
+///
+/// The interrupt line is used as the IRQ, which may be an I/O APIC input above the ISA IRQs.
+/// Those inputs stay masked until they are enabled here, so that a device without a driver
+/// cannot flood the processor with a level triggered interrupt nobody acknowledges.
+unsafe fn pci_irq_enable(mut pci: PciConfig) {
+    let interrupt = pci.read(PCI_CFG_INTERRUPT_LINE);
+    let line = (interrupt & 0xFF) as usize;
+    let pin = (interrupt >> 8) & 0xFF;
+    if pin == 0 || line == 0xFF {
+        return;
+    }
+
+    if apic::set_irq_enabled(line, true) {
+        if let Some(gsi) = apic::irq_gsi(line) {
+            debugln!("    IRQ {} on GSI {}", line, gsi);
         }
     }
 }
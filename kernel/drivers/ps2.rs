@@ -10,6 +10,8 @@ use graphics::display::VBEMODEINFO;
 
 use fs::KScheme;
 
+use schemes::event::event_notify;
+
 use drivers::kb_layouts::layouts;
 
 pub struct Ps2Keyboard<'a> {
@@ -57,6 +59,8 @@ pub struct Ps2 {
     caps_lock_toggle: bool,
     /// AltGr?
     altgr: bool,
+    /// Control?
+    ctrl: bool,
     /// The mouse packet
     mouse_packet: [u8; 4],
     /// Mouse packet index
@@ -82,6 +86,7 @@ impl Ps2 {
             caps_lock: false,
             caps_lock_toggle: false,
             altgr: false,
+            ctrl: false,
             mouse_packet: [0; 4],
             mouse_i: 0,
             mouse_x: 0,
@@ -253,6 +258,13 @@ impl Ps2 {
             }
         }
 
+        // Both control keys have the same scancode, the right one follows 0xE0
+        if scancode == 0x1D {
+            self.ctrl = true;
+        } else if scancode == 0x9D {
+            self.ctrl = false;
+        }
+
         let shift = self.caps_lock != (self.lshift || self.rshift);
 
         return Some(KeyEvent {
@@ -338,15 +350,24 @@ impl KScheme for Ps2 {
                             //Ignore mouse event
                         } else {
                             ::env().events.send(mouse_event.to_event(), "Ps2::on_irq mouse");
+                            event_notify("Ps2::on_irq mouse");
                         }
                     }
                 } else if status & 0x21 == 0x01 {
                     let data = self.data.read();
-                    if let Some(key_event) = self.keyboard_interrupt(data) {
+                    if let Some(mut key_event) = self.keyboard_interrupt(data) {
                         if unsafe { & *::env().console.get() }.draw {
+                            // The console gets control characters, like from a serial terminal
+                            if self.ctrl {
+                                key_event.character = match key_event.character {
+                                    c @ 'a' ... 'z' | c @ 'A' ... 'Z' => (c as u8 & 0x1F) as char,
+                                    c => c,
+                                };
+                            }
                             unsafe { &mut *::env().console.get() }.event(key_event.to_event());
                         } else {
                             ::env().events.send(key_event.to_event(), "Ps2::on_irq key");
+                            event_notify("Ps2::on_irq key");
                         }
                     }
                 } else {
@@ -1,7 +1,5 @@
 use alloc::boxed::Box;
 
-use collections::string::String;
-
 use common::event;
 
 use drivers::io::{Io, Pio};
@@ -97,12 +95,6 @@ impl KScheme for Serial {
                 }
 
                 c = '\0';
-            } else if c == '\x03' {
-                console.write(b"^C\n");
-                console.commands.send(String::new(), "Serial Control C");
-
-                c = '\0';
-                sc = 0;
             } else if c == '\x04' {
                 console.write(b"^D\n");
 
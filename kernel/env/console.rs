@@ -11,8 +11,13 @@ use core::{cmp, mem};
 use graphics::color::Color;
 use graphics::display::Display;
 
+use schemes::event::event_notify;
+
 use sync::WaitQueue;
 
+use syscall::signal::kill_group;
+use system::syscall::{SIGINT, SIGTSTP};
+
 fn ansi_color(value: u8) -> Color {
     match value {
         0 => Color::new(0x00, 0x00, 0x00),
@@ -66,6 +71,8 @@ pub struct Console {
     pub escape_sequence: bool,
     pub sequence: Vec<String>,
     pub raw_mode: bool,
+    /// The process group in the foreground, which receives the signals of the control keys, or 0
+    pub foreground_group: usize,
 }
 
 impl Console {
@@ -84,6 +91,7 @@ impl Console {
             escape_sequence: false,
             sequence: Vec::new(),
             raw_mode: false,
+            foreground_group: 0,
         }
     }
 
@@ -214,6 +222,32 @@ RAW MODE
                     self.raw_mode = false;
                     self.escape_sequence = false;
                 },
+/*
+@MANSTART{terminal-job-control}
+INTRODUCTION
+    The console has a foreground process group, which receives the signals of the control keys. Since Redox has no ioctl syscall, shells use an escape code for setting it.
+
+SETTING THE FOREGROUND PROCESS GROUP
+    CSI pgid p (^[[pgidp) puts the process group pgid in the foreground. Without pgid, the process group of the writing process is put in the foreground. A pgid of 0 leaves the console without a foreground process group.
+
+CONTROL KEYS
+    Unless the console is in raw mode:
+        - Ctrl-C sends SIGINT to the foreground process group.
+        - Ctrl-Z sends SIGTSTP to the foreground process group, which stops it until it is continued by SIGCONT.
+
+    Without a foreground process group, Ctrl-C ends the line being read instead.
+
+BACKGROUND PROCESS GROUPS
+    A process group that reads from the console while it is not in the foreground is sent SIGTTIN, which stops it until it is continued in the foreground.
+@MANEND
+*/
+                'p' => {
+                    self.foreground_group = match self.sequence.get(0).map_or("", |p| &p).parse::<usize>() {
+                        Ok(pgid) => pgid,
+                        Err(_) => unsafe { & *::env().contexts.get() }.current().map(|context| context.pgid).unwrap_or(0),
+                    };
+                    self.escape_sequence = false;
+                },
                 _ => self.escape_sequence = false,
             }
 
@@ -306,6 +340,11 @@ RAW MODE
         }
     }
 
+    /// Send a signal to the foreground process group, returning false if there is none
+    fn signal_foreground(&self, sig: usize) -> bool {
+        self.foreground_group > 0 && kill_group(self.foreground_group, sig).is_ok()
+    }
+
     pub fn event(&mut self, event: Event) {
         match event.to_option() {
             EventOption::Key(key_event) => {
@@ -329,6 +368,7 @@ RAW MODE
                             let mut command = String::new();
                             mem::swap(&mut self.command, &mut command);
                             self.commands.send(command, "Console::event command (raw)");
+                            event_notify("Console::event command (raw)");
                         }
                     } else {
                         match key_event.scancode {
@@ -340,6 +380,25 @@ RAW MODE
                             },
                             _ => match key_event.character {
                                 '\0' => (),
+                                '\x03' => {
+                                    self.redraw = true;
+
+                                    self.write(b"^C\n");
+                                    self.command.clear();
+
+                                    if ! self.signal_foreground(SIGINT) {
+                                        self.commands.send(String::new(), "Console::event interrupt");
+                                        event_notify("Console::event interrupt");
+                                    }
+                                },
+                                '\x1A' => {
+                                    self.redraw = true;
+
+                                    self.write(b"^Z\n");
+                                    self.command.clear();
+
+                                    self.signal_foreground(SIGTSTP);
+                                },
                                 c => {
                                     self.redraw = true;
 
@@ -350,6 +409,7 @@ RAW MODE
                                         let mut command = String::new();
                                         mem::swap(&mut self.command, &mut command);
                                         self.commands.send(command, "Console::event command (not raw)");
+                                        event_notify("Console::event command (not raw)");
                                     }
                                 }
                             },
@@ -1,5 +1,6 @@
 use alloc::boxed::Box;
 
+use collections::BTreeMap;
 use collections::string::{String, ToString};
 use collections::vec::Vec;
 use collections::vec_deque::VecDeque;
@@ -13,10 +14,10 @@ use disk::Disk;
 use network::Nic;
 use fs::{KScheme, Resource, Scheme, VecResource, Url};
 use logging::LogLevel;
-use sync::WaitQueue;
+use sync::{TicketLock, WaitCondition, WaitQueue};
 
-use system::error::{Error, Result, ENOENT, EEXIST};
-use system::syscall::{O_CREAT, Stat};
+use system::error::{Error, Result, ENOENT, EEXIST, EXDEV};
+use system::syscall::{O_CREAT, Stat, TimeSpec};
 
 use self::console::Console;
 
@@ -41,10 +42,16 @@ pub struct Environment {
     pub nics: UnsafeCell<Vec<Box<Nic>>>,
     /// Pending events
     pub events: WaitQueue<Event>,
+    /// Contexts waiting on futexes, by physical address of the futex
+    pub futexes: UnsafeCell<BTreeMap<usize, Box<WaitCondition>>>,
+    /// Contexts reading `event:`, woken when a resource may have become ready
+    pub event_condition: WaitCondition,
     /// Kernel logs
     pub logs: UnsafeCell<VecDeque<(Duration, LogLevel, String)>>,
     /// Schemes
     pub schemes: UnsafeCell<Vec<Box<KScheme>>>,
+    /// The lock of the list of schemes, which is not held while a scheme is called
+    pub schemes_lock: TicketLock,
 
     /// Interrupt stats
     pub interrupts: UnsafeCell<[u64; 256]>,
@@ -62,13 +69,38 @@ impl Environment {
             disks: UnsafeCell::new(Vec::new()),
             nics: UnsafeCell::new(Vec::new()),
             events: WaitQueue::new(),
+            futexes: UnsafeCell::new(BTreeMap::new()),
+            event_condition: WaitCondition::new(),
             logs: UnsafeCell::new(VecDeque::new()),
             schemes: UnsafeCell::new(Vec::new()),
+            schemes_lock: TicketLock::new(),
 
             interrupts: UnsafeCell::new([0; 256]),
         }
     }
 
+    /// Add a scheme
+    pub fn push_scheme(&self, scheme: Box<KScheme>) {
+        let _lock = self.schemes_lock.lock();
+        unsafe { &mut *self.schemes.get() }.push(scheme);
+    }
+
+    /// Find the scheme with the name `name`
+    ///
+    /// The lock of the list is only held while looking for it, as calls to schemes may block.
+    fn scheme(&self, name: &str) -> Result<&mut Box<KScheme>> {
+        let _lock = self.schemes_lock.lock();
+        for scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
+            if scheme.scheme() == name {
+                return Ok(scheme);
+            }
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Let the schemes handle an IRQ
+    ///
+    /// Schemes are not added or removed in interrupt handlers, so the list is not locked.
     pub fn on_irq(&self, irq: u8) {
         for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
             scheme.on_irq(irq);
@@ -76,6 +108,9 @@ impl Environment {
     }
 
     /// Open a new resource
+    ///
+    /// The scheme is given the effective user and group ID of the current context, or those of
+    /// root if there is none.
     pub fn open(&self, url: Url, flags: usize) -> Result<Box<Resource>> {
         let url_scheme = url.scheme();
         if url_scheme.is_empty() {
@@ -83,6 +118,7 @@ impl Environment {
             if url_path.trim_matches('/').is_empty() {
                 let mut list = String::new();
 
+                let _lock = self.schemes_lock.lock();
                 for scheme in unsafe { &mut *self.schemes.get() }.iter() {
                     let scheme_str = scheme.scheme();
                     if !scheme_str.is_empty() {
@@ -96,6 +132,7 @@ impl Environment {
 
                 Ok(box VecResource::new(":".to_string(), list.into_bytes()))
             } else if flags & O_CREAT == O_CREAT {
+                let _lock = self.schemes_lock.lock();
                 for scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
                     if scheme.scheme() == url_path {
                         return Err(Error::new(EEXIST));
@@ -113,12 +150,8 @@ impl Environment {
                 Err(Error::new(ENOENT))
             }
         } else {
-            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
-                if scheme.scheme() == url_scheme {
-                    return scheme.open(url, flags);
-                }
-            }
-            Err(Error::new(ENOENT))
+            let (uid, gid) = unsafe { & *self.contexts.get() }.current().map(|current| (current.euid, current.egid)).unwrap_or((0, 0));
+            try!(self.scheme(url_scheme)).open(url, flags, uid, gid)
         }
     }
 
@@ -126,11 +159,7 @@ impl Environment {
     pub fn mkdir(&self, url: Url, flags: usize) -> Result<()> {
         let url_scheme = url.scheme();
         if !url_scheme.is_empty() {
-            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
-                if scheme.scheme() == url_scheme {
-                    return scheme.mkdir(url, flags);
-                }
-            }
+            return try!(self.scheme(url_scheme)).mkdir(url, flags);
         }
         Err(Error::new(ENOENT))
     }
@@ -139,11 +168,7 @@ impl Environment {
     pub fn rmdir(&self, url: Url) -> Result<()> {
         let url_scheme = url.scheme();
         if !url_scheme.is_empty() {
-            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
-                if scheme.scheme() == url_scheme {
-                    return scheme.rmdir(url);
-                }
-            }
+            return try!(self.scheme(url_scheme)).rmdir(url);
         }
         Err(Error::new(ENOENT))
     }
@@ -152,11 +177,7 @@ impl Environment {
     pub fn stat(&self, url: Url, stat: &mut Stat) -> Result<()> {
         let url_scheme = url.scheme();
         if !url_scheme.is_empty() {
-            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
-                if scheme.scheme() == url_scheme {
-                    return scheme.stat(url, stat);
-                }
-            }
+            return try!(self.scheme(url_scheme)).stat(url, stat);
         }
         Err(Error::new(ENOENT))
     }
@@ -165,11 +186,76 @@ impl Environment {
     pub fn unlink(&self, url: Url) -> Result<()> {
         let url_scheme = url.scheme();
         if !url_scheme.is_empty() {
-            for mut scheme in unsafe { &mut *self.schemes.get() }.iter_mut() {
-                if scheme.scheme() == url_scheme {
-                    return scheme.unlink(url);
-                }
+            return try!(self.scheme(url_scheme)).unlink(url);
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Change the permissions of a resource
+    pub fn chmod(&self, url: Url, mode: usize) -> Result<()> {
+        let url_scheme = url.scheme();
+        if !url_scheme.is_empty() {
+            return try!(self.scheme(url_scheme)).chmod(url, mode);
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Change the owner and group of a resource
+    pub fn chown(&self, url: Url, uid: usize, gid: usize) -> Result<()> {
+        let url_scheme = url.scheme();
+        if !url_scheme.is_empty() {
+            return try!(self.scheme(url_scheme)).chown(url, uid, gid);
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Set the access and modification times of a resource
+    pub fn utimens(&self, url: Url, times: &[TimeSpec; 2]) -> Result<()> {
+        let url_scheme = url.scheme();
+        if !url_scheme.is_empty() {
+            return try!(self.scheme(url_scheme)).utimens(url, times);
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Rename a resource, which cannot move it to another scheme
+    pub fn rename(&self, old: Url, new: Url) -> Result<()> {
+        let url_scheme = old.scheme();
+        if !url_scheme.is_empty() {
+            if new.scheme() != url_scheme {
+                return Err(Error::new(EXDEV));
+            }
+            return try!(self.scheme(url_scheme)).rename(old, new);
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Create a hard link, which must be in the scheme of the resource
+    pub fn link(&self, old: Url, new: Url) -> Result<()> {
+        let url_scheme = old.scheme();
+        if !url_scheme.is_empty() {
+            if new.scheme() != url_scheme {
+                return Err(Error::new(EXDEV));
             }
+            return try!(self.scheme(url_scheme)).link(old, new);
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Create a symbolic link
+    pub fn symlink(&self, target: &str, url: Url) -> Result<()> {
+        let url_scheme = url.scheme();
+        if !url_scheme.is_empty() {
+            return try!(self.scheme(url_scheme)).symlink(target, url);
+        }
+        Err(Error::new(ENOENT))
+    }
+
+    /// Read the target of a symbolic link
+    pub fn readlink(&self, url: Url, buf: &mut [u8]) -> Result<usize> {
+        let url_scheme = url.scheme();
+        if !url_scheme.is_empty() {
+            return try!(self.scheme(url_scheme)).readlink(url, buf);
         }
         Err(Error::new(ENOENT))
     }
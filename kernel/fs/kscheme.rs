@@ -2,8 +2,8 @@ use super::{Resource, Url};
 
 use alloc::boxed::Box;
 
-use system::error::{Error, Result, EPERM};
-use system::syscall::Stat;
+use system::error::{Error, Result, ENOSYS, EPERM};
+use system::syscall::{Stat, TimeSpec};
 
 #[allow(unused_variables)]
 pub trait KScheme {
@@ -15,7 +15,8 @@ pub trait KScheme {
         ""
     }
 
-    fn open(&mut self, path: Url, flags: usize) -> Result<Box<Resource>> {
+    /// Open a resource, for a caller with the effective user ID `uid` and group ID `gid`
+    fn open(&mut self, path: Url, flags: usize, uid: usize, gid: usize) -> Result<Box<Resource>> {
         Err(Error::new(EPERM))
     }
 
@@ -34,4 +35,39 @@ pub trait KScheme {
     fn unlink(&mut self, path: Url) -> Result<()> {
         Err(Error::new(EPERM))
     }
+
+    /// Change the permissions of a resource to the lower bits of `mode`
+    fn chmod(&mut self, path: Url, mode: usize) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Change the owner and group of a resource
+    fn chown(&mut self, path: Url, uid: usize, gid: usize) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Set the access and modification times of a resource, in that order
+    fn utimens(&mut self, path: Url, times: &[TimeSpec; 2]) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Rename a resource, both paths are in this scheme
+    fn rename(&mut self, old: Url, new: Url) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Create a hard link `new` to the resource at `old`, both paths are in this scheme
+    fn link(&mut self, old: Url, new: Url) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Create a symbolic link at `path`, containing `target`
+    fn symlink(&mut self, target: &str, path: Url) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Read the target of a symbolic link, returning its length
+    fn readlink(&mut self, path: Url, buf: &mut [u8]) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
 }
@@ -1,7 +1,10 @@
+use alloc::arc::Arc;
 use alloc::boxed::Box;
 
-use system::error::{Error, Result, EPERM, ESPIPE};
-use system::syscall::Stat;
+use arch::context::SharedMemory;
+
+use system::error::{Error, Result, ENOSYS, EPERM, ESPIPE};
+use system::syscall::{Stat, TimeSpec};
 
 /// Resource seek
 #[derive(Copy, Clone, Debug)]
@@ -53,6 +56,36 @@ pub trait Resource {
         Err(Error::new(EPERM))
     }
 
+    /// Get the events that are ready without blocking, out of the `EVENT_*` flags in `flags`
+    /// Returns `ENOSYS` if the operation is not supported.
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Get the status flags with `F_GETFL`, or change `O_NONBLOCK` to the one in `arg` with `F_SETFL`
+    /// Returns `ENOSYS` if the operation is not supported.
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Change the permissions to the lower bits of `mode`
+    /// Returns `ENOSYS` if the operation is not supported.
+    fn chmod(&mut self, mode: usize) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Change the owner and group
+    /// Returns `ENOSYS` if the operation is not supported.
+    fn chown(&mut self, uid: usize, gid: usize) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
+    /// Set the access and modification times, in that order
+    /// Returns `ENOSYS` if the operation is not supported.
+    fn utimens(&mut self, times: &[TimeSpec; 2]) -> Result<()> {
+        Err(Error::new(ENOSYS))
+    }
+
     /// Sync all buffers
     /// Returns `EPERM` if the operation is not supported.
     fn sync(&mut self) -> Result<()> {
@@ -64,4 +97,11 @@ pub trait Resource {
     fn truncate(&mut self, len: usize) -> Result<()> {
         Err(Error::new(EPERM))
     }
+
+    /// Map `size` bytes starting at `offset` into memory, returning their physical address, and
+    /// the memory to keep alive while it is mapped unless it is the memory of a device
+    /// Returns `EPERM` if the operation is not supported.
+    fn mmap(&mut self, offset: usize, size: usize) -> Result<(usize, Option<Arc<SharedMemory>>)> {
+        Err(Error::new(EPERM))
+    }
 }
@@ -9,15 +9,19 @@ use core::mem::size_of;
 use core::ops::DerefMut;
 use core::{ptr, slice};
 
-use arch::context::{Context, ContextMemory};
+use arch::context::{Context, ContextMemory, SharedMemory};
+
+use schemes::event::event_notify;
 
 use sync::{WaitMap, WaitQueue};
 
 use system::error::{Error, Result, EFAULT, EINVAL, ENODEV, ESPIPE};
 use system::scheme::Packet;
 use system::syscall::{SYS_CLOSE, SYS_DUP, SYS_FPATH, SYS_FSTAT, SYS_FSYNC, SYS_FTRUNCATE,
-                    SYS_OPEN, SYS_LSEEK, SEEK_SET, SEEK_CUR, SEEK_END, SYS_MKDIR,
-                    SYS_READ, SYS_WRITE, SYS_RMDIR, SYS_STAT, SYS_UNLINK, Stat};
+                    SYS_MMAP, SYS_OPEN, SYS_LSEEK, SEEK_SET, SEEK_CUR, SEEK_END, SYS_MKDIR,
+                    SYS_READ, SYS_WRITE, SYS_RMDIR, SYS_STAT, SYS_UNLINK, SYS_RENAME, SYS_LINK,
+                    SYS_SYMLINK, SYS_READLINK, SYS_CHMOD, SYS_CHOWN, SYS_UTIMENS, SYS_FCHMOD,
+                    SYS_FCHOWN, SYS_FUTIMENS, SYS_FEVENT, SYS_FCNTL, Stat, TimeSpec};
 
 use super::{Resource, ResourceSeek, KScheme, Url};
 
@@ -40,7 +44,15 @@ impl SchemeInner {
         }
     }
 
+    /// Call the scheme with the credentials of the current context, or those of root if there is none
     fn call(inner: &Weak<SchemeInner>, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
+        let contexts = unsafe { & *::env().contexts.get() };
+        let (uid, gid) = contexts.current().map(|current| (current.euid, current.egid)).unwrap_or((0, 0));
+        SchemeInner::call_as(inner, uid, gid, a, b, c, d)
+    }
+
+    /// Call the scheme for a caller with the effective user ID `uid` and group ID `gid`
+    fn call_as(inner: &Weak<SchemeInner>, uid: usize, gid: usize, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
         if let Some(scheme) = inner.upgrade() {
             let id = scheme.next_id.get();
 
@@ -58,7 +70,9 @@ impl SchemeInner {
                 a: a,
                 b: b,
                 c: c,
-                d: d
+                d: d,
+                uid: uid,
+                gid: gid
             }, "SchemeInner::call todo");
 
             let res = Error::demux(scheme.done.receive(&id, "SchemeInner::call done").0);
@@ -83,6 +97,8 @@ impl SchemeInner {
                     virtual_size: size,
                     writeable: writeable,
                     allocated: false,
+                    shared: None,
+                    borrowed: None,
                 });
                 return Ok(virtual_address);
             }
@@ -91,6 +107,14 @@ impl SchemeInner {
         }
     }
 
+    fn lend(inner: &Weak<SchemeInner>, virtual_address: usize, size: usize) -> Result<(usize, Option<Arc<SharedMemory>>)> {
+        if let Some(scheme) = inner.upgrade() {
+            unsafe { (*scheme.context).lend(virtual_address, size) }
+        } else {
+            Err(Error::new(ENODEV))
+        }
+    }
+
     fn release(inner: &Weak<SchemeInner>, virtual_address: usize) {
         if let Some(scheme) = inner.upgrade() {
             unsafe {
@@ -106,7 +130,12 @@ impl SchemeInner {
 
 impl Drop for SchemeInner {
     fn drop(&mut self) {
-        unsafe { &mut *::env().schemes.get() }.retain(|scheme| scheme.scheme() != self.name);
+        let env = ::env();
+        let _lock = env.schemes_lock.lock();
+        unsafe { &mut *env.schemes.get() }.retain(|scheme| scheme.scheme() != self.name);
+
+        // The resources of the scheme are hung up
+        event_notify("SchemeInner::drop");
     }
 }
 
@@ -238,6 +267,37 @@ impl Resource for SchemeResource {
         }
     }
 
+    /// Get the events that are ready, which the scheme has to answer without blocking
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        self.call(SYS_FEVENT, self.file_id, flags, 0)
+    }
+
+    /// Get or set the status flags, the scheme returns `EAGAIN` itself for `O_NONBLOCK`
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        self.call(SYS_FCNTL, self.file_id, cmd, arg)
+    }
+
+    fn chmod(&mut self, mode: usize) -> Result<()> {
+        self.call(SYS_FCHMOD, self.file_id, mode, 0).and(Ok(()))
+    }
+
+    fn chown(&mut self, uid: usize, gid: usize) -> Result<()> {
+        self.call(SYS_FCHOWN, self.file_id, uid, gid).and(Ok(()))
+    }
+
+    /// Set the times, which are copied to the heap so the scheme can map them
+    fn utimens(&mut self, times: &[TimeSpec; 2]) -> Result<()> {
+        let times = times.to_vec();
+
+        let virtual_address = try!(self.capture(times.as_ptr() as usize, size_of::<[TimeSpec; 2]>(), false));
+
+        let result = self.call(SYS_FUTIMENS, self.file_id, virtual_address, 0);
+
+        self.release(virtual_address);
+
+        result.and(Ok(()))
+    }
+
     /// Sync the resource
     fn sync(&mut self) -> Result<()> {
         self.call(SYS_FSYNC, self.file_id, 0, 0).and(Ok(()))
@@ -246,6 +306,13 @@ impl Resource for SchemeResource {
     fn truncate(&mut self, len: usize) -> Result<()> {
         self.call(SYS_FTRUNCATE, self.file_id, len, 0).and(Ok(()))
     }
+
+    /// Map the resource, using memory from the address space of the scheme, which is kept alive
+    /// while it is mapped even if the scheme frees it or exits
+    fn mmap(&mut self, offset: usize, size: usize) -> Result<(usize, Option<Arc<SharedMemory>>)> {
+        let virtual_address = try!(self.call(SYS_MMAP, self.file_id, offset, size));
+        SchemeInner::lend(&self.inner, virtual_address, size)
+    }
 }
 
 impl Drop for SchemeResource {
@@ -310,17 +377,33 @@ impl Resource for SchemeServerResource {
         }
     }
 
-    /// Write to resource
+    /// Write replies to resource
+    ///
+    /// A packet with an `id` of 0 is not a reply, calls never have that ID. With `SYS_FEVENT` in
+    /// `a`, it notifies the readers of `event:` that the events of resources of the scheme may
+    /// have changed (see `Packet::fevent_notify`).
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         if buf.len() >= size_of::<Packet>() {
             let mut i = 0;
+            let mut notify = false;
 
             while i <= buf.len() - size_of::<Packet>() {
                 let packet = unsafe { & *(buf.as_ptr().offset(i as isize) as *const Packet) };
-                self.inner.done.send(packet.id, (packet.a, packet.b, packet.c, packet.d), "SchemeServerResource::write done");
+                if packet.id == 0 {
+                    if packet.a == SYS_FEVENT {
+                        notify = true;
+                    }
+                } else {
+                    self.inner.done.send(packet.id, (packet.a, packet.b, packet.c, packet.d), "SchemeServerResource::write done");
+                }
+
                 i += size_of::<Packet>();
             }
 
+            if notify {
+                event_notify("SchemeServerResource::write");
+            }
+
             Ok(i)
         } else {
             Err(Error::new(EINVAL))
@@ -366,6 +449,10 @@ impl Scheme {
         SchemeInner::call(&self.inner, a, b, c, d)
     }
 
+    fn call_as(&self, uid: usize, gid: usize, a: usize, b: usize, c: usize, d: usize) -> Result<usize> {
+        SchemeInner::call_as(&self.inner, uid, gid, a, b, c, d)
+    }
+
     fn capture(&self, physical_address: usize, size: usize, writeable: bool) -> Result<usize> {
         SchemeInner::capture(&self.inner, physical_address, size, writeable)
     }
@@ -384,12 +471,12 @@ impl KScheme for Scheme {
         &self.name
     }
 
-    fn open(&mut self, url: Url, flags: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, flags: usize, uid: usize, gid: usize) -> Result<Box<Resource>> {
         let c_str = url.to_string() + "\0";
 
         let virtual_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
 
-        let result = self.call(SYS_OPEN, virtual_address, flags, 0);
+        let result = self.call_as(uid, gid, SYS_OPEN, virtual_address, flags, 0);
 
         self.release(virtual_address);
 
@@ -462,4 +549,142 @@ impl KScheme for Scheme {
 
         result.and(Ok(()))
     }
+
+    fn chmod(&mut self, url: Url, mode: usize) -> Result<()> {
+        let c_str = url.to_string() + "\0";
+
+        let virtual_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
+
+        let result = self.call(SYS_CHMOD, virtual_address, mode, 0);
+
+        self.release(virtual_address);
+
+        result.and(Ok(()))
+    }
+
+    fn chown(&mut self, url: Url, uid: usize, gid: usize) -> Result<()> {
+        let c_str = url.to_string() + "\0";
+
+        let virtual_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
+
+        let result = self.call(SYS_CHOWN, virtual_address, uid, gid);
+
+        self.release(virtual_address);
+
+        result.and(Ok(()))
+    }
+
+    fn utimens(&mut self, url: Url, times: &[TimeSpec; 2]) -> Result<()> {
+        let c_str = url.to_string() + "\0";
+        let times = times.to_vec();
+
+        let virtual_address = try!(self.capture(c_str.as_ptr() as usize, c_str.len(), false));
+        let times_address = match self.capture(times.as_ptr() as usize, size_of::<[TimeSpec; 2]>(), false) {
+            Ok(address) => address,
+            Err(err) => {
+                self.release(virtual_address);
+                return Err(err);
+            }
+        };
+
+        let result = self.call(SYS_UTIMENS, virtual_address, times_address, 0);
+
+        self.release(times_address);
+        self.release(virtual_address);
+
+        result.and(Ok(()))
+    }
+
+    fn rename(&mut self, old: Url, new: Url) -> Result<()> {
+        let old_str = old.to_string() + "\0";
+        let new_str = new.to_string() + "\0";
+
+        let old_address = try!(self.capture(old_str.as_ptr() as usize, old_str.len(), false));
+        let new_address = match self.capture(new_str.as_ptr() as usize, new_str.len(), false) {
+            Ok(address) => address,
+            Err(err) => {
+                self.release(old_address);
+                return Err(err);
+            }
+        };
+
+        let result = self.call(SYS_RENAME, old_address, new_address, 0);
+
+        self.release(new_address);
+        self.release(old_address);
+
+        result.and(Ok(()))
+    }
+
+    fn link(&mut self, old: Url, new: Url) -> Result<()> {
+        let old_str = old.to_string() + "\0";
+        let new_str = new.to_string() + "\0";
+
+        let old_address = try!(self.capture(old_str.as_ptr() as usize, old_str.len(), false));
+        let new_address = match self.capture(new_str.as_ptr() as usize, new_str.len(), false) {
+            Ok(address) => address,
+            Err(err) => {
+                self.release(old_address);
+                return Err(err);
+            }
+        };
+
+        let result = self.call(SYS_LINK, old_address, new_address, 0);
+
+        self.release(new_address);
+        self.release(old_address);
+
+        result.and(Ok(()))
+    }
+
+    fn symlink(&mut self, target: &str, url: Url) -> Result<()> {
+        let target_str = target.to_owned() + "\0";
+        let c_str = url.to_string() + "\0";
+
+        let target_address = try!(self.capture(target_str.as_ptr() as usize, target_str.len(), false));
+        let virtual_address = match self.capture(c_str.as_ptr() as usize, c_str.len(), false) {
+            Ok(address) => address,
+            Err(err) => {
+                self.release(target_address);
+                return Err(err);
+            }
+        };
+
+        let result = self.call(SYS_SYMLINK, target_address, virtual_address, 0);
+
+        self.release(virtual_address);
+        self.release(target_address);
+
+        result.and(Ok(()))
+    }
+
+    fn readlink(&mut self, url: Url, buf: &mut [u8]) -> Result<usize> {
+        let contexts = unsafe { & *::env().contexts.get() };
+        let current = try!(contexts.current());
+        if let Ok(physical_address) = current.translate(buf.as_mut_ptr() as usize, buf.len()) {
+            let offset = physical_address % 4096;
+
+            let virtual_address = try!(self.capture(physical_address - offset, buf.len() + offset, true));
+
+            let c_str = url.to_string() + "\0";
+
+            let c_str_address = match self.capture(c_str.as_ptr() as usize, c_str.len(), false) {
+                Ok(address) => address,
+                Err(err) => {
+                    self.release(virtual_address);
+                    return Err(err);
+                }
+            };
+
+            let result = self.call(SYS_READLINK, c_str_address, virtual_address + offset, buf.len());
+
+            self.release(c_str_address);
+            self.release(virtual_address);
+
+            result
+        } else {
+            debugln!("{}:{} fault {:X} {}", file!(), line!(), buf.as_ptr() as usize, buf.len());
+            Err(Error::new(EFAULT))
+        }
+    }
 }
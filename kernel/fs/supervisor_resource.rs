@@ -1,59 +1,213 @@
-use core::{cmp, mem};
+use alloc::arc::Arc;
+
+use collections::vec::Vec;
+use collections::vec_deque::VecDeque;
+
+use core::cell::UnsafeCell;
+use core::{cmp, mem, ptr};
+
 use super::Resource;
-use system::error::Result;
-use system::scheme::Packet;
-use arch::context::{Context, context_switch};
+use system::error::{Error, Result, EINVAL, ESRCH};
+use system::syscall::{SupervisorCommand, SupervisorEvent, SUPERVISE_CONTINUE, SUPERVISE_GET_REGS,
+                      SUPERVISE_READ, SUPERVISE_RESULT, SUPERVISE_SET_REGS, SUPERVISE_WRITE};
+use arch::context::{Context, ContextFile, context_switch};
+use arch::regs::Regs;
+
+/// The flags that a supervisor can change: carry, parity, adjust, zero, sign, trap, direction and
+/// overflow
+const FLAGS_USER: usize = 0xDD5;
+
+/// The events of a supervised context, shared by the context and its supervisor resource
+pub struct SupervisorQueue {
+    /// The file table holding the supervisor resource, which the threads of the supervisor share
+    pub files: *const UnsafeCell<Vec<ContextFile>>,
+    /// The events that were not read yet
+    pub events: VecDeque<SupervisorEvent>,
+}
 
 /// A supervisor resource.
 ///
-/// Reading from it will simply read the relevant registers to the buffer (see `Packet`).
+/// Reading from it blocks until the supervised context has an event, and reads it as a
+/// `SupervisorEvent`.
 ///
-/// Writing will simply left shift EAX by one byte, and then OR it with the byte from the buffer,
-/// effectively writing the buffer to the EAX register (truncating the additional bytes).
+/// Writing a `SupervisorCommand` to it answers a stop of the supervised context, or accesses its
+/// registers and memory (see `sys_supervise`).
 pub struct SupervisorResource {
-    /// The jailed context.
-    ctx: *mut Context,
+    /// The PID of the jailed context.
+    pid: usize,
+    /// The events of the jailed context.
+    queue: Arc<UnsafeCell<SupervisorQueue>>,
 }
 
 impl SupervisorResource {
-    /// Create a new supervisor resource, supervising some PID.
-    pub unsafe fn new(ctx: *mut Context) -> Result<SupervisorResource> {
+    /// Create a new supervisor resource, supervising some context.
+    ///
+    /// If the context is already stopped, the event it is stopped at is the first to be read.
+    pub unsafe fn new(ctx: &mut Context, files: &Arc<UnsafeCell<Vec<ContextFile>>>) -> Result<SupervisorResource> {
+        let mut queue = SupervisorQueue {
+            files: &**files,
+            events: VecDeque::new(),
+        };
+        if let Some(event) = ctx.supervisor_stop {
+            queue.events.push_back(event);
+        }
+
+        let queue = Arc::new(UnsafeCell::new(queue));
+        ctx.supervised = true;
+        ctx.supervisor = Some(queue.clone());
+
         Ok(SupervisorResource {
-            ctx: ctx,
+            pid: ctx.pid,
+            queue: queue,
         })
     }
+
+    /// Get the jailed context, if it still exists
+    fn context<'a>(&self) -> Result<&'a mut Context> {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        let ctx = try!(contexts.find_mut(self.pid));
+        if ctx.exited {
+            Err(Error::new(ESRCH))
+        } else {
+            Ok(&mut **ctx)
+        }
+    }
+
+    /// Get the registers of the jailed context, if it is stopped
+    fn regs<'a>(&self) -> Result<&'a mut Regs> {
+        let ctx = try!(self.context());
+        if ctx.supervisor_stop.is_some() {
+            Ok(unsafe { &mut *ctx.supervisor_regs })
+        } else {
+            Err(Error::new(EINVAL))
+        }
+    }
+
+    /// Copy memory between the jailed context and the current context, a page at a time
+    ///
+    /// The memory of the jailed context may not be mapped, so it is accessed by physical address.
+    fn copy_memory(&self, address: usize, buf: usize, len: usize, write: bool) -> Result<()> {
+        let ctx = try!(self.context());
+        let contexts = unsafe { & *::env().contexts.get() };
+        let current = try!(contexts.current());
+        let buf = if write {
+            try!(current.get_slice(buf as *const u8, len)).as_ptr() as usize
+        } else {
+            try!(current.get_slice_mut(buf as *mut u8, len)).as_mut_ptr() as usize
+        };
+
+        let mut i = 0;
+        while i < len {
+            let count = cmp::min(len - i, 4096 - (address + i) % 4096);
+            let physical_address = try!(ctx.translate(address + i, count));
+            unsafe {
+                if write {
+                    ::memcpy(physical_address as *mut u8, (buf + i) as *const u8, count);
+                } else {
+                    ::memcpy((buf + i) as *mut u8, physical_address as *const u8, count);
+                }
+            }
+            i += count;
+        }
+
+        Ok(())
+    }
 }
 
 impl Resource for SupervisorResource {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let ctx = unsafe { &mut *self.ctx };
-        while !ctx.blocked_syscall {
-            unsafe { context_switch() };
+        if buf.len() < mem::size_of::<SupervisorEvent>() {
+            return Err(Error::new(EINVAL));
         }
 
-        let call: Packet = ctx.regs.into();
+        loop {
+            if let Some(event) = unsafe { (*self.queue.get()).events.pop_front() } {
+                for (&a, b) in event.iter().zip(buf.iter_mut()) {
+                    *b = a;
+                }
+                return Ok(event.len());
+            }
+
+            // There are no more events once the context is gone
+            if self.context().is_err() {
+                return Ok(0);
+            }
+
+            unsafe { context_switch() };
+        }
+    }
 
-        for (&a, b) in call.iter().zip(buf.iter_mut()) {
-            *b = a;
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.len() != mem::size_of::<SupervisorCommand>() {
+            return Err(Error::new(EINVAL));
         }
+        let command = unsafe { ptr::read(buf.as_ptr() as *const SupervisorCommand) };
 
-        ctx.blocked_syscall = false;
+        match command.op {
+            SUPERVISE_CONTINUE | SUPERVISE_RESULT => {
+                let ctx = try!(self.context());
+                if ctx.supervisor_stop.take().is_none() {
+                    return Err(Error::new(EINVAL));
+                }
+                if command.op == SUPERVISE_RESULT {
+                    ctx.supervisor_result = Some(command.a);
+                }
+                ctx.unblock("SupervisorResource::write");
+            },
+            SUPERVISE_GET_REGS => {
+                let regs = *try!(self.regs());
+                let contexts = unsafe { & *::env().contexts.get() };
+                *try!(try!(contexts.current()).get_ref_mut(command.a as *mut Regs)) = regs;
+            },
+            SUPERVISE_SET_REGS => {
+                let contexts = unsafe { & *::env().contexts.get() };
+                let mut new_regs = *try!(try!(contexts.current()).get_ref(command.a as *const Regs));
+                let regs = try!(self.regs());
+                // The process must stay in userspace, with the same privileges
+                new_regs.cs = regs.cs;
+                new_regs.ss = regs.ss;
+                new_regs.flags = (regs.flags & !FLAGS_USER) | (new_regs.flags & FLAGS_USER);
+                *regs = new_regs;
+            },
+            SUPERVISE_READ => try!(self.copy_memory(command.a, command.b, command.c, false)),
+            SUPERVISE_WRITE => try!(self.copy_memory(command.a, command.b, command.c, true)),
+            _ => return Err(Error::new(EINVAL)),
+        }
 
-        Ok(cmp::min(buf.len(), call.len()))
+        Ok(buf.len())
     }
+}
 
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        let ctx = unsafe { &mut *self.ctx };
+impl Drop for SupervisorResource {
+    /// Release the contexts attached to this resource, letting a stopped syscall proceed
+    ///
+    /// The children of the jailed context that no supervisor was attached to yet are released
+    /// too, as nobody is left to attach to them.
+    fn drop(&mut self) {
+        let contexts = unsafe { &mut *::env().contexts.get() };
 
-        for &i in buf.iter().take(mem::size_of::<usize>()) {
-            ctx.regs.ax <<= 8;
-            ctx.regs.ax |= i as usize;
+        let mut attached = Vec::new();
+        attached.push(self.pid);
+        for ctx in contexts.iter() {
+            if ctx.supervisor.as_ref().map_or(false, |queue| queue.get() == self.queue.get()) {
+                attached.push(ctx.pid);
+            }
         }
 
-        ctx.block("SupervisorResource::write");
+        for ctx in contexts.iter_mut() {
+            let release = if let Some(ref queue) = ctx.supervisor {
+                queue.get() == self.queue.get()
+            } else {
+                ctx.supervised && attached.contains(&ctx.ppid)
+            };
 
-        Ok(cmp::min(mem::size_of::<usize>(), buf.len()))
+            if release {
+                ctx.supervised = false;
+                ctx.supervisor = None;
+                if ctx.supervisor_stop.take().is_some() {
+                    ctx.unblock("SupervisorResource::drop");
+                }
+            }
+        }
     }
-
-    // TODO implement seek?
 }
@@ -0,0 +1,520 @@
+//! A stub for the GDB remote serial protocol
+//!
+//! The stub is enabled by a boot flag, which the bootloader sets when it is built with
+//! `make gdb=yes`. GDB talks to it on the second serial port, which `make qemu gdb=yes` serves on
+//! TCP port 4444, so that `make gdb_stub` can attach. The kernel stops at a breakpoint once it is
+//! initialized, so that breakpoints can be set before anything runs.
+//!
+//! Breakpoint and debug exceptions, in the kernel or in userspace, stop the processor and report to
+//! GDB. GDB sets breakpoints by writing `int3` to memory, and steps by setting the trap flag.
+//! Interrupting GDB with Ctrl-C stops the kernel at the next interrupt of the serial port.
+//!
+//! Contexts are listed as threads, with their PID plus one as the thread ID, as GDB reserves 0.
+//! The registers of the stopped context are the ones it was interrupted with, the registers of
+//! other contexts are the ones they switched with, so that their kernel stacks can be unwound.
+//! Memory is accessed in the selected context, the memory of the kernel in every context.
+//!
+//! Other processors keep running userspace while the kernel is stopped, until they enter it.
+
+use alloc::boxed::Box;
+
+use arch::context::Context;
+use arch::paging::{Page, PAGE_END, PAGE_ENTRY_SIZE, PAGE_SIZE, PAGE_TABLES, PF_PRESENT, PF_USER};
+use arch::regs::{Regs, GDB_REGS};
+
+use collections::Vec;
+
+use common::slice::GetSlice;
+
+use core::{cmp, mem, ptr, slice};
+
+use drivers::io::Io;
+use drivers::serial::{Serial, SERIALINFO};
+
+use syscall::SIGINT;
+
+/// The address of the boot flags, which the bootloader writes after the memory map
+const BOOT_FLAGS: *const u32 = 0x5000 as *const u32;
+/// The boot flag that enables the stub
+const BOOT_FLAG_GDB: u32 = 1;
+
+/// The second serial port, if it is not in the BIOS data area
+const SERIAL_PORT: u16 = 0x2F8;
+/// The IRQ of the second serial port
+pub const SERIAL_IRQ: u8 = 3;
+
+/// The trap flag, which makes the processor stop after the next instruction
+const FLAG_TRAP: usize = 1 << 8;
+/// The write protect bit of CR0, which makes read only pages read only for the kernel as well
+const CR0_WP: usize = 1 << 16;
+
+/// The serial port of GDB, if the stub is enabled
+static mut SERIAL: Option<Box<Serial>> = None;
+/// The thread selected for registers and memory, or 0 for the stopped thread
+static mut THREAD: usize = 0;
+/// Set if GDB waits for the next stop after continuing or stepping
+static mut RESUMED: bool = false;
+
+/// Enable the stub if the boot flag is set
+pub unsafe fn init() {
+    if ptr::read(BOOT_FLAGS) & BOOT_FLAG_GDB == BOOT_FLAG_GDB {
+        let port = match SERIALINFO {
+            Some(info) if info.ports[1] != 0 => info.ports[1],
+            _ => SERIAL_PORT,
+        };
+        SERIAL = Some(Serial::new(port, SERIAL_IRQ));
+        debugln!("GDB stub on serial port {:X}", port);
+    }
+}
+
+/// Check if the stub is enabled
+pub fn enabled() -> bool {
+    unsafe { SERIAL.is_some() }
+}
+
+/// Stop at a breakpoint if the stub is enabled, so that GDB can attach
+pub fn breakpoint() {
+    if enabled() {
+        unsafe { asm!("int3" : : : "memory" : "intel", "volatile") };
+    }
+}
+
+/// Handle an interrupt of the serial port, stopping if GDB sent Ctrl-C
+pub fn on_irq(regs: &mut Regs) {
+    let mut interrupted = false;
+    if let Some(serial) = unsafe { SERIAL.as_mut() } {
+        while serial.status.read() & 1 == 1 {
+            if serial.data.read() == 0x03 {
+                interrupted = true;
+            }
+        }
+    }
+
+    if interrupted {
+        stop(regs, SIGINT);
+    }
+}
+
+/// Stop and hand the processor to GDB, until it continues or steps
+///
+/// `signal` is reported as the reason of the stop, like `SIGTRAP` for breakpoints and steps.
+pub fn stop(regs: &mut Regs, signal: usize) {
+    if let Some(serial) = unsafe { SERIAL.as_mut() } {
+        let mut stub = Stub {
+            serial: &mut **serial,
+            regs: regs,
+            signal: signal,
+        };
+        stub.run();
+    }
+}
+
+/// Parse a hexadecimal number
+fn from_hex(data: &[u8]) -> Option<usize> {
+    if data.is_empty() {
+        return None;
+    }
+
+    let mut value = 0usize;
+    for &b in data.iter() {
+        match (b as char).to_digit(16) {
+            Some(digit) => value = value.wrapping_mul(16) | digit as usize,
+            None => return None,
+        }
+    }
+    Some(value)
+}
+
+/// Append bytes as hexadecimal digits
+fn push_hex(data: &mut Vec<u8>, bytes: &[u8]) {
+    const DIGITS: &'static [u8] = b"0123456789abcdef";
+    for &b in bytes.iter() {
+        data.push(DIGITS[(b >> 4) as usize]);
+        data.push(DIGITS[(b & 0xF) as usize]);
+    }
+}
+
+/// Append a number of `size` bytes as hexadecimal digits, in little endian
+fn push_value(data: &mut Vec<u8>, value: usize, size: usize) {
+    let mut bytes = [0; 8];
+    for i in 0..cmp::min(size, mem::size_of::<usize>()) {
+        bytes[i] = (value >> (i * 8)) as u8;
+    }
+    push_hex(data, bytes.get_slice(.. size));
+}
+
+/// Parse a number of `size` bytes in little endian from hexadecimal digits
+fn parse_value(data: &[u8], size: usize) -> Option<usize> {
+    if data.len() < size * 2 {
+        return None;
+    }
+
+    let mut value = 0;
+    for i in 0..cmp::min(size, mem::size_of::<usize>()) {
+        match from_hex(data.get_slice(i * 2 .. i * 2 + 2)) {
+            Some(byte) => value |= byte << (i * 8),
+            None => return None,
+        }
+    }
+    Some(value)
+}
+
+/// Split data at the first `separator`
+fn split(data: &[u8], separator: u8) -> (&[u8], &[u8]) {
+    match data.iter().position(|&b| b == separator) {
+        Some(i) => (data.get_slice(.. i), data.get_slice(i + 1 ..)),
+        None => (data, data.get_slice(data.len() ..)),
+    }
+}
+
+/// The thread ID of a context
+fn thread_id(context: &Context) -> usize {
+    context.pid + 1
+}
+
+/// Get the registers of a context which switched to another
+///
+/// `switch_to` does not push anything, so the stack pointer points to the return address.
+fn switched_regs(context: &Context) -> Regs {
+    let mut regs = context.regs;
+    if regs.sp != 0 {
+        regs.ip = unsafe { ptr::read(regs.sp as *const usize) };
+        regs.sp += mem::size_of::<usize>();
+    }
+    regs
+}
+
+/// Get the address where the kernel can access `len` bytes at `address` in a context
+///
+/// The bytes must not cross a page. Memory of userspace is translated by the context, memory of
+/// the kernel is identity mapped.
+fn access(context: Option<&Context>, address: usize, len: usize) -> Option<usize> {
+    if let Some(context) = context {
+        if let Ok(physical_address) = context.translate(address, len) {
+            return Some(physical_address);
+        }
+    }
+
+    if address / PAGE_SIZE < (PAGE_END - PAGE_TABLES) / PAGE_ENTRY_SIZE {
+        let entry = unsafe { Page::new(address).entry_data() };
+        if entry & PF_PRESENT == PF_PRESENT && entry & PF_USER == 0 {
+            return Some(address);
+        }
+    }
+
+    None
+}
+
+/// Copy to memory that may be read only, like the code of the kernel
+unsafe fn write_forced(address: usize, data: &[u8]) {
+    let cr0: usize;
+    asm!("mov $0, cr0" : "=r"(cr0) : : : "intel", "volatile");
+    asm!("mov cr0, $0" : : "r"(cr0 & !CR0_WP) : "memory" : "intel", "volatile");
+    ::memcpy(address as *mut u8, data.as_ptr(), data.len());
+    asm!("mov cr0, $0" : : "r"(cr0) : "memory" : "intel", "volatile");
+}
+
+/// The state of the stub while it is stopped
+struct Stub<'a> {
+    serial: &'a mut Serial,
+    /// The registers of the stopped context
+    regs: &'a mut Regs,
+    /// The signal reported for the stop
+    signal: usize,
+}
+
+impl<'a> Stub<'a> {
+    /// Read a packet, acknowledging it if its checksum matches
+    fn read_packet(&mut self) -> Vec<u8> {
+        loop {
+            while self.serial.readb() != b'$' {}
+
+            let mut data = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                let b = self.serial.readb();
+                if b == b'#' {
+                    break;
+                }
+                sum = sum.wrapping_add(b);
+                data.push(b);
+            }
+
+            let checksum = [self.serial.readb(), self.serial.readb()];
+            if from_hex(&checksum) == Some(sum as usize) {
+                self.serial.writeb(b'+');
+                return data;
+            } else {
+                self.serial.writeb(b'-');
+            }
+        }
+    }
+
+    /// Write a packet, until GDB acknowledges it
+    fn write_packet(&mut self, data: &[u8]) {
+        let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        let mut checksum = Vec::new();
+        push_hex(&mut checksum, &[sum]);
+
+        loop {
+            self.serial.writeb(b'$');
+            self.serial.write(data);
+            self.serial.writeb(b'#');
+            self.serial.write(&checksum);
+
+            loop {
+                match self.serial.readb() {
+                    b'+' => return,
+                    b'-' => break,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Get the stopped context, if there are contexts yet
+    fn current(&self) -> Option<&'static Context> {
+        let contexts = unsafe { & *::env().contexts.get() };
+        contexts.current().ok().map(|context| &**context)
+    }
+
+    /// Get the selected context
+    fn context(&self) -> Option<&'static Context> {
+        let thread = unsafe { THREAD };
+        if thread == 0 {
+            self.current()
+        } else {
+            let contexts = unsafe { & *::env().contexts.get() };
+            contexts.find(thread - 1).ok().map(|context| &**context)
+        }
+    }
+
+    /// Check if the selected thread is the stopped one
+    fn selected_stopped(&self) -> bool {
+        let thread = unsafe { THREAD };
+        thread == 0 || self.current().map_or(false, |current| thread_id(current) == thread)
+    }
+
+    /// Get the registers of the selected thread
+    fn selected_regs(&self) -> Option<Regs> {
+        if self.selected_stopped() {
+            Some(self.regs.interrupted())
+        } else {
+            self.context().map(switched_regs)
+        }
+    }
+
+    /// The reply to a stop, with the signal and the thread
+    fn stop_reply(&self) -> Vec<u8> {
+        let mut reply = Vec::new();
+        reply.push(b'T');
+        push_hex(&mut reply, &[self.signal as u8]);
+        if let Some(current) = self.current() {
+            reply.extend_from_slice(format!("thread:{:x};", thread_id(current)).as_bytes());
+        }
+        reply
+    }
+
+    /// Read memory of the selected context, as much as is mapped
+    fn read_memory(&self, address: usize, len: usize) -> Option<Vec<u8>> {
+        let context = self.context();
+
+        let mut data = Vec::new();
+        while data.len() < len {
+            let at = address.wrapping_add(data.len());
+            let count = cmp::min(len - data.len(), PAGE_SIZE - at % PAGE_SIZE);
+            match access(context, at, count) {
+                Some(ptr) => data.extend_from_slice(unsafe { slice::from_raw_parts(ptr as *const u8, count) }),
+                None => break,
+            }
+        }
+
+        if data.is_empty() && len > 0 {
+            None
+        } else {
+            Some(data)
+        }
+    }
+
+    /// Write memory of the selected context, which must all be mapped
+    fn write_memory(&self, address: usize, data: &[u8]) -> bool {
+        let context = self.context();
+
+        let mut i = 0;
+        while i < data.len() {
+            let at = address.wrapping_add(i);
+            let count = cmp::min(data.len() - i, PAGE_SIZE - at % PAGE_SIZE);
+            match access(context, at, count) {
+                Some(ptr) => unsafe { write_forced(ptr, data.get_slice(i .. i + count)) },
+                None => return false,
+            }
+            i += count;
+        }
+
+        true
+    }
+
+    /// Handle a query packet
+    fn query(&self, data: &[u8]) -> Vec<u8> {
+        let contexts = unsafe { & *::env().contexts.get() };
+
+        let mut reply = Vec::new();
+        if data.starts_with(b"Supported") {
+            reply.extend_from_slice(b"PacketSize=1000");
+        } else if data == b"C" {
+            if let Some(current) = self.current() {
+                reply.extend_from_slice(format!("QC{:x}", thread_id(current)).as_bytes());
+            }
+        } else if data == b"Attached" {
+            reply.push(b'1');
+        } else if data == b"fThreadInfo" {
+            reply.push(b'm');
+            for (i, context) in contexts.iter().enumerate() {
+                if i > 0 {
+                    reply.push(b',');
+                }
+                reply.extend_from_slice(format!("{:x}", thread_id(context)).as_bytes());
+            }
+        } else if data == b"sThreadInfo" {
+            reply.push(b'l');
+        } else if data.starts_with(b"ThreadExtraInfo,") {
+            let thread = from_hex(data.get_slice(16 ..)).unwrap_or(0);
+            if let Ok(context) = contexts.find(thread.wrapping_sub(1)) {
+                push_hex(&mut reply, context.name.as_bytes());
+            } else {
+                reply.extend_from_slice(b"E01");
+            }
+        }
+        reply
+    }
+
+    /// Answer packets until GDB continues or steps
+    fn run(&mut self) {
+        if unsafe { RESUMED } {
+            let reply = self.stop_reply();
+            self.write_packet(&reply);
+        }
+
+        loop {
+            let packet = self.read_packet();
+            let data = packet.get_slice(1 ..);
+
+            let mut reply = Vec::new();
+            match packet.get(0).map(|&b| b) {
+                Some(b'?') => reply = self.stop_reply(),
+                Some(b'g') => match self.selected_regs() {
+                    Some(regs) => for n in 0..GDB_REGS {
+                        let (value, size) = regs.gdb_reg(n);
+                        push_value(&mut reply, value, size);
+                    },
+                    None => reply.extend_from_slice(b"E01"),
+                },
+                Some(b'G') => if self.selected_stopped() {
+                    let mut regs = self.regs.interrupted();
+                    let mut offset = 0;
+                    for n in 0..GDB_REGS {
+                        let size = regs.gdb_reg(n).1;
+                        if let Some(value) = parse_value(data.get_slice(offset ..), size) {
+                            regs.set_gdb_reg(n, value);
+                        }
+                        offset += size * 2;
+                    }
+                    self.regs.set_interrupted(regs);
+                    reply.extend_from_slice(b"OK");
+                } else {
+                    reply.extend_from_slice(b"E01");
+                },
+                Some(b'p') => match (from_hex(data), self.selected_regs()) {
+                    (Some(n), Some(regs)) if n < GDB_REGS => {
+                        let (value, size) = regs.gdb_reg(n);
+                        push_value(&mut reply, value, size);
+                    },
+                    _ => reply.extend_from_slice(b"E01"),
+                },
+                Some(b'P') => {
+                    let (n, value) = split(data, b'=');
+                    match from_hex(n) {
+                        Some(n) if n < GDB_REGS && self.selected_stopped() => {
+                            let mut regs = self.regs.interrupted();
+                            if let Some(value) = parse_value(value, regs.gdb_reg(n).1) {
+                                regs.set_gdb_reg(n, value);
+                            }
+                            self.regs.set_interrupted(regs);
+                            reply.extend_from_slice(b"OK");
+                        },
+                        _ => reply.extend_from_slice(b"E01"),
+                    }
+                },
+                Some(b'm') => {
+                    let (address, len) = split(data, b',');
+                    match (from_hex(address), from_hex(len)) {
+                        (Some(address), Some(len)) => match self.read_memory(address, len) {
+                            Some(memory) => push_hex(&mut reply, &memory),
+                            None => reply.extend_from_slice(b"E01"),
+                        },
+                        _ => reply.extend_from_slice(b"E01"),
+                    }
+                },
+                Some(b'M') => {
+                    let (address, rest) = split(data, b',');
+                    let (len, hex) = split(rest, b':');
+                    let mut memory = Vec::new();
+                    for i in 0..hex.len() / 2 {
+                        if let Some(b) = from_hex(hex.get_slice(i * 2 .. i * 2 + 2)) {
+                            memory.push(b as u8);
+                        }
+                    }
+                    match (from_hex(address), from_hex(len)) {
+                        (Some(address), Some(len)) if len == memory.len() && self.write_memory(address, &memory) => {
+                            reply.extend_from_slice(b"OK");
+                        },
+                        _ => reply.extend_from_slice(b"E01"),
+                    }
+                },
+                Some(b'c') | Some(b's') => {
+                    if let Some(address) = from_hex(data) {
+                        self.regs.ip = address;
+                    }
+                    if packet[0] == b's' {
+                        self.regs.flags |= FLAG_TRAP;
+                    } else {
+                        self.regs.flags &= !FLAG_TRAP;
+                    }
+                    unsafe { RESUMED = true };
+                    return;
+                },
+                Some(b'D') => {
+                    self.regs.flags &= !FLAG_TRAP;
+                    unsafe { RESUMED = false };
+                    self.write_packet(b"OK");
+                    return;
+                },
+                Some(b'k') => {
+                    self.regs.flags &= !FLAG_TRAP;
+                    unsafe { RESUMED = false };
+                    return;
+                },
+                Some(b'H') => {
+                    // Continuing and stepping always resume every thread
+                    if data.get(0) == Some(&b'g') {
+                        let thread = data.get_slice(1 ..);
+                        unsafe { THREAD = if thread == b"-1" { 0 } else { from_hex(thread).unwrap_or(0) } };
+                    }
+                    reply.extend_from_slice(b"OK");
+                },
+                Some(b'T') => {
+                    let contexts = unsafe { & *::env().contexts.get() };
+                    match from_hex(data) {
+                        Some(thread) if contexts.find(thread.wrapping_sub(1)).is_ok() => reply.extend_from_slice(b"OK"),
+                        _ => reply.extend_from_slice(b"E01"),
+                    }
+                },
+                Some(b'q') => reply = self.query(data),
+                // Breakpoints are set by writing memory instead of with Z packets
+                _ => (),
+            }
+
+            self.write_packet(&reply);
+        }
+    }
+}
@@ -23,21 +23,22 @@ use acpi::Acpi;
 
 use alloc::boxed::Box;
 
+use arch::apic;
 use arch::context::{context_switch, Context};
 use arch::memory;
 use arch::paging::Page;
 use arch::regs::Regs;
-use arch::tss::Tss;
+use arch::smp;
 
 use collections::{String, Vec};
 use collections::string::ToString;
 
 use core::{mem, usize};
 
+use common::random;
 use common::time::Duration;
 
 use drivers::pci;
-use drivers::io::{Io, Pio};
 use drivers::ps2::*;
 use drivers::rtc::*;
 use drivers::serial::{self, Serial};
@@ -52,7 +53,7 @@ use schemes::context::ContextScheme;
 use schemes::debug::DebugScheme;
 use schemes::disk::DiskScheme;
 use schemes::display::DisplayScheme;
-use schemes::env::EnvScheme;
+use schemes::event::EventScheme;
 //use schemes::file::FileScheme;
 use schemes::initfs::InitFsScheme;
 use schemes::interrupt::InterruptScheme;
@@ -91,7 +92,7 @@ pub mod acpi;
 /// Architecture dependent objects.
 ///
 /// This module contains various mechanisms and primitives, such as ELF loading, interrupt locking,
-/// memory paging, and so on.
+/// memory paging, multiprocessing, and so on.
 ///
 /// This module is highly central to the kernel.
 pub mod arch;
@@ -102,6 +103,11 @@ pub mod arch;
 /// This module contains `ac97` and `intelhda` audio drivers. These are likely to be moved to
 /// userspace in the future.
 pub mod audio;
+/// Backtraces.
+///
+/// This module unwinds stacks of the kernel and of userspace, and resolves their addresses to
+/// symbols.
+pub mod backtrace;
 /// Disk drivers.
 ///
 /// Drivers for reading and writing disks. Currently includes drivers for following interfaces:
@@ -126,6 +132,11 @@ pub mod externs;
 /// This module manages virtual and non-virtual file systems. Furthermore, it defines URL,
 /// `Scheme`, and `Resource`.
 pub mod fs;
+/// GDB remote stub.
+///
+/// This module implements the GDB remote serial protocol on the second serial port, when it is
+/// enabled by the `gdb` boot flag.
+pub mod gdb;
 /// Graphic management.
 ///
 /// This module contains the initial display manager and various graphics primitives.
@@ -165,12 +176,6 @@ pub mod syscall;
 /// This modules contains drivers and other tools for USB.
 pub mod usb;
 
-/// The TTS pointer.
-///
-/// This static contains a mutable pointer to the TSS (task state segment), which is a data
-/// structure used on x86-based architectures for holding information about a specific task. See
-/// `Tss` for more information.
-pub static mut TSS_PTR: Option<&'static mut Tss> = None;
 /// The environment pointer.
 ///
 /// The pointer to the kernel environment, holding the state of the kernel.
@@ -191,29 +196,67 @@ pub fn env() -> &'static Environment {
 /// The PIT (programmable interval timer) duration.
 ///
 /// This duration defines the PIT interval, which is added to the monotonic clock and the real time
-/// clock, when interrupt 0x20 is received.
+/// clock on every tick. The local APIC timer is calibrated to the same interval.
 static PIT_DURATION: Duration = Duration {
     secs: 0,
     nanos: 4500572,
 };
 
+/// A tick of the scheduler, from the PIT or from the local APIC timer of a processor.
+///
+/// The time slice is accounted to the current context, as used in userspace if `user` is set.
+/// Every processor runs the scheduler, but only the boot processor advances the clocks and decays
+/// the usage of the processor by contexts.
+unsafe fn tick(user: bool) {
+    let contexts = &mut *env().contexts.get();
+
+    if smp::cpu_id() == 0 {
+        let clock_monotonic = &mut *env().clock_monotonic.get();
+        let secs = clock_monotonic.secs;
+        *clock_monotonic = *clock_monotonic + PIT_DURATION;
+        if clock_monotonic.secs != secs {
+            contexts.decay();
+        }
+
+        let clock_realtime = &mut *env().clock_realtime.get();
+        *clock_realtime = *clock_realtime + PIT_DURATION;
+
+        // The time stamp counter at the interrupt varies a little every time
+        random::add_entropy(random::rdtsc());
+    }
+
+    if let Ok(mut current) = contexts.current_mut() {
+        current.tick(user, PIT_DURATION);
+    }
+
+    context_switch();
+}
+
 /// The idle loop.
 ///
 /// This loop runs while the system is idle.
+///
+/// Each processor runs its own idle loop, which releases the kernel lock while halted.
 fn idle_loop() {
     loop {
         unsafe { asm!("cli" : : : : "intel", "volatile"); }
 
+        smp::lock();
+
         let mut halt = true;
 
-        for context in unsafe { & *env().contexts.get() }.iter().skip(1) {
-            if context.blocked == 0 {
-                halt = false;
-                break;
+        {
+            let contexts = unsafe { & *env().contexts.get() };
+            for (i, context) in contexts.iter().enumerate() {
+                if context.cpu.is_none() && context.blocked == 0 && contexts.running_on(i).is_none() {
+                    halt = false;
+                    break;
+                }
             }
         }
 
         if halt {
+            smp::unlock();
             unsafe { asm!("sti ; hlt" : : : : "intel", "volatile"); }
         } else {
             unsafe { asm!("sti ; nop ; cli" : : : : "intel", "volatile"); }
@@ -333,12 +376,12 @@ unsafe fn init(tss_data: usize) {
         }
     }
 
-    TSS_PTR = Some(&mut *(tss_data as *mut Tss));
+    smp::init(tss_data);
     ENV_PTR = Some(&mut *Box::into_raw(Environment::new()));
 
     match ENV_PTR {
         Some(ref mut env) => {
-            (&mut *env.contexts.get()).push(Context::root());
+            (&mut *env.contexts.get()).push_idle(0);
 
             let mut serial = Serial::new(0x3F8, 0x4);
 
@@ -382,7 +425,7 @@ unsafe fn init(tss_data: usize) {
                 }
             }
 
-            (&mut *env.schemes.get()).push(serial);
+            env.push_scheme(serial);
 
             (&mut *env.console.get()).draw = true;
 
@@ -394,45 +437,52 @@ unsafe fn init(tss_data: usize) {
                     & __data_start as *const u8 as usize, & __data_end as *const u8 as usize,
                     & __bss_start as *const u8 as usize, & __bss_end as *const u8 as usize);
 
+            gdb::init();
+
             if let Some(acpi) = Acpi::new() {
-                (&mut *env.schemes.get()).push(acpi);
+                if let Some(madt) = acpi.madt() {
+                    apic::init(madt);
+                    smp::start(madt);
+                }
+                env.push_scheme(acpi);
             }
 
             *env.clock_realtime.get() = Rtc::new().time();
+            random::init((*env.clock_realtime.get()).secs as u64);
 
-            (&mut *env.schemes.get()).push(Ps2::new());
+            env.push_scheme(Ps2::new());
 
             pci::pci_init(env);
 
-            (&mut *env.schemes.get()).push(DebugScheme::new());
-            (&mut *env.schemes.get()).push(InitFsScheme::new());
-            (&mut *env.schemes.get()).push(box ContextScheme);
-            (&mut *env.schemes.get()).push(box DisplayScheme);
-            (&mut *env.schemes.get()).push(box EnvScheme);
-            (&mut *env.schemes.get()).push(box InterruptScheme);
-            (&mut *env.schemes.get()).push(box MemoryScheme);
-            (&mut *env.schemes.get()).push(box SyslogScheme);
-            (&mut *env.schemes.get()).push(box TestScheme);
+            env.push_scheme(DebugScheme::new());
+            env.push_scheme(InitFsScheme::new());
+            env.push_scheme(box ContextScheme);
+            env.push_scheme(box DisplayScheme);
+            env.push_scheme(box EventScheme);
+            env.push_scheme(box InterruptScheme);
+            env.push_scheme(box MemoryScheme);
+            env.push_scheme(box SyslogScheme);
+            env.push_scheme(box TestScheme);
 
             //TODO: Do not do this! Find a better way
             let mut disks = Vec::new();
             disks.append(&mut *env.disks.get());
-            (&mut *env.schemes.get()).push(DiskScheme::new(disks));
+            env.push_scheme(DiskScheme::new(disks));
 
             /*
             let mut nics = Vec::new();
             nics.append(&mut env.nics.lock());
-            (&mut *env.schemes.get()).push(NetworkScheme::new(nics));
+            env.push_scheme(NetworkScheme::new(nics));
             */
 
-            (&mut *env.schemes.get()).push(box EthernetScheme);
-            //(&mut *env.schemes.get()).push(box ArpScheme);
-            //(&mut *env.schemes.get()).push(box IcmpScheme);
-            (&mut *env.schemes.get()).push(box IpScheme {
+            env.push_scheme(box EthernetScheme);
+            //env.push_scheme(box ArpScheme);
+            //env.push_scheme(box IcmpScheme);
+            env.push_scheme(box IpScheme {
                 arp: Vec::new()
             });
-            (&mut *env.schemes.get()).push(box TcpScheme);
-            (&mut *env.schemes.get()).push(box UdpScheme);
+            env.push_scheme(box TcpScheme);
+            env.push_scheme(box UdpScheme);
 
             Context::spawn("karp".into(),
                            box move || {
@@ -446,8 +496,11 @@ unsafe fn init(tss_data: usize) {
 
             (&mut *env.contexts.get()).enabled = true;
 
-            Context::spawn("kinit".into(),
-                           box move || {
+            // Let GDB attach before anything runs
+            gdb::breakpoint();
+
+            let init_pid = Context::spawn("kinit".into(),
+                                          box move || {
                 {
                     let wd_c = "initfs:/\0";
                     syscall::fs::chdir(wd_c.as_ptr()).unwrap();
@@ -456,21 +509,18 @@ unsafe fn init(tss_data: usize) {
                     syscall::fs::open(stdio_c.as_ptr(), 0).unwrap();
                     syscall::fs::open(stdio_c.as_ptr(), 0).unwrap();
                     syscall::fs::open(stdio_c.as_ptr(), 0).unwrap();
-
-                    let mut contexts = &mut *::env().contexts.get();
-                    let current = contexts.current_mut().unwrap();
-
-                    current.set_env_var("PATH", "file:/bin").unwrap();
-
-                    current.set_env_var("COLUMNS", &term_columns).unwrap();
-                    current.set_env_var("LINES", &term_lines).unwrap();
                 }
 
+                let vars = vec!["PATH=file:/bin".to_string(),
+                                format!("COLUMNS={}", term_columns),
+                                format!("LINES={}", term_lines)];
+
                 syslog_info!("The kernel has finished booting. Running /bin/init");
-                if let Err(err) = execute(vec!["initfs:/bin/init".to_string()]) {
+                if let Err(err) = execute(vec!["initfs:/bin/init".to_string()], vars) {
                     debugln!("kernel: init: failed to execute: {}", err);
                 }
             });
+            (&mut *env.contexts.get()).init_pid = init_pid;
         },
         None => unreachable!(),
     }
@@ -480,25 +530,25 @@ unsafe fn init(tss_data: usize) {
 #[inline(never)]
 #[no_mangle]
 /// Interrupt and exception handling.
-pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
+pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs, error: usize) {
     macro_rules! exception_inner {
-        ($name:expr) => ({
+        ($name:expr, $log:ident) => ({
             {
                 let contexts = unsafe { &mut *::env().contexts.get() };
                 if let Ok(context) = contexts.current() {
-                    debugln!("PID {}: {}", context.pid, context.name);
+                    $log!("PID {}: {}", context.pid, context.name);
 
                     if let Some(current_syscall) = context.current_syscall {
-                        debugln!("  SYS {:X}: {} {} {:X} {:X} {:X}", current_syscall.0, current_syscall.1, syscall::name(current_syscall.1), current_syscall.2, current_syscall.3, current_syscall.4);
+                        $log!("  SYS {:X}: {} {} {:X} {:X} {:X}", current_syscall.0, current_syscall.1, syscall::name(current_syscall.1), current_syscall.2, current_syscall.3, current_syscall.4);
                     }
                 }
             }
 
-            debugln!("  INT {:X}: {}", interrupt, $name);
-            debugln!("    CS:  {:08X}    IP:  {:08X}    FLG: {:08X}", regs.cs, regs.ip, regs.flags);
-            debugln!("    SS:  {:08X}    SP:  {:08X}    BP:  {:08X}", regs.ss, regs.sp, regs.bp);
-            debugln!("    AX:  {:08X}    BX:  {:08X}    CX:  {:08X}    DX:  {:08X}", regs.ax, regs.bx, regs.cx, regs.dx);
-            debugln!("    DI:  {:08X}    SI:  {:08X}", regs.di, regs.di);
+            $log!("  INT {:X}: {}", interrupt, $name);
+            $log!("    CS:  {:08X}    IP:  {:08X}    FLG: {:08X}", regs.cs, regs.ip, regs.flags);
+            $log!("    SS:  {:08X}    SP:  {:08X}    BP:  {:08X}", regs.ss, regs.sp, regs.bp);
+            $log!("    AX:  {:08X}    BX:  {:08X}    CX:  {:08X}    DX:  {:08X}", regs.ax, regs.bx, regs.cx, regs.dx);
+            $log!("    DI:  {:08X}    SI:  {:08X}", regs.di, regs.di);
 
             let cr0: usize;
             let cr2: usize;
@@ -510,7 +560,7 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
                 asm!("mov $0, cr3" : "=r"(cr3) : : : "intel", "volatile");
                 asm!("mov $0, cr4" : "=r"(cr4) : : : "intel", "volatile");
             }
-            debugln!("    CR0: {:08X}    CR2: {:08X}    CR3: {:08X}    CR4: {:08X}", cr0, cr2, cr3, cr4);
+            $log!("    CR0: {:08X}    CR2: {:08X}    CR3: {:08X}    CR4: {:08X}", cr0, cr2, cr3, cr4);
 
             let mut fsw: usize = 0;
             let mut fcw: usize = 0;
@@ -518,123 +568,156 @@ pub extern "cdecl" fn kernel(interrupt: usize, mut regs: &mut Regs) {
                 asm!("fnstsw $0" : "=*m"(&mut fsw) : : : "intel", "volatile");
                 asm!("fnstcw $0" : "=*m"(&mut fcw) : : : "intel", "volatile");
             }
-            debugln!("    FSW: {:08X}    FCW: {:08X}", fsw, fcw);
+            $log!("    FSW: {:08X}    FCW: {:08X}", fsw, fcw);
 
-            /* TODO: Stack dump
-            {
-                let contexts = unsafe { & *::env().contexts.get() };
-                if let Ok(context) = contexts.current() {
-                    let sp = regs.sp as *const usize;
-                    for y in -15..16 {
-                        debug!("    {:>3}:", y * 8 * 4);
-                        for x in 0..8 {
-                            let p = unsafe { sp.offset(-(x + y * 8)) };
-                            if let Ok(_) = context.translate(p as usize, 1) {
-                                debug!(" {:08X}", unsafe { ptr::read(p) });
-                            } else if context.kernel_stack > 0 && (p as usize) >= context.kernel_stack && (p as usize) < context.kernel_stack + CONTEXT_STACK_SIZE {
-                                debug!(" {:08X}", unsafe { ptr::read(p) });
-                            } else {
-                                debug!(" ????????");
-                            }
-                        }
-                        debug!("\n");
-                    }
-                }
+            $log!("    Backtrace:");
+            for frame in backtrace::trace(regs.ip, regs.bp, regs.cs & 3 == 3).iter() {
+                $log!("    {}", frame);
             }
-            */
         })
     };
 
     macro_rules! exception {
         ($name:expr) => ({
-            exception_inner!($name);
+            exception_inner!($name, debugln);
 
             loop {
                 unsafe { asm!("cli ; hlt" : : : : "intel", "volatile"); }
             }
+        });
+        ($name:expr, $sig:expr) => ({
+            // Faults in userspace only affect the faulting context
+            if regs.cs & 3 == 3 {
+                exception_inner!($name, syslog_error);
+                syscall::signal::fault(regs, $sig);
+            } else {
+                exception!($name);
+            }
         })
     };
 
     macro_rules! exception_error {
         ($name:expr) => ({
-            let error = regs.ip;
-            regs.ip = regs.cs;
-            regs.cs = regs.flags;
-            regs.flags = regs.sp;
-            regs.sp = regs.ss;
-            regs.ss = 0;
-            //regs.ss = regs.error;
-
-            exception_inner!($name);
+            exception_inner!($name, debugln);
             debugln!("    ERR: {:08X}", error);
 
             loop {
                 unsafe { asm!("cli ; hlt" : : : : "intel", "volatile"); }
             }
+        });
+        ($name:expr, $sig:expr) => ({
+            if regs.cs & 3 == 3 {
+                exception_inner!($name, syslog_error);
+                syslog_error!("    ERR: {:08X}", error);
+                syscall::signal::fault(regs, $sig);
+            } else {
+                exception_error!($name);
+            }
         })
     };
 
-    // Do not catch init interrupt
-    if interrupt < 0xFF {
+    // Released when returning to userspace, unless this processor was already in the kernel
+    let locked = smp::lock();
+
+    // Do not catch init interrupts
+    if interrupt < 0xFE {
         unsafe { (&mut *env().interrupts.get())[interrupt as usize] += 1 };
     }
 
     match interrupt {
-        0x20 => {
-            {
-                let mut clock_monotonic = unsafe { &mut *env().clock_monotonic.get() };
-                *clock_monotonic = *clock_monotonic + PIT_DURATION;
-            }
-            {
-                let mut clock_realtime = unsafe { &mut *env().clock_realtime.get() };
-                *clock_realtime = *clock_realtime + PIT_DURATION;
-            }
-
-            if let Ok(mut current) = unsafe { &mut *env().contexts.get() }.current_mut() {
-                current.time += 1;
-            }
-
-            unsafe { context_switch(); }
-        }
-        i @ 0x21 ... 0x2F => {
+        0x20 => unsafe { tick(regs.cs & 3 == 3) },
+        0x23 if gdb::enabled() => gdb::on_irq(regs), // The serial port of GDB
+        i @ 0x21 ... 0x5F => {
             env().on_irq(i as u8 - 0x20);
         },
         0x80 => syscall::handle(regs),
+        apic::SPURIOUS_INTERRUPT => (),
+        apic::TIMER_INTERRUPT => {
+            unsafe {
+                apic::eoi();
+                tick(regs.cs & 3 == 3);
+            }
+        },
+        0xFE => {
+            unsafe {
+                smp::init_ap(regs.ax);
+                idle_loop();
+            }
+        },
         0xFF => {
             unsafe {
                 init(regs.ax);
                 idle_loop();
             }
         },
-        0x0 => exception!("Divide by zero exception"),
-        0x1 => exception!("Debug exception"),
+        0x0 => exception!("Divide by zero exception", syscall::SIGFPE),
+        0x1 if gdb::enabled() => gdb::stop(regs, syscall::SIGTRAP),
+        0x1 => exception!("Debug exception", syscall::SIGTRAP),
         0x2 => exception!("Non-maskable interrupt"),
-        0x3 => exception!("Breakpoint exception"),
-        0x4 => exception!("Overflow exception"),
-        0x5 => exception!("Bound range exceeded exception"),
-        0x6 => exception!("Invalid opcode exception"),
+        0x3 if gdb::enabled() => gdb::stop(regs, syscall::SIGTRAP),
+        0x3 => exception!("Breakpoint exception", syscall::SIGTRAP),
+        0x4 => exception!("Overflow exception", syscall::SIGSEGV),
+        0x5 => exception!("Bound range exceeded exception", syscall::SIGSEGV),
+        0x6 => exception!("Invalid opcode exception", syscall::SIGILL),
         0x7 => exception!("Device not available exception"),
         0x8 => exception_error!("Double fault"),
-        0x9 => exception!("Coprocessor Segment Overrun"), // legacy
+        0x9 => exception!("Coprocessor Segment Overrun", syscall::SIGFPE), // legacy
         0xA => exception_error!("Invalid TSS exception"),
-        0xB => exception_error!("Segment not present exception"),
-        0xC => exception_error!("Stack-segment fault"),
-        0xD => exception_error!("General protection fault"),
-        0xE => exception_error!("Page fault"),
-        0x10 => exception!("x87 floating-point exception"),
-        0x11 => exception_error!("Alignment check exception"),
+        0xB => exception_error!("Segment not present exception", syscall::SIGBUS),
+        0xC => exception_error!("Stack-segment fault", syscall::SIGBUS),
+        0xD => exception_error!("General protection fault", syscall::SIGSEGV),
+        0xE => {
+            let cr2: usize;
+            unsafe { asm!("mov $0, cr2" : "=r"(cr2) : : : "intel", "volatile"); }
+
+            // A write to a present page may be to memory shared copy-on-write
+            let mut copied = false;
+            if error & 3 == 3 {
+                if let Ok(mut current) = unsafe { &mut *env().contexts.get() }.current_mut() {
+                    copied = unsafe { current.copy_on_write(cr2) };
+                }
+            }
+
+            // An access below the user stack grows it, until the stack limit is hit
+            let mut grown = false;
+            if ! copied && error & 1 == 0 && regs.cs & 3 == 3 {
+                if let Ok(mut current) = unsafe { &mut *env().contexts.get() }.current_mut() {
+                    match current.stack_fault(cr2) {
+                        Some(true) => grown = unsafe { current.grow_stack(cr2) }.is_ok(),
+                        Some(false) => {
+                            syslog_error!("PID {}: {}: stack overflow at {:X}, the stack limit is {} KB",
+                                          current.pid, current.name, cr2, current.stack_limit / 1024);
+                            // A signal handler could not run on the stack
+                            syscall::signal::terminate_core(regs, syscall::SIGSEGV);
+                        },
+                        None => ()
+                    }
+                }
+            }
+
+            if ! copied && ! grown {
+                exception_error!("Page fault", syscall::SIGSEGV);
+            }
+        },
+        0x10 => exception!("x87 floating-point exception", syscall::SIGFPE),
+        0x11 => exception_error!("Alignment check exception", syscall::SIGBUS),
         0x12 => exception!("Machine check exception"),
-        0x13 => exception!("SIMD floating-point exception"),
+        0x13 => exception!("SIMD floating-point exception", syscall::SIGFPE),
         0x14 => exception!("Virtualization exception"),
         0x1E => exception_error!("Security exception"),
         _ => exception!("Unknown Interrupt"),
     }
 
-    if interrupt >= 0x20 && interrupt < 0x30 {
-        if interrupt >= 0x28 {
-            Pio::<u8>::new(0xA0).write(0x20);
-        }
+    if interrupt >= apic::IRQ_BASE && interrupt < apic::IRQ_BASE + apic::IRQ_COUNT {
+        unsafe { apic::eoi_irq(interrupt - apic::IRQ_BASE) };
+    }
+
+    // Deliver signals when returning to userspace
+    if regs.cs & 3 == 3 {
+        syscall::signal::deliver(regs);
+    }
 
-        Pio::<u8>::new(0x20).write(0x20);
+    if locked {
+        smp::unlock();
     }
 }
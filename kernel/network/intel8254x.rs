@@ -16,6 +16,8 @@ use network::scheme::*;
 
 use fs::{KScheme, Resource, Url};
 
+use schemes::event::event_notify;
+
 use system::error::Result;
 
 const CTRL: u32 = 0x00;
@@ -117,7 +119,7 @@ impl KScheme for Intel8254x {
         "network"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         Ok(NetworkResource::new(self))
     }
 
@@ -175,10 +177,16 @@ impl NetworkScheme for Intel8254x {
         {
             let resources = unsafe { &mut *self.resources.get() };
 
+            let mut received = false;
             while let Some(bytes) = self.inbound.pop_front() {
                 for resource in resources.iter() {
                     unsafe { (**resource).inbound.send(bytes.clone(), "Intel8254x::sync") };
                 }
+                received = true;
+            }
+
+            if received {
+                event_notify("Intel8254x::sync");
             }
         }
     }
@@ -192,7 +200,7 @@ impl Intel8254x {
             pci: pci,
             base: base & 0xFFFFFFF0,
             memory_mapped: base & 1 == 0,
-            irq: pci.read(0x3C) as u8 & 0xF,
+            irq: pci.read(0x3C) as u8,
             resources: UnsafeCell::new(Vec::new()),
             inbound: VecDeque::new(),
             outbound: VecDeque::new(),
@@ -17,6 +17,8 @@ use network::scheme::*;
 
 use fs::{KScheme, Resource, Url};
 
+use schemes::event::event_notify;
+
 use system::error::Result;
 
 bitflags! {
@@ -129,7 +131,7 @@ impl Rtl8139 {
         }
 
         let base = unsafe { pci.read(0x10) as usize };
-        let irq = unsafe { pci.read(0x3C) as u8 & 0xF };
+        let irq = unsafe { pci.read(0x3C) as u8 };
 
         let mut module = box Rtl8139 {
             pci: pci,
@@ -237,7 +239,7 @@ impl KScheme for Rtl8139 {
         "network"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         Ok(NetworkResource::new(self))
     }
 
@@ -299,10 +301,16 @@ impl NetworkScheme for Rtl8139 {
         {
             let resources = unsafe { &mut *self.resources.get() };
 
+            let mut received = false;
             while let Some(bytes) = self.inbound.pop_front() {
                 for resource in resources.iter() {
                     unsafe { (**resource).inbound.send(bytes.clone(), "Rtl8139::sync") };
                 }
+                received = true;
+            }
+
+            if received {
+                event_notify("Rtl8139::sync");
             }
         }
     }
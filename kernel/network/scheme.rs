@@ -9,6 +9,7 @@ use core::ops::DerefMut;
 use fs::Resource;
 
 use system::error::Result;
+use system::syscall::{EVENT_READ, EVENT_WRITE};
 
 use sync::WaitQueue;
 
@@ -89,6 +90,18 @@ impl Resource for NetworkResource {
         return Ok(bytes.len());
     }
 
+    /// Readable once a packet was received, writing never blocks
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        let mut events = EVENT_WRITE;
+        unsafe {
+            (*self.nic).sync();
+            if ! (*self.ptr).inbound.inner().is_empty() {
+                events |= EVENT_READ;
+            }
+        }
+        Ok(events & flags)
+    }
+
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         unsafe {
             (&mut *(*self.ptr).outbound.get()).push_back(Vec::from(buf));
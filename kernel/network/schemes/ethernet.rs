@@ -12,6 +12,7 @@ use network::ethernet::*;
 use fs::{KScheme, Resource, Url};
 
 use system::error::{Error, Result, ENOENT};
+use system::syscall::{EVENT_READ, EVENT_WRITE};
 
 /// A ethernet resource
 pub struct EthernetResource {
@@ -25,6 +26,15 @@ pub struct EthernetResource {
     ethertype: u16,
 }
 
+impl EthernetResource {
+    /// Check if a frame is for this resource
+    fn accepts(&self, frame: &EthernetII) -> bool {
+        frame.header.ethertype.get() == self.ethertype && (unsafe { frame.header.dst.equals(MAC_ADDR) }
+            || frame.header.dst.equals(BROADCAST_MAC_ADDR)) && (frame.header.src.equals(self.peer_addr)
+            || self.peer_addr.equals(BROADCAST_MAC_ADDR))
+    }
+}
+
 impl Resource for EthernetResource {
     fn dup(&self) -> Result<Box<Resource>> {
         match self.network.dup() {
@@ -66,10 +76,7 @@ impl Resource for EthernetResource {
             match self.network.read(&mut bytes) {
                 Ok(count) => {
                     if let Some(frame) = EthernetII::from_bytes(bytes[.. count].to_vec()) {
-                        if frame.header.ethertype.get() == self.ethertype && (unsafe { frame.header.dst.equals(MAC_ADDR) }
-                            || frame.header.dst.equals(BROADCAST_MAC_ADDR)) && (frame.header.src.equals(self.peer_addr)
-                            || self.peer_addr.equals(BROADCAST_MAC_ADDR))
-                        {
+                        if self.accepts(&frame) {
                             for (b, d) in buf.iter_mut().zip(frame.data.iter()) {
                                 *b = *d;
                             }
@@ -83,6 +90,27 @@ impl Resource for EthernetResource {
         }
     }
 
+    /// Readable once a frame for this resource was received, which is kept for the next read
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        if flags & EVENT_READ == EVENT_READ {
+            while self.data.is_empty() && try!(self.network.event(EVENT_READ)) & EVENT_READ == EVENT_READ {
+                let mut bytes = [0; 8192];
+                let count = try!(self.network.read(&mut bytes));
+                if let Some(frame) = EthernetII::from_bytes(bytes[.. count].to_vec()) {
+                    if self.accepts(&frame) {
+                        self.data = frame.data;
+                    }
+                }
+            }
+        }
+
+        let mut events = EVENT_WRITE;
+        if ! self.data.is_empty() {
+            events |= EVENT_READ;
+        }
+        Ok(events & flags)
+    }
+
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         let data = Vec::from(buf);
 
@@ -112,7 +140,7 @@ impl KScheme for EthernetScheme {
         "ethernet"
     }
 
-    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         let parts: Vec<&str> = url.reference().split("/").collect();
         if let Some(host_string) = parts.get(0) {
             if let Some(ethertype_string) = parts.get(1) {
@@ -15,6 +15,7 @@ use super::arp::{Arp, ArpHeader};
 use fs::{KScheme, Resource, Url};
 
 use system::error::{Error, Result, ENOENT};
+use system::syscall::{EVENT_READ, EVENT_WRITE};
 
 /// A IP (internet protocole) resource
 pub struct IpResource {
@@ -25,6 +26,14 @@ pub struct IpResource {
     id: u16,
 }
 
+impl IpResource {
+    /// Check if a packet is for this resource
+    fn accepts(&self, packet: &Ipv4) -> bool {
+        packet.header.proto == self.proto && packet.header.dst.equals(IP_ADDR) &&
+        packet.header.src.equals(self.peer_addr)
+    }
+}
+
 impl Resource for IpResource {
     fn dup(&self) -> Result<Box<Resource>> {
         match self.link.dup() {
@@ -67,8 +76,7 @@ impl Resource for IpResource {
             match self.link.read(&mut bytes) {
                 Ok(count) => {
                     if let Some(packet) = Ipv4::from_bytes(bytes[.. count].to_vec()) {
-                        if packet.header.proto == self.proto && packet.header.dst.equals(IP_ADDR) &&
-                           packet.header.src.equals(self.peer_addr) {
+                        if self.accepts(&packet) {
                             for (b, d) in buf.iter_mut().zip(packet.data.iter()) {
                                 *b = *d;
                             }
@@ -82,6 +90,27 @@ impl Resource for IpResource {
         }
     }
 
+    /// Readable once a packet for this resource was received, which is kept for the next read
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        if flags & EVENT_READ == EVENT_READ {
+            while self.data.is_empty() && try!(self.link.event(EVENT_READ)) & EVENT_READ == EVENT_READ {
+                let mut bytes = [0; 8192];
+                let count = try!(self.link.read(&mut bytes));
+                if let Some(packet) = Ipv4::from_bytes(bytes[.. count].to_vec()) {
+                    if self.accepts(&packet) {
+                        self.data = packet.data;
+                    }
+                }
+            }
+        }
+
+        let mut events = EVENT_WRITE;
+        if ! self.data.is_empty() {
+            events |= EVENT_READ;
+        }
+        Ok(events & flags)
+    }
+
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         let ip_data = Vec::from(buf);
 
@@ -137,7 +166,7 @@ impl KScheme for IpScheme {
         "ip"
     }
 
-    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         let parts: Vec<&str> = url.reference().split('/').collect();
         if let Some(host_string) = parts.get(0) {
             if let Some(proto_string) = parts.get(1) {
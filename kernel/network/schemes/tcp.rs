@@ -13,7 +13,8 @@ use fs::{KScheme, Resource, Url};
 
 use network::common::{n16, n32, Checksum, Ipv4Addr, IP_ADDR, FromBytes, ToBytes};
 
-use system::error::{Error, Result, ENOENT, EPIPE};
+use system::error::{Error, Result, EAGAIN, EINVAL, ENOENT, EPIPE};
+use system::syscall::{EVENT_READ, EVENT_WRITE, F_GETFL, F_SETFL, O_NONBLOCK};
 
 #[derive(Copy, Clone)]
 #[repr(packed)]
@@ -73,11 +74,15 @@ impl ToBytes for Tcp {
 
 pub struct TcpStream {
     ip: Box<Resource>,
+    /// Data that was received but not read yet
+    data: Vec<u8>,
     peer_addr: Ipv4Addr,
     peer_port: u16,
     host_port: u16,
     sequence: u32,
     acknowledge: u32,
+    /// The status flags, which are shared by all file descriptors of the stream
+    flags: usize,
 }
 
 impl TcpStream {
@@ -92,66 +97,93 @@ impl TcpStream {
         Ok(cmp::min(buf.len(), path.len()))
     }
 
+    /// Handle a segment read from the IP resource, acknowledging it and returning its data if it
+    /// is for this stream
+    fn receive(&mut self, bytes: &[u8]) -> Option<Vec<u8>> {
+        if let Some(segment) = Tcp::from_bytes(bytes.to_vec()) {
+            if (segment.header.flags.get() & (TCP_PSH | TCP_SYN | TCP_ACK)) ==
+               (TCP_PSH | TCP_ACK) &&
+               segment.header.dst.get() == self.host_port &&
+               segment.header.src.get() == self.peer_port {
+                // Send ACK
+                self.sequence = segment.header.ack_num.get();
+                self.acknowledge = segment.header.sequence.get() +
+                                   segment.data.len() as u32;
+                let mut tcp = Tcp {
+                            header: TcpHeader {
+                                src: n16::new(self.host_port),
+                                dst: n16::new(self.peer_port),
+                                sequence: n32::new(self.sequence),
+                                ack_num: n32::new(self.acknowledge),
+                                flags: n16::new(((mem::size_of::<TcpHeader>() << 10) & 0xF000) as u16 | TCP_ACK),
+                                window_size: n16::new(65535),
+                                checksum: Checksum {
+                                    data: 0
+                                },
+                                urgent_pointer: n16::new(0)
+                            },
+                            options: Vec::new(),
+                            data: Vec::new()
+                        };
+
+                unsafe {
+                    let proto = n16::new(0x06);
+                    let segment_len = n16::new((mem::size_of::<TcpHeader>() + tcp.options.len() + tcp.data.len()) as u16);
+                    tcp.header.checksum.data = Checksum::compile(
+                                Checksum::sum((&IP_ADDR as *const Ipv4Addr) as usize, mem::size_of::<Ipv4Addr>()) +
+                                Checksum::sum((&self.peer_addr as *const Ipv4Addr) as usize, mem::size_of::<Ipv4Addr>()) +
+                                Checksum::sum((&proto as *const n16) as usize, mem::size_of::<n16>()) +
+                                Checksum::sum((&segment_len as *const n16) as usize, mem::size_of::<n16>()) +
+                                Checksum::sum((&tcp.header as *const TcpHeader) as usize, mem::size_of::<TcpHeader>()) +
+                                Checksum::sum(tcp.options.as_ptr() as usize, tcp.options.len()) +
+                                Checksum::sum(tcp.data.as_ptr() as usize, tcp.data.len())
+                                );
+                }
+
+                let _ = self.ip.write(&tcp.to_bytes());
+
+                return Some(segment.data);
+            }
+        }
+
+        None
+    }
+
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        loop {
+        while self.data.is_empty() {
             let mut bytes = [0; 8192];
-            match self.ip.read(&mut bytes) {
-                Ok(count) => {
-                    if let Some(segment) = Tcp::from_bytes(bytes[.. count].to_vec()) {
-                        if (segment.header.flags.get() & (TCP_PSH | TCP_SYN | TCP_ACK)) ==
-                           (TCP_PSH | TCP_ACK) &&
-                           segment.header.dst.get() == self.host_port &&
-                           segment.header.src.get() == self.peer_port {
-                            // Send ACK
-                            self.sequence = segment.header.ack_num.get();
-                            self.acknowledge = segment.header.sequence.get() +
-                                               segment.data.len() as u32;
-                            let mut tcp = Tcp {
-                                        header: TcpHeader {
-                                            src: n16::new(self.host_port),
-                                            dst: n16::new(self.peer_port),
-                                            sequence: n32::new(self.sequence),
-                                            ack_num: n32::new(self.acknowledge),
-                                            flags: n16::new(((mem::size_of::<TcpHeader>() << 10) & 0xF000) as u16 | TCP_ACK),
-                                            window_size: n16::new(65535),
-                                            checksum: Checksum {
-                                                data: 0
-                                            },
-                                            urgent_pointer: n16::new(0)
-                                        },
-                                        options: Vec::new(),
-                                        data: Vec::new()
-                                    };
-
-                            unsafe {
-                                let proto = n16::new(0x06);
-                                let segment_len = n16::new((mem::size_of::<TcpHeader>() + tcp.options.len() + tcp.data.len()) as u16);
-                                tcp.header.checksum.data = Checksum::compile(
-                                            Checksum::sum((&IP_ADDR as *const Ipv4Addr) as usize, mem::size_of::<Ipv4Addr>()) +
-                                            Checksum::sum((&self.peer_addr as *const Ipv4Addr) as usize, mem::size_of::<Ipv4Addr>()) +
-                                            Checksum::sum((&proto as *const n16) as usize, mem::size_of::<n16>()) +
-                                            Checksum::sum((&segment_len as *const n16) as usize, mem::size_of::<n16>()) +
-                                            Checksum::sum((&tcp.header as *const TcpHeader) as usize, mem::size_of::<TcpHeader>()) +
-                                            Checksum::sum(tcp.options.as_ptr() as usize, tcp.options.len()) +
-                                            Checksum::sum(tcp.data.as_ptr() as usize, tcp.data.len())
-                                            );
-                            }
+            let count = try!(self.ip.read(&mut bytes));
+            if let Some(data) = self.receive(&bytes[.. count]) {
+                self.data = data;
+            }
+        }
 
-                            let _ = self.ip.write(&tcp.to_bytes());
+        // The rest of the segment is kept for the next read
+        let count = cmp::min(buf.len(), self.data.len());
+        for (b, d) in buf.iter_mut().zip(self.data.drain(.. count)) {
+            *b = d;
+        }
+        Ok(count)
+    }
 
-                            // TODO: Support broken packets (one packet in two buffers)
-                            let mut i = 0;
-                            while i < buf.len() && i < segment.data.len() {
-                                buf[i] = segment.data[i];
-                                i += 1;
-                            }
-                            return Ok(i);
-                        }
-                    }
+    /// Readable once a segment with data for this stream was received, which is kept for the next
+    /// read
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        if flags & EVENT_READ == EVENT_READ {
+            while self.data.is_empty() && try!(self.ip.event(EVENT_READ)) & EVENT_READ == EVENT_READ {
+                let mut bytes = [0; 8192];
+                let count = try!(self.ip.read(&mut bytes));
+                if let Some(data) = self.receive(&bytes[.. count]) {
+                    self.data = data;
                 }
-                Err(err) => return Err(err),
             }
         }
+
+        let mut events = EVENT_WRITE;
+        if ! self.data.is_empty() {
+            events |= EVENT_READ;
+        }
+        Ok(events & flags)
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
@@ -437,13 +469,13 @@ impl Drop for TcpStream {
 
 /// A TCP resource
 pub struct TcpResource {
-    stream: Arc<UnsafeCell<TcpStream>>
+    stream: Arc<UnsafeCell<TcpStream>>,
 }
 
 impl Resource for TcpResource {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(box TcpResource {
-            stream: self.stream.clone()
+            stream: self.stream.clone(),
         })
     }
 
@@ -452,9 +484,31 @@ impl Resource for TcpResource {
     }
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // A received segment is kept in the stream by event
+        let flags = unsafe { (*self.stream.get()).flags };
+        if flags & O_NONBLOCK == O_NONBLOCK && try!(self.event(EVENT_READ)) & EVENT_READ != EVENT_READ {
+            return Err(Error::new(EAGAIN));
+        }
+
         unsafe { (*self.stream.get()).read(buf) }
     }
 
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        unsafe { (*self.stream.get()).event(flags) }
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        let stream = unsafe { &mut *self.stream.get() };
+        match cmd {
+            F_GETFL => Ok(stream.flags),
+            F_SETFL => {
+                stream.flags = (stream.flags & !O_NONBLOCK) | (arg & O_NONBLOCK);
+                Ok(0)
+            },
+            _ => Err(Error::new(EINVAL))
+        }
+    }
+
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         unsafe { (*self.stream.get()).write(buf) }
     }
@@ -472,7 +526,7 @@ impl KScheme for TcpScheme {
         "tcp"
     }
 
-    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, flags: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         let mut parts = url.reference().split('/');
         let remote = parts.next().unwrap_or("");
         let path = parts.next().unwrap_or("");
@@ -490,16 +544,18 @@ impl KScheme for TcpScheme {
                 Ok(ip) => {
                     let mut stream = TcpStream {
                         ip: ip,
+                        data: Vec::new(),
                         peer_addr: peer_addr,
                         peer_port: peer_port,
                         host_port: host_port,
                         sequence: rand() as u32,
                         acknowledge: 0,
+                        flags: flags,
                     };
 
                     if stream.client_establish() {
                         return Ok(box TcpResource {
-                            stream: Arc::new(UnsafeCell::new(stream))
+                            stream: Arc::new(UnsafeCell::new(stream)),
                         });
                     }
                 }
@@ -522,16 +578,18 @@ impl KScheme for TcpScheme {
 
                                     let mut stream = TcpStream {
                                         ip: ip,
+                                        data: Vec::new(),
                                         peer_addr: Ipv4Addr::from_string(&peer_addr.to_string()),
                                         peer_port: segment.header.src.get(),
                                         host_port: host_port,
                                         sequence: rand() as u32,
                                         acknowledge: segment.header.sequence.get(),
+                                        flags: flags,
                                     };
 
                                     if stream.server_establish(segment) {
                                         return Ok(box TcpResource {
-                                            stream: Arc::new(UnsafeCell::new(stream))
+                                            stream: Arc::new(UnsafeCell::new(stream)),
                                         });
                                     }
                                 }
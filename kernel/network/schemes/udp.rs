@@ -1,3 +1,4 @@
+use alloc::arc::Arc;
 use alloc::boxed::Box;
 
 use collections::Vec;
@@ -6,12 +7,14 @@ use collections::string::ToString;
 use common::random::rand;
 
 use core::{cmp, mem, ptr, slice, str};
+use core::cell::Cell;
 
 use fs::{KScheme, Resource, Url};
 
 use network::common::{n16, Checksum, Ipv4Addr, IP_ADDR, FromBytes, ToBytes};
 
-use system::error::{Error, Result, ENOENT};
+use system::error::{Error, Result, EAGAIN, EINVAL, ENOENT};
+use system::syscall::{EVENT_READ, EVENT_WRITE, F_GETFL, F_SETFL, O_NONBLOCK};
 
 #[derive(Copy, Clone)]
 #[repr(packed)]
@@ -61,6 +64,15 @@ pub struct UdpResource {
     peer_addr: Ipv4Addr,
     peer_port: u16,
     host_port: u16,
+    /// The status flags, which are shared with the duplicates of the resource
+    flags: Arc<Cell<usize>>,
+}
+
+impl UdpResource {
+    /// Check if a datagram is for this resource
+    fn accepts(&self, datagram: &Udp) -> bool {
+        datagram.header.dst.get() == self.host_port && datagram.header.src.get() == self.peer_port
+    }
 }
 
 impl Resource for UdpResource {
@@ -73,6 +85,7 @@ impl Resource for UdpResource {
                     peer_addr: self.peer_addr,
                     peer_port: self.peer_port,
                     host_port: self.host_port,
+                    flags: self.flags.clone(),
                 }))
             }
             Err(err) => Err(err),
@@ -91,6 +104,11 @@ impl Resource for UdpResource {
     }
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // A received datagram is kept in self.data by event
+        if self.flags.get() & O_NONBLOCK == O_NONBLOCK && try!(self.event(EVENT_READ)) & EVENT_READ != EVENT_READ {
+            return Err(Error::new(EAGAIN));
+        }
+
         if ! self.data.is_empty() {
             let mut bytes: Vec<u8> = Vec::new();
             mem::swap(&mut self.data, &mut bytes);
@@ -108,8 +126,7 @@ impl Resource for UdpResource {
             let mut bytes = [0; 8192];
             let count = try!(self.ip.read(&mut bytes));
             if let Some(datagram) = Udp::from_bytes(bytes[.. count].to_vec()) {
-                if datagram.header.dst.get() == self.host_port &&
-                   datagram.header.src.get() == self.peer_port {
+                if self.accepts(&datagram) {
                     // TODO: Allow splitting
                     let mut i = 0;
                     while i < buf.len() && i < datagram.data.len() {
@@ -122,6 +139,38 @@ impl Resource for UdpResource {
         }
     }
 
+    /// Readable once a datagram for this resource was received, which is kept for the next read
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        if flags & EVENT_READ == EVENT_READ {
+            while self.data.is_empty() && try!(self.ip.event(EVENT_READ)) & EVENT_READ == EVENT_READ {
+                let mut bytes = [0; 8192];
+                let count = try!(self.ip.read(&mut bytes));
+                if let Some(datagram) = Udp::from_bytes(bytes[.. count].to_vec()) {
+                    if self.accepts(&datagram) {
+                        self.data = datagram.data;
+                    }
+                }
+            }
+        }
+
+        let mut events = EVENT_WRITE;
+        if ! self.data.is_empty() {
+            events |= EVENT_READ;
+        }
+        Ok(events & flags)
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        match cmd {
+            F_GETFL => Ok(self.flags.get()),
+            F_SETFL => {
+                self.flags.set((self.flags.get() & !O_NONBLOCK) | (arg & O_NONBLOCK));
+                Ok(0)
+            },
+            _ => Err(Error::new(EINVAL))
+        }
+    }
+
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         let mut udp = Udp {
             header: UdpHeader {
@@ -166,7 +215,7 @@ impl KScheme for UdpScheme {
         "udp"
     }
 
-    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, flags: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         let mut parts = url.reference().split('/');
         let remote = parts.next().unwrap_or("");
         let path = parts.next().unwrap_or("");
@@ -191,6 +240,7 @@ impl KScheme for UdpScheme {
                                         peer_addr: Ipv4Addr::from_string(&peer_addr.to_string()),
                                         peer_port: datagram.header.src.get(),
                                         host_port: host_port,
+                                        flags: Arc::new(Cell::new(flags)),
                                     }));
                                 }
                             }
@@ -212,6 +262,7 @@ impl KScheme for UdpScheme {
                         peer_addr: Ipv4Addr::from_string(&peer_addr.to_string()),
                         peer_port: peer_port as u16,
                         host_port: host_port,
+                        flags: Arc::new(Cell::new(flags)),
                     }));
                 }
             }
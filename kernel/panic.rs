@@ -1,5 +1,6 @@
 use core::fmt;
 
+use backtrace;
 use syscall;
 
 #[lang="panic_fmt"]
@@ -17,6 +18,11 @@ pub extern "C" fn panic_fmt(args: fmt::Arguments, file: &'static str, line: u32)
 
     debugln!("  KP {}: {}: {}", file, line, args);
 
+    debugln!("    Backtrace:");
+    for frame in backtrace::trace(panic_fmt as usize, backtrace::frame_pointer(), false).iter() {
+        debugln!("    {}", frame);
+    }
+
     loop {
         unsafe { asm!("cli ; hlt" : : : : "intel", "volatile"); }
     }
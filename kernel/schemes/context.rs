@@ -2,89 +2,240 @@ use alloc::boxed::Box;
 
 use collections::string::{String, ToString};
 
-use arch::context;
+use arch::context::{self, Context, ContextMemory};
 
 use fs::{KScheme, Resource, Url, VecResource};
 
-use system::error::Result;
+use syscall;
+
+use system::error::{Error, Result, ENOENT};
+
+/// The entries of a context directory
+static ENTRIES: [&'static str; 7] = ["cwd", "env", "files", "layout", "memory", "status", "syscall"];
 
 pub struct ContextScheme;
 
+impl ContextScheme {
+    /// List the PIDs of all contexts
+    fn list() -> String {
+        let mut string = String::new();
+
+        let contexts = unsafe { & *::env().contexts.get() };
+        for context in contexts.iter() {
+            if ! string.is_empty() {
+                string.push('\n');
+            }
+            string.push_str(&format!("{}/", context.pid));
+        }
+
+        string
+    }
+
+    fn status(context: &Context) -> String {
+        let mut memory = 0;
+        if context.kernel_stack > 0 {
+            memory += context::CONTEXT_STACK_SIZE;
+        }
+        if let Some(ref stack) = context.stack {
+            memory += stack.virtual_size;
+        }
+        memory += unsafe { (*context.image.get()).size() };
+        memory += unsafe { (*context.heap.get()).size() };
+        memory += unsafe { (*context.mmap.get()).size() };
+
+        let memory_string = if memory >= 1024 * 1024 * 1024 {
+            format!("{} GB", memory / 1024 / 1024 / 1024)
+        } else if memory >= 1024 * 1024 {
+            format!("{} MB", memory / 1024 / 1024)
+        } else if memory >= 1024 {
+            format!("{} KB", memory / 1024)
+        } else {
+            format!("{} B", memory)
+        };
+
+        let mut flags_string = String::new();
+        if context.stack.is_some() {
+            flags_string.push('U');
+        } else {
+            flags_string.push('K');
+        }
+        if context.blocked > 0 {
+            flags_string.push('B');
+        }
+        if context.exited {
+            flags_string.push('E');
+        }
+        if context.vfork.is_some() {
+            flags_string.push('V');
+        }
+        if context.wake.is_some() {
+            flags_string.push('S');
+        }
+        if context.supervised {
+            flags_string.push('T');
+        }
+        if context.stopped {
+            flags_string.push('P');
+        }
+
+        format!("Name: {}\nPID: {}\nPPID: {}\nPGID: {}\nSID: {}\nFlags: {}\nIOPL: {}\nNice: {}\nPriority: {}\nSwitch: {}\nTime: {}\nUser: {}.{:03} s\nSystem: {}.{:03} s\nMemory: {}\nFiles: {}\n",
+                context.name,
+                context.pid,
+                context.ppid,
+                context.pgid,
+                context.sid,
+                flags_string,
+                context.iopl,
+                context.nice,
+                context.priority(),
+                context.switch,
+                context.time,
+                context.user_time.secs,
+                context.user_time.nanos / 1000000,
+                context.system_time.secs,
+                context.system_time.nanos / 1000000,
+                memory_string,
+                unsafe { (*context.files.get()).len() })
+    }
+
+    fn files(context: &Context) -> String {
+        let mut string = String::new();
+
+        for file in unsafe { (*context.files.get()).iter() } {
+            let mut buf = [0; 4096];
+            let path = match file.resource.path(&mut buf) {
+                Ok(count) => String::from_utf8_lossy(&buf[..count]).into_owned(),
+                Err(err) => format!("({})", err),
+            };
+            string.push_str(&format!("{:<6}{}\n", file.fd, path));
+        }
+
+        string
+    }
+
+    fn memory(context: &Context) -> String {
+        fn memory_line(zone: &str, mem: &ContextMemory) -> String {
+            // Private, shared copy-on-write, or shared with the owner of the memory
+            let sharing = if mem.allocated {
+                'p'
+            } else if mem.shared.is_some() {
+                'c'
+            } else {
+                's'
+            };
+
+            format!("{:<8}{:08X}-{:08X} {:>10} r{}{}\n",
+                    zone,
+                    mem.virtual_address,
+                    mem.virtual_address + mem.virtual_size,
+                    mem.virtual_size,
+                    if mem.writeable { 'w' } else { '-' },
+                    sharing)
+        }
+
+        let mut string = format!("{:<8}{:<18}{:>10} {}\n", "ZONE", "ADDRESS", "SIZE", "PERM");
+
+        for mem in unsafe { (*context.image.get()).memory.iter() } {
+            string.push_str(&memory_line("image", mem));
+        }
+        for mem in unsafe { (*context.heap.get()).memory.iter() } {
+            string.push_str(&memory_line("heap", mem));
+        }
+        for mem in unsafe { (*context.mmap.get()).memory.iter() } {
+            string.push_str(&memory_line("mmap", mem));
+        }
+        if let Some(ref stack) = context.stack {
+            string.push_str(&memory_line("stack", stack));
+        }
+
+        string
+    }
+
+    /// The ranges of the zones of a context, which are randomized when a program is executed
+    fn layout(context: &Context) -> String {
+        fn layout_line(zone: &str, address: usize, size: usize) -> String {
+            format!("{:<8}{:08X}-{:08X} {:>10}\n", zone, address, address + size, size)
+        }
+
+        let mut string = format!("{:<8}{:<18}{:>10}\n", "ZONE", "ADDRESS", "SIZE");
+
+        for &(name, ref zone) in [("image", &context.image), ("heap", &context.heap), ("mmap", &context.mmap)].iter() {
+            let zone = unsafe { &*zone.get() };
+            string.push_str(&layout_line(name, zone.address, zone.size));
+        }
+        if let Some(ref stack) = context.stack {
+            string.push_str(&layout_line("stack", stack.virtual_address, stack.virtual_size));
+        }
+
+        string
+    }
+
+    fn env(context: &Context) -> String {
+        let mut string = String::new();
+
+        for var in context.env_vars.iter() {
+            string = string + var + "\n";
+        }
+
+        string
+    }
+
+    fn syscall(context: &Context) -> String {
+        if let Some(current_syscall) = context.current_syscall {
+            format!("{} {} {:X} {:X} {:X} {:X}\n",
+                    current_syscall.1,
+                    syscall::name(current_syscall.1),
+                    current_syscall.2,
+                    current_syscall.3,
+                    current_syscall.4,
+                    current_syscall.0)
+        } else {
+            "running\n".to_string()
+        }
+    }
+}
+
 impl KScheme for ContextScheme {
     fn scheme(&self) -> &str {
         "context"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
-        let mut string = format!("{:<6}{:<6}{:<8}{:<8}{:<8}{:<6}{:<6}{:<6}{}\n",
-                                 "PID",
-                                 "PPID",
-                                 "SWITCH",
-                                 "TIME",
-                                 "MEM",
-                                 "FDS",
-                                 "FLG",
-                                 "IOPL",
-                                 "NAME");
-        {
-            let contexts = unsafe { & *::env().contexts.get() };
-            for context in contexts.iter() {
-                let mut memory = 0;
-                if context.kernel_stack > 0 {
-                    memory += context::CONTEXT_STACK_SIZE;
-                }
-                if let Some(ref stack) = context.stack {
-                    memory += stack.virtual_size;
-                }
-                memory += unsafe { (*context.image.get()).size() };
-                memory += unsafe { (*context.heap.get()).size() };
-                memory += unsafe { (*context.mmap.get()).size() };
-
-                let memory_string = if memory >= 1024 * 1024 * 1024 {
-                    format!("{} GB", memory / 1024 / 1024 / 1024)
-                } else if memory >= 1024 * 1024 {
-                    format!("{} MB", memory / 1024 / 1024)
-                } else if memory >= 1024 {
-                    format!("{} KB", memory / 1024)
-                } else {
-                    format!("{} B", memory)
-                };
-
-                let mut flags_string = String::new();
-                if context.stack.is_some() {
-                    flags_string.push('U');
-                } else {
-                    flags_string.push('K');
-                }
-                if context.blocked > 0 {
-                    flags_string.push('B');
-                }
-                if context.exited {
-                    flags_string.push('E');
-                }
-                if context.vfork.is_some() {
-                    flags_string.push('V');
-                }
-                if context.wake.is_some() {
-                    flags_string.push('S');
-                }
-                if context.supervised {
-                    flags_string.push('T');
-                }
-
-                string.push_str(&format!("{:<6}{:<6}{:<8}{:<8}{:<8}{:<6}{:<6}{:<6}{}\n",
-                                   context.pid,
-                                   context.ppid,
-                                   context.switch,
-                                   context.time,
-                                   memory_string,
-                                   unsafe { (*context.files.get()).len() },
-                                   flags_string,
-                                   context.iopl,
-                                   context.name));
-            }
+    /// Open `context:` to list PIDs, `context:<pid>` to list the entries of a context, or
+    /// `context:<pid>/<entry>` to read the entry
+    fn open(&mut self, url: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
+        let reference = url.reference().trim_matches('/');
+        if reference.is_empty() {
+            return Ok(box VecResource::new(url.to_string(), ContextScheme::list().into_bytes()));
         }
 
-        Ok(box VecResource::new("context:".to_string(), string.into_bytes()))
+        let mut parts = reference.splitn(2, '/');
+        let pid = try!(parts.next().unwrap_or("").parse::<usize>().or(Err(Error::new(ENOENT))));
+        let entry = parts.next().unwrap_or("");
+
+        let contexts = unsafe { & *::env().contexts.get() };
+        let context = try!(contexts.find(pid).or(Err(Error::new(ENOENT))));
+
+        let string = match entry {
+            "" => {
+                let mut list = String::new();
+                for entry in ENTRIES.iter() {
+                    if ! list.is_empty() {
+                        list.push('\n');
+                    }
+                    list.push_str(entry);
+                }
+                list
+            },
+            "cwd" => unsafe { (*context.cwd.get()).clone() },
+            "env" => ContextScheme::env(context),
+            "files" => ContextScheme::files(context),
+            "layout" => ContextScheme::layout(context),
+            "memory" => ContextScheme::memory(context),
+            "status" => ContextScheme::status(context),
+            "syscall" => ContextScheme::syscall(context),
+            _ => return Err(Error::new(ENOENT)),
+        };
+
+        Ok(box VecResource::new(url.to_string(), string.into_bytes()))
     }
 }
@@ -7,7 +7,37 @@ use core::cmp;
 
 use fs::{KScheme, Resource, Url};
 
-use system::error::Result;
+use syscall::signal::{kill_group, stop, terminate};
+
+use system::error::{Error, Result, EIO};
+use system::syscall::{EVENT_READ, EVENT_WRITE, SIGTTIN};
+
+/// Stop the process group of the current context with `SIGTTIN` until it is in the foreground of
+/// the console
+///
+/// If the current context does not stop for `SIGTTIN`, reading fails with `EIO`.
+fn wait_foreground() -> Result<()> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+    loop {
+        let foreground_group = unsafe { & *::env().console.get() }.foreground_group;
+        if foreground_group == 0 || current.pgid == foreground_group {
+            return Ok(());
+        }
+
+        if ! current.sig_stops(SIGTTIN) {
+            return Err(Error::new(EIO));
+        }
+
+        let _ = kill_group(current.pgid, SIGTTIN);
+        current.sig_pending &= !(1 << SIGTTIN);
+        stop(current, SIGTTIN);
+
+        if let Some(sig) = current.fatal_signal() {
+            terminate(sig);
+        }
+    }
+}
 
 /// A debug resource
 pub struct DebugResource {
@@ -35,6 +65,7 @@ impl Resource for DebugResource {
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         if self.command.is_empty() {
+            try!(wait_foreground());
             self.command = unsafe { &mut *::env().console.get() }.commands.receive("DebugResource::read");
         }
 
@@ -47,6 +78,15 @@ impl Resource for DebugResource {
         Ok(i)
     }
 
+    /// Readable once a command was entered on the console, writing never blocks
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        let mut events = EVENT_WRITE;
+        if ! self.command.is_empty() || ! unsafe { (*::env().console.get()).commands.inner() }.is_empty() {
+            events |= EVENT_READ;
+        }
+        Ok(events & flags)
+    }
+
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         unsafe { &mut *::env().console.get() }.write(buf);
         Ok(buf.len())
@@ -73,7 +113,7 @@ impl KScheme for DebugScheme {
         "debug"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         let console = unsafe { & *::env().console.get() };
         if let Some(ref display) = console.display {
             Ok(box DebugResource {
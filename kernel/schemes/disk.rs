@@ -11,7 +11,10 @@ use fs::{KScheme, Resource, ResourceSeek, Url, VecResource};
 
 use syscall::{MODE_DIR, MODE_FILE, Stat};
 
-use system::error::{Error, Result, ENOENT};
+use system::error::{Error, Result, EACCES, ENOENT};
+
+/// The size of a sector of a disk
+const SECTOR_SIZE: u64 = 512;
 
 /// A disk resource
 pub struct DiskResource {
@@ -60,11 +63,26 @@ impl Resource for DiskResource {
         Ok(self.seek as usize)
     }
 
+    fn stat(&self, stat: &mut Stat) -> Result<usize> {
+        disk_stat(unsafe { & *self.disk.get() }, stat);
+        Ok(0)
+    }
+
     fn sync(&mut self) -> Result<()> {
         Ok(())
     }
 }
 
+/// Describe a disk, which only root can open
+fn disk_stat(disk: &Box<Disk>, stat: &mut Stat) {
+    let size = disk.size();
+    stat.st_mode = MODE_FILE | 0o600;
+    stat.st_nlink = 1;
+    stat.st_size = size;
+    stat.st_blksize = SECTOR_SIZE as u32;
+    stat.st_blocks = (size + SECTOR_SIZE - 1) / SECTOR_SIZE;
+}
+
 impl Drop for DiskResource {
     fn drop(&mut self) {
         let _ = self.sync();
@@ -102,7 +120,7 @@ impl KScheme for DiskScheme {
         }
     }
 
-    fn open(&mut self, url: Url, _flags: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, _flags: usize, uid: usize, _: usize) -> Result<Box<Resource>> {
         let path = url.reference().trim_matches('/');
 
         if path.is_empty() {
@@ -118,6 +136,11 @@ impl KScheme for DiskScheme {
         } else {
             if let Ok(number) = path.parse::<usize>() {
                 if let Some(disk) = self.disks.get(number) {
+                    // Raw access to a disk bypasses the permissions of its filesystem
+                    if uid != 0 {
+                        return Err(Error::new(EACCES));
+                    }
+
                     return Ok(box DiskResource {
                         path: format!("disk:/{}", number),
                         disk: disk.clone(),
@@ -142,14 +165,14 @@ impl KScheme for DiskScheme {
                 list.push_str(&format!("{}", i));
             }
 
-            stat.st_mode = MODE_DIR;
-            stat.st_size = list.len() as u32;
+            stat.st_mode = MODE_DIR | 0o755;
+            stat.st_nlink = 1;
+            stat.st_size = list.len() as u64;
             return Ok(());
         } else {
             if let Ok(number) = path.parse::<usize>() {
                 if let Some(disk) = self.disks.get(number) {
-                    stat.st_mode = MODE_FILE;
-                    stat.st_size = unsafe { & *disk.get() }.size() as u32;
+                    disk_stat(unsafe { & *disk.get() }, stat);
                     return Ok(());
                 }
             }
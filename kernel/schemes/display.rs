@@ -1,3 +1,4 @@
+use alloc::arc::Arc;
 use alloc::boxed::Box;
 
 use collections::String;
@@ -5,12 +6,16 @@ use collections::String;
 use common::event::Event;
 
 use core::{cmp, ptr};
+use core::cell::Cell;
 use core::mem::size_of;
 
+use arch::context::SharedMemory;
+
 use fs::{KScheme, Resource, ResourceSeek, Url};
 
-use system::error::{Error, Result, EACCES, EBADF, ENOENT, EINVAL};
+use system::error::{Error, Result, EACCES, EAGAIN, EBADF, ENOENT, EINVAL};
 use system::graphics::fast_copy;
+use system::syscall::{EVENT_READ, EVENT_WRITE, F_GETFL, F_SETFL, O_NONBLOCK};
 
 /// A display resource
 pub struct DisplayResource {
@@ -18,13 +23,16 @@ pub struct DisplayResource {
     path: String,
     /// Seek
     seek: usize,
+    /// Flags, which are shared with the duplicates of the resource
+    flags: Arc<Cell<usize>>,
 }
 
 impl Resource for DisplayResource {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(Box::new(DisplayResource {
             path: self.path.clone(),
-            seek: self.seek
+            seek: self.seek,
+            flags: self.flags.clone(),
         }))
     }
 
@@ -41,6 +49,10 @@ impl Resource for DisplayResource {
 
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         if buf.len() >= size_of::<Event>() {
+            if self.flags.get() & O_NONBLOCK == O_NONBLOCK && unsafe { ::env().events.inner() }.is_empty() {
+                return Err(Error::new(EAGAIN));
+            }
+
             let event = ::env().events.receive("DisplayResource::read");
             unsafe { ptr::write(buf.as_mut_ptr().offset(0isize) as *mut Event, event) };
             let mut i = size_of::<Event>();
@@ -77,6 +89,26 @@ impl Resource for DisplayResource {
         }
     }
 
+    /// Readable once there are input events, writing never blocks
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        let mut events = EVENT_WRITE;
+        if ! unsafe { ::env().events.inner() }.is_empty() {
+            events |= EVENT_READ;
+        }
+        Ok(events & flags)
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        match cmd {
+            F_GETFL => Ok(self.flags.get()),
+            F_SETFL => {
+                self.flags.set((self.flags.get() & !O_NONBLOCK) | (arg & O_NONBLOCK));
+                Ok(0)
+            },
+            _ => Err(Error::new(EINVAL))
+        }
+    }
+
     fn seek(&mut self, pos: ResourceSeek) -> Result<usize> {
         let console = unsafe { & *::env().console.get() };
         if let Some(ref display) = console.display {
@@ -95,6 +127,20 @@ impl Resource for DisplayResource {
     fn sync(&mut self) -> Result<()> {
         Ok(())
     }
+
+    /// Map the framebuffer, so that it can be drawn to without copying
+    fn mmap(&mut self, offset: usize, size: usize) -> Result<(usize, Option<Arc<SharedMemory>>)> {
+        let console = unsafe { & *::env().console.get() };
+        if let Some(ref display) = console.display {
+            if offset + size <= display.size * 4 {
+                Ok((display.onscreen as usize + offset, None))
+            } else {
+                Err(Error::new(EINVAL))
+            }
+        } else {
+            Err(Error::new(EBADF))
+        }
+    }
 }
 
 pub struct DisplayScheme;
@@ -104,16 +150,17 @@ impl KScheme for DisplayScheme {
         "display"
     }
 
-    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, flags: usize, uid: usize, _: usize) -> Result<Box<Resource>> {
         if url.reference() == "manager" {
             let console = unsafe { &mut *::env().console.get() };
-            if console.draw {
+            if console.draw && uid == 0 {
                 console.draw = false;
 
                 if let Some(ref display) = console.display {
                     Ok(box DisplayResource {
                         path: format!("display:{}/{}", display.width, display.height),
                         seek: 0,
+                        flags: Arc::new(Cell::new(flags)),
                     })
                 } else {
                     Err(Error::new(ENOENT))
@@ -127,6 +174,7 @@ impl KScheme for DisplayScheme {
                 Ok(box DisplayResource {
                     path: format!("display:{}/{}", display.width, display.height),
                     seek: 0,
+                    flags: Arc::new(Cell::new(flags)),
                 })
             } else {
                 Err(Error::new(ENOENT))
@@ -0,0 +1,168 @@
+use alloc::boxed::Box;
+
+use collections::Vec;
+
+use core::{cmp, mem, ptr};
+use core::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
+
+use fs::{KScheme, Resource, Url};
+
+use system::error::{Error, Result, EINTR, EINVAL};
+use system::syscall::{FileEvent, EVENT_HUP};
+
+/// The number of notifications sent so far
+static NOTIFICATIONS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Wake up the contexts reading `event:`, as the events of some resource may have changed
+///
+/// Resources have to call this whenever they may become ready, or hung up.
+pub fn event_notify(reason: &str) {
+    NOTIFICATIONS.fetch_add(1, Ordering::SeqCst);
+    ::env().event_condition.notify(reason);
+}
+
+/// An event resource, which waits for the events of file descriptors of the current context
+///
+/// Writing a `FileEvent` registers the events of its file descriptor, reading blocks until some
+/// of them are ready (see `sys_fevent`).
+pub struct EventResource {
+    /// The registered file descriptors, with the events to wait for
+    events: Vec<FileEvent>,
+    /// The index of the registered file descriptor to ask first on the next read
+    next: usize,
+}
+
+impl EventResource {
+    /// Write the events that are ready to `buf`, returning the number of bytes written
+    fn ready(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        let current = try!(contexts.current_mut());
+
+        // File descriptors that were closed are forgotten
+        self.events.retain(|event| current.get_file(event.id).is_ok());
+
+        // The scan starts after the last file descriptor reported, so the later ones are not
+        // starved when the buffer is too small for all of them
+        let len = self.events.len();
+        let mut i = 0;
+        for offset in 0..len {
+            if i + mem::size_of::<FileEvent>() > buf.len() {
+                break;
+            }
+
+            let index = (self.next + offset) % len;
+            let event = self.events[index];
+
+            // A resource failing to report its events can not be waited for anymore
+            let flags = match current.get_file_mut(event.id).and_then(|resource| resource.event(event.flags)) {
+                Ok(flags) => flags & (event.flags | EVENT_HUP),
+                Err(_) => EVENT_HUP,
+            };
+            if flags != 0 {
+                let ready = FileEvent {
+                    id: event.id,
+                    flags: flags,
+                    data: event.data,
+                };
+                unsafe { ptr::write(buf.as_mut_ptr().offset(i as isize) as *mut FileEvent, ready) };
+                i += mem::size_of::<FileEvent>();
+                self.next = index + 1;
+            }
+        }
+
+        Ok(i)
+    }
+}
+
+impl Resource for EventResource {
+    fn dup(&self) -> Result<Box<Resource>> {
+        Ok(box EventResource {
+            events: self.events.clone(),
+            next: self.next,
+        })
+    }
+
+    fn path(&self, buf: &mut [u8]) -> Result<usize> {
+        let path = b"event:";
+
+        for (b, p) in buf.iter_mut().zip(path.iter()) {
+            *b = *p;
+        }
+
+        Ok(cmp::min(buf.len(), path.len()))
+    }
+
+    /// Wait until one of the registered file descriptors is ready
+    ///
+    /// Resources are asked for their events again only after one of them called `event_notify`.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.len() < mem::size_of::<FileEvent>() {
+            return Err(Error::new(EINVAL));
+        }
+
+        loop {
+            let notifications = NOTIFICATIONS.load(Ordering::SeqCst);
+
+            let count = try!(self.ready(buf));
+            if count > 0 || self.events.is_empty() {
+                return Ok(count);
+            }
+
+            // Asking a scheme for its events blocks, so resources may have been notified meanwhile
+            if NOTIFICATIONS.load(Ordering::SeqCst) != notifications {
+                continue;
+            }
+
+            {
+                let contexts = unsafe { &*::env().contexts.get() };
+                let current = try!(contexts.current());
+                if current.sig_pending & !current.sig_mask != 0 || current.fatal_signal().is_some() {
+                    return Err(Error::new(EINTR));
+                }
+            }
+
+            // The kernel lock is held, so no resource can be notified between this check and the wait
+            ::env().event_condition.wait("EventResource::read");
+        }
+    }
+
+    /// Register the events of file descriptors, or remove them if their flags are empty
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if buf.len() % mem::size_of::<FileEvent>() != 0 {
+            return Err(Error::new(EINVAL));
+        }
+
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        let current = try!(contexts.current_mut());
+
+        let mut i = 0;
+        while i < buf.len() {
+            let event = unsafe { ptr::read(buf.as_ptr().offset(i as isize) as *const FileEvent) };
+            self.events.retain(|registered| registered.id != event.id);
+            if event.flags != 0 {
+                // The resource has to support events
+                try!(try!(current.get_file_mut(event.id)).event(event.flags));
+                self.events.push(event);
+            }
+            i += mem::size_of::<FileEvent>();
+        }
+
+        Ok(i)
+    }
+}
+
+/// The event scheme, which opens a new `EventResource`
+pub struct EventScheme;
+
+impl KScheme for EventScheme {
+    fn scheme(&self) -> &str {
+        "event"
+    }
+
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
+        Ok(box EventResource {
+            events: Vec::new(),
+            next: 0,
+        })
+    }
+}
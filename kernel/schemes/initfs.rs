@@ -27,7 +27,7 @@ impl KScheme for InitFsScheme {
         "initfs"
     }
 
-    fn open(&mut self, url: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, url: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         let reference = url.reference().trim_matches('/');
         if reference.is_empty() {
             let mut list = String::new();
@@ -1,5 +1,7 @@
 use alloc::boxed::Box;
 
+use arch::apic;
+
 use collections::string::ToString;
 
 use fs::{KScheme, Resource, Url, VecResource};
@@ -32,7 +34,7 @@ impl KScheme for InterruptScheme {
         "interrupt"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         let mut string = format!("{:<6}{:<16}{}\n", "INT", "COUNT", "DESCRIPTION");
 
         {
@@ -42,8 +44,11 @@ impl KScheme for InterruptScheme {
 
                 if count > 0 {
                     let description = match interrupt {
-                        i @ 0x20 ... 0x30 => IRQ_NAME[i - 0x20],
+                        i @ 0x20 ... 0x2F => IRQ_NAME[i - 0x20],
+                        0x30 ... 0x5F => "PCI interrupt",
                         0x80 => "System Call",
+                        apic::SPURIOUS_INTERRUPT => "Spurious interrupt",
+                        apic::TIMER_INTERRUPT => "Local APIC Timer",
                         0x0 => "Divide by zero exception",
                         0x1 => "Debug exception",
                         0x2 => "Non-maskable interrupt",
@@ -16,7 +16,7 @@ impl KScheme for MemoryScheme {
         "memory"
     }
 
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         let string = format!("Memory Used: {} KB\nMemory Free: {} KB\n",
                              memory::memory_used() / 1024,
                              memory::memory_free() / 1024);
@@ -6,8 +6,8 @@ pub mod debug;
 pub mod disk;
 /// Display Scheme
 pub mod display;
-/// Environment variables scheme
-pub mod env;
+/// Event scheme
+pub mod event;
 /// Init Filesystem
 pub mod initfs;
 /// Interrupt scheme
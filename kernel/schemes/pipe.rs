@@ -1,23 +1,32 @@
 use alloc::arc::{Arc, Weak};
 use alloc::boxed::Box;
 
+use core::cell::Cell;
 use core::cmp;
 
 use fs::Resource;
 
 use sync::WaitQueue;
 
-use system::error::{Error, Result, EPIPE};
+use super::event::event_notify;
+
+use system::error::{Error, Result, EAGAIN, EINVAL, EPIPE};
+use system::syscall::{EVENT_HUP, EVENT_READ, EVENT_WRITE, F_GETFL, F_SETFL, O_NONBLOCK, O_RDONLY,
+                     O_WRONLY};
 
 /// Read side of a pipe
 pub struct PipeRead {
-    vec: Arc<WaitQueue<u8>>
+    vec: Arc<WaitQueue<u8>>,
+    /// The status flags, which are shared with the duplicates of this side
+    flags: Arc<Cell<usize>>,
 }
 
 impl PipeRead {
-    pub fn new() -> Self {
+    /// Create a pipe, which is nonblocking if `O_NONBLOCK` is in `flags`
+    pub fn new(flags: usize) -> Self {
         PipeRead {
-            vec: Arc::new(WaitQueue::new())
+            vec: Arc::new(WaitQueue::new()),
+            flags: Arc::new(Cell::new(O_RDONLY | (flags & O_NONBLOCK))),
         }
     }
 }
@@ -26,6 +35,7 @@ impl Resource for PipeRead {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(box PipeRead {
             vec: self.vec.clone(),
+            flags: self.flags.clone(),
         })
     }
 
@@ -42,6 +52,8 @@ impl Resource for PipeRead {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         if Arc::weak_count(&self.vec) == 0 && unsafe { self.vec.inner() }.is_empty() {
             Ok(0)
+        } else if self.flags.get() & O_NONBLOCK == O_NONBLOCK && unsafe { self.vec.inner() }.is_empty() {
+            Err(Error::new(EAGAIN))
         } else {
             if !buf.is_empty() {
                 buf[0] = self.vec.receive("PipeRead::read");
@@ -62,17 +74,50 @@ impl Resource for PipeRead {
             Ok(i)
         }
     }
+
+    /// The pipe is readable if it has data, or once all write sides are closed
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        let mut events = 0;
+        if ! unsafe { self.vec.inner() }.is_empty() {
+            events |= EVENT_READ;
+        }
+        if Arc::weak_count(&self.vec) == 0 {
+            events |= EVENT_READ | EVENT_HUP;
+        }
+        Ok(events & (flags | EVENT_HUP))
+    }
+
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        match cmd {
+            F_GETFL => Ok(self.flags.get()),
+            F_SETFL => {
+                self.flags.set((self.flags.get() & !O_NONBLOCK) | (arg & O_NONBLOCK));
+                Ok(0)
+            },
+            _ => Err(Error::new(EINVAL))
+        }
+    }
+}
+
+/// The write sides are hung up once the read side is closed
+impl Drop for PipeRead {
+    fn drop(&mut self) {
+        event_notify("PipeRead::drop");
+    }
 }
 
 /// Read side of a pipe
 pub struct PipeWrite {
     vec: Weak<WaitQueue<u8>>,
+    /// The status flags, which are shared with the duplicates of this side
+    flags: Arc<Cell<usize>>,
 }
 
 impl PipeWrite {
-    pub fn new(read: &PipeRead) -> Self {
+    pub fn new(read: &PipeRead, flags: usize) -> Self {
         PipeWrite {
             vec: Arc::downgrade(&read.vec),
+            flags: Arc::new(Cell::new(O_WRONLY | (flags & O_NONBLOCK))),
         }
     }
 }
@@ -81,6 +126,7 @@ impl Resource for PipeWrite {
     fn dup(&self) -> Result<Box<Resource>> {
         Ok(box PipeWrite {
             vec: self.vec.clone(),
+            flags: self.flags.clone(),
         })
     }
 
@@ -101,14 +147,45 @@ impl Resource for PipeWrite {
                     vec.send(b, "PipeWrite::write");
                 }
 
+                event_notify("PipeWrite::write");
+
                 Ok(buf.len())
             },
             None => Err(Error::new(EPIPE))
         }
     }
 
+    /// Writing never blocks, but fails once the read side is closed
+    fn event(&mut self, flags: usize) -> Result<usize> {
+        let events = if self.vec.upgrade().is_some() {
+            EVENT_WRITE
+        } else {
+            EVENT_HUP
+        };
+        Ok(events & (flags | EVENT_HUP))
+    }
+
+    /// Writing never blocks, so the flags are only kept for `F_GETFL`
+    fn fcntl(&mut self, cmd: usize, arg: usize) -> Result<usize> {
+        match cmd {
+            F_GETFL => Ok(self.flags.get()),
+            F_SETFL => {
+                self.flags.set((self.flags.get() & !O_NONBLOCK) | (arg & O_NONBLOCK));
+                Ok(0)
+            },
+            _ => Err(Error::new(EINVAL))
+        }
+    }
+
     fn sync(&mut self) -> Result<()> {
         //TODO: Wait until empty
         Ok(())
     }
 }
+
+/// The read side is hung up once all write sides are closed
+impl Drop for PipeWrite {
+    fn drop(&mut self) {
+        event_notify("PipeWrite::drop");
+    }
+}
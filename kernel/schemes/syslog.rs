@@ -15,7 +15,7 @@ impl KScheme for SyslogScheme {
     }
 
     /// Returns a resource. The `url` and `flags` arguments are currently unused.
-    fn open(&mut self, _: Url, _: usize) -> Result<Box<Resource>> {
+    fn open(&mut self, _: Url, _: usize, _: usize, _: usize) -> Result<Box<Resource>> {
         Ok(Box::new(SyslogResource {
             pos: 0,
         }))
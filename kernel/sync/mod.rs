@@ -1,7 +1,9 @@
+pub use self::ticket_lock::TicketLock;
 pub use self::wait_condition::WaitCondition;
 pub use self::wait_queue::WaitQueue;
 pub use self::wait_map::WaitMap;
 
+pub mod ticket_lock;
 pub mod wait_condition;
 pub mod wait_queue;
 pub mod wait_map;
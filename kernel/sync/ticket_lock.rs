@@ -0,0 +1,49 @@
+use arch::smp;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A spin lock that is handed out in the order it was asked for, so that no processor is starved
+///
+/// Interrupts are disabled while it is held. It must not be held while blocking, as another
+/// context on the same processor would spin forever.
+pub struct TicketLock {
+    next: AtomicUsize,
+    serving: AtomicUsize,
+}
+
+impl TicketLock {
+    pub const fn new() -> TicketLock {
+        TicketLock {
+            next: AtomicUsize::new(0),
+            serving: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take the lock, which is released when the guard is dropped
+    pub fn lock(&self) -> TicketLockGuard {
+        let interrupts = unsafe { smp::disable_interrupts() };
+
+        let ticket = self.next.fetch_add(1, Ordering::SeqCst);
+        while self.serving.load(Ordering::SeqCst) != ticket {
+            unsafe { asm!("pause" : : : "memory" : "intel", "volatile") };
+        }
+
+        TicketLockGuard {
+            lock: self,
+            interrupts: interrupts,
+        }
+    }
+}
+
+pub struct TicketLockGuard<'a> {
+    lock: &'a TicketLock,
+    /// Interrupts were enabled before the lock was taken
+    interrupts: bool,
+}
+
+impl<'a> Drop for TicketLockGuard<'a> {
+    fn drop(&mut self) {
+        self.lock.serving.fetch_add(1, Ordering::SeqCst);
+        unsafe { smp::restore_interrupts(self.interrupts) };
+    }
+}
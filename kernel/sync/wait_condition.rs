@@ -3,9 +3,11 @@ use arch::context::{context_switch, Context};
 use collections::Vec;
 
 use core::cell::UnsafeCell;
-use core::mem;
+use core::{cmp, mem};
 use core::ops::DerefMut;
 
+use syscall::signal::{stop, terminate};
+
 pub struct WaitCondition {
     contexts: UnsafeCell<Vec<*mut Context>>
 }
@@ -33,6 +35,16 @@ impl WaitCondition {
         }
     }
 
+    /// Wake up to `count` contexts, in the order they started waiting, returning how many were woken
+    pub fn notify_count(&self, count: usize, reason: &str) -> usize {
+        let contexts = unsafe { &mut *self.contexts.get() };
+        let count = cmp::min(count, contexts.len());
+        for context in contexts.drain(..count) {
+            unsafe { (*context).unblock(reason) }
+        }
+        count
+    }
+
     pub fn wait(&self, reason: &str) {
         {
             // debugln!("  WaitCondition::wait {:X} {}", self as *const _ as usize, reason);
@@ -40,11 +52,33 @@ impl WaitCondition {
                 let mut contexts = unsafe { &mut *self.contexts.get() };
                 contexts.push(context.deref_mut() as *mut Context);
                 (*context).block(reason);
+                context.waiting = true;
             } else {
                 // debugln!("    NOT FOUND {}/{}", unsafe { & *::env().contexts.get() }.i, unsafe { & *::env().contexts.get() }.len());
             }
         }
         unsafe { context_switch(); }
+
+        if let Ok(mut context) = unsafe { &mut *::env().contexts.get() }.current_mut() {
+            context.waiting = false;
+            let context_ptr = context.deref_mut() as *mut Context;
+
+            // A signal wakes the context up early, so it has to stop waiting. The caller returns
+            // EINTR or waits again
+            unsafe { &mut *self.contexts.get() }.retain(|&waiting| waiting != context_ptr);
+
+            // A signal stopping the context stops it here, and it returns to the caller once
+            // continued, which waits again if it has to
+            if let Some(sig) = context.stop_signal() {
+                context.sig_pending &= !(1 << sig);
+                stop(context, sig);
+            }
+
+            // A fatal signal has to exit
+            if let Some(sig) = context.fatal_signal() {
+                terminate(sig);
+            }
+        }
     }
 }
 
@@ -26,10 +26,50 @@ impl<K, V> WaitMap<K, V> where K: Ord {
 
     pub fn receive(&self, key: &K, reason: &str) -> V {
         loop {
-            if let Some(value) = unsafe { self.inner() }.remove(key) {
+            if let Some(value) = self.try_receive(key) {
                 return value;
             }
             self.condition.wait(reason);
         }
     }
+
+    pub fn try_receive(&self, key: &K) -> Option<V> {
+        unsafe { self.inner() }.remove(key)
+    }
+
+    pub fn receive_any(&self, reason: &str) -> (K, V) where K: Clone {
+        loop {
+            if let Some(entry) = self.try_receive_any() {
+                return entry;
+            }
+            self.condition.wait(reason);
+        }
+    }
+
+    pub fn try_receive_any(&self) -> Option<(K, V)> where K: Clone {
+        let key = match unsafe { self.inner() }.keys().next() {
+            Some(key) => key.clone(),
+            None => return None
+        };
+        self.try_receive(&key).map(|value| (key, value))
+    }
+
+    /// Wait for the first entry that `f` accepts, leaving the others
+    pub fn receive_where<F>(&self, f: F, reason: &str) -> (K, V) where K: Clone, F: Fn(&K, &V) -> bool {
+        loop {
+            if let Some(entry) = self.try_receive_where(&f) {
+                return entry;
+            }
+            self.condition.wait(reason);
+        }
+    }
+
+    /// Take the first entry that `f` accepts, leaving the others
+    pub fn try_receive_where<F>(&self, f: F) -> Option<(K, V)> where K: Clone, F: Fn(&K, &V) -> bool {
+        let key = match unsafe { self.inner() }.iter().find(|&(key, value)| f(key, value)) {
+            Some((key, _)) => key.clone(),
+            None => return None
+        };
+        self.try_receive(&key).map(|value| (key, value))
+    }
 }
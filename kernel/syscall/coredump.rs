@@ -0,0 +1,290 @@
+//! Core dumps of crashed contexts
+//!
+//! A core dump is an ELF file of type `ET_CORE`, which gdb reads together with the executable. It
+//! has a note segment with the registers and the name of the context, followed by a load segment
+//! for each region of its memory: the image, the heap, the mmap zone and the stack.
+//!
+//! The path is taken from the `COREDUMP` environment variable the program was executed with, with
+//! `%p` replaced by the PID. It defaults to `file:/tmp/core.%p`, an empty value disables core dumps.
+
+use arch::context::{Context, ContextMemory};
+use arch::elf::{elf_gregset, ElfAddr, ElfHeader, ElfOff, ElfSegment, ELF_CLASS, ELF_MACHINE};
+use arch::memory;
+use arch::regs::Regs;
+
+use collections::{String, Vec};
+
+use common::slice::GetSlice;
+
+use core::{mem, slice};
+
+use fs::{Resource, Url};
+
+use system::error::{Error, Result, EIO, ENOMEM};
+
+/// The path of core dumps, unless the `COREDUMP` environment variable is set
+const CORE_PATTERN: &'static str = "file:/tmp/core.%p";
+
+/// A core dump
+const ET_CORE: u16 = 4;
+/// A segment loaded into memory
+const PT_LOAD: u32 = 1;
+/// A segment of notes
+const PT_NOTE: u32 = 4;
+/// The segment is executable
+const PF_X: u32 = 1;
+/// The segment is writeable
+const PF_W: u32 = 2;
+/// The segment is readable
+const PF_R: u32 = 4;
+/// The note with the signal, the times and the registers
+const NT_PRSTATUS: u32 = 1;
+/// The note with the name of the process
+const NT_PRPSINFO: u32 = 3;
+
+/// Get the path of the core dump of a context, or `None` if core dumps are disabled
+pub fn path(context: &Context) -> Option<String> {
+    let pattern = context.get_env_var("COREDUMP").unwrap_or(CORE_PATTERN);
+    if pattern.is_empty() {
+        None
+    } else {
+        Some(pattern.replace("%p", &format!("{}", context.pid)))
+    }
+}
+
+/// Append the bytes of a value
+fn push<T>(data: &mut Vec<u8>, value: T) {
+    data.extend_from_slice(unsafe { slice::from_raw_parts(&value as *const T as *const u8, mem::size_of::<T>()) });
+}
+
+/// Append zeros until the length is a multiple of `align`
+fn pad(data: &mut Vec<u8>, align: usize) {
+    while data.len() % align != 0 {
+        data.push(0);
+    }
+}
+
+/// Append a note, with its name and description padded to 4 bytes
+fn push_note(data: &mut Vec<u8>, name: &[u8], _type: u32, desc: &[u8]) {
+    push(data, name.len() as u32 + 1);
+    push(data, desc.len() as u32);
+    push(data, _type);
+    data.extend_from_slice(name);
+    data.push(0);
+    pad(data, 4);
+    data.extend_from_slice(desc);
+    pad(data, 4);
+}
+
+/// The description of the `NT_PRSTATUS` note
+fn prstatus(context: &Context, regs: &Regs, sig: usize) -> Vec<u8> {
+    let mut desc = Vec::new();
+
+    // The signal information: the number, the code and the error number
+    push(&mut desc, sig as u32);
+    push(&mut desc, 0u32);
+    push(&mut desc, 0u32);
+    // The current signal
+    push(&mut desc, sig as u16);
+    pad(&mut desc, mem::size_of::<usize>());
+    push(&mut desc, context.sig_pending);
+    push(&mut desc, context.sig_mask);
+    // The PID, the parent PID, the process group and the session
+    push(&mut desc, context.pid as u32);
+    push(&mut desc, context.ppid as u32);
+    push(&mut desc, context.pgid as u32);
+    push(&mut desc, context.sid as u32);
+    // The user and system time, followed by the times of children which are not counted
+    for time in [context.user_time, context.system_time].iter() {
+        push(&mut desc, time.secs as usize);
+        push(&mut desc, time.nanos as usize / 1000);
+    }
+    for _ in 0..4 {
+        push(&mut desc, 0usize);
+    }
+    for &reg in elf_gregset(regs).iter() {
+        push(&mut desc, reg);
+    }
+    // The floating point registers are not saved
+    push(&mut desc, 0u32);
+    pad(&mut desc, mem::size_of::<usize>());
+
+    desc
+}
+
+/// The description of the `NT_PRPSINFO` note
+fn prpsinfo(context: &Context) -> Vec<u8> {
+    let mut desc = Vec::new();
+
+    // The state, its letter, the zombie flag and the nice value
+    desc.push(0);
+    desc.push(b'R');
+    desc.push(0);
+    desc.push(context.nice as i8 as u8);
+    pad(&mut desc, mem::size_of::<usize>());
+    // The flags
+    push(&mut desc, 0usize);
+    // The user and group, which are half of a word wide
+    push(&mut desc, 0usize);
+    // The PID, the parent PID, the process group and the session
+    push(&mut desc, context.pid as u32);
+    push(&mut desc, context.ppid as u32);
+    push(&mut desc, context.pgid as u32);
+    push(&mut desc, context.sid as u32);
+
+    // The file name and the arguments, both truncated and terminated by a zero
+    let fname = context.name.rsplit('/').next().unwrap_or("").as_bytes();
+    let psargs = context.name.as_bytes();
+    for &(name, len) in [(fname, 16), (psargs, 80)].iter() {
+        let start = desc.len();
+        desc.extend_from_slice(name.get_slice(.. len - 1));
+        desc.resize(start + len, 0);
+    }
+
+    desc
+}
+
+/// Write all of `data` to a resource
+fn write_all(resource: &mut Box<Resource>, mut data: &[u8]) -> Result<()> {
+    while ! data.is_empty() {
+        let count = try!(resource.write(data));
+        if count == 0 {
+            return Err(Error::new(EIO));
+        }
+        data = data.get_slice(count ..);
+    }
+
+    Ok(())
+}
+
+/// Write data of the kernel to a resource
+///
+/// Schemes find the data in the memory space of the current context, so it is copied to a
+/// temporary mapping first, like in `execute`.
+unsafe fn write_kernel(context: &Context, resource: &mut Box<Resource>, data: &[u8]) -> Result<()> {
+    let mmap = &mut *context.mmap.get();
+
+    let virtual_size = (data.len() + 4095) / 4096 * 4096;
+    let virtual_address = mmap.next_mem();
+
+    let physical_address = memory::alloc_aligned(virtual_size, 4096);
+    if physical_address == 0 {
+        return Err(Error::new(ENOMEM));
+    }
+
+    let mut memory = ContextMemory {
+        physical_address: physical_address,
+        virtual_address: virtual_address,
+        virtual_size: virtual_size,
+        writeable: true,
+        allocated: true,
+        shared: None,
+        borrowed: None,
+    };
+
+    memory.map();
+
+    mmap.memory.push(memory);
+
+    ::memcpy(virtual_address as *mut u8, data.as_ptr(), data.len());
+    let result = write_all(resource, slice::from_raw_parts(virtual_address as *const u8, data.len()));
+
+    let mut memory = mmap.memory.pop().unwrap();
+
+    memory.unmap();
+
+    result
+}
+
+/// Write a core dump of the current context, which is terminated by `sig`
+///
+/// `regs` are the registers of the context in userspace.
+pub fn write(path: &str, regs: &Regs, sig: usize) -> Result<()> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+
+    // The regions of memory, as the address, the size and if they are writeable
+    let mut regions: Vec<(usize, usize, bool)> = Vec::new();
+    if let Some(ref stack) = current.stack {
+        regions.push((stack.virtual_address, stack.virtual_size, true));
+    }
+    for zone in [&current.image, &current.heap, &current.mmap].iter() {
+        for mem in unsafe { &*zone.get() }.memory.iter() {
+            if mem.virtual_size > 0 {
+                regions.push((mem.virtual_address, mem.virtual_size, mem.writeable));
+            }
+        }
+    }
+    regions.sort();
+
+    let mut notes = Vec::new();
+    push_note(&mut notes, b"CORE", NT_PRSTATUS, &prstatus(current, regs, sig));
+    push_note(&mut notes, b"CORE", NT_PRPSINFO, &prpsinfo(current));
+
+    let ph_len = regions.len() + 1;
+    let notes_off = mem::size_of::<ElfHeader>() + ph_len * mem::size_of::<ElfSegment>();
+    let data_off = (notes_off + notes.len() + 4095) / 4096 * 4096;
+
+    let mut data = Vec::new();
+    push(&mut data, ElfHeader {
+        magic: *b"\x7FELF",
+        class: ELF_CLASS,
+        endian: 1,
+        ver: 1,
+        abi: [0; 2],
+        pad: [0; 7],
+        _type: ET_CORE,
+        machine: ELF_MACHINE,
+        ver_2: 1,
+        entry: 0,
+        ph_off: mem::size_of::<ElfHeader>() as ElfOff,
+        sh_off: 0,
+        flags: 0,
+        h_len: mem::size_of::<ElfHeader>() as u16,
+        ph_ent_len: mem::size_of::<ElfSegment>() as u16,
+        ph_len: ph_len as u16,
+        sh_ent_len: 0,
+        sh_len: 0,
+        sh_str_index: 0,
+    });
+
+    // The sizes have the width of an address on both x86 and x86_64
+    push(&mut data, ElfSegment {
+        _type: PT_NOTE,
+        flags: 0,
+        off: notes_off as ElfOff,
+        vaddr: 0,
+        paddr: 0,
+        file_len: notes.len() as ElfAddr,
+        mem_len: 0,
+        align: 4,
+    });
+
+    let mut off = data_off;
+    for &(address, size, writeable) in regions.iter() {
+        push(&mut data, ElfSegment {
+            _type: PT_LOAD,
+            flags: if writeable { PF_R | PF_W } else { PF_R | PF_X },
+            off: off as ElfOff,
+            vaddr: address as ElfAddr,
+            paddr: 0,
+            file_len: size as ElfAddr,
+            mem_len: size as ElfAddr,
+            align: 4096,
+        });
+        off += size;
+    }
+
+    data.extend_from_slice(&notes);
+    data.resize(data_off, 0);
+
+    let mut resource = try!(try!(Url::from_str(path)).create());
+    try!(unsafe { write_kernel(current, &mut resource, &data) });
+
+    // The memory is written from where it is mapped, which schemes can find
+    for &(address, size, _) in regions.iter() {
+        try!(write_all(&mut resource, unsafe { slice::from_raw_parts(address as *const u8, size) }));
+    }
+
+    resource.sync()
+}
@@ -4,15 +4,18 @@ use alloc::arc::Arc;
 
 use arch::context::{CONTEXT_IMAGE_ADDR, CONTEXT_IMAGE_SIZE, CONTEXT_HEAP_ADDR, CONTEXT_HEAP_SIZE,
                     CONTEXT_MMAP_ADDR, CONTEXT_MMAP_SIZE, CONTEXT_STACK_SIZE, CONTEXT_STACK_ADDR,
+                    CONTEXT_STACK_GUARD, CONTEXT_STACK_LIMIT_MAX, CONTEXT_STACK_RANDOM,
                     context_switch, context_userspace, Context, ContextMemory, ContextZone};
-use arch::elf::Elf;
+use arch::elf::{Elf, ElfSegment, ET_DYN, ET_EXEC};
 use arch::memory;
 use arch::regs::Regs;
+use arch::smp;
 
 use collections::borrow::ToOwned;
 use collections::string::String;
 use collections::vec::Vec;
 
+use common::random;
 use common::slice::GetSlice;
 
 use core::cell::UnsafeCell;
@@ -22,70 +25,114 @@ use core::{mem, ptr, slice, str};
 use fs::Url;
 
 use system::error::{Error, Result, ENOEXEC, ENOMEM};
+use system::syscall::{SigAction, AT_BASE, AT_ENTRY, AT_NULL, AT_PAGESZ, AT_PHDR, AT_PHENT, AT_PHNUM,
+                      AT_RANDOM, SIG_IGN};
+
+/// Get a random offset of whole pages, below `range`
+///
+/// This randomizes the layout of the address space of a context, to make addresses hard to guess.
+fn random_offset(range: usize) -> usize {
+    random::rand() % (range / 4096) * 4096
+}
 
-pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<String>) -> ! {
-    Context::spawn("kexec".into(),
-                   box move || {
-        let context = unsafe { &mut *context_ptr };
+/// Place a string in the image of a context, returning its address
+fn push_string(context: &mut Context, mut string: String) -> usize {
+    if ! string.ends_with('\0') {
+        string.push('\0');
+    }
 
-        let mut context_args: Vec<usize> = Vec::new();
-        context_args.push(0); // ENVP
-        context_args.push(0); // ARGV NULL
-        let mut argc = 0;
-        while let Some(mut arg) = args.pop() {
-            if ! arg.ends_with('\0') {
-                arg.push('\0');
-            }
+    let mut physical_address = string.as_ptr() as usize;
+    if physical_address >= 0x80000000 {
+        physical_address -= 0x80000000;
+    }
 
-            let mut physical_address = arg.as_ptr() as usize;
-            if physical_address >= 0x80000000 {
-                physical_address -= 0x80000000;
-            }
+    let virtual_address = unsafe { (*context.image.get()).next_mem() };
+    let virtual_size = string.len();
 
-            let virtual_address = unsafe { (*context.image.get()).next_mem() };
-            let virtual_size = arg.len();
+    mem::forget(string);
 
-            mem::forget(arg);
+    unsafe {
+        (*context.image.get()).memory.push(ContextMemory {
+            physical_address: physical_address,
+            virtual_address: virtual_address,
+            virtual_size: virtual_size,
+            writeable: false,
+            allocated: true,
+            shared: None,
+            borrowed: None,
+        });
+    }
 
-            unsafe {
-                (*context.image.get()).memory.push(ContextMemory {
-                    physical_address: physical_address,
-                    virtual_address: virtual_address,
-                    virtual_size: virtual_size,
-                    writeable: false,
-                    allocated: true,
-                });
-            }
+    virtual_address
+}
 
-            context_args.push(virtual_address as usize);
-            argc += 1;
-        }
-        context_args.push(argc);
+/// Start the program loaded in a context
+///
+/// The stack is set up as by other Unix kernels: the argument count, the arguments, the
+/// environment variables and the auxiliary vector `auxv`, to which `AT_RANDOM` is added. The stack
+/// is placed at a random address, and grows down on demand up to the stack limit of the context.
+pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<String>, mut vars: Vec<String>, auxv: Vec<(usize, usize)>) -> ! {
+    Context::spawn("kexec".into(),
+                   box move || {
+        let context = unsafe { &mut *context_ptr };
 
         context.iopl = 0;
 
         context.regs = Regs::default();
         context.regs.sp = context.kernel_stack + CONTEXT_STACK_SIZE - 128;
 
-        context.stack = Some(ContextMemory {
+        // There is room below the stack for it to grow to the highest limit, and for a guard page
+        let stack_top = CONTEXT_STACK_ADDR + CONTEXT_STACK_GUARD + CONTEXT_STACK_LIMIT_MAX + random_offset(CONTEXT_STACK_RANDOM);
+        let stack = ContextMemory {
             physical_address: unsafe { memory::alloc_aligned(CONTEXT_STACK_SIZE, 4096) },
-            virtual_address: CONTEXT_STACK_ADDR,
+            virtual_address: stack_top - CONTEXT_STACK_SIZE,
             virtual_size: CONTEXT_STACK_SIZE,
             writeable: true,
             allocated: true,
-        });
-
-        let user_sp = if let Some(ref stack) = context.stack {
-            let mut sp = stack.physical_address + stack.virtual_size - 128;
-            for arg in context_args.iter() {
-                sp -= mem::size_of::<usize>();
-                unsafe { ptr::write(sp as *mut usize, *arg) };
-            }
-            sp - stack.physical_address + stack.virtual_address
-        } else {
-            0
+            shared: None,
+            borrowed: None,
         };
 
+        // The random bytes of AT_RANDOM are placed at the top of the stack
+        let random_sp = stack.physical_address + stack.virtual_size - 128 - 16;
+        for i in 0..16 {
+            unsafe { ptr::write((random_sp + i) as *mut u8, random::rand() as u8) };
+        }
+
+        // Pushed from the highest address down
+        let mut context_args: Vec<usize> = Vec::new();
+        context_args.push(0); // AT_NULL value
+        context_args.push(AT_NULL);
+        context_args.push(random_sp - stack.physical_address + stack.virtual_address);
+        context_args.push(AT_RANDOM);
+        for &(key, value) in auxv.iter().rev() {
+            context_args.push(value);
+            context_args.push(key);
+        }
+        context_args.push(0); // ENVP NULL
+        while let Some(var) = vars.pop() {
+            let address = push_string(context, var);
+            context_args.push(address);
+        }
+        context_args.push(0); // ARGV NULL
+        let mut argc = 0;
+        while let Some(arg) = args.pop() {
+            let address = push_string(context, arg);
+            context_args.push(address);
+            argc += 1;
+        }
+        context_args.push(argc);
+
+        // The stack pointer is aligned to 16 bytes when the program starts
+        let mut sp = (random_sp - context_args.len() * mem::size_of::<usize>()) & !15;
+        let user_sp = sp - stack.physical_address + stack.virtual_address;
+        for arg in context_args.iter().rev() {
+            unsafe { ptr::write(sp as *mut usize, *arg) };
+            sp += mem::size_of::<usize>();
+        }
+
+        context.stack = Some(stack);
+
         unsafe {
             context.push(0x20 | 3);
             context.push(user_sp);
@@ -93,6 +140,8 @@ pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<Str
             context.push(0x18 | 3);
             context.push(entry);
             context.push(context_userspace as usize);
+            // The kernel lock is released before returning to userspace
+            context.push(smp::unlock as usize);
         }
 
         if let Some(vfork) = context.vfork.take() {
@@ -105,62 +154,135 @@ pub fn execute_thread(context_ptr: *mut Context, entry: usize, mut args: Vec<Str
     }
 }
 
-/// Execute an executable
-pub fn execute(mut args: Vec<String>) -> Result<usize> {
-    let contexts = unsafe { &mut *::env().contexts.get() };
-    let current = try!(contexts.current_mut());
+/// The lowest load address of position independent executables
+const PIE_BASE: usize = CONTEXT_IMAGE_ADDR;
 
+/// The lowest load address of interpreters, in the upper half of the image
+const INTERP_BASE: usize = CONTEXT_IMAGE_ADDR + CONTEXT_IMAGE_SIZE / 2;
+
+/// The part of a zone that its randomized start is chosen from
+const RANDOM_FRACTION: usize = 4;
+
+/// Read the contents of an executable
+pub fn read_executable(current: &Context, url: Url) -> Result<Vec<u8>> {
     let mut vec: Vec<u8> = Vec::new();
 
-    let path = current.canonicalize(args.get(0).map_or("", |p| &p));
-    let url = try!(Url::from_str(&path));
-    {
-        let mut resource = try!(url.open());
+    let mut resource = try!(url.open());
 
-        // Hack to allow file scheme to find memory in context's memory space
-        unsafe {
-            let mmap = &mut *current.mmap.get();
+    // Hack to allow file scheme to find memory in context's memory space
+    unsafe {
+        let mmap = &mut *current.mmap.get();
 
-            let virtual_size = 1024*1024;
-            let virtual_address = mmap.next_mem();
+        let virtual_size = 1024*1024;
+        let virtual_address = mmap.next_mem();
 
-            let physical_address = memory::alloc_aligned(virtual_size, 4096);
-            if physical_address == 0 {
-                return Err(Error::new(ENOMEM));
-            }
+        let physical_address = memory::alloc_aligned(virtual_size, 4096);
+        if physical_address == 0 {
+            return Err(Error::new(ENOMEM));
+        }
 
-            let mut memory = ContextMemory {
-                physical_address: physical_address,
-                virtual_address: virtual_address,
-                virtual_size: virtual_size,
-                writeable: true,
-                allocated: true,
-            };
-
-            memory.map();
-
-            mmap.memory.push(memory);
-
-            let mut read_loop = || -> Result<usize> {
-                loop {
-                    let mut bytes = slice::from_raw_parts_mut(virtual_address as *mut u8, virtual_size);
-                    match resource.read(&mut bytes) {
-                        Ok(0) => return Ok(0),
-                        Ok(count) => vec.extend_from_slice(bytes.get_slice(.. count)),
-                        Err(err) => return Err(err)
-                    }
+        let mut memory = ContextMemory {
+            physical_address: physical_address,
+            virtual_address: virtual_address,
+            virtual_size: virtual_size,
+            writeable: true,
+            allocated: true,
+            shared: None,
+            borrowed: None,
+        };
+
+        memory.map();
+
+        mmap.memory.push(memory);
+
+        let mut read_loop = || -> Result<usize> {
+            loop {
+                let mut bytes = slice::from_raw_parts_mut(virtual_address as *mut u8, virtual_size);
+                match resource.read(&mut bytes) {
+                    Ok(0) => return Ok(0),
+                    Ok(count) => vec.extend_from_slice(bytes.get_slice(.. count)),
+                    Err(err) => return Err(err)
                 }
-            };
+            }
+        };
 
-            let res = read_loop();
+        let res = read_loop();
 
-            let mut memory = mmap.memory.pop().unwrap();
+        let mut memory = mmap.memory.pop().unwrap();
 
-            memory.unmap();
+        memory.unmap();
 
-            try!(res);
+        try!(res);
+    }
+
+    Ok(vec)
+}
+
+/// Get the address an executable is loaded at, added to the addresses of its segments
+///
+/// Position independent executables and shared objects are loaded at `base`, other executables at
+/// the addresses they were linked for.
+fn load_base(executable: &Elf, base: usize) -> Result<usize> {
+    match unsafe { executable.object_type() } {
+        ET_EXEC => Ok(0),
+        ET_DYN => Ok(base),
+        _ => Err(Error::new(ENOEXEC))
+    }
+}
+
+/// Load the segments of an executable into the image of the current context, at `base`
+fn load_segments(image: &mut ContextZone, executable: &Elf, segments: &[ElfSegment], base: usize) {
+    for segment in segments.iter() {
+        let virtual_address = base + segment.vaddr as usize;
+        let virtual_size = segment.mem_len as usize;
+
+        let offset = virtual_address % 4096;
+
+        let physical_address = unsafe { memory::alloc_aligned(virtual_size + offset, 4096) };
+
+        if physical_address == 0 {
+            panic!("OOM in exec");
         }
+
+        let mut memory = ContextMemory {
+            physical_address: physical_address,
+            virtual_address: virtual_address - offset,
+            virtual_size: virtual_size + offset,
+            writeable: true,
+            allocated: true,
+            shared: None,
+            borrowed: None,
+        };
+
+        unsafe { memory.map() };
+
+        // Copy progbits
+        unsafe {
+            ::memcpy(virtual_address as *mut u8,
+                    executable.data.as_ptr().offset(segment.off as isize),
+                    segment.file_len as usize)
+        };
+
+        unsafe { memory.unmap() };
+
+        memory.writeable = segment.flags & 2 == 2;
+
+        image.memory.push(memory);
     }
+}
+
+/// Execute an executable, with environment variables given as `NAME=VALUE`
+///
+/// If the executable names an interpreter, like the dynamic loader, the interpreter is loaded
+/// alongside it and started instead. The interpreter finds the executable through the auxiliary
+/// vector, and its own load address in `AT_BASE`.
+pub fn execute(mut args: Vec<String>, vars: Vec<String>) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+
+    let path = current.canonicalize(args.get(0).map_or("", |p| &p));
+    let url = try!(Url::from_str(&path));
+    let vec = try!(read_executable(current, url));
 
     if vec.starts_with(b"#!") {
         if let Some(mut arg) = args.get_mut(0) {
@@ -178,13 +300,47 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
         if i == 0 {
             args.insert(i, "/bin/sh".to_owned());
         }
-        execute(args)
+        execute(args, vars)
     } else {
         match Elf::from(&vec) {
             Ok(executable) => {
+                let base = try!(load_base(&executable, PIE_BASE + random_offset(CONTEXT_IMAGE_SIZE / RANDOM_FRACTION)));
                 let entry = unsafe { executable.entry() };
                 let segments = unsafe { executable.load_segment() };
 
+                // The interpreter is read before the current image is replaced
+                let interp_vec = match unsafe { executable.interpreter() } {
+                    Some(interp) => {
+                        let interp_path = if interp.find(':').is_none() && interp.starts_with('/') {
+                            format!("{}:{}", url.scheme(), interp)
+                        } else {
+                            current.canonicalize(interp)
+                        };
+                        let interp_url = try!(Url::from_str(&interp_path));
+                        Some(try!(read_executable(current, interp_url)))
+                    },
+                    None => None
+                };
+
+                let interp = match interp_vec {
+                    Some(ref interp_vec) => match Elf::from(interp_vec) {
+                        Ok(interp) => {
+                            let interp_base = try!(load_base(&interp, INTERP_BASE + random_offset(CONTEXT_IMAGE_SIZE / RANDOM_FRACTION)));
+                            let interp_entry = unsafe { interp.entry() };
+                            let interp_segments = unsafe { interp.load_segment() };
+                            if interp_entry == 0 || interp_segments.is_empty() {
+                                return Err(Error::new(ENOEXEC));
+                            }
+                            Some((interp, interp_base, interp_entry, interp_segments))
+                        },
+                        Err(msg) => {
+                            debugln!("execute: failed to load interpreter of '{:?}': {}", url, msg);
+                            return Err(Error::new(ENOEXEC));
+                        }
+                    },
+                    None => None
+                };
+
                 if entry > 0 && ! segments.is_empty() {
                     unsafe { current.unmap() };
 
@@ -192,47 +348,31 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
                     current.cwd = Arc::new(UnsafeCell::new(unsafe { (*current.cwd.get()).clone() }));
 
                     current.image = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_IMAGE_ADDR, CONTEXT_IMAGE_SIZE)));
-                    current.heap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_HEAP_ADDR, CONTEXT_HEAP_SIZE)));
-                    current.mmap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_MMAP_ADDR, CONTEXT_MMAP_SIZE)));
-                    current.env_vars = Arc::new(UnsafeCell::new(unsafe { (*current.env_vars.get()).clone() }));
+                    current.image_base = base;
+                    current.env_vars = Arc::new(vars.clone());
+
+                    // The heap and memory maps start at a random address in their zone
+                    let heap_offset = random_offset(CONTEXT_HEAP_SIZE / RANDOM_FRACTION);
+                    current.heap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_HEAP_ADDR + heap_offset, CONTEXT_HEAP_SIZE - heap_offset)));
+                    let mmap_offset = random_offset(CONTEXT_MMAP_SIZE / RANDOM_FRACTION);
+                    current.mmap = Arc::new(UnsafeCell::new(ContextZone::new(CONTEXT_MMAP_ADDR + mmap_offset, CONTEXT_MMAP_SIZE - mmap_offset)));
+
+                    // Caught signals are reset to the default action, ignored signals stay ignored
+                    let mut sig_actions = unsafe { *current.sig_actions.get() };
+                    for action in sig_actions.iter_mut() {
+                        if action.sa_handler != SIG_IGN {
+                            *action = SigAction::default();
+                        }
+                    }
+                    current.sig_actions = Arc::new(UnsafeCell::new(sig_actions));
 
                     {
                         let image = unsafe { &mut *current.image.get() };
 
-                        for segment in segments.iter() {
-                            let virtual_address = segment.vaddr as usize;
-                            let virtual_size = segment.mem_len as usize;
-
-                            let offset = virtual_address % 4096;
-
-                            let physical_address = unsafe { memory::alloc_aligned(virtual_size + offset, 4096) };
-
-                            if physical_address == 0 {
-                                panic!("OOM in exec");
-                            }
-
-                            let mut memory = ContextMemory {
-                                physical_address: physical_address,
-                                virtual_address: virtual_address - offset,
-                                virtual_size: virtual_size + offset,
-                                writeable: true,
-                                allocated: true,
-                            };
-
-                            unsafe { memory.map() };
-
-                            // Copy progbits
-                            unsafe {
-                                ::memcpy(virtual_address as *mut u8,
-                                        executable.data.as_ptr().offset(segment.off as isize),
-                                        segment.file_len as usize)
-                            };
+                        load_segments(image, &executable, &segments, base);
 
-                            unsafe { memory.unmap() };
-
-                            memory.writeable = segment.flags & 2 == 2;
-
-                            image.memory.push(memory);
+                        if let Some((ref interp, interp_base, _, ref interp_segments)) = interp {
+                            load_segments(image, interp, interp_segments, interp_base);
                         }
                     }
 
@@ -240,7 +380,24 @@ pub fn execute(mut args: Vec<String>) -> Result<usize> {
 
                     unsafe { current.map() };
 
-                    execute_thread(current.deref_mut(), entry, args);
+                    let (mut phdr, phent, phnum) = unsafe { executable.program_headers() };
+                    if phdr > 0 {
+                        phdr += base;
+                    }
+                    let mut auxv = vec![(AT_PHDR, phdr),
+                                        (AT_PHENT, phent),
+                                        (AT_PHNUM, phnum),
+                                        (AT_PAGESZ, 4096),
+                                        (AT_ENTRY, base + entry)];
+
+                    let start = if let Some((_, interp_base, interp_entry, _)) = interp {
+                        auxv.push((AT_BASE, interp_base));
+                        interp_base + interp_entry
+                    } else {
+                        base + entry
+                    };
+
+                    execute_thread(current.deref_mut(), start, args, vars, auxv);
                 } else {
                     Err(Error::new(ENOEXEC))
                 }
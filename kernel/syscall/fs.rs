@@ -2,13 +2,15 @@
 
 use arch::context::ContextFile;
 
+use common::time::Duration;
+
 use fs::{ResourceSeek, Url};
 
 use schemes::pipe::{PipeRead, PipeWrite};
 
 use system::c_string_to_str;
 
-use syscall::{Stat, SEEK_CUR, SEEK_END, SEEK_SET};
+use syscall::{Stat, TimeSpec, EVENT_HUP, F_GETFL, F_SETFL, SEEK_CUR, SEEK_END, SEEK_SET, UTIME_NOW, UTIME_OMIT};
 
 use system::error::{Error, Result, EBADF, EFAULT, EINVAL};
 
@@ -58,6 +60,22 @@ pub fn chdir(path: *const u8) -> Result<usize> {
     Ok(0)
 }
 
+/// Change the permissions of a resource
+pub fn chmod(path: *const u8, mode: usize) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(c_string_to_str(path));
+    ::env().chmod(try!(Url::from_str(&path_string)), mode).and(Ok(0))
+}
+
+/// Change the owner and group of a resource
+pub fn chown(path: *const u8, uid: usize, gid: usize) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(c_string_to_str(path));
+    ::env().chown(try!(Url::from_str(&path_string)), uid, gid).and(Ok(0))
+}
+
 /** <!-- @MANSTART{sys_close} -->
 NAME
     sys_close - close a file descriptor
@@ -143,6 +161,43 @@ pub fn dup(fd: usize) -> Result<usize> {
     Ok(new_fd)
 }
 
+/// Change the permissions of an open resource
+pub fn fchmod(fd: usize, mode: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let mut resource = try!(current.get_file_mut(fd));
+    resource.chmod(mode).and(Ok(0))
+}
+
+/// Change the owner and group of an open resource
+pub fn fchown(fd: usize, uid: usize, gid: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let mut resource = try!(current.get_file_mut(fd));
+    resource.chown(uid, gid).and(Ok(0))
+}
+
+/// Get the status flags of an open resource, or change them
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> Result<usize> {
+    match cmd {
+        F_GETFL | F_SETFL => {
+            let contexts = unsafe { &mut *::env().contexts.get() };
+            let mut current = try!(contexts.current_mut());
+            let mut resource = try!(current.get_file_mut(fd));
+            resource.fcntl(cmd, arg)
+        },
+        _ => Err(Error::new(EINVAL))
+    }
+}
+
+/// Get the events of an open resource that are ready, without blocking
+pub fn fevent(fd: usize, flags: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let mut resource = try!(current.get_file_mut(fd));
+    resource.event(flags).map(|events| events & (flags | EVENT_HUP))
+}
+
 pub fn fpath(fd: usize, buf: *mut u8, count: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = contexts.current()?;
@@ -160,6 +215,8 @@ pub fn fstat(fd: usize, stat: *mut Stat) -> Result<usize> {
     let current = contexts.current()?;
     let resource = current.get_file(fd)?;
     let stat_safe = current.get_ref_mut(stat)?;
+
+    *stat_safe = Stat::default();
     resource.stat(stat_safe)
 }
 
@@ -233,7 +290,29 @@ pub fn ftruncate(fd: usize, length: usize) -> Result<usize> {
     resource.truncate(length).and(Ok(0))
 }
 
-//TODO: Link
+/// Check the times of `sys_utimens` and `sys_futimens`, replacing `UTIME_NOW` by the current time
+fn resolve_times(times: &[TimeSpec; 2]) -> Result<[TimeSpec; 2]> {
+    let mut times = *times;
+    for time in times.iter_mut() {
+        if time.tv_nsec == UTIME_NOW {
+            let now = Duration::realtime();
+            time.tv_sec = now.secs;
+            time.tv_nsec = now.nanos;
+        } else if time.tv_nsec != UTIME_OMIT && (time.tv_nsec < 0 || time.tv_nsec >= 1000000000) {
+            return Err(Error::new(EINVAL));
+        }
+    }
+    Ok(times)
+}
+
+/// Set the access and modification times of an open resource
+pub fn futimens(fd: usize, times: *const [TimeSpec; 2]) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let mut current = try!(contexts.current_mut());
+    let times = try!(resolve_times(try!(current.get_ref(times))));
+    let mut resource = try!(current.get_file_mut(fd));
+    resource.utimens(&times).and(Ok(0))
+}
 
 /** <!-- @MANSTART{sys_lseek} -->
 NAME
@@ -395,12 +474,12 @@ pub fn open(path_c: *const u8, flags: usize) -> Result<usize> {
     Ok(fd)
 }
 
-pub fn pipe2(fds: *mut usize, _flags: usize) -> Result<usize> {
+pub fn pipe2(fds: *mut usize, flags: usize) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
     if fds as usize > 0 {
-        let read = box PipeRead::new();
-        let write = box PipeWrite::new(&read);
+        let read = box PipeRead::new(flags);
+        let write = box PipeWrite::new(&read, flags);
 
         unsafe {
             *fds.offset(0) = current.next_fd();
@@ -438,6 +517,9 @@ RETURN VALUE
     Err(err) is returned where err is one of the following errors
 
 ERRORS
+    EAGAIN
+        fd was opened with O_NONBLOCK, or it was set with sys_fcntl, and no data is available
+
     EBADF
         fd is not a valid open file decriptor
 
@@ -465,6 +547,34 @@ pub fn read(fd: usize, buf: *mut u8, count: usize) -> Result<usize> {
     }
 }
 
+/// Create a hard link `new` to the resource at `old`, which must be in the same scheme
+pub fn link(old: *const u8, new: *const u8) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let old_string = current.canonicalize(c_string_to_str(old));
+    let new_string = current.canonicalize(c_string_to_str(new));
+    ::env().link(try!(Url::from_str(&old_string)), try!(Url::from_str(&new_string))).and(Ok(0))
+}
+
+/// Read the target of a symbolic link
+pub fn readlink(path: *const u8, buf: *mut u8, count: usize) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(c_string_to_str(path));
+    let url = try!(Url::from_str(&path_string));
+    let buf_safe = try!(current.get_slice_mut(buf, count));
+    ::env().readlink(url, buf_safe)
+}
+
+/// Rename a resource, which must stay in the same scheme
+pub fn rename(old: *const u8, new: *const u8) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let old_string = current.canonicalize(c_string_to_str(old));
+    let new_string = current.canonicalize(c_string_to_str(new));
+    ::env().rename(try!(Url::from_str(&old_string)), try!(Url::from_str(&new_string))).and(Ok(0))
+}
+
 pub fn rmdir(path: *const u8) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
@@ -483,6 +593,16 @@ pub fn stat(path: *const u8, stat: *mut Stat) -> Result<usize> {
     ::env().stat(url, stat_safe).and(Ok(0))
 }
 
+/// Create a symbolic link at `path`
+///
+/// The target is stored as it was given, so a relative target is resolved from the link.
+pub fn symlink(target: *const u8, path: *const u8) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(c_string_to_str(path));
+    ::env().symlink(c_string_to_str(target), try!(Url::from_str(&path_string))).and(Ok(0))
+}
+
 pub fn unlink(path: *const u8) -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
@@ -490,6 +610,15 @@ pub fn unlink(path: *const u8) -> Result<usize> {
     ::env().unlink(try!(Url::from_str(&path_string))).and(Ok(0))
 }
 
+/// Set the access and modification times of a resource
+pub fn utimens(path: *const u8, times: *const [TimeSpec; 2]) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let path_string = current.canonicalize(c_string_to_str(path));
+    let times = try!(resolve_times(try!(current.get_ref(times))));
+    ::env().utimens(try!(Url::from_str(&path_string)), &times).and(Ok(0))
+}
+
 /** <!-- @MANSTART{sys_write} -->
 NAME
     sys_write - read from a file descriptor
@@ -0,0 +1,71 @@
+//! System calls related to futexes.
+//!
+//! A futex is a word of user memory that contexts can wait on, and be woken by another context.
+//! Waiting contexts are found by the physical address of the word, so that threads, and processes
+//! sharing memory, use the same futex.
+
+use alloc::boxed::Box;
+
+use arch::context::Context;
+
+use core::{mem, ptr};
+
+use sync::WaitCondition;
+
+use system::error::{Error, Result, EAGAIN, EINVAL};
+use system::syscall::{FUTEX_WAIT, FUTEX_WAKE};
+
+/// Wait on a futex or wake contexts waiting on it
+///
+/// `FUTEX_WAIT` blocks until the futex is woken, unless it no longer holds `val`, which returns
+/// `EAGAIN`. `FUTEX_WAKE` wakes up to `val` contexts and returns how many were woken.
+pub fn futex(addr: *mut usize, op: usize, val: usize) -> Result<usize> {
+    if addr as usize % mem::size_of::<usize>() != 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+    let physical_address = try!(current.translate(addr as usize, mem::size_of::<usize>()));
+
+    let futexes = unsafe { &mut *::env().futexes.get() };
+    match op {
+        FUTEX_WAIT => {
+            // The kernel lock is held, so the futex can not be woken between this check and the wait
+            let value = unsafe { ptr::read_volatile(try!(current.get_ref(addr))) };
+            if value != val {
+                return Err(Error::new(EAGAIN));
+            }
+
+            // The condition is not held across the wait, a fatal signal would never drop it. It
+            // stays in the map as long as a context has it as its futex, see `futex_release`
+            let condition = &**futexes.entry(physical_address)
+                                      .or_insert_with(|| Box::new(WaitCondition::new())) as *const WaitCondition;
+            current.futex = Some(physical_address);
+            unsafe { (*condition).wait("futex wait") };
+
+            if let Ok(current) = contexts.current_mut() {
+                futex_release(current);
+            }
+
+            Ok(0)
+        },
+        FUTEX_WAKE => {
+            Ok(futexes.get(&physical_address).map_or(0, |condition| condition.notify_count(val, "futex wake")))
+        },
+        _ => Err(Error::new(EINVAL)),
+    }
+}
+
+/// Stop waiting on a futex, removing it when no other context waits on it
+///
+/// This is done when the wait returns, and when the context exits, which a fatal signal does
+/// from inside the wait.
+pub fn futex_release(context: &mut Context) {
+    if let Some(physical_address) = context.futex.take() {
+        let contexts = unsafe { & *::env().contexts.get() };
+        if ! contexts.iter().any(|context| context.futex == Some(physical_address)) {
+            unsafe { &mut *::env().futexes.get() }.remove(&physical_address);
+        }
+    }
+}
@@ -3,7 +3,12 @@
 use arch::context::ContextMemory;
 use arch::memory;
 
-use system::error::Result;
+use core::slice;
+
+use fs::ResourceSeek;
+
+use system::error::{Error, Result, EINVAL, ENOMEM};
+use system::syscall::{MmapArgs, MAP_ANONYMOUS, MAP_FIXED, MAP_SHARED, PROT_NONE, PROT_WRITE};
 
 //TODO: Refactor file to propogate results
 
@@ -16,6 +21,14 @@ pub fn brk(addr: usize) -> Result<usize> {
 
         // TODO: Make this smarter, currently it attempt to resize the entire data segment
         if let Some(mut mem) = unsafe { (*current.heap.get()).memory.last_mut() } {
+            // Memory shared copy-on-write has to be copied before it can be resized
+            if mem.writeable && mem.shared.is_some() {
+                unsafe {
+                    mem.unshare();
+                    mem.map();
+                }
+            }
+
             if mem.writeable && mem.allocated {
                 if addr >= mem.virtual_address {
                     unsafe { mem.unmap() };
@@ -45,7 +58,9 @@ pub fn brk(addr: usize) -> Result<usize> {
                     virtual_address: ret,
                     virtual_size: size,
                     writeable: true,
-                    allocated: true
+                    allocated: true,
+                    shared: None,
+                    borrowed: None,
                 };
                 ret = mem.virtual_address + mem.virtual_size;
 
@@ -63,3 +78,185 @@ pub fn brk(addr: usize) -> Result<usize> {
 
     Ok(ret)
 }
+
+/// Map anonymous memory or a file into the address space of the current context
+///
+/// Shared file mappings use the memory of the resource when it supports it, all other file
+/// mappings are private copies of the file contents.
+pub fn mmap(args: *const MmapArgs) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+    let args = *try!(current.get_ref(args));
+
+    if args.len == 0 || args.offset % 4096 != 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let size = (args.len + 4095) / 4096 * 4096;
+    let writeable = args.prot & PROT_WRITE == PROT_WRITE;
+
+    let mmap = unsafe { &mut *current.mmap.get() };
+
+    let virtual_address = if args.flags & MAP_FIXED == MAP_FIXED {
+        if args.addr % 4096 != 0 || args.addr < mmap.address ||
+           args.addr + size > mmap.address + mmap.size {
+            return Err(Error::new(EINVAL));
+        }
+        try!(unsafe { mmap.unmap_range(args.addr, size) });
+        args.addr
+    } else {
+        let next_mem = mmap.next_mem();
+        if next_mem + size > mmap.address + mmap.size {
+            return Err(Error::new(ENOMEM));
+        }
+        next_mem
+    };
+
+    if args.flags & MAP_ANONYMOUS == MAP_ANONYMOUS {
+        let physical_address = unsafe { memory::alloc_aligned(size, 4096) };
+        if physical_address == 0 {
+            return Err(Error::new(ENOMEM));
+        }
+
+        let mut mem = ContextMemory {
+            physical_address: physical_address,
+            virtual_address: virtual_address,
+            virtual_size: size,
+            writeable: writeable,
+            allocated: true,
+            shared: None,
+            borrowed: None,
+        };
+        unsafe {
+            mem.map();
+            mmap.memory.push(mem);
+        }
+
+        return Ok(virtual_address);
+    }
+
+    let resource = try!(current.get_file_mut(args.fd));
+
+    if args.flags & MAP_SHARED == MAP_SHARED {
+        match resource.mmap(args.offset, size) {
+            Ok((physical_address, borrowed)) => {
+                if physical_address % 4096 != 0 {
+                    return Err(Error::new(EINVAL));
+                }
+
+                let mut mem = ContextMemory {
+                    physical_address: physical_address,
+                    virtual_address: virtual_address,
+                    virtual_size: size,
+                    writeable: writeable,
+                    allocated: false,
+                    shared: None,
+                    borrowed: borrowed,
+                };
+                unsafe {
+                    mem.map();
+                    mmap.memory.push(mem);
+                }
+
+                return Ok(virtual_address);
+            }
+            // Read only shared mappings can not observe the difference from a private copy
+            Err(err) => if writeable {
+                return Err(err);
+            }
+        }
+    }
+
+    // Map the copy writeable until the file contents have been read into it
+    let physical_address = unsafe { memory::alloc_aligned(size, 4096) };
+    if physical_address == 0 {
+        return Err(Error::new(ENOMEM));
+    }
+
+    let mut mem = ContextMemory {
+        physical_address: physical_address,
+        virtual_address: virtual_address,
+        virtual_size: size,
+        writeable: true,
+        allocated: true,
+        shared: None,
+        borrowed: None,
+    };
+    unsafe {
+        mem.map();
+        mmap.memory.push(mem);
+    }
+
+    let buf = unsafe { slice::from_raw_parts_mut(virtual_address as *mut u8, size) };
+    let result = resource.seek(ResourceSeek::Current(0)).and_then(|position| {
+        let result = resource.seek(ResourceSeek::Start(args.offset)).and_then(|_| {
+            let mut read = 0;
+            while read < buf.len() {
+                match try!(resource.read(&mut buf[read..])) {
+                    0 => break,
+                    count => read += count,
+                }
+            }
+            Ok(read)
+        });
+
+        try!(resource.seek(ResourceSeek::Start(position)));
+        result
+    });
+
+    if let Err(err) = result {
+        if let Some(mut mem) = mmap.memory.pop() {
+            unsafe { mem.unmap() };
+        }
+        return Err(err);
+    }
+
+    if ! writeable {
+        if let Ok(mut mem) = mmap.get_mem_mut(virtual_address) {
+            mem.writeable = false;
+            unsafe { mem.map() };
+        }
+    }
+
+    Ok(virtual_address)
+}
+
+/// Unmap memory mapped by `mmap`
+pub fn munmap(addr: usize, len: usize) -> Result<usize> {
+    if addr % 4096 != 0 || len == 0 {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+
+    let size = (len + 4095) / 4096 * 4096;
+    unsafe { (*current.mmap.get()).unmap_range(addr, size) }.and(Ok(0))
+}
+
+/// Change the protection of memory in the current context
+///
+/// Memory is always readable, so only `PROT_WRITE` is taken into account. The range must cover
+/// whole mappings.
+pub fn mprotect(addr: usize, len: usize, prot: usize) -> Result<usize> {
+    if addr % 4096 != 0 || len == 0 || prot == PROT_NONE {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+
+    let size = (len + 4095) / 4096 * 4096;
+    let writeable = prot & PROT_WRITE == PROT_WRITE;
+
+    let mut count = 0;
+    for zone in [&current.image, &current.heap, &current.mmap].iter() {
+        count += try!(unsafe { (*zone.get()).protect_range(addr, size, writeable) });
+    }
+
+    if count > 0 {
+        Ok(0)
+    } else {
+        Err(Error::new(ENOMEM))
+    }
+}
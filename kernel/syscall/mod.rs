@@ -4,42 +4,77 @@ pub use system::syscall::*;
 use arch::regs::Regs;
 use arch::context::context_switch;
 
+pub mod coredump;
 pub mod execute;
 pub mod fs;
+pub mod futex;
 pub mod memory;
 pub mod process;
+pub mod signal;
 pub mod time;
 
 pub fn name(number: usize) -> &'static str {
     match number {
         // Redox
+        SYS_FEVENT => "fevent",
         SYS_SUPERVISE => "supervise",
 
         // Unix
         SYS_BRK => "brk",
         SYS_CHDIR => "chdir",
+        SYS_CHMOD => "chmod",
+        SYS_CHOWN => "chown",
         SYS_CLONE => "clone",
         SYS_CLOSE => "close",
         SYS_CLOCK_GETTIME => "clock_gettime",
         SYS_DUP => "dup",
         SYS_EXECVE => "execve",
         SYS_EXIT => "exit",
+        SYS_FCHMOD => "fchmod",
+        SYS_FCHOWN => "fchown",
+        SYS_FCNTL => "fcntl",
         SYS_FPATH => "fpath",
         SYS_FSTAT => "fstat",
         SYS_FSYNC => "fsync",
         SYS_FTRUNCATE => "ftruncate",
+        SYS_FUTEX => "futex",
+        SYS_FUTIMENS => "futimens",
+        SYS_GETEGID => "getegid",
+        SYS_GETEUID => "geteuid",
+        SYS_GETGID => "getgid",
+        SYS_GETPGID => "getpgid",
         SYS_GETPID => "getpid",
+        SYS_GETPRIORITY => "getpriority",
+        SYS_GETRLIMIT => "getrlimit",
+        SYS_GETUID => "getuid",
         SYS_IOPL => "iopl",
-        // TODO: link
+        SYS_KILL => "kill",
+        SYS_LINK => "link",
         SYS_LSEEK => "lseek",
         SYS_MKDIR => "mkdir",
+        SYS_MMAP => "mmap",
+        SYS_MPROTECT => "mprotect",
+        SYS_MUNMAP => "munmap",
         SYS_NANOSLEEP => "nanosleep",
         SYS_OPEN => "open",
         SYS_PIPE2 => "pipe2",
         SYS_READ => "read",
+        SYS_READLINK => "readlink",
+        SYS_RENAME => "rename",
         SYS_RMDIR => "rmdir",
+        SYS_SETGID => "setgid",
+        SYS_SETPGID => "setpgid",
+        SYS_SETPRIORITY => "setpriority",
+        SYS_SETRLIMIT => "setrlimit",
+        SYS_SETSID => "setsid",
+        SYS_SETUID => "setuid",
+        SYS_SIGACTION => "sigaction",
+        SYS_SIGPROCMASK => "sigprocmask",
+        SYS_SIGRETURN => "sigreturn",
         SYS_STAT => "stat",
+        SYS_SYMLINK => "symlink",
         SYS_UNLINK => "unlink",
+        SYS_UTIMENS => "utimens",
         SYS_WAITPID => "waitpid",
         SYS_WRITE => "write",
         SYS_YIELD => "yield",
@@ -60,24 +95,30 @@ pub fn handle(regs: &mut Regs) {
         if let Ok(cur) = contexts.current_mut() {
             cur.current_syscall = Some((regs.ip, regs.ax, regs.bx, regs.cx, regs.dx));
             // debugln!("PID {}: {} @ {:X}: {} {} {:X} {:X} {:X}", cur.pid, cur.name, regs.ip, regs.ax, name(regs.ax), regs.bx, regs.cx, regs.dx);
-            if cur.supervised {
-                // Block the process.
-                cur.blocked_syscall = true;
-                cur.block("syscall::handle Supervise");
-                // Clear the timer.
-                cur.wake = None;
-
-                loop {
-                    if cur.blocked > 0 {
-                        unsafe { context_switch() };
-                    } else {
-                        return;
-                    }
-                }
-            }
         }
     }
 
+    let event = SupervisorEvent {
+        kind: SUPERVISE_SYSCALL,
+        pid: 0,
+        a: regs.ax,
+        b: regs.bx,
+        c: regs.cx,
+        d: regs.dx,
+    };
+    if let Some(result) = supervisor_stop(regs, event) {
+        let contexts = unsafe { &mut *::env().contexts.get() };
+        if let Ok(cur) = contexts.current_mut() {
+            cur.current_syscall = None;
+        }
+
+        regs.ax = result;
+        return;
+    }
+
+    // The supervisor may have changed the syscall
+    let number = regs.ax;
+
     let result = match regs.ax {
         // These are arranged in such a way that the most frequent syscalls preceeds less frequent
         // once, to acheive the best performance.
@@ -89,26 +130,58 @@ pub fn handle(regs: &mut Regs) {
         SYS_OPEN => fs::open(regs.bx as *const u8, regs.cx),
         SYS_CLOSE => fs::close(regs.bx),
         SYS_CLONE => process::clone(regs),
+        SYS_FUTEX => futex::futex(regs.bx as *mut usize, regs.cx, regs.dx),
         SYS_MKDIR => fs::mkdir(regs.bx as *const u8, regs.cx),
         SYS_NANOSLEEP => time::nanosleep(regs.bx as *const TimeSpec, regs.cx as *mut TimeSpec),
         SYS_FPATH => fs::fpath(regs.bx, regs.cx as *mut u8, regs.dx),
         SYS_FSTAT => fs::fstat(regs.bx, regs.cx as *mut Stat),
+        SYS_FEVENT => fs::fevent(regs.bx, regs.cx),
         SYS_FSYNC => fs::fsync(regs.bx),
         SYS_FTRUNCATE => fs::ftruncate(regs.bx, regs.cx),
+        SYS_FCHMOD => fs::fchmod(regs.bx, regs.cx),
+        SYS_FCHOWN => fs::fchown(regs.bx, regs.cx, regs.dx),
+        SYS_FCNTL => fs::fcntl(regs.bx, regs.cx, regs.dx),
+        SYS_FUTIMENS => fs::futimens(regs.bx, regs.cx as *const [TimeSpec; 2]),
         SYS_DUP => fs::dup(regs.bx),
         SYS_IOPL => process::iopl(regs),
         SYS_CLOCK_GETTIME => time::clock_gettime(regs.bx, regs.cx as *mut TimeSpec),
-        SYS_EXECVE => process::execve(regs.bx as *const u8, regs.cx as *const *const u8),
-        SYS_EXIT => process::exit(regs.bx),
+        SYS_EXECVE => process::execve(regs.bx as *const u8, regs.cx as *const *const u8, regs.dx as *const *const u8),
+        SYS_EXIT => process::exit((regs.bx & 0xFF) << 8),
         SYS_GETPID => process::getpid(),
-        // TODO: link
+        SYS_GETPGID => process::getpgid(regs.bx),
+        SYS_SETPGID => process::setpgid(regs.bx, regs.cx),
+        SYS_SETSID => process::setsid(),
+        SYS_GETUID => process::getuid(),
+        SYS_GETEUID => process::geteuid(),
+        SYS_GETGID => process::getgid(),
+        SYS_GETEGID => process::getegid(),
+        SYS_SETUID => process::setuid(regs),
+        SYS_SETGID => process::setgid(regs.bx),
+        SYS_GETPRIORITY => process::getpriority(regs.bx, regs.cx),
+        SYS_SETPRIORITY => process::setpriority(regs.bx, regs.cx, regs.dx as isize),
+        SYS_GETRLIMIT => process::getrlimit(regs.bx, regs.cx as *mut Rlimit),
+        SYS_SETRLIMIT => process::setrlimit(regs.bx, regs.cx as *const Rlimit),
+        SYS_KILL => signal::kill(regs.bx as isize, regs.cx),
+        SYS_LINK => fs::link(regs.bx as *const u8, regs.cx as *const u8),
         SYS_PIPE2 => fs::pipe2(regs.bx as *mut usize, regs.cx),
+        SYS_READLINK => fs::readlink(regs.bx as *const u8, regs.cx as *mut u8, regs.dx),
+        SYS_RENAME => fs::rename(regs.bx as *const u8, regs.cx as *const u8),
         SYS_RMDIR => fs::rmdir(regs.bx as *const u8),
+        SYS_SIGACTION => signal::sigaction(regs.bx, regs.cx as *const SigAction, regs.dx as *mut SigAction),
+        SYS_SIGPROCMASK => signal::sigprocmask(regs.bx, regs.cx as *const usize, regs.dx as *mut usize),
+        SYS_SIGRETURN => signal::sigreturn(regs),
         SYS_STAT => fs::stat(regs.bx as *const u8, regs.cx as *mut Stat),
+        SYS_SYMLINK => fs::symlink(regs.bx as *const u8, regs.cx as *const u8),
         SYS_UNLINK => fs::unlink(regs.bx as *const u8),
+        SYS_UTIMENS => fs::utimens(regs.bx as *const u8, regs.cx as *const [TimeSpec; 2]),
         SYS_WAITPID => process::waitpid(regs.bx as isize, regs.cx as *mut usize, regs.dx),
         SYS_BRK => memory::brk(regs.bx),
+        SYS_MMAP => memory::mmap(regs.bx as *const MmapArgs),
+        SYS_MPROTECT => memory::mprotect(regs.bx, regs.cx, regs.dx),
+        SYS_MUNMAP => memory::munmap(regs.bx, regs.cx),
         SYS_CHDIR => fs::chdir(regs.bx as *const u8),
+        SYS_CHMOD => fs::chmod(regs.bx as *const u8, regs.cx),
+        SYS_CHOWN => fs::chown(regs.bx as *const u8, regs.cx, regs.dx),
         SYS_SUPERVISE => process::supervise(regs.bx),
         _ => Err(Error::new(ENOSYS)),
     };
@@ -122,4 +195,52 @@ pub fn handle(regs: &mut Regs) {
     }
 
     regs.ax = Error::mux(result);
+
+    let event = SupervisorEvent {
+        kind: SUPERVISE_RETURN,
+        pid: 0,
+        a: number,
+        b: regs.ax,
+        ..SupervisorEvent::default()
+    };
+    if let Some(result) = supervisor_stop(regs, event) {
+        regs.ax = result;
+    }
+}
+
+/// Stop the current context at an event, if it is supervised, until the supervisor answers it
+///
+/// Returns the result the supervisor answered with, or `None` if the syscall should proceed.
+fn supervisor_stop(regs: &mut Regs, mut event: SupervisorEvent) -> Option<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let cur = match contexts.current_mut() {
+        Ok(cur) => &mut **cur,
+        Err(_) => return None,
+    };
+
+    if ! cur.supervised {
+        return None;
+    }
+
+    event.pid = cur.pid;
+    cur.supervisor_stop = Some(event);
+    cur.supervisor_regs = regs as *mut Regs;
+    cur.supervisor_result = None;
+    cur.supervisor_event(event);
+
+    // Block the process.
+    cur.block("syscall::handle Supervise");
+    // Clear the timer.
+    cur.wake = None;
+
+    while cur.blocked > 0 {
+        unsafe { context_switch() };
+    }
+
+    if cur.supervisor_stop.take().is_some() {
+        // Woken up by a signal before the supervisor answered
+        Some(Error::mux(Err(Error::new(EINTR))))
+    } else {
+        cur.supervisor_result.take()
+    }
 }
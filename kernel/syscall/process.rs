@@ -1,19 +1,22 @@
 //! System calls related to process managment.
 
-use arch::context::{context_clone, context_switch, ContextFile};
+use arch::context::{context_clone, context_switch, Context, ContextFile, NICE_MAX, NICE_MIN, CONTEXT_STACK_LIMIT_MAX};
 use arch::regs::Regs;
 
 use collections::{BTreeMap, Vec};
 use collections::string::ToString;
 
-use core::mem;
+use core::{cmp, mem};
 use core::ops::DerefMut;
 
 use system::{c_array_to_slice, c_string_to_str};
 
-use system::error::{Error, Result, ECHILD, EINVAL, EACCES};
+use system::error::{Error, Result, ECHILD, EINVAL, EACCES, EPERM, ESRCH};
+use system::syscall::{Rlimit, SupervisorEvent, PRIO_PROCESS, RLIMIT_STACK, SIGCHLD, SUPERVISE_EXIT,
+                      WCONTINUED, WNOHANG, WUNTRACED};
 
 use super::execute::execute;
+use super::futex::futex_release;
 
 use fs::SupervisorResource;
 
@@ -21,29 +24,44 @@ pub fn clone(regs: &Regs) -> Result<usize> {
     unsafe { context_clone(regs) }
 }
 
-pub fn execve(path: *const u8, args: *const *const u8) -> Result<usize> {
+/// Execute a program, with an empty environment if `envs` is null
+pub fn execve(path: *const u8, args: *const *const u8, envs: *const *const u8) -> Result<usize> {
     let mut args_vec = Vec::new();
     args_vec.push(c_string_to_str(path).to_string());
     for arg in c_array_to_slice(args) {
         args_vec.push(c_string_to_str(*arg).to_string());
     }
 
-    execute(args_vec)
+    let mut vars_vec = Vec::new();
+    for env in c_array_to_slice(envs) {
+        vars_vec.push(c_string_to_str(*env).to_string());
+    }
+
+    execute(args_vec, vars_vec)
 }
 
 /// Exit context
+///
+/// The status is the wait status reported to the parent, see `waitpid`
 pub fn exit(status: usize) -> ! {
     {
         let contexts = unsafe { &mut *::env().contexts.get() };
 
         let mut statuses = BTreeMap::new();
-        let (pid, ppid) = {
+        let (pid, ppid, current_ptr) = {
             if let Ok(mut current) = contexts.current_mut() {
+                current.supervisor_event(SupervisorEvent {
+                    kind: SUPERVISE_EXIT,
+                    pid: current.pid,
+                    a: status,
+                    ..SupervisorEvent::default()
+                });
                 mem::swap(&mut statuses, &mut unsafe { current.statuses.inner() }.deref_mut());
                 current.exit();
-                (current.pid, current.ppid)
+                futex_release(current);
+                (current.pid, current.ppid, current.deref_mut() as *mut Context)
             } else {
-                (0, 0)
+                (0, 0, 0 as *mut Context)
             }
         };
 
@@ -54,6 +72,12 @@ pub fn exit(status: usize) -> ! {
                 for (pid, status) in statuses.iter() {
                     context.statuses.send(*pid, *status, "exit child status");
                 }
+                context.signal(SIGCHLD);
+            }
+
+            // A vfork child must not unblock this context after it is gone
+            if context.vfork == Some(current_ptr) {
+                context.vfork = None;
             }
 
             // Move children to parent
@@ -68,18 +92,229 @@ pub fn exit(status: usize) -> ! {
     }
 }
 
+/// Get the effective group ID of the current context
+pub fn getegid() -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    Ok(current.egid)
+}
+
+/// Get the effective user ID of the current context
+pub fn geteuid() -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    Ok(current.euid)
+}
+
+/// Get the real group ID of the current context
+pub fn getgid() -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    Ok(current.gid)
+}
+
+/// Get the process group of a context, or of the current context if `pid` is zero
+pub fn getpgid(pid: usize) -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let context = if pid == 0 {
+        try!(contexts.current())
+    } else {
+        try!(contexts.find(pid))
+    };
+    Ok(context.pgid)
+}
+
 pub fn getpid() -> Result<usize> {
     let contexts = unsafe { & *::env().contexts.get() };
     let current = try!(contexts.current());
     Ok(current.pid)
 }
 
+/// Get the real user ID of the current context
+pub fn getuid() -> Result<usize> {
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    Ok(current.uid)
+}
+
+/// Set the group IDs of the current context
+///
+/// A context with an effective user ID of zero sets both the real and the effective group ID.
+/// Others can only set the effective group ID to the real group ID.
+pub fn setgid(gid: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+    if current.euid == 0 {
+        current.gid = gid;
+        current.egid = gid;
+    } else if gid == current.gid {
+        current.egid = gid;
+    } else {
+        return Err(Error::new(EPERM));
+    }
+    Ok(0)
+}
+
+/// Set the user IDs of the current context
+///
+/// A context with an effective user ID of zero sets both the real and the effective user ID, so
+/// it can not get its privileges back. Others can only set the effective user ID to the real user
+/// ID.
+///
+/// Dropping root also drops the I/O privilege level, so the context can not access ports anymore.
+pub fn setuid(regs: &mut Regs) -> Result<usize> {
+    let uid = regs.bx;
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+    if current.euid == 0 {
+        current.uid = uid;
+        current.euid = uid;
+        if uid != 0 {
+            current.iopl = 0;
+            regs.flags &= !0x3000;
+        }
+    } else if uid == current.uid {
+        current.euid = uid;
+    } else {
+        return Err(Error::new(EPERM));
+    }
+    Ok(0)
+}
+
+/// Move the current context or one of its children into a process group
+///
+/// A `pid` of zero is the current context, and a `pgid` of zero is the PID of the context, which
+/// creates a new process group led by it. Any other process group has to exist in the session of
+/// the current context. Session leaders can not be moved.
+pub fn setpgid(pid: usize, pgid: usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+
+    let (current_pid, current_sid) = {
+        let current = try!(contexts.current());
+        (current.pid, current.sid)
+    };
+    let pid = if pid == 0 { current_pid } else { pid };
+    let pgid = if pgid == 0 { pid } else { pgid };
+
+    if pgid != pid && ! contexts.iter().any(|context| context.pgid == pgid && context.sid == current_sid && ! context.exited) {
+        return Err(Error::new(EPERM));
+    }
+
+    let context = try!(contexts.find_mut(pid));
+    if context.exited || (context.pid != current_pid && context.ppid != current_pid) {
+        return Err(Error::new(ESRCH));
+    }
+    if context.sid != current_sid || context.pid == context.sid {
+        return Err(Error::new(EPERM));
+    }
+
+    context.pgid = pgid;
+    Ok(0)
+}
+
+/// Create a new session with a new process group, both led by the current context
+///
+/// The leader of a process group can not create a session, as the group would be split between
+/// two sessions.
+pub fn setsid() -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+    if current.pgid == current.pid {
+        return Err(Error::new(EPERM));
+    }
+
+    current.sid = current.pid;
+    current.pgid = current.pid;
+    Ok(current.sid)
+}
+
+/// Get the nice value of a context, returned as `20 - nice` so that it is never negative
+pub fn getpriority(which: usize, who: usize) -> Result<usize> {
+    if which != PRIO_PROCESS {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { & *::env().contexts.get() };
+    let context = if who == 0 {
+        try!(contexts.current())
+    } else {
+        try!(contexts.find(who))
+    };
+    Ok((20 - context.nice) as usize)
+}
+
+/// Set the nice value of a context, limited to the range from `NICE_MIN` to `NICE_MAX`
+///
+/// Only contexts of the same user can be changed, and the nice value can only be raised, unless
+/// the current context is privileged.
+pub fn setpriority(which: usize, who: usize, prio: isize) -> Result<usize> {
+    if which != PRIO_PROCESS {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let euid = try!(contexts.current()).euid;
+    let context = if who == 0 {
+        try!(contexts.current_mut())
+    } else {
+        try!(contexts.find_mut(who))
+    };
+    if euid != 0 && euid != context.uid {
+        return Err(Error::new(EPERM));
+    }
+    let nice = cmp::max(NICE_MIN, cmp::min(prio, NICE_MAX));
+    if euid != 0 && nice < context.nice {
+        return Err(Error::new(EACCES));
+    }
+    context.nice = nice;
+    Ok(0)
+}
+
+/// Get a resource limit of the current context, only `RLIMIT_STACK` is supported
+pub fn getrlimit(resource: usize, rlim: *mut Rlimit) -> Result<usize> {
+    if resource != RLIMIT_STACK {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { & *::env().contexts.get() };
+    let current = try!(contexts.current());
+    let rlim_safe = try!(current.get_ref_mut(rlim));
+    *rlim_safe = Rlimit {
+        rlim_cur: current.stack_limit,
+        rlim_max: CONTEXT_STACK_LIMIT_MAX,
+    };
+    Ok(0)
+}
+
+/// Set a resource limit of the current context, only `RLIMIT_STACK` is supported
+///
+/// The stack limit is rounded up to a whole page. It applies to stacks that grow after the call,
+/// and is inherited by new contexts.
+pub fn setrlimit(resource: usize, rlim: *const Rlimit) -> Result<usize> {
+    if resource != RLIMIT_STACK {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+    let rlim_safe = *try!(current.get_ref(rlim));
+    if rlim_safe.rlim_cur > CONTEXT_STACK_LIMIT_MAX {
+        return Err(Error::new(EINVAL));
+    }
+    current.stack_limit = (rlim_safe.rlim_cur + 4095) / 4096 * 4096;
+    Ok(0)
+}
+
 #[cfg(target_arch = "x86")]
 pub fn iopl(regs: &mut Regs) -> Result<usize> {
     let level = regs.bx;
     if level <= 3 {
         let contexts = unsafe { &mut *::env().contexts.get() };
         let mut current = try!(contexts.current_mut());
+        // Port I/O bypasses the permissions of the device schemes
+        if current.euid != 0 {
+            return Err(Error::new(EPERM));
+        }
         current.iopl = level;
 
         regs.flags &= 0xFFFFFFFF - 0x3000;
@@ -97,6 +332,10 @@ pub fn iopl(regs: &mut Regs) -> Result<usize> {
     if level <= 3 {
         let contexts = unsafe { &mut *::env().contexts.get() };
         let mut current = try!(contexts.current_mut());
+        // Port I/O bypasses the permissions of the device schemes
+        if current.euid != 0 {
+            return Err(Error::new(EPERM));
+        }
         current.iopl = level;
 
         regs.flags &= 0xFFFFFFFFFFFFFFFF - 0x3000;
@@ -108,22 +347,56 @@ pub fn iopl(regs: &mut Regs) -> Result<usize> {
     }
 }
 
-//TODO: Finish implementation, add more functions to WaitMap so that matching any or using WNOHANG works
-pub fn waitpid(pid: isize, status_ptr: *mut usize, _options: usize) -> Result<usize> {
+/// Wait for a child to exit
+///
+/// A positive `pid` waits for that child, any other `pid` waits for whichever child exits first.
+/// With `WNOHANG`, 0 is returned instead of blocking when no child has exited yet. With
+/// `WUNTRACED` and `WCONTINUED`, children that were stopped or continued are reported too.
+///
+/// The status is encoded as on other Unix systems: a normal exit has the exit code in bits 8 to
+/// 15, and termination by a signal has the signal number in the low 7 bits. A stop has `0x7F` in
+/// the low 8 bits and the signal number in bits 8 to 15, and a continue is `0xFFFF`.
+pub fn waitpid(pid: isize, status_ptr: *mut usize, options: usize) -> Result<usize> {
     let contexts = unsafe { &mut *::env().contexts.get() };
-    let current = try!(contexts.current_mut());
 
-    if pid > 0 {
-        let status = current.statuses.receive(&(pid as usize), "waitpid status");
+    let has_children = {
+        let current = try!(contexts.current());
+        let current_pid = current.pid;
+        contexts.iter().any(|context| {
+            context.ppid == current_pid && ! context.exited && (pid <= 0 || context.pid == pid as usize)
+        })
+    };
 
-        if let Ok(status_safe) = current.get_ref_mut(status_ptr) {
-            *status_safe = status;
+    let current = try!(contexts.current_mut());
+
+    let wanted = |child_pid: &usize, status: &usize| -> bool {
+        if pid > 0 && *child_pid != pid as usize {
+            false
+        } else if *status & 0xFF == 0x7F {
+            options & WUNTRACED == WUNTRACED
+        } else if *status == 0xFFFF {
+            options & WCONTINUED == WCONTINUED
+        } else {
+            true
+        }
+    };
+
+    let (child_pid, status) = match current.statuses.try_receive_where(&wanted) {
+        Some(entry) => entry,
+        None => if ! has_children {
+            return Err(Error::new(ECHILD));
+        } else if options & WNOHANG == WNOHANG {
+            return Ok(0);
+        } else {
+            current.statuses.receive_where(&wanted, "waitpid status")
         }
+    };
 
-        Ok(pid as usize)
-    } else {
-        Err(Error::new(ECHILD))
+    if let Ok(status_safe) = current.get_ref_mut(status_ptr) {
+        *status_safe = status;
     }
+
+    Ok(child_pid)
 }
 
 pub fn sched_yield() -> Result<usize> {
@@ -135,31 +408,43 @@ pub fn sched_yield() -> Result<usize> {
 
 /// Supervise a child process of the current context.
 ///
-/// This will make all syscalls the given process makes mark the process as blocked, until it is
-/// handled by the supervisor (parrent process) through the returned handle (for details, see the
-/// docs in the `system` crate).
+/// This will make all syscalls the given process makes stop the process, until it is handled by
+/// the supervisor (parrent process) through the returned handle (for details, see the docs in the
+/// `system` crate).
 ///
-/// This routine is done by having a field defining whether the process is blocked by a syscall.
-/// When the syscall is read from the file handle, this field is set to false, but the process is
-/// still stopped (because it is marked as `blocked`), until the new value of the EAX register is
-/// written to the file handle.
+/// The children of a supervised process are supervised as well, the supervisor of a process can
+/// also supervise its children.
 pub fn supervise(pid: usize) -> Result<usize> {
     let contexts = unsafe { &mut *::env().contexts.get() };
-    let cur_pid = try!(contexts.current_mut()).pid;
+    let (cur_pid, cur_files) = {
+        let current = try!(contexts.current());
+        (current.pid, current.files.clone())
+    };
 
-    let procc;
+    let resource;
 
     {
-        let jailed = try!(contexts.find_mut(pid));
-
-        // Make sure that this is actually a child process of the invoker.
-        if jailed.ppid != cur_pid {
-            return Err(Error::new(EACCES));
+        let jailed_ppid = try!(contexts.find(pid)).ppid;
+
+        // Make sure that this is actually a child process of the invoker, or of a process it
+        // supervises. The supervisor resource of the parent has to be in the file table of the
+        // invoker, so that any thread of the supervisor can supervise the children
+        if jailed_ppid != cur_pid {
+            let parent = try!(contexts.find(jailed_ppid).map_err(|_| Error::new(EACCES)));
+            let owned = parent.supervisor.as_ref().map_or(false, |queue| {
+                unsafe { (*queue.get()).files } == &*cur_files as *const _
+            });
+            if ! owned {
+                return Err(Error::new(EACCES));
+            }
         }
 
-        jailed.supervised = true;
+        let jailed = try!(contexts.find_mut(pid));
+        if jailed.supervisor.is_some() {
+            return Err(Error::new(EPERM));
+        }
 
-        procc = &mut **jailed as *mut _;
+        resource = box try!(unsafe { SupervisorResource::new(jailed, &cur_files) });
     }
 
     let current = try!(contexts.current_mut());
@@ -169,7 +454,7 @@ pub fn supervise(pid: usize) -> Result<usize> {
     unsafe {
         (*current.files.get()).push(ContextFile {
             fd: fd,
-            resource: box try!(SupervisorResource::new(procc)),
+            resource: resource,
         });
     }
 
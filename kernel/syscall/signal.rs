@@ -0,0 +1,355 @@
+//! System calls related to signals.
+
+use arch::context::{context_switch, Context};
+use arch::regs::Regs;
+
+use core::mem;
+
+use system::error::{Error, Result, EINVAL, EPERM, ESRCH};
+use system::syscall::{SigAction, NSIG, SIGABRT, SIGBUS, SIGCHLD, SIGFPE, SIGILL, SIGKILL, SIGQUIT,
+                      SIGSEGV, SIGSTOP, SIGSYS, SIGTRAP, SIGXCPU, SIGXFSZ, SIG_BLOCK, SIG_DFL,
+                      SIG_IGN, SIG_SETMASK, SIG_UNBLOCK};
+
+use super::coredump;
+use super::process::exit;
+
+/// The frame pushed to the user stack when a signal handler is called
+///
+/// `sigreturn` reads it back to restore the state from before the handler was called.
+#[derive(Copy, Clone)]
+#[repr(packed)]
+struct SignalFrame {
+    regs: Regs,
+    sig_mask: usize,
+}
+
+/// Signals that can never be blocked, caught or ignored
+const SIG_UNCATCHABLE: usize = 1 << SIGKILL | 1 << SIGSTOP;
+
+/// Signals that write a core dump when they terminate a context
+const SIG_CORE: usize = 1 << SIGQUIT | 1 << SIGILL | 1 << SIGTRAP | 1 << SIGABRT | 1 << SIGBUS |
+                        1 << SIGFPE | 1 << SIGSEGV | 1 << SIGXCPU | 1 << SIGXFSZ | 1 << SIGSYS;
+
+/// The flag in the exit status that tells that a core dump was written
+const WCOREFLAG: usize = 0x80;
+
+/// Check if a context with the user IDs `uid` and `euid` may send signals to a context
+fn may_signal(uid: usize, euid: usize, context: &Context) -> bool {
+    euid == 0 || uid == context.uid || euid == context.uid
+}
+
+/// Send a signal to the contexts of userspace that `target` selects
+///
+/// If `sender` is set to the real and effective user ID of the sender, contexts of other users
+/// are skipped, unless the sender is privileged.
+fn kill_where<F: Fn(&Context) -> bool>(target: F, sig: usize, sender: Option<(usize, usize)>) -> Result<usize> {
+    if sig >= NSIG {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { &mut *::env().contexts.get() };
+
+    let mut count = 0;
+    let mut denied = false;
+    for context in contexts.iter_mut() {
+        if target(&**context) && ! context.exited && context.stack.is_some() {
+            if let Some((uid, euid)) = sender {
+                if ! may_signal(uid, euid, context) {
+                    denied = true;
+                    continue;
+                }
+            }
+
+            if sig > 0 {
+                context.signal(sig);
+            }
+            count += 1;
+        }
+    }
+
+    if count > 0 {
+        Ok(0)
+    } else if denied {
+        Err(Error::new(EPERM))
+    } else {
+        Err(Error::new(ESRCH))
+    }
+}
+
+/// Send a signal to a context, or to a process group
+///
+/// A positive `pid` is a context, zero is the process group of the current context, `-1` is every
+/// context of userspace but init and the current one, and any other negative `pid` is the process
+/// group `-pid`. Only contexts of the same user can be signalled, unless the current context is
+/// privileged.
+pub fn kill(pid: isize, sig: usize) -> Result<usize> {
+    if sig >= NSIG {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { &mut *::env().contexts.get() };
+
+    let (current_pid, current_pgid, sender) = {
+        let current = try!(contexts.current());
+        (current.pid, current.pgid, (current.uid, current.euid))
+    };
+
+    if pid == 0 {
+        return kill_where(|context| context.pgid == current_pgid, sig, Some(sender));
+    } else if pid == -1 {
+        let init_pid = contexts.init_pid;
+        return kill_where(|context| context.pid != current_pid && context.pid != init_pid,
+                          sig,
+                          Some(sender));
+    } else if pid < -1 {
+        let pgid = -pid as usize;
+        return kill_where(|context| context.pgid == pgid, sig, Some(sender));
+    }
+
+    let context = try!(contexts.find_mut(pid as usize));
+    if context.exited {
+        return Err(Error::new(ESRCH));
+    }
+
+    // Kernel contexts have no userspace to deliver signals to
+    if context.stack.is_none() || ! may_signal(sender.0, sender.1, context) {
+        return Err(Error::new(EPERM));
+    }
+
+    // Signal 0 only checks that the context exists
+    if sig > 0 {
+        context.signal(sig);
+    }
+
+    Ok(0)
+}
+
+/// Send a signal to every context of userspace in a process group, on behalf of the kernel
+pub fn kill_group(pgid: usize, sig: usize) -> Result<usize> {
+    kill_where(|context| context.pgid == pgid, sig, None)
+}
+
+/// Examine and change a signal action
+pub fn sigaction(sig: usize, act: *const SigAction, oldact: *mut SigAction) -> Result<usize> {
+    if sig == 0 || sig >= NSIG {
+        return Err(Error::new(EINVAL));
+    }
+
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+
+    if oldact as usize > 0 {
+        let oldact_safe = try!(current.get_ref_mut(oldact));
+        *oldact_safe = current.sig_action(sig);
+    }
+
+    if act as usize > 0 {
+        let act_safe = *try!(current.get_ref(act));
+        if SIG_UNCATCHABLE & 1 << sig == 1 << sig && act_safe.sa_handler != SIG_DFL {
+            return Err(Error::new(EINVAL));
+        }
+
+        unsafe { (*current.sig_actions.get())[sig] = act_safe };
+
+        // Discard pending signals that are now ignored
+        if act_safe.sa_handler == SIG_IGN {
+            current.sig_pending &= !(1 << sig);
+        }
+    }
+
+    Ok(0)
+}
+
+/// Examine and change blocked signals
+pub fn sigprocmask(how: usize, set: *const usize, oldset: *mut usize) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+
+    if oldset as usize > 0 {
+        let oldset_safe = try!(current.get_ref_mut(oldset));
+        *oldset_safe = current.sig_mask;
+    }
+
+    if set as usize > 0 {
+        let set_safe = *try!(current.get_ref(set));
+        let mask = match how {
+            SIG_BLOCK => current.sig_mask | set_safe,
+            SIG_UNBLOCK => current.sig_mask & !set_safe,
+            SIG_SETMASK => set_safe,
+            _ => return Err(Error::new(EINVAL)),
+        };
+        current.sig_mask = mask & !SIG_UNCATCHABLE;
+    }
+
+    Ok(0)
+}
+
+/// Return from a signal handler, restoring the registers saved by `deliver`
+///
+/// The handler has returned into the restorer, popping the return address, so the stack pointer
+/// points to the signal number argument, which is followed by the `SignalFrame`.
+pub fn sigreturn(regs: &mut Regs) -> Result<usize> {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = try!(contexts.current_mut());
+
+    let frame_ptr = (regs.sp + mem::size_of::<usize>()) as *const SignalFrame;
+    let frame = *try!(current.get_ref(frame_ptr));
+
+    // Only restore general purpose registers, keep the selectors and privileged flags intact
+    let cs = regs.cs;
+    let ss = regs.ss;
+    let flags = regs.flags;
+    *regs = frame.regs;
+    regs.cs = cs;
+    regs.ss = ss;
+    regs.flags = (frame.regs.flags & 0xCD5) | (flags & !0xCD5);
+
+    current.sig_mask = frame.sig_mask & !SIG_UNCATCHABLE;
+
+    // The return value is placed in AX, so return the saved value of AX
+    Ok(regs.ax)
+}
+
+/// Terminate the current context because of a signal
+pub fn terminate(sig: usize) -> ! {
+    exit(sig & 0x7F)
+}
+
+/// Terminate the current context because of a signal, writing a core dump if the signal does
+///
+/// `regs` are the registers of the context in userspace.
+pub fn terminate_core(regs: &Regs, sig: usize) -> ! {
+    let mut status = sig & 0x7F;
+
+    if SIG_CORE & 1 << sig == 1 << sig {
+        let contexts = unsafe { & *::env().contexts.get() };
+        if let Ok(current) = contexts.current() {
+            if let Some(path) = coredump::path(current) {
+                match coredump::write(&path, regs, sig) {
+                    Ok(()) => {
+                        syslog_info!("PID {}: {}: core dumped to {}", current.pid, current.name, path);
+                        status |= WCOREFLAG;
+                    },
+                    Err(err) => syslog_error!("PID {}: {}: failed to dump core to {}: {}", current.pid, current.name, path, err)
+                }
+            }
+        }
+    }
+
+    exit(status)
+}
+
+/// Tell the parent of a context that it was stopped or continued, like it is told of an exit
+fn notify_parent(context: &Context, status: usize) {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    if let Ok(parent) = contexts.find_mut(context.ppid) {
+        parent.statuses.send(context.pid, status, "stop parent status");
+        parent.signal(SIGCHLD);
+    }
+}
+
+/// Stop the current context because of a signal, until it is continued by `SIGCONT` or killed
+///
+/// The parent can wait for the stop with `WUNTRACED`, and for the continue with `WCONTINUED`.
+pub fn stop(current: &mut Context, sig: usize) {
+    current.stopped = true;
+    notify_parent(current, sig << 8 | 0x7F);
+
+    while current.stopped && current.sig_pending & 1 << SIGKILL == 0 {
+        current.block("signal::stop");
+        unsafe { context_switch() };
+    }
+
+    if ! current.stopped {
+        notify_parent(current, 0xFFFF);
+    }
+    current.stopped = false;
+}
+
+/// Send a signal to the current context for a fault in userspace, such as a page fault
+///
+/// Returning to the faulting instruction would fault again, so the context is terminated unless
+/// the signal will be caught by a handler.
+pub fn fault(regs: &Regs, sig: usize) {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    if let Ok(current) = contexts.current_mut() {
+        let handler = current.sig_action(sig).sa_handler;
+        if handler == SIG_DFL || handler == SIG_IGN || current.sig_mask & 1 << sig == 1 << sig {
+            terminate_core(regs, sig);
+        }
+
+        current.sig_pending |= 1 << sig;
+    }
+}
+
+/// Deliver pending signals to the current context
+///
+/// This is called when returning to userspace. Signals with a default action are handled in the
+/// kernel, stopping or terminating the context, and a signal with a handler causes the handler to
+/// be called when the context resumes.
+pub fn deliver(regs: &mut Regs) {
+    let contexts = unsafe { &mut *::env().contexts.get() };
+    let current = match contexts.current_mut() {
+        Ok(current) => current,
+        Err(_) => return
+    };
+
+    while current.sig_pending & !current.sig_mask != 0 {
+        let pending = current.sig_pending & !current.sig_mask;
+        let sig = (1..NSIG).find(|sig| pending & 1 << sig == 1 << sig).unwrap_or(0);
+        current.sig_pending &= !(1 << sig);
+
+        if sig == 0 || current.sig_ignored(sig) {
+            continue;
+        }
+
+        let action = current.sig_action(sig);
+        if action.sa_handler == SIG_DFL {
+            if current.sig_stops(sig) {
+                stop(current, sig);
+                continue;
+            }
+
+            terminate_core(regs, sig);
+        }
+
+        let frame = SignalFrame {
+            regs: *regs,
+            sig_mask: current.sig_mask,
+        };
+
+        // Skip the red zone, then push the frame, the signal number and the return address,
+        // keeping the stack aligned as it would be after a call instruction. The stack grows down
+        // to the return address if needed.
+        let frame_sp = ((regs.sp - 128 - mem::size_of::<SignalFrame>()) & !15) - 8;
+        let restorer_sp = frame_sp - 2 * mem::size_of::<usize>();
+
+        if current.stack_fault(restorer_sp) == Some(true) {
+            let _ = unsafe { current.grow_stack(restorer_sp) };
+        }
+
+        match current.get_ref_mut(frame_sp as *mut SignalFrame)
+                     .and_then(|_| current.get_slice_mut(restorer_sp as *mut usize, 2)) {
+            Ok(stack) => {
+                unsafe { *(frame_sp as *mut SignalFrame) = frame };
+                stack[1] = sig;
+                stack[0] = action.sa_restorer;
+            },
+            Err(_) => {
+                // The stack is not usable, so the handler can not be called
+                debugln!("PID {}: {}: failed to deliver signal {}: bad stack {:X}", current.pid, current.name, sig, regs.sp);
+                terminate(SIGKILL);
+            }
+        }
+
+        current.sig_mask |= action.sa_mask | 1 << sig;
+        current.sig_mask &= !SIG_UNCATCHABLE;
+
+        regs.sp = restorer_sp;
+        regs.ip = action.sa_handler;
+        // The signal number is passed on the stack, and in DI for the x86_64 calling convention
+        regs.di = sig;
+
+        break;
+    }
+}
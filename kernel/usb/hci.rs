@@ -8,6 +8,8 @@ use core::{cmp, mem, ptr, slice};
 
 use graphics::display::VBEMODEINFO;
 
+use schemes::event::event_notify;
+
 use super::{Packet, Pipe, Setup};
 use super::desc::*;
 
@@ -160,6 +162,7 @@ pub trait Hci {
                                                 //ignore mouse event
                                             } else {
                                                 ::env().events.send(mouse_event.to_event(), "HCI events send");
+                                                event_notify("HCI events send");
                                             }
                                         }
 
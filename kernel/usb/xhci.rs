@@ -58,7 +58,7 @@ impl Xhci {
         let mut module = box Xhci {
             pci: pci,
             base: pci.read(0x10) as usize & 0xFFFFFFF0,
-            irq: pci.read(0x3C) as u8 & 0xF,
+            irq: pci.read(0x3C) as u8,
         };
         module.init();
         module
@@ -5,7 +5,7 @@ use alloc::boxed::Box;
 use core_collections::borrow::ToOwned;
 
 use ffi::{OsString, OsStr};
-use fs::{self, File};
+use fs::File;
 use path::{Path, PathBuf};
 use string::{String, ToString};
 use sys_common::AsInner;
@@ -16,7 +16,7 @@ use fmt;
 use system::error::ENOENT;
 use system::syscall::sys_chdir;
 
-use io::{Error, Result, Read, Write};
+use io::{Error, Result};
 
 static mut _args: *mut Vec<&'static str> = 0 as *mut Vec<&'static str>;
 
@@ -69,6 +69,40 @@ pub unsafe fn args_destroy() {
     }
 }
 
+static mut _vars: *mut Vec<(String, String)> = 0 as *mut Vec<(String, String)>;
+
+/// Get the environment variables of the process, which are created on first use if the process
+/// was not started by `lang_start`
+fn vars_mut() -> &'static mut Vec<(String, String)> {
+    unsafe {
+        if _vars as usize == 0 {
+            _vars = Box::into_raw(box Vec::new());
+        }
+        &mut *_vars
+    }
+}
+
+/// Initialize environment variables, from the `NAME=VALUE` strings passed by `execve`
+pub unsafe fn vars_init(vars: Vec<&'static str>) {
+    let mut variables = Vec::new();
+    for var in vars.iter() {
+        if let Some(equal_sign) = var.find('=') {
+            if equal_sign > 0 {
+                variables.push((var[.. equal_sign].to_owned(), var[equal_sign + 1 ..].to_owned()));
+            }
+        }
+    }
+    _vars = Box::into_raw(box variables);
+}
+
+/// Destroy environment variables
+pub unsafe fn vars_destroy() {
+    if _vars as usize > 0 {
+        drop(Box::from_raw(_vars));
+        _vars = 0 as *mut Vec<(String, String)>;
+    }
+}
+
 /// Private function to get the path from a custom location
 /// If the custom directory cannot be found, None will be returned
 fn get_path_from(location : &str) -> Result<PathBuf> {
@@ -162,10 +196,10 @@ impl error::Error for VarError {
 /// or if the variable is not present then `Err` is returned
 pub fn var<K: AsRef<OsStr>>(key: K) -> ::core::result::Result<String, VarError> {
     if let Some(key_str) = key.as_ref().to_str() {
-        let mut file = try!(File::open(&("env:".to_owned() + key_str)).or(Err(VarError::NotPresent)));
-        let mut string = String::new();
-        try!(file.read_to_string(&mut string).or(Err(VarError::NotPresent)));
-        Ok(string)
+        match vars_mut().iter().find(|&&(ref name, _)| name == key_str) {
+            Some(&(_, ref value)) => Ok(value.clone()),
+            None => Err(VarError::NotPresent)
+        }
     } else {
         Err(VarError::NotUnicode(key.as_ref().to_owned()))
     }
@@ -182,18 +216,27 @@ pub fn var_os<K: AsRef<OsStr>>(key: K) -> Option<OsString> {
 }
 
 /// Sets the environment variable `key` to the value `value` for the current process
+///
+/// The environment variables are passed to processes started with `Command`.
 pub fn set_var<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, value: V) {
     if let (Some(key_str), Some(value_str)) = (key.as_ref().to_str(), value.as_ref().to_str()) {
-        if let Ok(mut file) = File::open(&("env:".to_owned() + key_str)) {
-            let _ = file.write_all(value_str.as_bytes());
+        if key_str.is_empty() || key_str.contains('=') {
+            return;
+        }
+
+        let vars = vars_mut();
+        if let Some(var) = vars.iter_mut().find(|&&mut (ref name, _)| name == key_str) {
+            var.1 = value_str.to_owned();
+            return;
         }
+        vars.push((key_str.to_owned(), value_str.to_owned()));
     }
 }
 
 /// Removes an environment variable from the environment of the current process
 pub fn remove_var<K: AsRef<OsStr>>(key: K) {
     if let Some(key_str) = key.as_ref().to_str() {
-        let _ = fs::remove_file(&("env:".to_owned() + key_str));
+        vars_mut().retain(|&(ref name, _)| name != key_str);
     }
 }
 
@@ -216,19 +259,5 @@ impl Iterator for Vars {
 
 /// Returns an iterator over the environment variables of the current process
 pub fn vars() -> Vars {
-    let mut variables: Vec<(String, String)> = Vec::new();
-    if let Ok(mut file) = File::open("env:") {
-        let mut string = String::new();
-        if file.read_to_string(&mut string).is_ok() {
-            for line in string.lines() {
-                if let Some(equal_sign) = line.chars().position(|c| c == '=') {
-                    let name = line.chars().take(equal_sign).collect::<String>();
-                    let value = line.chars().skip(equal_sign+1).collect::<String>();
-                    variables.push((name, value));
-                }
-            }
-            return Vars { vars: variables, pos: 0 };
-        }
-    }
-    Vars { vars: Vec::new(), pos: 0 }
+    Vars { vars: vars_mut().clone(), pos: 0 }
 }
@@ -1,15 +1,19 @@
 use core_collections::borrow::ToOwned;
-use io::{self, BufRead, BufReader, Read, Error, Result, Write, Seek, SeekFrom};
+use io::{self, BufRead, BufReader, Read, Error, ErrorKind, Result, Write, Seek, SeekFrom};
+use os::unix::fs::PermissionsExt;
 use os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use mem;
 use path::{PathBuf, Path};
 use string::String;
 use sys_common::AsInner;
+use time::{Duration, SystemTime, UNIX_EPOCH};
 use vec::Vec;
 
+use system::error::{ENOSYS, EXDEV};
 use system::syscall::{sys_open, sys_dup, sys_close, sys_fpath, sys_ftruncate, sys_read,
-              sys_write, sys_lseek, sys_fsync, sys_mkdir, sys_rmdir, sys_stat, sys_unlink};
-use system::syscall::{O_RDWR, O_RDONLY, O_WRONLY, O_APPEND, O_CREAT, O_TRUNC, MODE_DIR, MODE_FILE, SEEK_SET, SEEK_CUR, SEEK_END, Stat};
+              sys_write, sys_lseek, sys_fsync, sys_fstat, sys_mkdir, sys_rmdir, sys_stat, sys_unlink,
+              sys_rename, sys_link, sys_symlink, sys_readlink, sys_chmod, sys_fchmod};
+use system::syscall::{O_RDWR, O_RDONLY, O_WRONLY, O_APPEND, O_CREAT, O_TRUNC, MODE_DIR, MODE_FILE, MODE_PERM, SEEK_SET, SEEK_CUR, SEEK_END, Stat};
 
 /// A Unix-style file
 #[derive(Debug)]
@@ -53,6 +57,20 @@ impl File {
         }
     }
 
+    /// Get information about the file
+    pub fn metadata(&self) -> Result<Metadata> {
+        let mut stat = Stat::default();
+        try!(sys_fstat(self.fd, &mut stat).map_err(|x| Error::from_sys(x)));
+        Ok(Metadata {
+            stat: stat
+        })
+    }
+
+    /// Change the permissions of the file
+    pub fn set_permissions(&self, perm: Permissions) -> Result<()> {
+        sys_fchmod(self.fd, perm.mode as usize).and(Ok(())).map_err(|x| Error::from_sys(x))
+    }
+
     /// Flush the file data and metadata
     pub fn sync_all(&mut self) -> Result<()> {
         sys_fsync(self.fd).and(Ok(())).map_err(|x| Error::from_sys(x))
@@ -243,7 +261,79 @@ impl Metadata {
     }
 
     pub fn len(&self) -> u64 {
-        self.stat.st_size as u64
+        self.stat.st_size
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        Permissions {
+            mode: self.stat.st_mode & MODE_PERM
+        }
+    }
+
+    /// The time of the last modification of the contents
+    ///
+    /// Returns an error if the scheme does not keep it.
+    pub fn modified(&self) -> Result<SystemTime> {
+        stat_time(self.stat.st_mtime, self.stat.st_mtime_nsec)
+    }
+
+    /// The time of the last access
+    ///
+    /// Returns an error if the scheme does not keep it.
+    pub fn accessed(&self) -> Result<SystemTime> {
+        stat_time(self.stat.st_atime, self.stat.st_atime_nsec)
+    }
+
+    /// The time of creation, which Redox does not keep
+    pub fn created(&self) -> Result<SystemTime> {
+        Err(Error::new(ErrorKind::Other, "not supported"))
+    }
+}
+
+/// Convert a time of `Stat`, which schemes that do not keep it leave at zero
+fn stat_time(secs: u64, nsecs: u32) -> Result<SystemTime> {
+    if secs == 0 && nsecs == 0 {
+        Err(Error::new(ErrorKind::Other, "not supported by the scheme"))
+    } else {
+        Ok(UNIX_EPOCH + Duration::new(secs, nsecs))
+    }
+}
+
+/// The permissions of a file, in the lower bits of its mode
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    mode: u16
+}
+
+impl Permissions {
+    /// True if no one can write to the file
+    pub fn readonly(&self) -> bool {
+        self.mode & 0o222 == 0
+    }
+
+    /// Remove or restore the write permissions of everyone
+    pub fn set_readonly(&mut self, readonly: bool) {
+        if readonly {
+            self.mode &= !0o222;
+        } else {
+            self.mode |= 0o222;
+        }
+    }
+}
+
+impl PermissionsExt for Permissions {
+    fn mode(&self) -> u32 {
+        self.mode as u32
+    }
+
+    fn set_mode(&mut self, mode: u32) {
+        self.mode = mode as u16 & MODE_PERM;
+    }
+
+    fn from_mode(mode: u32) -> Permissions {
+        Permissions {
+            mode: mode as u16 & MODE_PERM
+        }
     }
 }
 
@@ -335,33 +425,110 @@ pub fn metadata<P: AsRef<Path>>(path: P) -> Result<Metadata> {
 }
 
 /// Get information about a file without following symlinks
-/// Warning: Redox does not currently support symlinks
+/// Warning: symlinks are still followed, as a link itself cannot be stat-ed yet
 pub fn symlink_metadata<P: AsRef<Path>>(path: P) -> Result<Metadata> {
     metadata(path)
 }
 
+/// Change the permissions of a file
+pub fn set_permissions<P: AsRef<Path>>(path: P, perm: Permissions) -> Result<()> {
+    let path_str = path.as_ref().as_os_str().as_inner();
+    let mut path_c = path_str.to_owned();
+    path_c.push_str("\0");
+    unsafe {
+        sys_chmod(path_c.as_ptr(), perm.mode as usize).and(Ok(()))
+    }.map_err(|x| Error::from_sys(x))
+}
+
 /// Create a new directory, using a path
-/// The default mode of the directory is 744
+/// The default mode of the directory is 755
 pub fn create_dir<P: AsRef<Path>>(path: P) -> Result<()> {
     let path_str = path.as_ref().as_os_str().as_inner();
     let mut path_c = path_str.to_owned();
     path_c.push_str("\0");
     unsafe {
-        sys_mkdir(path_c.as_ptr(), 755).and(Ok(())).map_err(|x| Error::from_sys(x))
+        sys_mkdir(path_c.as_ptr(), 0o755).and(Ok(())).map_err(|x| Error::from_sys(x))
     }
 }
 
 /// Copy the contents of one file to another
+///
+/// The permissions of the file are copied too, unless the scheme of the new file does not support
+/// them.
 pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<u64> {
     let mut infile = try!(File::open(from));
     let mut outfile = try!(File::create(to));
-    io::copy(&mut infile, &mut outfile)
+    let count = try!(io::copy(&mut infile, &mut outfile));
+    let metadata = try!(infile.metadata());
+    if let Err(err) = outfile.set_permissions(metadata.permissions()) {
+        let err = err.into_sys();
+        if err.errno != ENOSYS {
+            return Err(Error::from_sys(err));
+        }
+    }
+    Ok(count)
 }
 
 /// Rename a file or directory to a new name
+///
+/// A file is copied and removed instead when its scheme can not rename it, or when the paths are
+/// in different schemes.
 pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<()> {
-    try!(copy(Path::new(from.as_ref()), to));
-    remove_file(from)
+    let mut from_c = from.as_ref().as_os_str().as_inner().to_owned();
+    from_c.push_str("\0");
+    let mut to_c = to.as_ref().as_os_str().as_inner().to_owned();
+    to_c.push_str("\0");
+    match unsafe { sys_rename(from_c.as_ptr(), to_c.as_ptr()) } {
+        Ok(_) => Ok(()),
+        Err(err) => if (err.errno == ENOSYS || err.errno == EXDEV) && try!(metadata(from.as_ref())).is_file() {
+            try!(copy(from.as_ref(), to));
+            remove_file(from)
+        } else {
+            Err(Error::from_sys(err))
+        }
+    }
+}
+
+/// Create a new hard link `dst` to the file at `src`, which must be in the same scheme
+pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let mut src_c = src.as_ref().as_os_str().as_inner().to_owned();
+    src_c.push_str("\0");
+    let mut dst_c = dst.as_ref().as_os_str().as_inner().to_owned();
+    dst_c.push_str("\0");
+    unsafe {
+        sys_link(src_c.as_ptr(), dst_c.as_ptr()).and(Ok(()))
+    }.map_err(|x| Error::from_sys(x))
+}
+
+/// Create a new symbolic link `dst`, pointing to `src`
+///
+/// The path of `src` is stored as it is, so a relative path is followed from the link.
+pub fn soft_link<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let mut src_c = src.as_ref().as_os_str().as_inner().to_owned();
+    src_c.push_str("\0");
+    let mut dst_c = dst.as_ref().as_os_str().as_inner().to_owned();
+    dst_c.push_str("\0");
+    unsafe {
+        sys_symlink(src_c.as_ptr(), dst_c.as_ptr()).and(Ok(()))
+    }.map_err(|x| Error::from_sys(x))
+}
+
+/// Read the path that a symbolic link points to
+pub fn read_link<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
+    let mut path_c = path.as_ref().as_os_str().as_inner().to_owned();
+    path_c.push_str("\0");
+
+    // A target that fills the buffer may be truncated, so it is read again with a larger one
+    let mut buf = vec![0; 4096];
+    loop {
+        let count = try!(unsafe { sys_readlink(path_c.as_ptr(), &mut buf) }.map_err(|x| Error::from_sys(x)));
+        if count < buf.len() {
+            buf.truncate(count);
+            return Ok(PathBuf::from(String::from_utf8_lossy(&buf).into_owned()));
+        }
+        let len = buf.len() * 2;
+        buf.resize(len, 0);
+    }
 }
 
 /// Return an iterator over the entries within a directory
@@ -0,0 +1,13 @@
+use fs::Permissions;
+
+/// Unix-specific extensions to `Permissions`
+pub trait PermissionsExt {
+    /// The permission bits of the mode, like `0o755`
+    fn mode(&self) -> u32;
+
+    /// Set the permission bits of the mode
+    fn set_mode(&mut self, mode: u32);
+
+    /// Create permissions from the permission bits of a mode
+    fn from_mode(mode: u32) -> Permissions;
+}
@@ -0,0 +1,23 @@
+use io::Result;
+use process::Command;
+
+/// Unix-specific extensions to `ExitStatus`
+pub trait ExitStatusExt {
+    /// If the process was terminated by a signal, returns that signal
+    fn signal(&self) -> Option<i32>;
+}
+
+/// Unix-specific extensions to `Command`
+pub trait CommandExt {
+    /// Set the user ID of the child process, which drops the privileges of a child of root
+    fn uid(&mut self, id: u32) -> &mut Command;
+
+    /// Set the group ID of the child process, which is set before the user ID
+    fn gid(&mut self, id: u32) -> &mut Command;
+
+    /// Run a closure in the child process before it executes the program
+    ///
+    /// The closures run after the user and group IDs are set, in the order they were added. An
+    /// error stops the child process, and is returned by `spawn`.
+    fn before_exec<F>(&mut self, f: F) -> &mut Command where F: FnMut() -> Result<()> + Send + Sync + 'static;
+}
@@ -1,15 +1,18 @@
 use boxed::Box;
 use core::mem;
+use env;
 use fmt;
 use io::{Result, Read, Write};
 use os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use os::unix::process::{CommandExt, ExitStatusExt};
 use ops::DerefMut;
 use string::{String, ToString};
 use core_collections::borrow::ToOwned;
 use vec::Vec;
 
 use io::Error;
-use system::syscall::{sys_clone, sys_close, sys_dup, sys_execve, sys_exit, sys_pipe2, sys_read, sys_write, sys_waitpid, CLONE_VM, CLONE_VFORK, CLONE_SUPERVISE};
+use system::syscall::{sys_clone, sys_close, sys_dup, sys_execve, sys_exit, sys_kill, sys_pipe2, sys_read, sys_setgid, sys_setuid, sys_write, sys_waitpid, CLONE_VM, CLONE_VFORK, CLONE_SUPERVISE, SIGKILL,
+                     wexitstatus, wifexited, wifsignaled, wtermsig};
 use system::error::Error as SysError;
 
 pub struct ExitStatus {
@@ -21,8 +24,35 @@ impl ExitStatus {
         self.status == 0
     }
 
+    /// Returns the exit code of the process, or `None` if it was terminated by a signal
     pub fn code(&self) -> Option<i32> {
-        Some(self.status as i32)
+        if wifexited(self.status) {
+            Some(wexitstatus(self.status) as i32)
+        } else {
+            None
+        }
+    }
+}
+
+impl ExitStatusExt for ExitStatus {
+    fn signal(&self) -> Option<i32> {
+        if wifsignaled(self.status) {
+            Some(wtermsig(self.status) as i32)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(code) = self.code() {
+            write!(f, "exit code: {}", code)
+        } else if let Some(signal) = self.signal() {
+            write!(f, "signal: {}", signal)
+        } else {
+            write!(f, "unrecognized wait status: {} {:#x}", self.status, self.status)
+        }
     }
 }
 
@@ -93,6 +123,10 @@ impl Child {
         self.pid as u32
     }
 
+    pub fn kill(&mut self) -> Result<()> {
+        sys_kill(self.pid, SIGKILL).and(Ok(())).map_err(|x| Error::from_sys(x))
+    }
+
     pub fn wait(&mut self) -> Result<ExitStatus> {
         let mut status: usize = 0;
         sys_waitpid(self.pid, &mut status, 0).map(|_| ExitStatus { status: status }).map_err(|x| Error::from_sys(x))
@@ -102,9 +136,17 @@ impl Child {
 pub struct Command {
     pub path: String,
     pub args: Vec<String>,
+    /// The environment variables, if they are not the ones of the current process
+    vars: Option<Vec<(String, String)>>,
     stdin: Stdio,
     stdout: Stdio,
     stderr: Stdio,
+    /// The user ID of the child process, if it is not the one of the current process
+    uid: Option<usize>,
+    /// The group ID of the child process, if it is not the one of the current process
+    gid: Option<usize>,
+    /// The closures run in the child process before it executes the program
+    closures: Vec<Box<FnMut() -> Result<()> + Send + Sync>>,
 }
 
 impl fmt::Debug for Command {
@@ -122,9 +164,13 @@ impl Command {
         Command {
             path: path.to_owned(),
             args: Vec::new(),
+            vars: None,
             stdin: Stdio::inherit(),
             stdout: Stdio::inherit(),
             stderr: Stdio::inherit(),
+            uid: None,
+            gid: None,
+            closures: Vec::new(),
         }
     }
 
@@ -133,6 +179,34 @@ impl Command {
         self
     }
 
+    /// Get the environment variables to modify, starting from the ones of the current process
+    fn vars_mut(&mut self) -> &mut Vec<(String, String)> {
+        if self.vars.is_none() {
+            self.vars = Some(env::vars().collect());
+        }
+        self.vars.as_mut().unwrap()
+    }
+
+    /// Set an environment variable of the child process
+    pub fn env(&mut self, key: &str, val: &str) -> &mut Command {
+        self.env_remove(key);
+        self.vars_mut().push((key.to_owned(), val.to_owned()));
+        self
+    }
+
+    /// Remove an environment variable of the child process
+    pub fn env_remove(&mut self, key: &str) -> &mut Command {
+        self.vars_mut().retain(|&(ref name, _)| name != key);
+        self
+    }
+
+    /// Clear the environment variables of the child process, which does not inherit the ones of
+    /// the current process
+    pub fn env_clear(&mut self) -> &mut Command {
+        self.vars = Some(Vec::new());
+        self
+    }
+
     pub fn stdin(&mut self, cfg: Stdio) -> &mut Command {
         self.stdin = cfg;
         self
@@ -156,7 +230,8 @@ impl Command {
     ///
     /// This means that the system calls will block the process, until being handled by the
     /// parrent. Handling can be done by calling `.id()`, and then using `sys_supervise` to start
-    /// supervising this process. Refer to the respective documentation for more information.
+    /// supervising this process. Refer to the respective documentation for more information, and
+    /// to `strace` for an example.
     pub fn spawn_supervise(&mut self) -> Result<Child> {
         self.exec(CLONE_VM | CLONE_SUPERVISE)
     }
@@ -167,7 +242,7 @@ impl Command {
         let path_c = if self.path.contains('/') {
             self.path.to_owned() + "\0"
         } else {
-            let mut path_env = super::env::var("PATH").unwrap_or(".".to_string());
+            let mut path_env = env::var("PATH").unwrap_or(".".to_string());
 
             if ! path_env.ends_with('/') {
                 path_env.push('/');
@@ -189,10 +264,28 @@ impl Command {
         }
         args_c.push(0 as *const u8);
 
+        let mut vars_vec: Vec<String> = Vec::new();
+        let vars = match self.vars {
+            Some(ref vars) => vars.clone(),
+            None => env::vars().collect()
+        };
+        for (name, value) in vars {
+            vars_vec.push(format!("{}={}\0", name, value));
+        }
+
+        let mut vars_c: Vec<*const u8> = Vec::new();
+        for var_vec in vars_vec.iter() {
+            vars_c.push(var_vec.as_ptr());
+        }
+        vars_c.push(0 as *const u8);
+
         let child_res = res.deref_mut() as *mut usize;
         let child_stderr = self.stderr.inner;
         let child_stdout = self.stdout.inner;
         let child_stdin = self.stdin.inner;
+        let child_uid = self.uid;
+        let child_gid = self.gid;
+        let child_closures = &mut self.closures as *mut Vec<Box<FnMut() -> Result<()> + Send + Sync>>;
         let child_code = Box::new(move || -> Result<usize> {
             let child_stderr_res = match child_stderr {
                 StdioType::Piped(read, write) => {
@@ -261,7 +354,19 @@ impl Command {
             let _ = try!(child_stdout_res);
             let _ = try!(child_stdin_res);
 
-            unsafe { sys_execve(path_c.as_ptr(), args_c.as_ptr()) }.map_err(|x| Error::from_sys(x))
+            // The group is set first, as it can not be changed without the privileges of root
+            if let Some(gid) = child_gid {
+                try!(sys_setgid(gid).map_err(|x| Error::from_sys(x)));
+            }
+            if let Some(uid) = child_uid {
+                try!(sys_setuid(uid).map_err(|x| Error::from_sys(x)));
+            }
+
+            for closure in unsafe { (*child_closures).iter_mut() } {
+                try!(closure());
+            }
+
+            unsafe { sys_execve(path_c.as_ptr(), args_c.as_ptr(), vars_c.as_ptr()) }.map_err(|x| Error::from_sys(x))
         });
 
         match unsafe { sys_clone(flags) } {
@@ -362,6 +467,23 @@ impl Command {
     }
 }
 
+impl CommandExt for Command {
+    fn uid(&mut self, id: u32) -> &mut Command {
+        self.uid = Some(id as usize);
+        self
+    }
+
+    fn gid(&mut self, id: u32) -> &mut Command {
+        self.gid = Some(id as usize);
+        self
+    }
+
+    fn before_exec<F>(&mut self, f: F) -> &mut Command where F: FnMut() -> Result<()> + Send + Sync + 'static {
+        self.closures.push(Box::new(f));
+        self
+    }
+}
+
 #[derive(Copy, Clone)]
 enum StdioType {
     Piped(usize, usize),
@@ -1,6 +1,6 @@
 use core::{fmt, mem, ptr, slice, str};
 use panic::panic_impl;
-use env::{args_init, args_destroy};
+use env::{args_init, args_destroy, vars_init, vars_destroy};
 use system::syscall::sys_exit;
 use vec::Vec;
 
@@ -65,6 +65,19 @@ pub unsafe extern "C" fn _start_stack(stack: *const usize){
     let _ = sys_exit(main(argc, argv));
 }
 
+/// Get a null terminated string passed on the stack of the program
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let mut len = 0;
+    for j in 0..4096 {
+        len = j;
+        if ptr::read(ptr.offset(j)) == 0 {
+            break;
+        }
+    }
+    let utf8: &'static [u8] = slice::from_raw_parts(ptr, len as usize);
+    str::from_utf8_unchecked(utf8)
+}
+
 #[lang = "start"]
 fn lang_start(main: *const u8, argc: usize, argv: *const *const u8) -> usize {
     unsafe {
@@ -72,22 +85,29 @@ fn lang_start(main: *const u8, argc: usize, argv: *const *const u8) -> usize {
         for i in 0..argc as isize {
             let arg = ptr::read(argv.offset(i));
             if arg as usize > 0 {
-                let mut len = 0;
-                for j in 0..4096 {
-                    len = j;
-                    if ptr::read(arg.offset(j)) == 0 {
-                        break;
-                    }
-                }
-                let utf8: &'static [u8] = slice::from_raw_parts(arg, len as usize);
-                args.push(str::from_utf8_unchecked(utf8));
+                args.push(c_str(arg));
+            }
+        }
+
+        // The environment variables follow the null pointer ending the arguments
+        let envp = argv.offset(argc as isize + 1);
+        let mut vars: Vec<&'static str> = Vec::new();
+        let mut i = 0;
+        loop {
+            let var = ptr::read(envp.offset(i));
+            if var as usize == 0 {
+                break;
             }
+            vars.push(c_str(var));
+            i += 1;
         }
 
         args_init(args);
+        vars_init(vars);
 
         mem::transmute::<_, fn()>(main)();
 
+        vars_destroy();
         args_destroy();
     }
 
@@ -0,0 +1,99 @@
+use sync::{Condvar, Mutex};
+
+struct BarrierState {
+    count: usize,
+    generation: usize,
+}
+
+/// A barrier, which blocks a number of threads until all of them have reached it. See rust std's
+/// Barrier.
+pub struct Barrier {
+    lock: Mutex<BarrierState>,
+    condvar: Condvar,
+    num_threads: usize,
+}
+
+/// The result of `Barrier::wait`
+pub struct BarrierWaitResult(bool);
+
+impl Barrier {
+    /// Create a new barrier for `n` threads
+    pub fn new(n: usize) -> Barrier {
+        Barrier {
+            lock: Mutex::new(BarrierState {
+                count: 0,
+                generation: 0,
+            }),
+            condvar: Condvar::new(),
+            num_threads: n,
+        }
+    }
+
+    /// Block until all threads have called `wait`
+    ///
+    /// The barrier can be used again once all threads have been released. One of the threads gets
+    /// a result for which `is_leader` is true.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let mut lock = self.lock.lock().unwrap();
+        let generation = lock.generation;
+        lock.count += 1;
+        if lock.count < self.num_threads {
+            while generation == lock.generation && lock.count < self.num_threads {
+                lock = self.condvar.wait(lock).unwrap();
+            }
+            BarrierWaitResult(false)
+        } else {
+            lock.count = 0;
+            lock.generation = lock.generation.wrapping_add(1);
+            self.condvar.notify_all();
+            BarrierWaitResult(true)
+        }
+    }
+}
+
+impl BarrierWaitResult {
+    /// Check if this thread is the leader, which is true for one of the threads released
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prelude::v1::*;
+
+    use sync::{Arc, Barrier};
+    use sync::mpsc::channel;
+    use thread;
+
+    #[test]
+    fn test_barrier() {
+        const N: usize = 10;
+
+        let barrier = Arc::new(Barrier::new(N));
+        let (tx, rx) = channel();
+
+        for _ in 0..N - 1 {
+            let c = barrier.clone();
+            let tx = tx.clone();
+            thread::spawn(move|| {
+                tx.send(c.wait().is_leader()).unwrap();
+            });
+        }
+
+        // At this point, all spawned threads should be blocked,
+        // so we shouldn't get anything from the port
+        assert!(rx.try_recv().is_err());
+
+        let mut leader_found = barrier.wait().is_leader();
+
+        // Now, the barrier is cleared and we should get data.
+        for _ in 0..N - 1 {
+            if rx.recv().unwrap() {
+                assert!(!leader_found);
+                leader_found = true;
+            }
+        }
+        assert!(leader_found);
+    }
+}
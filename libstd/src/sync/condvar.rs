@@ -0,0 +1,87 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::usize;
+
+use super::futex;
+use super::mutex::{self, guard_lock, MutexGuard};
+
+/// A condition variable, used to block a thread until another thread changes the data protected by
+/// a mutex. See rust std's Condvar.
+///
+/// Waiting threads block on a futex holding a sequence number, which is incremented by every
+/// notification.
+pub struct Condvar {
+    seq: AtomicUsize,
+}
+
+impl Condvar {
+    /// Create a new condition variable
+    pub const fn new() -> Condvar {
+        Condvar {
+            seq: AtomicUsize::new(0),
+        }
+    }
+
+    /// Release the mutex of `guard` and block until this condition variable is notified, then
+    /// acquire the mutex again
+    ///
+    /// Like with rust std's Condvar, the thread may wake up without a notification, so the
+    /// condition has to be checked in a loop.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> Result<MutexGuard<'a, T>, ()> {
+        let lock = guard_lock(&guard);
+        let seq = self.seq.load(Ordering::SeqCst);
+
+        mutex::unlock(lock);
+        futex::wait(&self.seq, seq);
+        // Other threads may have been woken at the same time, so the lock is taken as contended
+        mutex::lock_contended(lock);
+
+        Ok(guard)
+    }
+
+    /// Wake up one thread blocked on this condition variable
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        futex::wake(&self.seq, 1);
+    }
+
+    /// Wake up all threads blocked on this condition variable
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::SeqCst);
+        futex::wake(&self.seq, usize::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Condvar {
+        Condvar::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prelude::v1::*;
+
+    use sync::{Arc, Condvar, Mutex};
+    use thread;
+
+    #[test]
+    fn notify_one() {
+        let pair = Arc::new((Mutex::new(false), Condvar::new()));
+        let pair2 = pair.clone();
+
+        let handle = thread::spawn(move || {
+            let &(ref lock, ref cvar) = &*pair2;
+            *lock.lock().unwrap() = true;
+            cvar.notify_one();
+        });
+
+        let &(ref lock, ref cvar) = &*pair;
+        let mut started = lock.lock().unwrap();
+        while !*started {
+            started = cvar.wait(started).unwrap();
+        }
+        drop(started);
+
+        handle.join();
+    }
+}
@@ -0,0 +1,15 @@
+use core::sync::atomic::AtomicUsize;
+
+use system::syscall::{sys_futex, FUTEX_WAIT, FUTEX_WAKE};
+
+/// Block while `futex` holds `value`, until it is woken by `wake`
+///
+/// This may return early, so the caller has to check the value again.
+pub fn wait(futex: &AtomicUsize, value: usize) {
+    let _ = unsafe { sys_futex(futex as *const AtomicUsize as *mut usize, FUTEX_WAIT, value) };
+}
+
+/// Wake up to `count` threads waiting on `futex`, returning how many were woken
+pub fn wake(futex: &AtomicUsize, count: usize) -> usize {
+    unsafe { sys_futex(futex as *const AtomicUsize as *mut usize, FUTEX_WAKE, count) }.unwrap_or(0)
+}
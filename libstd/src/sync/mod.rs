@@ -1,10 +1,15 @@
 pub use alloc::arc::{Arc, Weak};
 pub use core::sync::atomic;
+pub use self::barrier::{Barrier, BarrierWaitResult};
+pub use self::condvar::Condvar;
 pub use self::mutex::{Mutex, MutexGuard, StaticMutex};
 pub use self::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 pub use self::once::Once;
 
 pub mod mpsc;
+mod barrier;
+mod condvar;
+mod futex;
 mod mutex;
 mod once;
 mod rwlock;
@@ -1,12 +1,46 @@
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut, Drop};
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::futex;
+
+/// The lock is free
+const UNLOCKED: usize = 0;
+/// The lock is held, and no thread is waiting for it
+const LOCKED: usize = 1;
+/// The lock is held, and threads may be waiting for it
+const CONTENDED: usize = 2;
+
+/// Acquire a lock, blocking on its futex while it is held by another thread
+pub fn lock(lock: &AtomicUsize) {
+    let state = lock.compare_and_swap(UNLOCKED, LOCKED, Ordering::SeqCst);
+    if state != UNLOCKED {
+        if state == CONTENDED || lock.swap(CONTENDED, Ordering::SeqCst) != UNLOCKED {
+            lock_contended(lock);
+        }
+    }
+}
 
-use thread;
+/// Acquire a lock, marking it as contended so that the next unlock wakes a waiting thread
+pub fn lock_contended(lock: &AtomicUsize) {
+    loop {
+        futex::wait(lock, CONTENDED);
+        if lock.swap(CONTENDED, Ordering::SeqCst) == UNLOCKED {
+            break;
+        }
+    }
+}
+
+/// Release a lock, waking a thread waiting for it
+pub fn unlock(lock: &AtomicUsize) {
+    if lock.swap(UNLOCKED, Ordering::SeqCst) == CONTENDED {
+        futex::wake(lock, 1);
+    }
+}
 
 /// A mutex, i.e. a form of safe shared memory between threads. See rust std's Mutex.
 pub struct Mutex<T: ?Sized> {
-    lock: AtomicBool,
+    lock: AtomicUsize,
     value: UnsafeCell<T>,
 }
 
@@ -14,7 +48,7 @@ impl<T> Mutex<T> {
     /// Create a new mutex with value `value`.
     pub fn new(value: T) -> Self {
         Mutex {
-            lock: AtomicBool::new(false),
+            lock: AtomicUsize::new(UNLOCKED),
             value: UnsafeCell::new(value),
         }
     }
@@ -23,11 +57,18 @@ impl<T> Mutex<T> {
 impl<T: ?Sized> Mutex<T> {
     /// Lock the mutex
     pub fn lock(&self) -> Result<MutexGuard<T>, ()> {
-        while self.lock.compare_and_swap(false, true, Ordering::SeqCst) {
-            thread::yield_now();
-        }
+        lock(&self.lock);
         Ok(MutexGuard::new(&self.lock, &self.value))
     }
+
+    /// Try to lock the mutex, without blocking
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        if self.lock.compare_and_swap(UNLOCKED, LOCKED, Ordering::SeqCst) == UNLOCKED {
+            Some(MutexGuard::new(&self.lock, &self.value))
+        } else {
+            None
+        }
+    }
 }
 
 struct Dummy(UnsafeCell<()>);
@@ -35,28 +76,27 @@ unsafe impl Sync for Dummy {}
 static DUMMY: Dummy = Dummy(UnsafeCell::new(()));
 
 pub struct StaticMutex {
-    lock: AtomicBool,
+    lock: AtomicUsize,
 }
 
 impl StaticMutex {
     /// Create a new mutex with value `value`.
     pub const fn new() -> Self {
         StaticMutex {
-            lock: AtomicBool::new(false),
+            lock: AtomicUsize::new(UNLOCKED),
         }
     }
 
     /// Lock the mutex
     pub fn lock(&'static self) -> Result<MutexGuard<()>, ()> {
-        while self.lock.compare_and_swap(false, true, Ordering::SeqCst) {
-            thread::yield_now();
-        }
+        lock(&self.lock);
         Ok(MutexGuard::new(&self.lock, &DUMMY.0)) // TODO catch panics
     }
 
     pub unsafe fn destroy(&'static self) {
-        if !self.lock.compare_and_swap(true, false, Ordering::SeqCst) {
-            // Mutex was already unlocked!
+        if self.lock.load(Ordering::SeqCst) != UNLOCKED {
+            // Mutex was not unlocked!
+            unlock(&self.lock);
         }
     }
 }
@@ -67,12 +107,12 @@ unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
 
 /// A mutex guard (returned by .lock())
 pub struct MutexGuard<'a, T: ?Sized + 'a> {
-    lock: &'a AtomicBool,
+    lock: &'a AtomicUsize,
     data: &'a UnsafeCell<T>,
 }
 
 impl<'mutex, T: ?Sized> MutexGuard<'mutex, T> {
-    fn new(lock: &'mutex AtomicBool, data: &'mutex UnsafeCell<T>) -> Self {
+    fn new(lock: &'mutex AtomicUsize, data: &'mutex UnsafeCell<T>) -> Self {
         MutexGuard {
             lock: lock,
             data: data,
@@ -80,6 +120,11 @@ impl<'mutex, T: ?Sized> MutexGuard<'mutex, T> {
     }
 }
 
+/// Get the lock of a mutex guard, used by `Condvar` to release and acquire it while waiting
+pub fn guard_lock<'a, T: ?Sized>(guard: &MutexGuard<'a, T>) -> &'a AtomicUsize {
+    guard.lock
+}
+
 impl<'mutex, T: ?Sized> Deref for MutexGuard<'mutex, T> {
     type Target = T;
 
@@ -96,8 +141,6 @@ impl<'mutex, T: ?Sized> DerefMut for MutexGuard<'mutex, T> {
 
 impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
     fn drop(&mut self) {
-        if !self.lock.compare_and_swap(true, false, Ordering::SeqCst) {
-            // Mutex was already unlocked!
-        }
+        unlock(self.lock);
     }
 }
@@ -1,17 +1,20 @@
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 
-use sync::Mutex;
-
-use thread;
+use sync::{Condvar, Mutex};
 
 struct RwLockInner {
     writer: bool,
     readers: usize,
 }
 
+/// A reader-writer lock. See rust std's RwLock.
+///
+/// Threads waiting for the lock block on a condition variable, which is notified when the last
+/// reader or the writer releases it.
 pub struct RwLock<T: ?Sized> {
     inner: Mutex<RwLockInner>,
+    condvar: Condvar,
     value: UnsafeCell<T>,
 }
 
@@ -23,6 +26,7 @@ impl<T> RwLock<T> {
                 writer: false,
                 readers: 0,
             }),
+            condvar: Condvar::new(),
             value: UnsafeCell::new(value),
         }
     }
@@ -31,32 +35,22 @@ impl<T> RwLock<T> {
 impl<T: ?Sized> RwLock<T> {
     /// Lock for read
     pub fn read(&self) -> RwLockReadGuard<T> {
-        loop {
-            {
-                let mut inner = self.inner.lock().unwrap();
-                if inner.writer == false {
-                    inner.readers += 1;
-                    break;
-                }
-            }
-            thread::yield_now();
+        let mut inner = self.inner.lock().unwrap();
+        while inner.writer {
+            inner = self.condvar.wait(inner).unwrap();
         }
-        RwLockReadGuard::new(&self.inner, &self.value)
+        inner.readers += 1;
+        RwLockReadGuard::new(&self.inner, &self.condvar, &self.value)
     }
 
     /// Lock for write
     pub fn write(&self) -> RwLockWriteGuard<T> {
-        loop {
-            {
-                let mut inner = self.inner.lock().unwrap();
-                if inner.writer == false && inner.readers == 0 {
-                    inner.writer = true;
-                    break;
-                }
-            }
-            thread::yield_now();
+        let mut inner = self.inner.lock().unwrap();
+        while inner.writer || inner.readers > 0 {
+            inner = self.condvar.wait(inner).unwrap();
         }
-        RwLockWriteGuard::new(&self.inner, &self.value)
+        inner.writer = true;
+        RwLockWriteGuard::new(&self.inner, &self.condvar, &self.value)
     }
 }
 
@@ -67,13 +61,15 @@ unsafe impl<T: ?Sized + Send> Sync for RwLock<T> { }
 /// A read guard (returned by .read())
 pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
     inner: &'a Mutex<RwLockInner>,
+    condvar: &'a Condvar,
     data: &'a UnsafeCell<T>,
 }
 
 impl<'rwlock, T: ?Sized> RwLockReadGuard<'rwlock, T> {
-    fn new(inner: &'rwlock Mutex<RwLockInner>, data: &'rwlock UnsafeCell<T>) -> Self {
+    fn new(inner: &'rwlock Mutex<RwLockInner>, condvar: &'rwlock Condvar, data: &'rwlock UnsafeCell<T>) -> Self {
         RwLockReadGuard {
             inner: inner,
+            condvar: condvar,
             data: data,
         }
     }
@@ -91,6 +87,9 @@ impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
     fn drop(&mut self) {
         let mut inner = self.inner.lock().unwrap();
         inner.readers -= 1;
+        if inner.readers == 0 {
+            self.condvar.notify_all();
+        }
     }
 }
 
@@ -98,13 +97,15 @@ impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
 /// A write guard (returned by .write())
 pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
     inner: &'a Mutex<RwLockInner>,
+    condvar: &'a Condvar,
     data: &'a UnsafeCell<T>,
 }
 
 impl<'rwlock, T: ?Sized> RwLockWriteGuard<'rwlock, T> {
-    fn new(inner: &'rwlock Mutex<RwLockInner>, data: &'rwlock UnsafeCell<T>) -> Self {
+    fn new(inner: &'rwlock Mutex<RwLockInner>, condvar: &'rwlock Condvar, data: &'rwlock UnsafeCell<T>) -> Self {
         RwLockWriteGuard {
             inner: inner,
+            condvar: condvar,
             data: data,
         }
     }
@@ -128,5 +129,6 @@ impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
     fn drop(&mut self) {
         let mut inner = self.inner.lock().unwrap();
         inner.writer = false;
+        self.condvar.notify_all();
     }
 }
@@ -3,9 +3,12 @@ use fs::File;
 use io::{Error, ErrorKind, Result, Read, Write};
 use iter::Iterator;
 use net::{SocketAddr, Shutdown};
+use os::unix::io::AsRawFd;
 use time::Duration;
 use vec::Vec;
 
+use system::syscall::{sys_fcntl, F_GETFL, F_SETFL, O_NONBLOCK};
+
 pub struct LookupHost;
 
 impl Iterator for LookupHost {
@@ -19,6 +22,22 @@ pub fn lookup_host(_host: &str) -> Result<LookupHost> {
     Err(Error::new(ErrorKind::Other, "Not implemented"))
 }
 
+/// Check if `O_NONBLOCK` is set on a socket
+fn nonblocking(file: &File) -> Result<bool> {
+    sys_fcntl(file.as_raw_fd(), F_GETFL, 0).map(|flags| flags & O_NONBLOCK == O_NONBLOCK).map_err(|x| Error::from_sys(x))
+}
+
+/// Set or clear `O_NONBLOCK` on a socket, so that reads return `WouldBlock` instead of blocking
+fn set_nonblocking(file: &File, nonblocking: bool) -> Result<()> {
+    let flags = try!(sys_fcntl(file.as_raw_fd(), F_GETFL, 0).map_err(|x| Error::from_sys(x)));
+    let flags = if nonblocking {
+        flags | O_NONBLOCK
+    } else {
+        flags & !O_NONBLOCK
+    };
+    sys_fcntl(file.as_raw_fd(), F_SETFL, flags).and(Ok(())).map_err(|x| Error::from_sys(x))
+}
+
 #[derive(Debug)]
 pub struct TcpStream(UnsafeCell<File>);
 
@@ -66,7 +85,7 @@ impl TcpStream {
     }
 
     pub fn nonblocking(&self) -> Result<bool> {
-        Err(Error::new(ErrorKind::Other, "Not implemented"))
+        nonblocking(unsafe { &*self.0.get() })
     }
 
     pub fn only_v6(&self) -> Result<bool> {
@@ -89,8 +108,8 @@ impl TcpStream {
         Err(Error::new(ErrorKind::Other, "Not implemented"))
     }
 
-    pub fn set_nonblocking(&self, _nonblocking: bool) -> Result<()> {
-        Err(Error::new(ErrorKind::Other, "Not implemented"))
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        set_nonblocking(unsafe { &*self.0.get() }, nonblocking)
     }
 
     pub fn set_only_v6(&self, _only_v6: bool) -> Result<()> {
@@ -194,7 +213,7 @@ impl UdpSocket {
     }
 
     pub fn nonblocking(&self) -> Result<bool> {
-        Err(Error::new(ErrorKind::Other, "Not implemented"))
+        nonblocking(unsafe { &*self.0.get() })
     }
 
     pub fn only_v6(&self) -> Result<bool> {
@@ -217,8 +236,8 @@ impl UdpSocket {
         Err(Error::new(ErrorKind::Other, "Not implemented"))
     }
 
-    pub fn set_nonblocking(&self, _nonblocking: bool) -> Result<()> {
-        Err(Error::new(ErrorKind::Other, "Not implemented"))
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<()> {
+        set_nonblocking(unsafe { &*self.0.get() }, nonblocking)
     }
 
     pub fn set_only_v6(&self, _only_v6: bool) -> Result<()> {
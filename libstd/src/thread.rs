@@ -63,6 +63,11 @@ pub fn sleep_ms(ms: u32) {
 /// panics, `join` will return an `Err` containing the argument given to
 /// `panic`.
 ///
+/// The thread gets a stack of its own, with a guard page below it. The stack
+/// grows when it is used up to the `RLIMIT_STACK` limit of the process, a
+/// thread that runs past the limit terminates the process with a stack
+/// overflow.
+///
 /// # Panics
 ///
 /// Panics if the OS fails to create a thread; use `Builder::spawn`